@@ -0,0 +1,55 @@
+//! Cost of each verification phase and of the output formats on top of it
+//!
+//! Run with `cargo bench --features bench --bench verify`.
+
+use aws_nitro_enclaves_attestation::{bench, NitroAdDoc, Verifier};
+use chrono::{TimeZone, Utc};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+static AD_BLOB: &[u8] = include_bytes!("../tests/data/nitro_ad_debug.bin");
+static ROOT_CERT: &[u8] = include_bytes!("../tests/data/aws_root.der");
+const VERIFY_AT: u64 = 1614967200;
+
+fn phases(c: &mut Criterion) {
+    let now = Utc.timestamp_opt(VERIFY_AT as i64, 0).unwrap();
+    let parsed = bench::parse(AD_BLOB, now).unwrap();
+    let (_, spki) = parsed.verify_chain(ROOT_CERT, VERIFY_AT).unwrap();
+
+    let mut group = c.benchmark_group("phase");
+    group.bench_function("parse", |b| b.iter(|| bench::parse(black_box(AD_BLOB), now).unwrap()));
+    group.bench_function("chain", |b| {
+        b.iter(|| parsed.verify_chain(black_box(ROOT_CERT), VERIFY_AT).unwrap())
+    });
+    group.bench_function("signature", |b| {
+        b.iter(|| assert!(parsed.verify_signature(black_box(&spki)).unwrap()))
+    });
+    group.finish();
+}
+
+fn verify(c: &mut Criterion) {
+    let verifier = Verifier::new(ROOT_CERT);
+
+    let mut group = c.benchmark_group("verify");
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| NitroAdDoc::from_bytes(black_box(AD_BLOB), ROOT_CERT, VERIFY_AT).unwrap())
+    });
+    group.bench_function("verifier", |b| {
+        b.iter(|| verifier.verify(black_box(AD_BLOB), VERIFY_AT).unwrap())
+    });
+    group.bench_function("verify_fast", |b| {
+        b.iter(|| verifier.verify_fast(black_box(AD_BLOB), VERIFY_AT).unwrap())
+    });
+    group.finish();
+}
+
+fn output(c: &mut Criterion) {
+    let doc = NitroAdDoc::from_bytes(AD_BLOB, ROOT_CERT, VERIFY_AT).unwrap();
+
+    let mut group = c.benchmark_group("output");
+    group.bench_function("to_json", |b| b.iter(|| doc.to_json().unwrap()));
+    group.bench_function("report", |b| b.iter(|| doc.report()));
+    group.finish();
+}
+
+criterion_group!(benches, phases, verify, output);
+criterion_main!(benches);
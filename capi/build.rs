@@ -0,0 +1,8 @@
+// major version of the shared object, NITRO_AD_ABI_VERSION of the library's ffi module
+const ABI_VERSION: u32 = 1;
+
+fn main() {
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        println!("cargo:rustc-cdylib-link-arg=-Wl,-soname,libnitro_ad.so.{}", ABI_VERSION);
+    }
+}
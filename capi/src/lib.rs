@@ -0,0 +1,3 @@
+//! Shared and static library of the `ffi` feature, see the library's ffi module
+
+pub use aws_nitro_enclaves_attestation::ffi::*;
@@ -0,0 +1,71 @@
+//! WASI component of the `nitro:attestation/verifier` interface in `wit/`
+//!
+//! ```bash
+//! cargo build --release --target wasm32-wasip2
+//! ```
+//! Like the `wasm` crate this needs clang for the wasm32 build of *ring*.
+
+use aws_nitro_enclaves_attestation::report::VerificationReport;
+use aws_nitro_enclaves_attestation::{NitroAdDoc, NitroAdError};
+
+wit_bindgen::generate!({
+    world: "attestation",
+    path: "wit",
+});
+
+use exports::nitro::attestation::verifier::{Error, Guest, Report};
+
+struct Component;
+
+impl From<VerificationReport> for Report {
+    fn from(report: VerificationReport) -> Self {
+        Report {
+            document_sha384: report.document_sha384,
+            module_id: report.module_id,
+            document_timestamp_ms: report.document_timestamp_ms,
+            verified_at: report.verified_at,
+            pcrs: report.pcrs.into_iter().collect(),
+            debug_mode: report.debug_mode,
+            chain_error: report.chain_error,
+        }
+    }
+}
+
+impl From<NitroAdError> for Error {
+    fn from(e: NitroAdError) -> Self {
+        Error {
+            code: e.code(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl Guest for Component {
+    fn verify(document: Vec<u8>, root_cert: Vec<u8>, unix_ts_sec: u64) -> Result<Report, Error> {
+        let doc = NitroAdDoc::from_bytes(&document, &root_cert, unix_ts_sec)?;
+        Ok(doc.report().into())
+    }
+}
+
+export!(Component);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let ad_blob = include_bytes!("../../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../../tests/data/aws_root.der");
+
+        let report = Component::verify(ad_blob.to_vec(), root_cert.to_vec(), 1614967200).unwrap();
+        assert_eq!(report.pcrs.len(), 16);
+        assert_eq!(report.chain_error, None);
+
+        let report = Component::verify(ad_blob.to_vec(), root_cert.to_vec(), 1714967200).unwrap();
+        assert!(report.chain_error.is_some());
+
+        let err = Component::verify(ad_blob[..10].to_vec(), root_cert.to_vec(), 1614967200).unwrap_err();
+        assert_eq!(err.code, 2);
+    }
+}
@@ -0,0 +1,168 @@
+//! C++ API through cxx
+//!
+//! `nitro::verify()` returns a `rust::Box<nitro::NitroAttestation>`, which releases
+//! the document when it goes out of scope. Failures throw `rust::Error`, whose
+//! `what()` is the `NitroAdError` message. Byte strings come as `std::vector<uint8_t>`;
+//! text as `rust::String`, which converts to `std::string`.
+//!
+//! The cxx generated header lands in the target dir as
+//! `cxxbridge/aws-nitro-enclaves-attestation-cxx/src/lib.rs.h`.
+
+use aws_nitro_enclaves_attestation::{Bytes, NitroAdDoc, NitroAdError};
+use cxx::{CxxVector, UniquePtr};
+
+#[cxx::bridge(namespace = "nitro")]
+mod ffi {
+    extern "Rust" {
+        /// Verified attestation document
+        type NitroAttestation;
+
+        /// Verifies the document `doc` against the DER encoded `root_cert` at
+        /// `unix_ts_sec`. Certificate chain failures throw too.
+        fn verify(
+            doc: &CxxVector<u8>,
+            root_cert: &CxxVector<u8>,
+            unix_ts_sec: u64,
+        ) -> Result<Box<NitroAttestation>>;
+
+        fn module_id(self: &NitroAttestation) -> &str;
+
+        fn digest(self: &NitroAttestation) -> &str;
+
+        /// Document timestamp, milliseconds since the unix epoch
+        fn timestamp_ms(self: &NitroAttestation) -> i64;
+
+        /// Number of PCRs, indexes run from 0 to the count excluded
+        fn pcr_count(self: &NitroAttestation) -> usize;
+
+        /// PCR `index`, throws for absent PCRs
+        fn pcr(self: &NitroAttestation, index: u8) -> Result<UniquePtr<CxxVector<u8>>>;
+
+        /// Enclave runs in debug mode, its PCRs attest nothing
+        fn is_debug_mode(self: &NitroAttestation) -> bool;
+
+        fn has_public_key(self: &NitroAttestation) -> bool;
+
+        /// `public_key`, empty when absent
+        fn public_key(self: &NitroAttestation) -> UniquePtr<CxxVector<u8>>;
+
+        fn has_user_data(self: &NitroAttestation) -> bool;
+
+        /// `user_data`, empty when absent
+        fn user_data(self: &NitroAttestation) -> UniquePtr<CxxVector<u8>>;
+
+        fn has_nonce(self: &NitroAttestation) -> bool;
+
+        /// `nonce`, empty when absent
+        fn nonce(self: &NitroAttestation) -> UniquePtr<CxxVector<u8>>;
+
+        /// JSON of `NitroAdDoc::to_json`
+        fn to_json(self: &NitroAttestation) -> Result<String>;
+    }
+}
+
+pub struct NitroAttestation(NitroAdDoc<'static>);
+
+fn vector(bytes: &[u8]) -> UniquePtr<CxxVector<u8>> {
+    let mut vector = CxxVector::new();
+    for b in bytes {
+        vector.pin_mut().push(*b);
+    }
+    vector
+}
+
+fn optional_vector(bytes: &Option<Bytes>) -> UniquePtr<CxxVector<u8>> {
+    vector(bytes.as_deref().unwrap_or_default())
+}
+
+fn verify(
+    doc: &CxxVector<u8>,
+    root_cert: &CxxVector<u8>,
+    unix_ts_sec: u64,
+) -> Result<Box<NitroAttestation>, NitroAdError> {
+    let doc = NitroAdDoc::from_bytes(doc.as_slice(), root_cert.as_slice(), unix_ts_sec)?;
+    match doc.verification_error() {
+        Some(e) => Err(NitroAdError::VerificationError(e)),
+        None => Ok(Box::new(NitroAttestation(doc.into_owned()))),
+    }
+}
+
+impl NitroAttestation {
+    fn module_id(&self) -> &str {
+        &self.0.payload().module_id
+    }
+
+    fn digest(&self) -> &str {
+        &self.0.payload().digest
+    }
+
+    fn timestamp_ms(&self) -> i64 {
+        self.0.payload().timestamp.timestamp_millis()
+    }
+
+    fn pcr_count(&self) -> usize {
+        self.0.payload().pcrs.len()
+    }
+
+    fn pcr(&self, index: u8) -> Result<UniquePtr<CxxVector<u8>>, NitroAdError> {
+        let pcr = self.0.payload().pcrs.get(&index).ok_or(NitroAdError::MissingPcr(index))?;
+        Ok(vector(pcr))
+    }
+
+    fn is_debug_mode(&self) -> bool {
+        self.0.payload().is_debug_mode()
+    }
+
+    fn has_public_key(&self) -> bool {
+        self.0.payload().public_key.is_some()
+    }
+
+    fn public_key(&self) -> UniquePtr<CxxVector<u8>> {
+        optional_vector(&self.0.payload().public_key)
+    }
+
+    fn has_user_data(&self) -> bool {
+        self.0.payload().user_data.is_some()
+    }
+
+    fn user_data(&self) -> UniquePtr<CxxVector<u8>> {
+        optional_vector(&self.0.payload().user_data)
+    }
+
+    fn has_nonce(&self) -> bool {
+        self.0.payload().nonce.is_some()
+    }
+
+    fn nonce(&self) -> UniquePtr<CxxVector<u8>> {
+        optional_vector(&self.0.payload().nonce)
+    }
+
+    fn to_json(&self) -> Result<String, NitroAdError> {
+        self.0.to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let ad_blob = vector(include_bytes!("../../tests/data/nitro_ad_debug.bin"));
+        let root_cert = vector(include_bytes!("../../tests/data/aws_root.der"));
+
+        let doc = verify(&ad_blob, &root_cert, 1614967200).unwrap();
+        assert_eq!(doc.digest(), "SHA384");
+        assert_eq!(doc.pcr_count(), 16);
+        assert_eq!(doc.pcr(0).unwrap().as_slice(), &[0; 48][..]);
+        assert!(matches!(doc.pcr(31), Err(NitroAdError::MissingPcr(31))));
+        assert!(doc.is_debug_mode());
+        assert!(!doc.has_nonce());
+        assert!(doc.nonce().is_empty());
+
+        assert!(matches!(
+            verify(&ad_blob, &root_cert, 1714967200),
+            Err(NitroAdError::VerificationError(_))
+        ));
+    }
+}
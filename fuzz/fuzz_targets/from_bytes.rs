@@ -0,0 +1,11 @@
+#![no_main]
+
+use aws_nitro_enclaves_attestation::NitroAdDoc;
+use libfuzzer_sys::fuzz_target;
+
+static ROOT_CERT: &[u8] = include_bytes!("../../tests/data/aws_root.der");
+
+// raw bytes through the full verification path
+fuzz_target!(|data: &[u8]| {
+    let _ = NitroAdDoc::from_bytes(data, ROOT_CERT, 1614967200);
+});
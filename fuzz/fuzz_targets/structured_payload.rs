@@ -0,0 +1,12 @@
+#![no_main]
+
+use aws_nitro_enclaves_attestation::fuzzing::ArbitraryDocument;
+use aws_nitro_enclaves_attestation::NitroAdDoc;
+use libfuzzer_sys::fuzz_target;
+
+// well-formed envelopes with arbitrary payloads through the COSE/CBOR checks
+fuzz_target!(|doc: ArbitraryDocument| {
+    if let Ok(doc) = NitroAdDoc::from_bytes_relaxed(&doc.to_bytes()) {
+        let _ = doc.to_json();
+    }
+});
@@ -0,0 +1,101 @@
+/*
+ * C API of aws-nitro-enclaves-attestation, exported by libnitro_ad.
+ *
+ * Functions return 0 on success or an error code, see nitro_ad_error_description().
+ * Documents are opaque NitroAdDocument handles owned by the caller until
+ * nitro_ad_free(); byte strings of the getters live as long as their handle.
+ * No struct layouts are part of the ABI. NITRO_AD_ABI_VERSION, the soname major
+ * version, changes when functions are removed or change signature or meaning.
+ */
+
+#ifndef NITRO_AD_H
+#define NITRO_AD_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Version of the C API. Incremented on incompatible changes, i.e. removed or changed
+// functions; additions keep it. Shared objects are named `libnitro_ad.so.<version>`.
+#define NITRO_AD_ABI_VERSION 1
+
+// Null pointer or otherwise unusable argument
+#define NITRO_AD_ERR_INVALID_ARGUMENT 70
+
+// Requested optional document field is absent
+#define NITRO_AD_ERR_ABSENT_FIELD 71
+
+// Verified attestation document
+typedef struct NitroAdDocument NitroAdDocument;
+
+// `NITRO_AD_ABI_VERSION` of the library, for programs checking at runtime that the
+// loaded library matches the header they were built against
+uint32_t nitro_ad_abi_version(void);
+
+// Verifies the document `doc` against the DER encoded `root_cert` at `unix_ts_sec`.
+// Unlike `NitroAdDoc::from_bytes`, certificate chain failures are errors. Stores
+// the document handle in `*out` on success.
+//
+// # Safety
+//
+// `doc` and `root_cert` point to `doc_len` and `root_cert_len` readable bytes,
+// `out` is a valid pointer to write a handle to.
+uint32_t nitro_ad_verify(const uint8_t *doc,
+                         size_t doc_len,
+                         const uint8_t *root_cert,
+                         size_t root_cert_len,
+                         uint64_t unix_ts_sec,
+                         struct NitroAdDocument **out);
+
+// Releases a handle of `nitro_ad_verify()`, null handles are ignored
+//
+// # Safety
+//
+// `doc` is null or a handle of `nitro_ad_verify()` not released before.
+void nitro_ad_free(struct NitroAdDocument *doc);
+
+// JSON of `NitroAdDoc::to_json`, NUL terminated. Stores a string to release with
+// `nitro_ad_string_free()` in `*out` on success.
+//
+// # Safety
+//
+// `doc` is a live handle of `nitro_ad_verify()`, `out` a valid pointer to write to.
+uint32_t nitro_ad_to_json(const struct NitroAdDocument *doc, char **out);
+
+// Releases a string of `nitro_ad_to_json()`, null strings are ignored
+//
+// # Safety
+//
+// `s` is null or a string of this library not released before.
+void nitro_ad_string_free(char *s);
+
+// Stores PCR `index` in `*data` and `*len`. The bytes live as long as `doc`.
+//
+// # Safety
+//
+// `doc` is a live handle of `nitro_ad_verify()`, `data` and `len` valid pointers to write to.
+uint32_t nitro_ad_pcr(const struct NitroAdDocument *doc,
+                      uint8_t index,
+                      const uint8_t **data,
+                      size_t *len);
+
+// Number of PCRs of `doc`, indexes run from 0 to the count excluded. 0 for null handles.
+//
+// # Safety
+//
+// `doc` is null or a live handle of `nitro_ad_verify()`.
+size_t nitro_ad_pcr_count(const struct NitroAdDocument *doc);
+
+// Stores the document `public_key` in `*data` and `*len`, fails with
+// `NITRO_AD_ERR_ABSENT_FIELD` for documents without one. The bytes live as long as `doc`.
+//
+// # Safety
+//
+// `doc` is a live handle of `nitro_ad_verify()`, `data` and `len` valid pointers to write to.
+uint32_t nitro_ad_public_key(const struct NitroAdDocument *doc, const uint8_t **data, size_t *len);
+
+// Static NUL terminated description of an error code, null for unknown codes
+const char *nitro_ad_error_description(uint32_t code);
+
+#endif  /* NITRO_AD_H */
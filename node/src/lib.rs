@@ -0,0 +1,59 @@
+//! Node.js API through napi-rs
+//!
+//! ```bash
+//! napi build --platform --release
+//! ```
+//! builds the addon, exporting [`verify`] as `verify`:
+//! ```js
+//! const { verify } = require('./aws-nitro-enclaves-attestation.linux-x64-gnu.node');
+//! const doc = verify(documentBuffer, awsRootDer, Date.now() / 1000);
+//! ```
+
+use aws_nitro_enclaves_attestation::{NitroAdDoc, NitroAdError};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+/// Verified attestation document
+#[napi(object)]
+pub struct AttestationDocument {
+    pub module_id: String,
+    pub digest: String,
+    /// RFC 3339
+    pub timestamp: String,
+    /// PCR values, indexed by PCR
+    pub pcrs: Vec<Buffer>,
+    pub public_key: Option<Buffer>,
+    pub user_data: Option<Buffer>,
+    pub nonce: Option<Buffer>,
+    /// Certificate chain error, undefined when the chain verified
+    pub verification_error: Option<String>,
+    /// Enclave runs in debug mode, its PCRs attest nothing
+    pub debug_mode: bool,
+}
+
+fn to_napi_err(e: NitroAdError) -> napi::Error {
+    napi::Error::from_reason(format!("{} (error code {})", e, e.code()))
+}
+
+/// Verifies `document` against the DER encoded `root_der` at `timestamp`, seconds
+/// since the unix epoch. Throws when the document is malformed or its signature
+/// doesn't verify; certificate chain failures are reported in `verificationError`.
+#[napi]
+pub fn verify(document: Buffer, root_der: Buffer, timestamp: f64) -> napi::Result<AttestationDocument> {
+    let doc = NitroAdDoc::from_bytes(&document, &root_der, timestamp as u64).map_err(to_napi_err)?;
+    let payload = doc.payload();
+    let buffer = |b: &[u8]| Buffer::from(b.to_vec());
+
+    Ok(AttestationDocument {
+        module_id: payload.module_id.clone(),
+        digest: payload.digest.clone(),
+        timestamp: payload.timestamp.to_rfc3339(),
+        // indexes are consecutive from 0, see NitroAdDocPayload::validate
+        pcrs: payload.pcrs.values().map(|pcr| buffer(pcr)).collect(),
+        public_key: payload.public_key.as_deref().map(buffer),
+        user_data: payload.user_data.as_deref().map(buffer),
+        nonce: payload.nonce.as_deref().map(buffer),
+        verification_error: doc.verification_error().map(|e| e.to_string()),
+        debug_mode: payload.is_debug_mode(),
+    })
+}
@@ -0,0 +1,3 @@
+//! maturin package of the `python` feature, see the library's python module
+
+pub use aws_nitro_enclaves_attestation::python::nitro_attestation;
@@ -0,0 +1,95 @@
+//! Audit archives of past verifications
+//!
+//! An [`AttestationArchive`] bundles the raw document with everything needed to
+//! repeat its verification: the trusted root, the verification time, the hash of
+//! the policy applied and the recorded outcome. [`AttestationArchive::validate`]
+//! re-runs the verification and checks that it reaches the same outcome.
+
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+use crate::report::VerificationReport;
+use crate::{NitroAdDoc, NitroAdError};
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationArchive {
+    pub version: u32,
+    /// Raw COSE_Sign1 document
+    pub document: ByteBuf,
+    /// DER encoded root certificate the chain was verified against
+    pub root_certificate: ByteBuf,
+    /// Seconds since the unix epoch
+    pub verified_at: u64,
+    /// SHA384 of the caller's policy encoding, if a policy was applied
+    pub policy_sha384: Option<ByteBuf>,
+    pub outcome: VerificationReport,
+}
+
+impl AttestationArchive {
+    /// Archive of `doc`, verified against `root_cert`
+    pub fn new(doc: &NitroAdDoc, root_cert: &[u8], policy_sha384: Option<&[u8]>) -> Self {
+        AttestationArchive {
+            version: ARCHIVE_VERSION,
+            document: ByteBuf::from(doc.as_bytes()),
+            root_certificate: ByteBuf::from(root_cert),
+            verified_at: doc.verified_at(),
+            policy_sha384: policy_sha384.map(ByteBuf::from),
+            outcome: doc.report(),
+        }
+    }
+
+    pub fn to_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, NitroAdError> {
+        let archive: Self = serde_cbor::from_slice(bytes)?;
+        if archive.version != ARCHIVE_VERSION {
+            return Err(NitroAdError::UnsupportedArchiveVersion(archive.version));
+        }
+        Ok(archive)
+    }
+
+    /// Verifies the archived document again, at the archived time and against the
+    /// archived root, and checks the outcome matches the recorded one
+    pub fn validate(&self) -> Result<NitroAdDoc<'_>, NitroAdError> {
+        let doc = NitroAdDoc::from_bytes(&self.document, &self.root_certificate, self.verified_at)?;
+        if doc.report() != self.outcome {
+            return Err(NitroAdError::ArchiveMismatch);
+        }
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_roundtrip() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let archive = AttestationArchive::new(&doc, root_cert, Some(&[7u8; 48]));
+        let loaded = AttestationArchive::from_cbor(&archive.to_cbor()?)?;
+        assert_eq!(loaded, archive);
+        assert_eq!(loaded.validate()?.payload().module_id, doc.payload().module_id);
+
+        // outcome recorded for a different time doesn't hold up
+        let mut tampered = loaded.clone();
+        tampered.verified_at = 1714967200;
+        assert!(matches!(tampered.validate(), Err(NitroAdError::ArchiveMismatch)));
+
+        let mut future = loaded;
+        future.version = 2;
+        assert!(matches!(
+            AttestationArchive::from_cbor(&future.to_cbor()?),
+            Err(NitroAdError::UnsupportedArchiveVersion(2))
+        ));
+
+        Ok(())
+    }
+}
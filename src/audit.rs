@@ -0,0 +1,227 @@
+//! Audit trail of verification decisions
+//!
+//! A [`Verifier`](crate::Verifier) given an [`AuditSink`] with
+//! [`Verifier::with_audit_sink`](crate::Verifier::with_audit_sink) passes it an
+//! [`AuditRecord`] for every [`verify`](crate::Verifier::verify) and
+//! [`verify_fast`](crate::Verifier::verify_fast) call, accepted or not. Records identify
+//! the document by its SHA384 and carry the decision, the enclave's `module_id` and
+//! PCRs, but only SHA384 hashes of `user_data`, `nonce` and `public_key`, which may hold
+//! secrets or personal data. Equal values still hash equally, so records can be
+//! correlated with the data the caller holds.
+//!
+//! Closures are sinks, and [`JsonLinesAuditSink`] appends records to a file or other
+//! writer, one JSON object per line:
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::audit::JsonLinesAuditSink;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let aws_root_der = Vec::new();
+//! let log = std::fs::OpenOptions::new().append(true).create(true).open("audit.jsonl")?;
+//! let verifier = Verifier::new(aws_root_der).with_audit_sink(JsonLinesAuditSink::new(log));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::sha384;
+use crate::{NitroAdDocPayload, NitroAdError};
+
+/// Redacted summary of a verification attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Verification time in seconds since the unix epoch
+    pub verified_at: u64,
+    /// hex encoded SHA384 of the whole document
+    pub document_sha384: String,
+    pub accepted: bool,
+    pub error: Option<String>,
+    /// [`NitroAdError::code`] of `error`
+    pub error_code: Option<u32>,
+    /// Payload fields below are absent if the document didn't parse or its signature
+    /// didn't verify, and unchecked if `error` is a certificate chain failure
+    pub module_id: Option<String>,
+    pub document_timestamp_ms: Option<i64>,
+    /// hex encoded PCR values
+    pub pcrs: BTreeMap<u8, String>,
+    /// hex encoded SHA384 of the `user_data`
+    pub user_data_sha384: Option<String>,
+    /// hex encoded SHA384 of the `nonce`
+    pub nonce_sha384: Option<String>,
+    /// hex encoded SHA384 of the `public_key`
+    pub public_key_sha384: Option<String>,
+}
+
+impl AuditRecord {
+    /// Record of verifying `document` at `unix_ts_sec`, which decoded to `payload` if
+    /// its signature verified, and failed with `error` unless accepted
+    pub fn new(
+        document: &[u8],
+        unix_ts_sec: u64,
+        payload: Option<&NitroAdDocPayload>,
+        error: Option<&NitroAdError>,
+    ) -> Self {
+        let digest = |value: &[u8]| hex::encode(sha384(value));
+        let mut record = AuditRecord {
+            verified_at: unix_ts_sec,
+            document_sha384: digest(document),
+            accepted: error.is_none(),
+            error: error.map(|e| e.to_string()),
+            error_code: error.map(|e| e.code()),
+            module_id: None,
+            document_timestamp_ms: None,
+            pcrs: BTreeMap::new(),
+            user_data_sha384: None,
+            nonce_sha384: None,
+            public_key_sha384: None,
+        };
+        if let Some(payload) = payload {
+            record.module_id = Some(payload.module_id.clone());
+            record.document_timestamp_ms = Some(payload.timestamp.timestamp_millis());
+            record.pcrs = payload.pcrs.iter().map(|(i, value)| (*i, hex::encode(value))).collect();
+            record.user_data_sha384 = payload.user_data.as_deref().map(digest);
+            record.nonce_sha384 = payload.nonce.as_deref().map(digest);
+            record.public_key_sha384 = payload.public_key.as_deref().map(digest);
+        }
+        record
+    }
+
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Receiver of the [`AuditRecord`]s of a verifier, called on the verifying thread
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F: Fn(&AuditRecord) + Send + Sync> AuditSink for F {
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// Sink writing each record as a line of JSON to a writer
+pub struct JsonLinesAuditSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesAuditSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesAuditSink {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+/// Write errors are dropped rather than failing verification; give the sink a writer
+/// that reports them itself if the trail must be complete.
+impl<W: Write + Send> AuditSink for JsonLinesAuditSink<W> {
+    fn record(&self, record: &AuditRecord) {
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Ok(line) = record.to_json() {
+            let _ = writeln!(writer, "{}", line).and_then(|()| writer.flush());
+        }
+    }
+}
+
+/// Sink of a [`Verifier`](crate::Verifier)
+pub(crate) struct AuditHook(pub(crate) Arc<dyn AuditSink>);
+
+impl fmt::Debug for AuditHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AuditSink")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Verifier, VerifierPolicy};
+
+    fn recording_verifier(verifier: Verifier) -> (Verifier, Arc<Mutex<Vec<AuditRecord>>>) {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&records);
+        let sink = move |record: &AuditRecord| sink.lock().unwrap().push(record.clone());
+        (verifier.with_audit_sink(sink), records)
+    }
+
+    #[test]
+    fn test_audit_records() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let policy = VerifierPolicy::new().with_nonce(&b"challenge"[..]);
+        let (verifier, records) = recording_verifier(Verifier::new(&root_cert[..]));
+        let (nonce_verifier, nonce_records) =
+            recording_verifier(Verifier::new(&root_cert[..]).with_policy(policy));
+
+        verifier.verify(ad_blob, 1614967200).unwrap();
+        verifier.verify_fast(ad_blob, 1614967200).unwrap();
+        let mut tampered = *ad_blob;
+        tampered[ad_blob.len() - 1] ^= 0x01;
+        assert!(verifier.verify(&tampered, 1614967200).is_err());
+        assert!(nonce_verifier.verify(ad_blob, 1614967200).is_err());
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].accepted);
+        assert_eq!(records[0], records[1]);
+        assert_eq!(records[0].document_sha384, hex::encode(sha384(ad_blob)));
+        let module_id = "i-026ae32a18c80f866-enc01780356441553dc";
+        assert_eq!(records[0].module_id.as_deref(), Some(module_id));
+        assert_eq!(records[0].pcrs.len(), 16);
+
+        assert!(!records[2].accepted);
+        assert_eq!(records[2].error_code, Some(NitroAdError::InvalidSignature.code()));
+        assert_eq!(records[2].module_id, None);
+
+        let nonce_records = nonce_records.lock().unwrap();
+        assert_eq!(nonce_records[0].error_code, Some(NitroAdError::NonceMismatch.code()));
+        assert_eq!(nonce_records[0].module_id, records[0].module_id);
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_redaction() -> Result<(), NitroAdError> {
+        use crate::nsm::{AttestationRequest, Attester};
+        use crate::testing::MockNsm;
+
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let document = mock.attest(AttestationRequest {
+            user_data: Some(b"customer 42"),
+            nonce: Some(b"session token"),
+            ..Default::default()
+        })?;
+        let mut log = Vec::new();
+        JsonLinesAuditSink::new(&mut log).record(&AuditRecord::new(&document, now, None, None));
+        let (verifier, records) = recording_verifier(Verifier::new(mock.root_cert()));
+        verifier.verify(&document, now)?;
+
+        let record = &records.lock().unwrap()[0];
+        assert_eq!(record.user_data_sha384, Some(hex::encode(sha384(b"customer 42"))));
+        assert_eq!(record.nonce_sha384, Some(hex::encode(sha384(b"session token"))));
+        assert_eq!(record.public_key_sha384, None);
+        let json = record.to_json()?;
+        for secret in &[&b"customer 42"[..], b"session token"] {
+            assert!(!json.contains(&hex::encode(secret)));
+            assert!(!json.contains(&base64::encode(secret)));
+            assert!(!json.contains(std::str::from_utf8(secret).unwrap()));
+        }
+
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.ends_with('\n'));
+        let logged: AuditRecord = serde_json::from_str(log.trim_end())?;
+        assert_eq!(logged.document_sha384, record.document_sha384);
+        Ok(())
+    }
+}
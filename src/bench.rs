@@ -0,0 +1,51 @@
+//! Single verification phases, for the benchmarks in `benches/`
+//!
+//! [`NitroAdDoc::from_bytes`](crate::NitroAdDoc::from_bytes) runs all of them in
+//! turn: [`parse`], [`ParsedDocument::verify_chain`] and
+//! [`ParsedDocument::verify_signature`]. Not a stable API.
+
+use chrono::{DateTime, Utc};
+use pki_types::{CertificateDer, SubjectPublicKeyInfoDer};
+
+use crate::crypto::{CryptoBackend, DefaultBackend};
+use crate::{parse_and_validate_payload, verify_chain, verify_cose_signature, CoseSign1};
+use crate::{NitroAdDocPayload, NitroAdError};
+
+/// Document decoded and its payload validated, nothing verified yet
+pub struct ParsedDocument<'a> {
+    cose: CoseSign1<'a>,
+    payload: NitroAdDocPayload<'a>,
+}
+
+/// Decodes the COSE_Sign1 envelope and payload of `bytes` and validates the payload at `now`
+pub fn parse(bytes: &[u8], now: DateTime<Utc>) -> Result<ParsedDocument<'_>, NitroAdError> {
+    let (cose, payload) = parse_and_validate_payload(bytes, now)?;
+    Ok(ParsedDocument { cose, payload })
+}
+
+impl ParsedDocument<'_> {
+    pub fn payload(&self) -> &NitroAdDocPayload<'_> {
+        &self.payload
+    }
+
+    /// Checks the certificate chain up to `root_cert` at `unix_ts_sec`, returns the
+    /// chain error and the signing certificate key
+    pub fn verify_chain(
+        &self,
+        root_cert: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<(Option<webpki::Error>, SubjectPublicKeyInfoDer<'static>), NitroAdError> {
+        let root_cert = CertificateDer::from(root_cert);
+        let anchor = webpki::anchor_from_trusted_cert(&root_cert)
+            .map_err(NitroAdError::InvalidRootCertificate)?;
+        verify_chain(&self.payload, anchor, unix_ts_sec)
+    }
+
+    /// Checks the COSE signature against the signing certificate key `spki` with
+    /// the [`DefaultBackend`]
+    pub fn verify_signature(&self, spki: &[u8]) -> Result<bool, NitroAdError> {
+        let backend = DefaultBackend::default();
+        let key = backend.parse_spki(spki)?;
+        verify_cose_signature(&self.cose.0, &key, &backend)
+    }
+}
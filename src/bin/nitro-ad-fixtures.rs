@@ -0,0 +1,107 @@
+//! Writes a family of test attestation documents into a directory
+//!
+//! ```bash
+//! cargo run --features testing --bin nitro-ad-fixtures -- fixtures/
+//! ```
+//! All documents are signed by one generated chain, whose root lands in `root.der`.
+//! `manifest.json` lists every document with the time to verify it at and the outcome
+//! this library produces then: the error code of `NitroAdDoc::from_bytes`, the
+//! certificate chain error, or the error code of checking `expected_nonce`.
+
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+use aws_nitro_enclaves_attestation::testing::DocumentBuilder;
+use aws_nitro_enclaves_attestation::{NitroAdDoc, VerifierPolicy};
+
+const NONCE: &[u8] = b"fixture-challenge";
+
+/// Manifest entry of `document`, written to `dir` as `name`.bin
+fn fixture(
+    dir: &Path,
+    name: &str,
+    document: &[u8],
+    root_cert: &[u8],
+    verify_at: u64,
+    expected_nonce: Option<&[u8]>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let file = format!("{}.bin", name);
+    fs::write(dir.join(&file), document)?;
+
+    let outcome = NitroAdDoc::from_bytes(document, root_cert, verify_at).map(|doc| {
+        let chain_error = doc.verification_error().map(|e| e.to_string());
+        let policy = match expected_nonce {
+            Some(nonce) => VerifierPolicy::new().with_nonce(nonce),
+            None => VerifierPolicy::new(),
+        };
+        let policy_code = policy.check_payload(doc.payload()).err().map(|e| e.code());
+        (chain_error, policy_code, doc.payload().is_debug_mode())
+    });
+    let mut entry = json!({
+        "file": file,
+        "verify_at": verify_at,
+        "expected_nonce": expected_nonce.map(hex::encode),
+    });
+    match outcome {
+        Ok((chain_error, policy_code, debug_mode)) => {
+            entry["error_code"] = Value::Null;
+            entry["chain_error"] = json!(chain_error);
+            entry["policy_error_code"] = json!(policy_code);
+            entry["debug_mode"] = json!(debug_mode);
+        }
+        Err(e) => {
+            entry["error_code"] = json!(e.code());
+            entry["error"] = json!(e.to_string());
+        }
+    }
+    Ok(entry)
+}
+
+fn generate(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+
+    // documents are an hour old when verified, like ones fetched from a running enclave
+    let now = Utc::now();
+    let builder = DocumentBuilder::new()?.with_timestamp(now - Duration::hours(1));
+    let root_cert = builder.root_cert();
+    fs::write(dir.join("root.der"), &root_cert)?;
+    let now = now.timestamp() as u64;
+    let expired = builder.certificate_expiry().timestamp() as u64 + 1;
+
+    let valid = builder.clone().with_nonce(NONCE).build()?;
+    let truncated = &valid[..valid.len() / 2];
+    let fixtures = [
+        ("valid", valid.clone(), now, Some(NONCE)),
+        ("expired_cert", valid.clone(), expired, None),
+        ("wrong_pcr_length", builder.clone().with_pcr(0, vec![0; 20]).build()?, now, None),
+        ("missing_nonce", builder.clone().build()?, now, Some(NONCE)),
+        ("debug_mode", builder.clone().with_debug_mode().build()?, now, None),
+        ("truncated_cbor", truncated.to_vec(), now, None),
+    ];
+
+    let mut manifest = Vec::new();
+    for (name, document, verify_at, nonce) in &fixtures {
+        manifest.push(fixture(dir, name, document, &root_cert, *verify_at, *nonce)?);
+    }
+    fs::write(dir.join("manifest.json"), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+fn main() {
+    let dir = match std::env::args_os().nth(1) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("usage: nitro-ad-fixtures <output directory>");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = generate(Path::new(&dir)) {
+        eprintln!("nitro-ad-fixtures: {}", e);
+        process::exit(1);
+    }
+}
+
@@ -0,0 +1,626 @@
+//! Command line tool for attestation documents
+//!
+//! ```bash
+//! cargo install aws-nitro-enclaves-attestation --features cli
+//! nitro-attest verify document.bin --root aws_root.der --expect-pcrs pcrs.json
+//! ```
+//! `verify` checks the document's signature and certificate chain against the root,
+//! DER or PEM, at `--at`, a Unix timestamp or RFC 3339 time defaulting to now, and its
+//! PCRs against the expected ones given, then exits with 0, or with 1 and the error on
+//! stderr. Expected PCRs are a JSON object of hex values keyed `PCR0`, `PCR1`, ..., the
+//! format of the `Measurements` in `nitro-cli build-enclave` output, which is accepted
+//! as is. Usage errors exit with 2.
+//!
+//! `inspect` prints the decoded document as JSON or, with `--format table`, as a table
+//! for people, whether or not it verifies: with `--root` the outcome of `verify`
+//! without PCR checks is part of the output, `verified` and `verification_error` in
+//! JSON, otherwise the signature and certificates go unchecked. It exits with 0
+//! whenever the document decodes.
+//!
+//! `pcrs` prints the document's PCRs, one `PCR<index> <hex>` line each. With
+//! `--compare`, a file of expected PCRs like the one of `--expect-pcrs`, it prints
+//! `ok`, `mismatch` with both values, or `missing` for each expected PCR instead and
+//! exits with 1 unless all match, to gate CI pipelines on the measurements of a build.
+//! Neither reads a root: `pcrs` compares the values without verifying the document.
+//!
+//! `fetch-root` downloads the AWS Nitro Enclaves root certificate zip from [`ROOT_URL`],
+//! or `--url`, checks its SHA-256 hash against the one AWS publishes, [`ROOT_ZIP_SHA256`],
+//! and writes the certificate as `aws_root.der` and `aws_root.pem` into `--out`, the
+//! current directory unless given.
+//!
+//! `gen-test-doc`, built with the `testing` feature too, writes a synthetic document
+//! signed by a fresh [`TestChain`](aws_nitro_enclaves_attestation::testing::TestChain)
+//! and that chain's root, for integration tests of other verifiers. `--pcrs` sets PCRs
+//! from a file like the one of `--expect-pcrs`, the other options set the fields of
+//! the same name, hex encoded or, for `--public-key`, read from a file.
+//! ```bash
+//! nitro-attest gen-test-doc doc.bin --root root.der --pcrs pcrs.json --nonce 0102
+//! nitro-attest verify doc.bin --root root.der --expect-pcrs pcrs.json
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::process;
+
+use chrono::{DateTime, Utc};
+use serde_bytes::ByteBuf;
+use serde_cbor::Value as CborValue;
+use sha2::{Digest, Sha256};
+
+use aws_nitro_enclaves_attestation::output::{
+    pcr_meaning, CertificateOutput, DocumentOutput, JsonOptions,
+};
+use aws_nitro_enclaves_attestation::{NitroAdDocPayload, NitroAdError, Verifier, VerifierPolicy};
+
+const USAGE: &str = "usage:
+    nitro-attest verify <doc.bin> --root <der|pem> [--at <timestamp>] [--expect-pcrs <file>]
+    nitro-attest inspect <doc.bin> [--format json|table] [--root <der|pem>] [--at <timestamp>]
+    nitro-attest pcrs <doc.bin> [--compare <file>]
+    nitro-attest fetch-root [--out <dir>] [--url <url>]
+    nitro-attest gen-test-doc <doc.bin> --root <der> [--pcrs <file>] [--module-id <id>]
+        [--timestamp <timestamp>] [--nonce <hex>] [--user-data <hex>] [--public-key <file>]
+        [--debug]";
+
+/// Download location of the AWS Nitro Enclaves root certificate, see
+/// https://docs.aws.amazon.com/enclaves/latest/user/verify-root.html
+const ROOT_URL: &str = "https://aws-nitro-enclaves.amazonaws.com/AWS_NitroEnclaves_Root-G1.zip";
+
+/// SHA-256 hash of the zip at [`ROOT_URL`], as published by AWS
+const ROOT_ZIP_SHA256: &str = "8cf60e2b2efca96c6a9e71e851d00c1b6991cc09eadbe64a6a1d1b1eb9faff7c";
+
+/// Failure of a subcommand, with the exit code to report it with
+struct Failure {
+    message: String,
+    code: i32,
+}
+
+impl Failure {
+    fn usage(message: impl Into<String>) -> Self {
+        Failure {
+            message: format!("{}\n{}", message.into(), USAGE),
+            code: 2,
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        Failure {
+            message: message.into(),
+            code: 1,
+        }
+    }
+}
+
+impl From<NitroAdError> for Failure {
+    fn from(e: NitroAdError) -> Self {
+        Failure::failed(format!("{} (error {})", e, e.code()))
+    }
+}
+
+/// Positional arguments, `--flag value` options and `--switch`es of a subcommand
+struct Options {
+    positional: Vec<String>,
+    values: BTreeMap<String, String>,
+    switches: BTreeSet<String>,
+}
+
+impl Options {
+    /// Splits `args` into positional arguments, the values of `flags` and `switches`
+    fn parse(args: &[String], flags: &[&str], switches: &[&str]) -> Result<Self, Failure> {
+        let mut options = Options {
+            positional: Vec::new(),
+            values: BTreeMap::new(),
+            switches: BTreeSet::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.positional.push(arg.clone());
+                continue;
+            }
+            if switches.contains(&arg.as_str()) {
+                options.switches.insert(arg.clone());
+                continue;
+            }
+            if !flags.contains(&arg.as_str()) {
+                return Err(Failure::usage(format!("unknown option {}", arg)));
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| Failure::usage(format!("{} needs a value", arg)))?;
+            options.values.insert(arg.clone(), value.clone());
+        }
+        Ok(options)
+    }
+
+    fn value(&self, flag: &str) -> Option<&str> {
+        self.values.get(flag).map(String::as_str)
+    }
+
+    // only gen-test-doc has switches
+    #[cfg_attr(not(feature = "testing"), allow(dead_code))]
+    fn switch(&self, switch: &str) -> bool {
+        self.switches.contains(switch)
+    }
+
+    /// The only positional argument, named `name` in errors
+    fn single(&self, name: &str) -> Result<&str, Failure> {
+        match self.positional.as_slice() {
+            [arg] => Ok(arg),
+            [] => Err(Failure::usage(format!("missing {}", name))),
+            _ => Err(Failure::usage("too many arguments")),
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, Failure> {
+    fs::read(path).map_err(|e| Failure::failed(format!("{}: {}", path, e)))
+}
+
+/// DER root certificate of the file at `path`, converting PEM
+fn read_root(path: &str) -> Result<Vec<u8>, Failure> {
+    let root = read(path)?;
+    if !root.starts_with(b"-----BEGIN") {
+        return Ok(root);
+    }
+    x509_parser::pem::parse_x509_pem(&root)
+        .map(|(_, pem)| pem.contents)
+        .map_err(|e| Failure::failed(format!("{}: invalid PEM: {}", path, e)))
+}
+
+/// Unix timestamp of `--at`, in seconds or RFC 3339
+fn parse_timestamp(at: &str) -> Result<u64, Failure> {
+    at.parse().or_else(|_| {
+        DateTime::parse_from_rfc3339(at)
+            .map(|time| time.timestamp() as u64)
+            .map_err(|_| Failure::usage(format!("invalid timestamp {}", at)))
+    })
+}
+
+/// Time of `--at`, or now
+fn verification_time(options: &Options) -> Result<u64, Failure> {
+    match options.value("--at") {
+        Some(at) => parse_timestamp(at),
+        None => Ok(Utc::now().timestamp() as u64),
+    }
+}
+
+fn read_pcrs(path: &str) -> Result<BTreeMap<u8, Vec<u8>>, Failure> {
+    let json = fs::read_to_string(path).map_err(|e| Failure::failed(format!("{}: {}", path, e)))?;
+    let policy = VerifierPolicy::new().with_measurements_json(&json);
+    Ok(policy.map_err(|e| Failure::failed(format!("{}: {}", path, e)))?.pcrs)
+}
+
+fn verify(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args, &["--root", "--at", "--expect-pcrs"], &[])?;
+    let document = read(options.single("document")?)?;
+    let root = read_root(options.value("--root").ok_or_else(|| Failure::usage("missing --root"))?)?;
+    let now = verification_time(&options)?;
+    let mut policy = VerifierPolicy::new();
+    if let Some(path) = options.value("--expect-pcrs") {
+        for (index, value) in read_pcrs(path)? {
+            policy = policy.with_pcr(index, value);
+        }
+    }
+
+    let doc = Verifier::new(root).with_policy(policy).verify(&document, now)?;
+    let payload = doc.payload();
+    println!("OK {} timestamp {}", payload.module_id, payload.timestamp);
+    Ok(())
+}
+
+/// Payload of the COSE_Sign1 `document`, decoded without checking its signature
+fn decode_payload(document: &[u8]) -> Result<Vec<u8>, Failure> {
+    let (_, _, payload, _): (ByteBuf, CborValue, ByteBuf, ByteBuf) =
+        serde_cbor::from_slice(document)
+            .map_err(|e| Failure::failed(format!("not a COSE_Sign1 document: {}", e)))?;
+    Ok(payload.into_vec())
+}
+
+fn parse_payload(payload: &[u8]) -> Result<NitroAdDocPayload<'_>, Failure> {
+    serde_cbor::from_slice(payload).map_err(|e| Failure::failed(format!("invalid payload: {}", e)))
+}
+
+/// Rows of the `--format table` output of `inspect`
+fn table(output: &DocumentOutput, verification: &str) -> Vec<(String, String)> {
+    let mut rows = vec![
+        (String::from("module_id"), output.module_id.clone()),
+        (String::from("digest"), output.digest.clone()),
+        (String::from("timestamp"), output.timestamp.clone()),
+        (String::from("verification"), String::from(verification)),
+    ];
+    for (index, value) in &output.pcrs {
+        let value = match pcr_meaning(*index) {
+            Some(meaning) => format!("{} ({})", value, meaning),
+            None => value.clone(),
+        };
+        rows.push((format!("PCR{}", index), value));
+    }
+    for (i, cert) in output.certs.iter().enumerate() {
+        let name = if i + 1 == output.certs.len() {
+            String::from("certificate")
+        } else {
+            format!("cabundle[{}]", i)
+        };
+        let validity = format!("{} to {}", cert.validity.not_before, cert.validity.not_after);
+        rows.push((name, cert.subject.clone()));
+        rows.push((String::from("  issuer"), cert.issuer.clone()));
+        rows.push((String::from("  valid"), validity));
+    }
+    let optional = [
+        ("public_key", &output.public_key),
+        ("user_data", &output.user_data),
+        ("nonce", &output.nonce),
+    ];
+    for (name, value) in &optional {
+        rows.push((String::from(*name), String::from(value.as_deref().unwrap_or("-"))));
+    }
+    rows
+}
+
+fn inspect(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args, &["--format", "--root", "--at"], &[])?;
+    let format = options.value("--format").unwrap_or("json");
+    if format != "json" && format != "table" {
+        return Err(Failure::usage(format!("unknown format {}", format)));
+    }
+    let document = read(options.single("document")?)?;
+    let payload = decode_payload(&document)?;
+    let payload = parse_payload(&payload)?;
+    let mut output = DocumentOutput::from_payload(&payload, &JsonOptions::default())?;
+
+    let verified = match options.value("--root") {
+        Some(root) => {
+            let verifier = Verifier::new(read_root(root)?);
+            let outcome = verifier.verify(&document, verification_time(&options)?);
+            output.verification_error = outcome.err().map(|e| Failure::from(e).message);
+            Some(output.verification_error.is_none())
+        }
+        None => None,
+    };
+
+    if format == "json" {
+        let mut json = serde_json::to_value(&output).map_err(NitroAdError::from)?;
+        json["verified"] = verified.into();
+        // serializing a Value doesn't fail
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        return Ok(());
+    }
+    let verification = match (&output.verification_error, verified) {
+        (Some(e), _) => format!("failed: {}", e),
+        (None, Some(_)) => String::from("ok"),
+        (None, None) => String::from("not checked, no --root"),
+    };
+    for (name, value) in table(&output, &verification) {
+        println!("{:<14}{}", name, value);
+    }
+    Ok(())
+}
+
+/// Lines of `pcrs --compare` for the PCRs of `expected`, and whether all match
+fn compare_pcrs(
+    actual: &BTreeMap<u8, impl AsRef<[u8]>>,
+    expected: &BTreeMap<u8, Vec<u8>>,
+) -> (Vec<String>, bool) {
+    let mut lines = Vec::new();
+    let mut matched = true;
+    for (index, expected) in expected {
+        match actual.get(index).map(AsRef::as_ref) {
+            Some(actual) if actual == expected.as_slice() => lines.push(format!("PCR{} ok", index)),
+            Some(actual) => {
+                matched = false;
+                lines.push(format!("PCR{} mismatch", index));
+                lines.push(format!("  expected {}", hex::encode(expected)));
+                lines.push(format!("  actual   {}", hex::encode(actual)));
+            }
+            None => {
+                matched = false;
+                lines.push(format!("PCR{} missing", index));
+            }
+        }
+    }
+    (lines, matched)
+}
+
+fn pcrs(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args, &["--compare"], &[])?;
+    let document = read(options.single("document")?)?;
+    let payload = decode_payload(&document)?;
+    let payload = parse_payload(&payload)?;
+
+    let expected = match options.value("--compare") {
+        Some(path) => read_pcrs(path)?,
+        None => {
+            for (index, value) in &payload.pcrs {
+                println!("PCR{} {}", index, hex::encode(value));
+            }
+            return Ok(());
+        }
+    };
+    let (lines, matched) = compare_pcrs(&payload.pcrs, &expected);
+    for line in lines {
+        println!("{}", line);
+    }
+    if !matched {
+        return Err(Failure::failed("PCRs differ from the expected measurements"));
+    }
+    Ok(())
+}
+
+/// PEM file and DER root certificate in a root certificate `zip` whose SHA-256 hash,
+/// hex encoded, is `sha256`
+fn extract_root(zip: &[u8], sha256: &str) -> Result<(Vec<u8>, Vec<u8>), Failure> {
+    let hash = hex::encode(Sha256::digest(zip));
+    if hash != sha256 {
+        return Err(Failure::failed(format!("zip hash {} isn't the published {}", hash, sha256)));
+    }
+    let invalid = |e: &dyn std::fmt::Display| Failure::failed(format!("invalid zip: {}", e));
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip)).map_err(|e| invalid(&e))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| invalid(&e))?;
+        if !file.name().ends_with(".pem") {
+            continue;
+        }
+        let mut pem = Vec::new();
+        file.read_to_end(&mut pem).map_err(|e| invalid(&e))?;
+        let (_, der) = x509_parser::pem::parse_x509_pem(&pem).map_err(|e| invalid(&e))?;
+        // the certificate must parse before it is trusted as a root
+        CertificateOutput::from_der(&der.contents)?;
+        return Ok((pem, der.contents));
+    }
+    Err(invalid(&"no PEM file"))
+}
+
+fn fetch_root(args: &[String]) -> Result<(), Failure> {
+    let options = Options::parse(args, &["--out", "--url"], &[])?;
+    if !options.positional.is_empty() {
+        return Err(Failure::usage("too many arguments"));
+    }
+    let url = options.value("--url").unwrap_or(ROOT_URL);
+    let out = Path::new(options.value("--out").unwrap_or("."));
+
+    let mut zip = Vec::new();
+    ureq::get(url)
+        .call()
+        .map_err(|e| Failure::failed(format!("{}: {}", url, e)))?
+        .into_reader()
+        .read_to_end(&mut zip)
+        .map_err(|e| Failure::failed(format!("{}: {}", url, e)))?;
+    let (pem, der) = extract_root(&zip, ROOT_ZIP_SHA256)?;
+
+    let write = |name: &str, contents: &[u8]| {
+        let path = out.join(name);
+        fs::write(&path, contents)
+            .map_err(|e| Failure::failed(format!("{}: {}", path.display(), e)))
+    };
+    write("aws_root.der", &der)?;
+    write("aws_root.pem", &pem)?;
+    let [der, pem] = ["aws_root.der", "aws_root.pem"].map(|name| out.join(name));
+    println!("wrote {} and {}", der.display(), pem.display());
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+fn gen_test_doc(args: &[String]) -> Result<(), Failure> {
+    use aws_nitro_enclaves_attestation::testing::DocumentBuilder;
+    use chrono::TimeZone;
+
+    let flags = [
+        "--root",
+        "--pcrs",
+        "--module-id",
+        "--timestamp",
+        "--nonce",
+        "--user-data",
+        "--public-key",
+    ];
+    let options = Options::parse(args, &flags, &["--debug"])?;
+    let out = options.single("output file")?;
+    let root = options.value("--root").ok_or_else(|| Failure::usage("missing --root"))?;
+    let hex_value = |flag: &str| {
+        let invalid = |_| Failure::usage(format!("{} needs hex", flag));
+        let decode = |value| hex::decode(value).map_err(invalid);
+        options.value(flag).map(decode).transpose()
+    };
+
+    let mut builder = DocumentBuilder::new()?;
+    if let Some(path) = options.value("--pcrs") {
+        for (index, value) in read_pcrs(path)? {
+            builder = builder.with_pcr(index, value);
+        }
+    }
+    if options.switch("--debug") {
+        builder = builder.with_debug_mode();
+    }
+    if let Some(module_id) = options.value("--module-id") {
+        builder = builder.with_module_id(module_id);
+    }
+    if let Some(timestamp) = options.value("--timestamp") {
+        let timestamp = Utc
+            .timestamp_opt(parse_timestamp(timestamp)? as i64, 0)
+            .single()
+            .ok_or_else(|| Failure::usage(format!("invalid timestamp {}", timestamp)))?;
+        builder = builder.with_timestamp(timestamp);
+    }
+    if let Some(nonce) = hex_value("--nonce")? {
+        builder = builder.with_nonce(nonce);
+    }
+    if let Some(user_data) = hex_value("--user-data")? {
+        builder = builder.with_user_data(user_data);
+    }
+    if let Some(path) = options.value("--public-key") {
+        builder = builder.with_public_key(read(path)?);
+    }
+
+    let write = |path: &str, contents: &[u8]| {
+        fs::write(path, contents).map_err(|e| Failure::failed(format!("{}: {}", path, e)))
+    };
+    write(out, &builder.build()?)?;
+    write(root, &builder.root_cert())?;
+    println!("wrote {} signed by the root in {}", out, root);
+    Ok(())
+}
+
+#[cfg(not(feature = "testing"))]
+fn gen_test_doc(_: &[String]) -> Result<(), Failure> {
+    Err(Failure::failed("gen-test-doc needs nitro-attest built with the testing feature"))
+}
+
+fn run(args: &[String]) -> Result<(), Failure> {
+    match args.split_first() {
+        Some((command, args)) if command == "verify" => verify(args),
+        Some((command, args)) if command == "inspect" => inspect(args),
+        Some((command, args)) if command == "pcrs" => pcrs(args),
+        Some((command, args)) if command == "fetch-root" => fetch_root(args),
+        Some((command, args)) if command == "gen-test-doc" => gen_test_doc(args),
+        Some((command, _)) => Err(Failure::usage(format!("unknown command {}", command))),
+        None => Err(Failure::usage("missing command")),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(failure) = run(&args) {
+        eprintln!("nitro-attest: {}", failure.message);
+        process::exit(failure.code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_timestamp("1614967200").ok(), Some(1614967200));
+        assert_eq!(parse_timestamp("2021-03-05T18:00:00Z").ok(), Some(1614967200));
+        assert!(parse_timestamp("yesterday").is_err());
+
+        let options = Options::parse(&args(&["doc.bin", "--root", "root.der"]), &["--root"], &[]);
+        let options = options.ok().unwrap();
+        assert_eq!(options.single("document").ok(), Some("doc.bin"));
+        assert_eq!(options.value("--root"), Some("root.der"));
+        assert!(!options.switch("--debug"));
+        assert!(Options::parse(&args(&["--root"]), &["--root"], &[]).is_err());
+        assert!(Options::parse(&args(&["--other", "x"]), &["--root"], &[]).is_err());
+        let options = Options::parse(&args(&["--debug", "doc.bin"]), &[], &["--debug"]).ok();
+        assert!(options.unwrap().switch("--debug"));
+        assert_eq!(run(&args(&["frobnicate"])).err().map(|failure| failure.code), Some(2));
+    }
+
+    #[test]
+    fn test_inspect() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let document = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/nitro_ad_debug.bin");
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/aws_root.der");
+        let payload = decode_payload(&read(document).ok().unwrap()).ok().unwrap();
+        let payload = parse_payload(&payload).ok().unwrap();
+        let output = DocumentOutput::from_payload(&payload, &JsonOptions::default()).unwrap();
+        let rows = table(&output, "ok");
+        assert_eq!(rows[0], (String::from("module_id"), payload.module_id.clone()));
+        let pcr0 = format!("{} (enclave image file)", hex::encode(&payload.pcrs[&0]));
+        assert!(rows.contains(&(String::from("PCR0"), pcr0)));
+        assert_eq!(rows.iter().filter(|(name, _)| name == "  issuer").count(), 5);
+        assert_eq!(rows.last().map(|(name, _)| name.as_str()), Some("nonce"));
+
+        // documents failing verification are still shown
+        assert!(inspect(&args(&[document, "--root", root, "--format", "table"])).is_ok());
+        assert!(inspect(&args(&[document])).is_ok());
+        assert_eq!(inspect(&args(&[root])).err().map(|failure| failure.code), Some(1));
+        let yaml = inspect(&args(&[document, "--format", "yaml"]));
+        assert_eq!(yaml.err().map(|failure| failure.code), Some(2));
+    }
+
+    #[test]
+    fn test_compare_pcrs() {
+        let pcrs = |indices: &[u8], value: Option<u8>| -> BTreeMap<u8, Vec<u8>> {
+            indices.iter().map(|i| (*i, vec![value.unwrap_or(*i); 48])).collect()
+        };
+        let actual = pcrs(&[0, 1, 2], None);
+        let (lines, matched) = compare_pcrs(&actual, &pcrs(&[0, 1], None));
+        assert!(matched);
+        assert_eq!(lines, ["PCR0 ok", "PCR1 ok"]);
+
+        let expected = pcrs(&[1, 8], Some(0));
+        let (lines, matched) = compare_pcrs(&actual, &expected);
+        assert!(!matched);
+        assert_eq!(lines[0], "PCR1 mismatch");
+        assert_eq!(lines[1], format!("  expected {}", "00".repeat(48)));
+        assert_eq!(lines[2], format!("  actual   {}", "01".repeat(48)));
+        assert_eq!(lines[3], "PCR8 missing");
+    }
+
+    #[test]
+    fn test_extract_root() {
+        use std::io::Write;
+
+        let pem = include_bytes!("../../tests/data/aws_root.pem");
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("root.pem", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(pem).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let sha256 = hex::encode(Sha256::digest(&zip));
+
+        let (extracted_pem, der) = extract_root(&zip, &sha256).ok().unwrap();
+        assert_eq!(extracted_pem, pem);
+        assert_eq!(der, include_bytes!("../../tests/data/aws_root.der"));
+        assert!(extract_root(&zip, ROOT_ZIP_SHA256).is_err());
+        assert!(extract_root(b"not a zip", &hex::encode(Sha256::digest(b"not a zip"))).is_err());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_gen_test_doc() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let dir = std::env::temp_dir().join(format!("nitro-attest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let pcrs = serde_json::json!({ "PCR0": "ab".repeat(48), "PCR8": "cd".repeat(48) });
+        fs::write(path("pcrs.json"), pcrs.to_string()).unwrap();
+
+        let (doc, root, expected) = (path("doc.bin"), path("root.der"), path("pcrs.json"));
+        let generated = gen_test_doc(&args(&[
+            &doc,
+            "--root",
+            &root,
+            "--pcrs",
+            &expected,
+            "--module-id",
+            "i-test-enc1",
+            "--nonce",
+            "0102",
+        ]));
+        assert!(generated.is_ok());
+        assert!(verify(&args(&[&doc, "--root", &root, "--expect-pcrs", &expected])).is_ok());
+
+        let document = read(&doc).ok().unwrap();
+        let payload = decode_payload(&document).ok().unwrap();
+        let payload = parse_payload(&payload).ok().unwrap();
+        assert_eq!(payload.module_id, "i-test-enc1");
+        assert_eq!(payload.nonce.as_deref(), Some(&[1, 2][..]));
+        assert_eq!(&payload.pcrs[&1][..], &[2; 48][..]);
+
+        let debug = gen_test_doc(&args(&[&doc, "--root", &root, "--debug"]));
+        assert!(debug.is_ok());
+        assert!(verify(&args(&[&doc, "--root", &root, "--expect-pcrs", &expected])).is_err());
+        let bad_nonce = gen_test_doc(&args(&[&doc, "--root", &root, "--nonce", "xyz"]));
+        assert_eq!(bad_nonce.err().map(|failure| failure.code), Some(2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let document = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/nitro_ad_debug.bin");
+        let der = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/aws_root.der");
+        let pem = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/aws_root.pem");
+        for root in &[der, pem] {
+            assert!(verify(&args(&[document, "--root", root, "--at", "1614967200"])).is_ok());
+        }
+        let failure = verify(&args(&[document, "--root", der])).err().unwrap();
+        assert_eq!(failure.code, 1);
+        assert_eq!(verify(&args(&[document])).err().map(|failure| failure.code), Some(2));
+    }
+}
@@ -0,0 +1,196 @@
+//! Bundles of several documents
+//!
+//! An [`AttestationBundle`] carries the documents of a group of enclaves, e.g. one per
+//! enclave of a fleet, in a single CBOR value, each under a name such as its instance
+//! ID. [`AttestationBundle::verify`] checks all of them with one [`Verifier`], so against
+//! one policy, and collects the outcome of each in a [`BundleReport`]. One rejected
+//! document doesn't stop the others from being verified.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::bundle::AttestationBundle;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, first, second, now) = (Vec::new(), Vec::new(), Vec::new(), 0);
+//! let bundle = AttestationBundle::new()
+//!     .with_document("i-0123456789abcdef0", first)
+//!     .with_document("i-0fedcba9876543210", second);
+//! let report = AttestationBundle::from_cbor(&bundle.to_cbor()?)?
+//!     .verify(&Verifier::new(aws_root_der), now);
+//! for (name, e) in report.rejected() {
+//!     eprintln!("{}: {}", name, e);
+//! }
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+use crate::crypto::CryptoBackend;
+use crate::report::VerificationReport;
+use crate::{NitroAdError, Verifier};
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledDocument {
+    /// Caller's name of the enclave, not checked against the document
+    pub name: String,
+    /// Raw COSE_Sign1 document
+    pub document: ByteBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationBundle {
+    pub version: u32,
+    pub documents: Vec<BundledDocument>,
+}
+
+impl Default for AttestationBundle {
+    fn default() -> Self {
+        AttestationBundle {
+            version: BUNDLE_VERSION,
+            documents: Vec::new(),
+        }
+    }
+}
+
+impl AttestationBundle {
+    /// Bundle without documents
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `document` under `name`
+    pub fn with_document(mut self, name: impl Into<String>, document: impl Into<Vec<u8>>) -> Self {
+        self.documents.push(BundledDocument {
+            name: name.into(),
+            document: ByteBuf::from(document.into()),
+        });
+        self
+    }
+
+    pub fn to_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, NitroAdError> {
+        let bundle: Self = serde_cbor::from_slice(bytes)?;
+        if bundle.version != BUNDLE_VERSION {
+            return Err(NitroAdError::UnsupportedBundleVersion(bundle.version));
+        }
+        Ok(bundle)
+    }
+
+    /// [`Verifier::verify`] of every document at `unix_ts_sec`, in parallel with the
+    /// `rayon` feature, see [`Verifier::verify_batch`]. Results are in bundle order.
+    pub fn verify<B: CryptoBackend + Sync>(
+        &self,
+        verifier: &Verifier<B>,
+        unix_ts_sec: u64,
+    ) -> BundleReport {
+        let documents: Vec<&[u8]> = self.documents.iter().map(|d| d.document.as_slice()).collect();
+        #[cfg(feature = "rayon")]
+        let results = verifier.verify_batch(&documents, unix_ts_sec);
+        #[cfg(not(feature = "rayon"))]
+        let results = documents
+            .iter()
+            .map(|bytes| verifier.verify(bytes, unix_ts_sec).map(|doc| doc.report()))
+            .collect::<Vec<_>>();
+        let results = self
+            .documents
+            .iter()
+            .zip(results)
+            .map(|(bundled, result)| BundleResult {
+                name: bundled.name.clone(),
+                result,
+            })
+            .collect();
+        BundleReport { results }
+    }
+}
+
+/// Outcome of the verification of one bundled document
+#[derive(Debug)]
+pub struct BundleResult {
+    pub name: String,
+    pub result: Result<VerificationReport, NitroAdError>,
+}
+
+/// Outcomes of [`AttestationBundle::verify`], in bundle order
+#[derive(Debug)]
+pub struct BundleReport {
+    pub results: Vec<BundleResult>,
+}
+
+impl BundleReport {
+    /// Whether the bundle had documents and all of them were accepted
+    pub fn all_accepted(&self) -> bool {
+        !self.results.is_empty() && self.results.iter().all(|r| r.result.is_ok())
+    }
+
+    /// Names and reports of the accepted documents
+    pub fn accepted(&self) -> impl Iterator<Item = (&str, &VerificationReport)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().ok().map(|report| (r.name.as_str(), report)))
+    }
+
+    /// Names of the rejected documents and why they were rejected
+    pub fn rejected(&self) -> impl Iterator<Item = (&str, &NitroAdError)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().err().map(|e| (r.name.as_str(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::VerifierPolicy;
+
+    #[test]
+    fn test_bundle_roundtrip() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let bundle = AttestationBundle::new()
+            .with_document("first", &ad_blob[..])
+            .with_document("second", &ad_blob[..]);
+        let loaded = AttestationBundle::from_cbor(&bundle.to_cbor()?)?;
+        assert_eq!(loaded, bundle);
+        assert_eq!(loaded.documents[1].name, "second");
+
+        let mut newer = bundle;
+        newer.version = BUNDLE_VERSION + 1;
+        assert!(matches!(
+            AttestationBundle::from_cbor(&newer.to_cbor()?),
+            Err(NitroAdError::UnsupportedBundleVersion(2))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_bundle() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let mut tampered = *ad_blob;
+        tampered[ad_blob.len() - 1] ^= 0x01;
+        let bundle = AttestationBundle::new()
+            .with_document("a", &ad_blob[..])
+            .with_document("b", &tampered[..])
+            .with_document("c", &ad_blob[..]);
+
+        let report = bundle.verify(&Verifier::new(&root_cert[..]), 1614967200);
+        assert!(!report.all_accepted());
+        assert_eq!(report.accepted().map(|(name, _)| name).collect::<Vec<_>>(), ["a", "c"]);
+        let rejected: Vec<_> = report.rejected().collect();
+        assert_eq!(rejected.len(), 1);
+        assert!(matches!(rejected[0], ("b", NitroAdError::InvalidSignature)));
+
+        let single = AttestationBundle::new().with_document("a", &ad_blob[..]);
+        assert!(single.verify(&Verifier::new(&root_cert[..]), 1614967200).all_accepted());
+        let nonce_verifier = Verifier::new(&root_cert[..])
+            .with_policy(VerifierPolicy::new().with_nonce(&b"challenge"[..]));
+        let report = single.verify(&nonce_verifier, 1614967200);
+        assert!(matches!(report.rejected().next(), Some(("a", NitroAdError::NonceMismatch))));
+        assert!(!AttestationBundle::new().verify(&nonce_verifier, 1614967200).all_accepted());
+    }
+}
@@ -0,0 +1,133 @@
+//! Byte strings borrowed from the document they were decoded from
+//!
+//! Decoding a document with `serde_cbor::from_slice` borrows every definite length
+//! byte string, which is how the NSM encodes them, from the input. Indefinite length
+//! strings are concatenated into an owned buffer.
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Deref;
+
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Visitor};
+use serde::{Serialize, Serializer};
+
+/// Byte string of a document, borrowed from it or owned
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bytes<'a>(Cow<'a, [u8]>);
+
+impl<'a> Bytes<'a> {
+    /// Same bytes, owned
+    pub fn into_owned(self) -> Bytes<'static> {
+        Bytes(Cow::Owned(self.0.into_owned()))
+    }
+
+    /// Bytes borrowed from the document, `None` for owned ones
+    pub fn as_borrowed(&self) -> Option<&'a [u8]> {
+        match self.0 {
+            Cow::Borrowed(bytes) => Some(bytes),
+            Cow::Owned(_) => None,
+        }
+    }
+
+    /// Buffer of owned bytes, `None` for borrowed ones
+    pub(crate) fn owned_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match &mut self.0 {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(bytes) => Some(bytes),
+        }
+    }
+}
+
+impl Deref for Bytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Bytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<'a> From<&'a [u8]> for Bytes<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Bytes(Cow::Borrowed(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Bytes<'static> {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(Cow::Owned(bytes))
+    }
+}
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Bytes<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Bytes<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Bytes<'de>, E> {
+                Ok(Bytes(Cow::Borrowed(v)))
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Bytes<'de>, E> {
+                Ok(Bytes(Cow::Owned(v.to_vec())))
+            }
+
+            fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Bytes<'de>, E> {
+                Ok(Bytes(Cow::Owned(v)))
+            }
+
+            // formats without byte strings, e.g. JSON arrays of numbers
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bytes<'de>, A::Error> {
+                let mut bytes = Vec::new();
+                while let Some(b) = seq.next_element()? {
+                    bytes.push(b);
+                }
+                Ok(Bytes(Cow::Owned(bytes)))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borrows_definite_length_strings() {
+        let encoded = serde_cbor::to_vec(&Bytes::from(vec![1u8, 2, 3])).unwrap();
+        let bytes: Bytes = serde_cbor::from_slice(&encoded).unwrap();
+        assert_eq!(bytes.as_borrowed(), Some(&encoded[1..]));
+        assert_eq!(bytes.clone().into_owned().as_borrowed(), None);
+
+        // indefinite length: 2 chunks of 1 byte
+        let bytes: Bytes = serde_cbor::from_slice(&[0x5f, 0x41, 0x01, 0x41, 0x02, 0xff]).unwrap();
+        assert_eq!(bytes.as_borrowed(), None);
+        assert_eq!(&*bytes, &[1, 2]);
+    }
+}
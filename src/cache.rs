@@ -0,0 +1,252 @@
+//! Verification results cache
+//!
+//! Services presented the same document over and over, e.g. with every request of a
+//! session, can skip the signature and chain checks after the first time. A
+//! [`CachingVerifier`] keys the [`VerificationReport`] of each accepted document by the
+//! document's SHA384 and returns it again for the same bytes until the cache TTL
+//! passes, the document's first certificate expires or the document grows older than
+//! the policy's maximum age, whichever comes first. Rejected
+//! documents aren't cached, and cache hits reach neither the audit sink nor the
+//! metrics of the underlying [`Verifier`].
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::cache::CachingVerifier;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! let verifier = CachingVerifier::new(Verifier::new(aws_root_der), 60);
+//! let report = verifier.verify(&document, now)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::crypto::sha384;
+use crate::report::VerificationReport;
+use crate::{NitroAdError, Verifier};
+
+/// Reports kept unless [`CachingVerifier::with_capacity`] says otherwise
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+struct CachedReport {
+    report: VerificationReport,
+    /// First second the report is no longer returned
+    expires_at: u64,
+}
+
+/// [`Verifier`] remembering the reports of accepted documents
+pub struct CachingVerifier {
+    verifier: Verifier,
+    ttl_secs: u64,
+    capacity: usize,
+    entries: Mutex<HashMap<[u8; 48], CachedReport>>,
+}
+
+impl CachingVerifier {
+    /// Cache of [`DEFAULT_CACHE_CAPACITY`] reports of `verifier`, each returned for at
+    /// most `ttl_secs` after its verification
+    pub fn new(verifier: Verifier, ttl_secs: u64) -> Self {
+        CachingVerifier {
+            verifier,
+            ttl_secs,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keeps at most `capacity` reports, dropping the ones expiring first when full
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// Report of [`Verifier::verify`] of `bytes` at `unix_ts_sec`, or the one of an
+    /// earlier verification of the same bytes within the TTL. Cached reports keep the
+    /// `verified_at` of that verification.
+    pub fn verify(
+        &self,
+        bytes: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<VerificationReport, NitroAdError> {
+        let key = sha384(bytes);
+        if let Some(cached) = self.entries().get(&key) {
+            if cached.report.verified_at <= unix_ts_sec && unix_ts_sec < cached.expires_at {
+                return Ok(cached.report.clone());
+            }
+        }
+
+        let doc = self.verifier.verify(bytes, unix_ts_sec)?;
+        let report = doc.report();
+        let not_after = doc.payload().chain_not_after().unwrap_or(0).max(0) as u64;
+        let too_old_at = self.verifier.policy().too_old_at(doc.payload()).unwrap_or(u64::MAX);
+        let expires_at = unix_ts_sec.saturating_add(self.ttl_secs).min(not_after).min(too_old_at);
+        if expires_at > unix_ts_sec && self.capacity > 0 {
+            let mut entries = self.entries();
+            if entries.len() >= self.capacity && !entries.contains_key(&key) {
+                entries.retain(|_, cached| cached.expires_at > unix_ts_sec);
+                if entries.len() >= self.capacity {
+                    let soonest = entries.iter().min_by_key(|(_, cached)| cached.expires_at);
+                    if let Some(soonest) = soonest.map(|(key, _)| *key) {
+                        entries.remove(&soonest);
+                    }
+                }
+            }
+            let cached = CachedReport {
+                report: report.clone(),
+                expires_at,
+            };
+            entries.insert(key, cached);
+        }
+        Ok(report)
+    }
+
+    /// Number of cached reports, expired ones included until evicted
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all cached reports, e.g. after the expected measurements changed
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<[u8; 48], CachedReport>> {
+        // entries are inserted whole, so those of a panicked thread are still valid
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::audit::AuditRecord;
+    use crate::VerifierPolicy;
+
+    /// Verifier counting its verifications
+    fn counting_verifier(root_cert: &[u8]) -> (Verifier, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        let sink = move |_: &AuditRecord| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        };
+        (Verifier::new(root_cert).with_audit_sink(sink), count)
+    }
+
+    #[test]
+    fn test_cached_reports() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let (verifier, verifications) = counting_verifier(root_cert);
+        let cache = CachingVerifier::new(verifier, 60);
+
+        let report = cache.verify(ad_blob, 1614967200)?;
+        assert_eq!(cache.verify(ad_blob, 1614967259)?, report);
+        assert_eq!(verifications.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.len(), 1);
+
+        // expired, and before the cached verification
+        assert_eq!(cache.verify(ad_blob, 1614967260)?.verified_at, 1614967260);
+        assert_eq!(cache.verify(ad_blob, 1614967200)?.verified_at, 1614967200);
+        assert_eq!(verifications.load(Ordering::SeqCst), 3);
+
+        let mut tampered = *ad_blob;
+        tampered[ad_blob.len() - 1] ^= 0x01;
+        for _ in 0..2 {
+            let result = cache.verify(&tampered, 1614967200);
+            assert!(matches!(result, Err(NitroAdError::InvalidSignature)));
+        }
+        assert_eq!(verifications.load(Ordering::SeqCst), 5);
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+
+        let rejecting = Verifier::new(&root_cert[..])
+            .with_policy(VerifierPolicy::new().with_nonce(&b"challenge"[..]));
+        let cache = CachingVerifier::new(rejecting, 60);
+        assert!(cache.verify(ad_blob, 1614967200).is_err());
+        assert!(cache.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bounded_by_max_age() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = crate::NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let issued = doc.payload().timestamp.timestamp() as u64;
+        let (verifier, verifications) = counting_verifier(root_cert);
+        let verifier = verifier.with_policy(VerifierPolicy::new().with_max_age(30));
+        let cache = CachingVerifier::new(verifier, 60);
+
+        cache.verify(ad_blob, issued + 1)?;
+        cache.verify(ad_blob, issued + 30)?;
+        assert_eq!(verifications.load(Ordering::SeqCst), 1);
+        // within the TTL, but older than the policy allows
+        assert!(matches!(
+            cache.verify(ad_blob, issued + 59),
+            Err(NitroAdError::DocumentTooOld { age_secs: 59 })
+        ));
+        assert_eq!(verifications.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bounded_by_cert_expiry() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let (verifier, verifications) = counting_verifier(root_cert);
+        let cache = CachingVerifier::new(verifier, u64::MAX);
+
+        cache.verify(ad_blob, 1614967200)?;
+        let doc = crate::NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let not_after = doc.payload().chain_not_after().unwrap() as u64;
+        cache.verify(ad_blob, not_after - 1)?;
+        assert_eq!(verifications.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            cache.verify(ad_blob, not_after + 1),
+            Err(NitroAdError::VerificationError(webpki::Error::CertExpired { .. }))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_capacity() -> Result<(), NitroAdError> {
+        use crate::nsm::{AttestationRequest, Attester};
+        use crate::testing::MockNsm;
+
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let documents = (0..3u8)
+            .map(|i| {
+                mock.attest(AttestationRequest {
+                    nonce: Some(&[i]),
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let cache = CachingVerifier::new(Verifier::new(mock.root_cert()), 60).with_capacity(2);
+
+        cache.verify(&documents[0], now)?;
+        cache.verify(&documents[1], now + 1)?;
+        cache.verify(&documents[2], now + 2)?;
+        assert_eq!(cache.len(), 2);
+        // the first document expired soonest and made room
+        let entries = cache.entries();
+        assert!(!entries.contains_key(&sha384(&documents[0])));
+        assert!(entries.contains_key(&sha384(&documents[2])));
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+//! Certificate chain verification profile of attestation documents
+//!
+//! The chain is checked by webpki like a TLS server chain, except for the extended
+//! key usage: signing certificates of the Nitro Secure Module carry none, and no key
+//! purpose is defined for attestation documents, so none is required.
+
+use webpki::{ExtendedKeyUsageValidator, KeyPurposeIdIter};
+
+pub(crate) static ALL_SIGALGS: &[&dyn pki_types::SignatureVerificationAlgorithm] = &[
+    webpki::ring::ECDSA_P256_SHA256,
+    webpki::ring::ECDSA_P256_SHA384,
+    webpki::ring::ECDSA_P384_SHA256,
+    webpki::ring::ECDSA_P384_SHA384,
+    webpki::ring::ED25519,
+];
+
+/// Extended key usage check of the attestation profile, accepting any well-formed extension
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AttestationProfile;
+
+impl ExtendedKeyUsageValidator for AttestationProfile {
+    fn validate(&self, iter: KeyPurposeIdIter<'_, '_>) -> Result<(), webpki::Error> {
+        for purpose in iter {
+            purpose?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Challenge-response freshness
+//!
+//! A verifier proves that a document is fresh by having the enclave include a random
+//! challenge as its `nonce`. [`Challenge::generate`] draws the challenge from the
+//! operating system's CSPRNG and records when it expires; a [`Session`] pairs it with
+//! the [`Verifier`] of the document answering it and is consumed by the verification,
+//! so each challenge is accepted once.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::challenge::Session;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # fn request_document(_: &[u8]) -> Vec<u8> { Vec::new() }
+//! # let aws_root_der = Vec::new();
+//! # let now = 0;
+//! let session = Session::new(Verifier::new(aws_root_der))?;
+//! let document = request_document(session.challenge().nonce());
+//! let doc = session.verify(&document, now)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::io;
+
+use chrono::{Duration, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use subtle::ConstantTimeEq;
+
+use crate::{NitroAdDoc, NitroAdError, Verifier};
+
+/// Shortest challenge [`Challenge::generate_with`] draws, and the length of
+/// [`Challenge::generate`]'s
+pub const MIN_CHALLENGE_LEN: usize = 32;
+
+/// Longest challenge [`Challenge::generate_with`] draws
+pub const MAX_CHALLENGE_LEN: usize = 64;
+
+/// Time an enclave has to answer a [`Challenge::generate`] challenge
+pub const DEFAULT_CHALLENGE_TTL_SECS: i64 = 300;
+
+/// Random nonce for an enclave to include in its document, with its expiry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    nonce: Vec<u8>,
+    issued_at: u64,
+    expires_at: u64,
+}
+
+impl Challenge {
+    /// Challenge of [`MIN_CHALLENGE_LEN`] bytes, expiring after
+    /// [`DEFAULT_CHALLENGE_TTL_SECS`]
+    pub fn generate() -> Result<Self, NitroAdError> {
+        Self::generate_with(MIN_CHALLENGE_LEN, Duration::seconds(DEFAULT_CHALLENGE_TTL_SECS))
+    }
+
+    /// Challenge of `len` bytes, [`MIN_CHALLENGE_LEN`] to [`MAX_CHALLENGE_LEN`],
+    /// expiring after `ttl`
+    pub fn generate_with(len: usize, ttl: Duration) -> Result<Self, NitroAdError> {
+        if !(MIN_CHALLENGE_LEN..=MAX_CHALLENGE_LEN).contains(&len) {
+            return Err(NitroAdError::BadChallengeLength(len));
+        }
+        let mut nonce = vec![0; len];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| {
+            NitroAdError::IoError(io::Error::other("system randomness source failed"))
+        })?;
+        let now = Utc::now();
+        Ok(Challenge {
+            nonce,
+            issued_at: now.timestamp() as u64,
+            expires_at: (now + ttl).timestamp() as u64,
+        })
+    }
+
+    /// Bytes to send as the document's `nonce`
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Unix time the challenge was generated at
+    pub fn issued_at(&self) -> u64 {
+        self.issued_at
+    }
+
+    /// Unix time after which documents answering the challenge are rejected
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+
+    pub fn is_expired(&self, unix_ts_sec: u64) -> bool {
+        unix_ts_sec > self.expires_at
+    }
+}
+
+/// Challenge awaiting the document answering it
+#[derive(Debug)]
+pub struct Session {
+    verifier: Verifier,
+    challenge: Challenge,
+}
+
+impl Session {
+    /// Session with a fresh [`Challenge::generate`] challenge
+    pub fn new(verifier: Verifier) -> Result<Self, NitroAdError> {
+        Ok(Self::with_challenge(verifier, Challenge::generate()?))
+    }
+
+    pub fn with_challenge(verifier: Verifier, challenge: Challenge) -> Self {
+        Session {
+            verifier,
+            challenge,
+        }
+    }
+
+    pub fn challenge(&self) -> &Challenge {
+        &self.challenge
+    }
+
+    /// Verifies `document` at `unix_ts_sec` with the verifier and checks that its
+    /// `nonce` is the unexpired challenge. Fails with [`NitroAdError::ChallengeExpired`]
+    /// past the expiry, before looking at the document.
+    pub fn verify<'a>(
+        self,
+        document: &'a [u8],
+        unix_ts_sec: u64,
+    ) -> Result<NitroAdDoc<'a>, NitroAdError> {
+        if self.challenge.is_expired(unix_ts_sec) {
+            return Err(NitroAdError::ChallengeExpired {
+                expires_at: self.challenge.expires_at,
+            });
+        }
+        let doc = self.verifier.verify(document, unix_ts_sec)?;
+        match doc.payload().nonce.as_deref() {
+            Some(nonce) if bool::from(nonce.ct_eq(&self.challenge.nonce)) => Ok(doc),
+            _ => Err(NitroAdError::NonceMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let challenge = Challenge::generate().unwrap();
+        assert_eq!(challenge.nonce().len(), MIN_CHALLENGE_LEN);
+        assert_eq!(
+            challenge.expires_at() - challenge.issued_at(),
+            DEFAULT_CHALLENGE_TTL_SECS as u64
+        );
+        assert!(!challenge.is_expired(challenge.expires_at()));
+        assert!(challenge.is_expired(challenge.expires_at() + 1));
+        assert_ne!(challenge.nonce(), Challenge::generate().unwrap().nonce());
+
+        let long = Challenge::generate_with(MAX_CHALLENGE_LEN, Duration::seconds(10)).unwrap();
+        assert_eq!(long.nonce().len(), MAX_CHALLENGE_LEN);
+        for len in &[0, MIN_CHALLENGE_LEN - 1, MAX_CHALLENGE_LEN + 1] {
+            assert!(matches!(
+                Challenge::generate_with(*len, Duration::seconds(10)),
+                Err(NitroAdError::BadChallengeLength(l)) if l == *len
+            ));
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let challenge = Challenge {
+            nonce: b"challenge".to_vec(),
+            issued_at: 1614967200,
+            expires_at: 1614967200 + 300,
+        };
+
+        // the fixture document carries no nonce
+        let session = Session::with_challenge(Verifier::new(&root_cert[..]), challenge.clone());
+        assert!(matches!(session.verify(ad_blob, 1614967200), Err(NitroAdError::NonceMismatch)));
+
+        let session = Session::with_challenge(Verifier::new(&root_cert[..]), challenge);
+        assert!(matches!(
+            session.verify(ad_blob, 1614967200 + 301),
+            Err(NitroAdError::ChallengeExpired { expires_at: 1614967500 })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_session_with_mock() -> Result<(), NitroAdError> {
+        use crate::nsm::{AttestationRequest, Attester};
+        use crate::testing::MockNsm;
+
+        let mock = MockNsm::new()?;
+        let session = Session::new(Verifier::new(mock.root_cert()))?;
+        let document = mock.attest(AttestationRequest {
+            nonce: Some(session.challenge().nonce()),
+            ..Default::default()
+        })?;
+        let now = Utc::now().timestamp() as u64;
+        let doc = session.verify(&document, now)?;
+        assert_eq!(doc.payload().nonce.as_deref().map(<[u8]>::len), Some(MIN_CHALLENGE_LEN));
+        Ok(())
+    }
+}
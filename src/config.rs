@@ -0,0 +1,189 @@
+//! Verification policies in configuration files
+//!
+//! A [`PolicyConfig`] holds everything a [`Verifier`] checks: the root certificate, the
+//! expected PCRs and `user_data`, the maximum document age, the handling of debug-mode
+//! documents and the optional fields documents must carry. Kept in TOML or YAML files,
+//! policies are reviewed and deployed like any other configuration instead of being
+//! compiled in. Unknown keys are errors, so a misspelled one can't loosen a policy.
+//! ```toml
+//! # relative to the directory of the policy file
+//! root_cert = "aws_root.pem"
+//! max_age_secs = 300
+//! debug_mode = "reject"
+//! required_fields = ["nonce", "public_key"]
+//!
+//! # like the Measurements of nitro-cli build-enclave
+//! [pcrs]
+//! PCR0 = "8f4e..."
+//! PCR8 = "4ab6..."
+//! ```
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::config::PolicyConfig;
+//!
+//! let verifier = PolicyConfig::load("/etc/nitro-ad/policy.toml")?.verifier()?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+
+use crate::env::read_root_cert;
+use crate::policy::{DebugMode, DocumentField};
+use crate::{NitroAdError, Verifier, VerifierPolicy};
+
+/// Contents of a policy file, see the [module documentation](self)
+#[serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// DER or PEM root certificate file
+    pub root_cert: Option<PathBuf>,
+    /// Hex encoded expected PCRs keyed `PCR0`, `PCR1`, ...
+    pub pcrs: BTreeMap<String, String>,
+    /// Expected `user_data`, hex encoded
+    #[serde_as(as = "Option<Hex>")]
+    pub user_data: Option<Vec<u8>>,
+    pub max_age_secs: Option<u64>,
+    pub debug_mode: DebugMode,
+    pub required_fields: BTreeSet<DocumentField>,
+}
+
+impl PolicyConfig {
+    pub fn from_toml(toml: &str) -> Result<Self, NitroAdError> {
+        toml::from_str(toml).map_err(|e| NitroAdError::InvalidConfig(e.to_string()))
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, NitroAdError> {
+        serde_yaml::from_str(yaml).map_err(|e| NitroAdError::InvalidConfig(e.to_string()))
+    }
+
+    /// Policy of the file at `path`, TOML or YAML by its `.toml`, `.yaml` or `.yml`
+    /// extension. `root_cert` is resolved against the directory of the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NitroAdError> {
+        let path = path.as_ref();
+        let invalid = |e: &dyn std::fmt::Display| {
+            NitroAdError::InvalidConfig(format!("{}: {}", path.display(), e))
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("yaml") | Some("yml") => Self::from_yaml(&contents),
+            _ => return Err(invalid(&"not a .toml, .yaml or .yml file")),
+        };
+        let mut config = config.map_err(|e| invalid(&e))?;
+        if let (Some(root), Some(dir)) = (&config.root_cert, path.parent()) {
+            config.root_cert = Some(dir.join(root));
+        }
+        Ok(config)
+    }
+
+    /// Policy checking the PCRs, `user_data`, age, debug mode and fields of the file.
+    /// Unlike [`VerifierPolicy::with_measurements_json`], every `pcrs` key must be a PCR.
+    pub fn policy(&self) -> Result<VerifierPolicy, NitroAdError> {
+        let mut policy = VerifierPolicy::new();
+        for (key, value) in &self.pcrs {
+            let invalid =
+                |message| NitroAdError::InvalidConfig(format!("pcrs.{} {}", key, message));
+            let index = key.strip_prefix("PCR").and_then(|index| index.parse().ok());
+            let index = index.ok_or_else(|| invalid("is not a PCR"))?;
+            policy = policy.with_pcr(index, hex::decode(value).map_err(|_| invalid("is not hex"))?);
+        }
+        if let Some(user_data) = &self.user_data {
+            policy = policy.with_user_data(user_data.clone());
+        }
+        if let Some(max_age_secs) = self.max_age_secs {
+            policy = policy.with_max_age(max_age_secs);
+        }
+        policy.debug_mode = self.debug_mode;
+        policy.required_fields = self.required_fields.clone();
+        Ok(policy)
+    }
+
+    /// Verifier trusting `root_cert`, with [`PolicyConfig::policy`]
+    pub fn verifier(&self) -> Result<Verifier, NitroAdError> {
+        let root = self
+            .root_cert
+            .as_deref()
+            .ok_or_else(|| NitroAdError::InvalidConfig(String::from("root_cert is not set")))?;
+        Ok(Verifier::new(read_root_cert(root)?).with_policy(self.policy()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static POLICY_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/policy.toml");
+
+    #[test]
+    fn test_load_policy_file() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let config = PolicyConfig::load(POLICY_FILE)?;
+        assert!(config.root_cert.as_deref().unwrap().ends_with("tests/data/aws_root.pem"));
+        let verifier = config.verifier()?;
+        assert_eq!(verifier.root_cert(), &include_bytes!("../tests/data/aws_root.der")[..]);
+        assert_eq!(verifier.policy().pcrs[&0], vec![0; 48]);
+        assert!(verifier.verify(ad_blob, 1614967200).is_ok());
+        assert!(matches!(
+            verifier.verify(ad_blob, 1614967400),
+            Err(NitroAdError::DocumentTooOld { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_and_yaml() -> Result<(), NitroAdError> {
+        let toml = r#"
+            max_age_secs = 300
+            debug_mode = "reject"
+            required_fields = ["nonce", "public_key"]
+            user_data = "cafe"
+
+            [pcrs]
+            PCR8 = "abab"
+        "#;
+        let yaml = "
+            max_age_secs: 300
+            debug_mode: reject
+            required_fields: [public_key, nonce]
+            user_data: cafe
+            pcrs:
+              PCR8: abab
+        ";
+        let config = PolicyConfig::from_toml(toml)?;
+        assert_eq!(PolicyConfig::from_yaml(yaml)?, config);
+
+        let policy = config.policy()?;
+        assert_eq!(policy.pcrs[&8], vec![0xab; 2]);
+        assert_eq!(policy.user_data.as_deref(), Some(&[0xca, 0xfe][..]));
+        assert_eq!(policy.max_age_secs, Some(300));
+        assert_eq!(policy.debug_mode, DebugMode::Reject);
+        assert!(policy.required_fields.contains(&DocumentField::PublicKey));
+        assert!(matches!(config.verifier(), Err(NitroAdError::InvalidConfig(_))));
+        assert_eq!(PolicyConfig::from_toml("")?.policy()?, VerifierPolicy::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_policy_files() {
+        let invalid = |toml: &str| PolicyConfig::from_toml(toml).and_then(|c| c.policy()).err();
+        // misspelled keys and unknown values don't fall back to accepting documents
+        assert!(matches!(invalid("max_age = 300"), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid(r#"debug_mode = "deny""#), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(
+            invalid(r#"required_fields = ["pcrs"]"#),
+            Some(NitroAdError::InvalidConfig(_))
+        ));
+        assert!(matches!(invalid("[pcrs]\nPCR0 = \"zz\""), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid("[pcrs]\npcr0 = \"00\""), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid("[pcrs]\nPCR = \"00\""), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(
+            PolicyConfig::load("policy.json"),
+            Err(NitroAdError::InvalidConfig(e)) if e.starts_with("policy.json: ")
+        ));
+    }
+}
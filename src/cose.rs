@@ -0,0 +1,196 @@
+//! COSE header maps
+//!
+//! Headers are decoded keeping only the values this library interprets, rather
+//! than into `serde_cbor::Value`, which needs std.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use serde::de::{Deserialize, Deserializer, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+/// COSE header label, see <https://tools.ietf.org/html/rfc8152#section-1.4>
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HeaderLabel {
+    Int(i128),
+    Text(String),
+}
+
+/// Header parameter value, as far as this library interprets it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HeaderValue {
+    Int(i128),
+    Text(String),
+    Array(Vec<HeaderValue>),
+    /// Any other CBOR item, skipped while decoding
+    Other,
+}
+
+impl HeaderValue {
+    /// Label held by the value, `None` for values which can't be labels
+    pub fn as_label(&self) -> Option<HeaderLabel> {
+        match self {
+            HeaderValue::Int(l) => Some(HeaderLabel::Int(*l)),
+            HeaderValue::Text(l) => Some(HeaderLabel::Text(l.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// COSE header map, label to value. Maps with keys which can't be labels, or with a label
+/// twice, are rejected: decoders keeping different duplicates would disagree on the headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HeaderMap(BTreeMap<HeaderLabel, HeaderValue>);
+
+impl HeaderMap {
+    pub fn get(&self, label: &HeaderLabel) -> Option<&HeaderValue> {
+        self.0.get(label)
+    }
+}
+
+struct HeaderValueVisitor;
+
+impl<'de> Visitor<'de> for HeaderValueVisitor {
+    type Value = HeaderValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a CBOR item")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Other)
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Int(v.into()))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Int(v.into()))
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Int(v))
+    }
+
+    fn visit_u128<E: serde::de::Error>(self, v: u128) -> Result<HeaderValue, E> {
+        // beyond the CBOR integer range
+        i128::try_from(v)
+            .map(HeaderValue::Int)
+            .map_err(|_| E::custom("integer out of range"))
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Other)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Text(String::from(v)))
+    }
+
+    fn visit_bytes<E>(self, _: &[u8]) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Other)
+    }
+
+    fn visit_unit<E>(self) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Other)
+    }
+
+    fn visit_none<E>(self) -> Result<HeaderValue, E> {
+        Ok(HeaderValue::Other)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<HeaderValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(HeaderValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<HeaderValue, A::Error> {
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(HeaderValue::Other)
+    }
+}
+
+impl<'de> Deserialize<'de> for HeaderValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(HeaderValueVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for HeaderMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HeaderMapVisitor;
+
+        impl<'de> Visitor<'de> for HeaderMapVisitor {
+            type Value = HeaderMap;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a COSE header map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<HeaderMap, A::Error> {
+                let mut headers = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry::<HeaderValue, HeaderValue>()? {
+                    let label = key
+                        .as_label()
+                        .ok_or_else(|| A::Error::custom("header label is not an int or text"))?;
+                    if headers.insert(label, value).is_some() {
+                        return Err(A::Error::custom("duplicate header label"));
+                    }
+                }
+                Ok(HeaderMap(headers))
+            }
+        }
+
+        deserializer.deserialize_map(HeaderMapVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_cbor::Value as CborValue;
+
+    #[test]
+    fn test_decode_header_map() {
+        let mut map = BTreeMap::new();
+        map.insert(CborValue::Integer(1), CborValue::Integer(-35));
+        map.insert(CborValue::Integer(2), CborValue::Array(vec![
+            CborValue::Integer(1),
+            CborValue::Text("x".into()),
+            CborValue::Bytes(vec![1]),
+        ]));
+        map.insert(CborValue::Integer(33), CborValue::Array(vec![CborValue::Bytes(vec![0; 4])]));
+        map.insert(CborValue::Text("nested".into()), CborValue::Map(map.clone()));
+
+        let headers: HeaderMap = serde_cbor::from_slice(&serde_cbor::to_vec(&map).unwrap()).unwrap();
+        assert_eq!(headers.0.len(), 4);
+        assert_eq!(headers.get(&HeaderLabel::Int(1)), Some(&HeaderValue::Int(-35)));
+        assert_eq!(
+            headers.get(&HeaderLabel::Int(2)),
+            Some(&HeaderValue::Array(vec![
+                HeaderValue::Int(1),
+                HeaderValue::Text("x".into()),
+                HeaderValue::Other,
+            ]))
+        );
+        assert_eq!(headers.get(&HeaderLabel::Text("nested".into())), Some(&HeaderValue::Other));
+
+        assert!(serde_cbor::from_slice::<HeaderMap>(&[0x80]).is_err());
+        map.insert(CborValue::Bytes(vec![7]), CborValue::Null);
+        assert!(serde_cbor::from_slice::<HeaderMap>(&serde_cbor::to_vec(&map).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_header_label() {
+        // {1: -7, 1: -35}
+        let err = serde_cbor::from_slice::<HeaderMap>(&[0xa2, 0x01, 0x26, 0x01, 0x38, 0x22]);
+        assert!(err.unwrap_err().to_string().contains("duplicate header label"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Signature verification backends
+//!
+//! Document verification only needs the ES384 primitives of [`CryptoBackend`].
+//! Each backend feature provides an implementation, [`DefaultBackend`] is used
+//! by [`NitroAdDoc::from_bytes`](crate::NitroAdDoc::from_bytes) and other ones
+//! can be passed to [`NitroAdDoc::from_bytes_with`](crate::NitroAdDoc::from_bytes_with).
+//!
+//! | feature             | backend        | default when enabled     |
+//! |---------------------|----------------|--------------------------|
+//! | `openssl`           | [`OpenSsl`]    | if none of the others is |
+//! | `rust-crypto`       | [`RustCrypto`] | unless AWS-LC is enabled |
+//! | `aws-lc-rs`, `fips` | [`AwsLc`]      | always                   |
+//!
+//! Either way, certificate chains are checked by webpki. Attestation result
+//! tokens and signed reports need the `openssl` feature regardless of the
+//! backend chosen here. With the `fips` feature, `aws_lc_rs::try_fips_mode()`
+//! confirms the validated module is in use.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::NitroAdError;
+
+#[cfg(not(any(
+    feature = "openssl",
+    feature = "rust-crypto",
+    feature = "aws-lc-rs",
+    feature = "fips"
+)))]
+compile_error!("enable the openssl, rust-crypto or aws-lc-rs feature");
+
+/// ES384 primitives of document signature verification
+pub trait CryptoBackend {
+    /// Parsed P-384 public key
+    type PublicKey;
+
+    /// Parses a DER encoded SubjectPublicKeyInfo holding a P-384 key
+    fn parse_spki(&self, spki: &[u8]) -> Result<Self::PublicKey, NitroAdError>;
+
+    /// Checks the ES384 r||s signature `sig` of `data`, malformed signatures don't verify
+    fn verify_es384(&self, key: &Self::PublicKey, data: &[u8], sig: &[u8]) -> bool;
+}
+
+#[cfg(any(feature = "aws-lc-rs", feature = "fips"))]
+pub type DefaultBackend = AwsLc;
+
+#[cfg(all(
+    feature = "rust-crypto",
+    not(any(feature = "aws-lc-rs", feature = "fips"))
+))]
+pub type DefaultBackend = RustCrypto;
+
+#[cfg(all(
+    feature = "openssl",
+    not(any(feature = "rust-crypto", feature = "aws-lc-rs", feature = "fips"))
+))]
+pub type DefaultBackend = OpenSsl;
+
+/// SHA384 digest of `data`, computed with the [`DefaultBackend`] library
+#[cfg(any(feature = "aws-lc-rs", feature = "fips"))]
+pub(crate) fn sha384(data: &[u8]) -> [u8; 48] {
+    let mut hash = [0u8; 48];
+    hash.copy_from_slice(aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA384, data).as_ref());
+    hash
+}
+
+/// SHA384 digest of `data`, computed with the [`DefaultBackend`] library
+#[cfg(all(
+    feature = "rust-crypto",
+    not(any(feature = "aws-lc-rs", feature = "fips"))
+))]
+// reports are the only user, and need std
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) fn sha384(data: &[u8]) -> [u8; 48] {
+    use sha2::Digest;
+    sha2::Sha384::digest(data).into()
+}
+
+/// SHA384 digest of `data`, computed with the [`DefaultBackend`] library
+#[cfg(all(
+    feature = "openssl",
+    not(any(feature = "rust-crypto", feature = "aws-lc-rs", feature = "fips"))
+))]
+pub(crate) fn sha384(data: &[u8]) -> [u8; 48] {
+    openssl::sha::sha384(data)
+}
+
+/// AWS-LC through `aws-lc-rs`, FIPS validated with the `fips` feature
+#[cfg(any(feature = "aws-lc-rs", feature = "fips"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AwsLc;
+
+#[cfg(any(feature = "aws-lc-rs", feature = "fips"))]
+impl CryptoBackend for AwsLc {
+    type PublicKey = aws_lc_rs::signature::ParsedPublicKey;
+
+    fn parse_spki(&self, spki: &[u8]) -> Result<Self::PublicKey, NitroAdError> {
+        aws_lc_rs::signature::ParsedPublicKey::new(
+            &aws_lc_rs::signature::ECDSA_P384_SHA384_FIXED,
+            spki,
+        )
+        .map_err(|e| NitroAdError::InvalidSigningKey(e.to_string()))
+    }
+
+    fn verify_es384(&self, key: &Self::PublicKey, data: &[u8], sig: &[u8]) -> bool {
+        key.verify_sig(data, sig).is_ok()
+    }
+}
+
+/// Pure-Rust `p384` crate
+#[cfg(feature = "rust-crypto")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RustCrypto;
+
+#[cfg(feature = "rust-crypto")]
+impl CryptoBackend for RustCrypto {
+    type PublicKey = p384::ecdsa::VerifyingKey;
+
+    fn parse_spki(&self, spki: &[u8]) -> Result<Self::PublicKey, NitroAdError> {
+        use p384::pkcs8::DecodePublicKey;
+
+        p384::ecdsa::VerifyingKey::from_public_key_der(spki)
+            .map_err(|e| NitroAdError::InvalidSigningKey(e.to_string()))
+    }
+
+    fn verify_es384(&self, key: &Self::PublicKey, data: &[u8], sig: &[u8]) -> bool {
+        use p384::ecdsa::signature::Verifier;
+
+        match p384::ecdsa::Signature::from_slice(sig) {
+            Ok(sig) => key.verify(data, &sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// OpenSSL
+#[cfg(feature = "openssl")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenSsl;
+
+#[cfg(feature = "openssl")]
+impl CryptoBackend for OpenSsl {
+    type PublicKey = openssl::ec::EcKey<openssl::pkey::Public>;
+
+    fn parse_spki(&self, spki: &[u8]) -> Result<Self::PublicKey, NitroAdError> {
+        let key = openssl::pkey::PKey::public_key_from_der(spki)
+            .and_then(|key| key.ec_key())
+            .map_err(|e| NitroAdError::InvalidSigningKey(e.to_string()))?;
+
+        crate::es384::check_curve(&key)
+            .map_err(|_| NitroAdError::InvalidSigningKey(String::from("not a P-384 key")))?;
+        Ok(key)
+    }
+
+    fn verify_es384(&self, key: &Self::PublicKey, data: &[u8], sig: &[u8]) -> bool {
+        crate::es384::verify(data, sig, key).unwrap_or(false)
+    }
+}
+
+/// DER encoded ECDSA-Sig-Value of the r||s signature `raw`
+pub(crate) fn ecdsa_raw_to_der(raw: &[u8]) -> Vec<u8> {
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let mut integers = Vec::with_capacity(raw.len() + 6);
+    for scalar in &[r, s] {
+        let skip = scalar.iter().take_while(|&&b| b == 0).count();
+        let scalar = &scalar[skip..];
+        // a zero byte keeps INTEGERs with the high bit set, and zero, positive
+        let pad = scalar.first().is_none_or(|b| b & 0x80 != 0);
+        der_header(&mut integers, 0x02, scalar.len() + pad as usize);
+        if pad {
+            integers.push(0);
+        }
+        integers.extend_from_slice(scalar);
+    }
+    let mut der = Vec::with_capacity(integers.len() + 3);
+    der_header(&mut der, 0x30, integers.len());
+    der.extend_from_slice(&integers);
+    der
+}
+
+/// Appends the DER tag and length octets of a `len` bytes long value
+pub(crate) fn der_header(out: &mut Vec<u8>, tag: u8, len: usize) {
+    out.push(tag);
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let octets = len.to_be_bytes();
+        let skip = octets.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (octets.len() - skip) as u8);
+        out.extend_from_slice(&octets[skip..]);
+    }
+}
+
+/// r||s form, with `len` bytes per scalar, of a DER encoded ECDSA-Sig-Value. `None` if
+/// it is malformed or a scalar is longer than `len` bytes.
+#[cfg(feature = "std")]
+pub(crate) fn ecdsa_der_to_raw(der: &[u8], len: usize) -> Option<Vec<u8>> {
+    let (sequence, rest) = der_element(der, 0x30)?;
+    let (r, sequence) = der_element(sequence, 0x02)?;
+    let (s, sequence) = der_element(sequence, 0x02)?;
+    if !rest.is_empty() || !sequence.is_empty() {
+        return None;
+    }
+    let mut raw = alloc::vec![0; 2 * len];
+    for (scalar, out) in [r, s].iter().zip(raw.chunks_mut(len)) {
+        // INTEGERs are signed, so positive ones may start with a zero byte
+        let skip = scalar.iter().take_while(|&&b| b == 0).count();
+        let scalar = &scalar[skip..];
+        if scalar.len() > len {
+            return None;
+        }
+        out[len - scalar.len()..].copy_from_slice(scalar);
+    }
+    Some(raw)
+}
+
+/// Contents of the DER element with `tag` at the start of `der`, and the bytes after it
+#[cfg(feature = "std")]
+pub(crate) fn der_element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (found, contents, rest) = der_any(der)?;
+    (found == tag).then_some((contents, rest))
+}
+
+/// Tag and contents of the DER element at the start of `der`, and the bytes after it.
+/// Lengths take up to four octets.
+#[cfg(feature = "std")]
+pub(crate) fn der_any(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+    let (len, rest) = match len {
+        0..=0x7f => (len as usize, rest),
+        0x81..=0x84 => {
+            let octets = rest.get(..(len & 0x7f) as usize)?;
+            let len = octets.iter().fold(0, |len, &octet| len << 8 | octet as usize);
+            (len, &rest[octets.len()..])
+        }
+        _ => return None,
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::NitroAdDoc;
+
+    fn check_backend<B: CryptoBackend>(backend: &B) {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes_with(ad_blob, root_cert, 1614967200, backend).unwrap();
+        assert!(doc.verification_error().is_none());
+
+        let mut ad_blob_copy = *ad_blob;
+        ad_blob_copy[ad_blob.len() - 1] ^= 0x01;
+        assert!(matches!(
+            NitroAdDoc::from_bytes_with(&ad_blob_copy, root_cert, 1614967200, backend),
+            Err(NitroAdError::InvalidSignature)
+        ));
+
+        assert!(matches!(
+            backend.parse_spki(&[0x30, 0x00]),
+            Err(NitroAdError::InvalidSigningKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_sha384() {
+        assert_eq!(
+            hex::encode(sha384(b"abc")),
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed\
+             8086072ba1e7cc2358baeca134c825a7"
+        );
+    }
+
+    #[test]
+    fn test_ecdsa_raw_to_der() {
+        let raw = [0, 0, 0, 0x80, 0, 0, 0, 0];
+        assert_eq!(ecdsa_raw_to_der(&raw), [0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x00]);
+        let raw = [0x7f; 96];
+        let der = ecdsa_raw_to_der(&raw);
+        assert_eq!(der[..4], [0x30, 0x64, 0x02, 0x30]);
+        assert_eq!(der.len(), 102);
+        #[cfg(feature = "std")]
+        assert_eq!(ecdsa_der_to_raw(&der, 48).unwrap(), raw.to_vec());
+    }
+
+    #[test]
+    fn test_der_header() {
+        let header = |len| {
+            let mut out = Vec::new();
+            der_header(&mut out, 0x04, len);
+            out
+        };
+        assert_eq!(header(0x7f), [0x04, 0x7f]);
+        assert_eq!(header(0x80), [0x04, 0x81, 0x80]);
+        assert_eq!(header(0x100), [0x04, 0x82, 0x01, 0x00]);
+        assert_eq!(header(0x12345), [0x04, 0x83, 0x01, 0x23, 0x45]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_der_element() {
+        let mut der = Vec::new();
+        der_header(&mut der, 0x04, 0x100);
+        der.extend_from_slice(&[7; 0x100]);
+        der.push(0);
+        assert_eq!(der_element(&der, 0x04), Some((&[7; 0x100][..], &[0][..])));
+        assert_eq!(der_any(&der).map(|(tag, _, _)| tag), Some(0x04));
+        assert_eq!(der_element(&der, 0x30), None);
+        assert_eq!(der_element(&der[..0x103], 0x04), None);
+        assert_eq!(der_element(&[0x04, 0x85, 0, 0, 0, 0, 0], 0x04), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_ecdsa_der_to_raw() {
+        // r = 0x80 takes a zero byte to stay positive
+        let der = [0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01];
+        assert_eq!(ecdsa_der_to_raw(&der, 4).unwrap(), [0, 0, 0, 0x80, 0, 0, 0, 1]);
+        assert_eq!(ecdsa_der_to_raw(&der, 1).unwrap(), [0x80, 1]);
+
+        let mut trailing = der.to_vec();
+        trailing.push(0);
+        assert_eq!(ecdsa_der_to_raw(&trailing, 4), None);
+        assert_eq!(ecdsa_der_to_raw(&der[..8], 4), None);
+        assert_eq!(ecdsa_der_to_raw(&[0x31, 0x00], 4), None);
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn test_openssl_backend() {
+        check_backend(&OpenSsl);
+    }
+
+    #[test]
+    #[cfg(feature = "rust-crypto")]
+    fn test_rust_crypto_backend() {
+        check_backend(&RustCrypto);
+    }
+
+    #[test]
+    #[cfg(any(feature = "aws-lc-rs", feature = "fips"))]
+    fn test_aws_lc_backend() {
+        check_backend(&AwsLc);
+    }
+}
@@ -0,0 +1,375 @@
+//! CBOR diagnostic notation (RFC 8949 section 8) of raw documents
+//!
+//! The printer works on raw bytes and never fails: malformed input is printed up
+//! to the offending offset followed by a `/ error: ... /` comment, which makes it
+//! usable on documents [`NitroAdDoc::from_bytes`](crate::NitroAdDoc::from_bytes) rejects.
+
+use std::fmt::Write;
+
+// deeper nesting than any attestation document has, guards against stack exhaustion
+const MAX_DEPTH: usize = 64;
+
+/// Diagnostic notation of a single CBOR data item
+pub fn cbor_diag(bytes: &[u8]) -> String {
+    let mut printer = Printer::new(bytes);
+    printer.item(0);
+    printer.finish()
+}
+
+/// Diagnostic notation of a COSE_Sign1 structure, with the CBOR encoded protected
+/// header and payload shown as embedded items (`<< ... >>`, RFC 8610 appendix G.3)
+pub fn cose_sign1_diag(bytes: &[u8]) -> String {
+    let mut printer = Printer::new(bytes);
+    printer.cose_sign1();
+    printer.finish()
+}
+
+#[derive(Debug)]
+struct Malformed(String);
+
+type Result<T> = std::result::Result<T, Malformed>;
+
+struct Printer<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    out: String,
+    error: Option<Malformed>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Printer {
+            bytes,
+            pos: 0,
+            out: String::new(),
+            error: None,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        match self.error {
+            Some(Malformed(e)) => {
+                write!(self.out, " / error: {} at offset {} /", e, self.pos).unwrap();
+            }
+            None if self.pos < self.bytes.len() => {
+                write!(
+                    self.out,
+                    " / error: {} trailing bytes at offset {} /",
+                    self.bytes.len() - self.pos,
+                    self.pos
+                )
+                .unwrap();
+            }
+            None => {}
+        }
+        self.out
+    }
+
+    fn item(&mut self, indent: usize) {
+        if let Err(e) = self.try_item(indent) {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    fn cose_sign1(&mut self) {
+        if let Err(e) = self.try_cose_sign1() {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    fn try_cose_sign1(&mut self) -> Result<()> {
+        let (major, info) = self.head()?;
+        if major == 6 {
+            let tag = self.argument(info)?;
+            write!(self.out, "{}(", tag).unwrap();
+            self.try_cose_sign1()?;
+            self.out.push(')');
+            return Ok(());
+        }
+        if major != 4 || info == 31 || self.argument(info)? != 4 {
+            return Err(Malformed(String::from("COSE_Sign1 must be an array of 4 items")));
+        }
+
+        self.out.push_str("[\n  / protected / ");
+        self.embedded_bstr(1)?;
+        self.out.push_str(",\n  / unprotected / ");
+        self.try_item(1)?;
+        self.out.push_str(",\n  / payload / ");
+        self.embedded_bstr(1)?;
+        self.out.push_str(",\n  / signature / ");
+        self.try_item(1)?;
+        self.out.push_str("\n]");
+        Ok(())
+    }
+
+    /// Definite length byte string holding a CBOR item, printed as `<< item >>`
+    fn embedded_bstr(&mut self, indent: usize) -> Result<()> {
+        let start = self.pos;
+        let (major, info) = self.head()?;
+        if major != 2 || info == 31 {
+            self.pos = start;
+            return self.try_item(indent);
+        }
+        let len = self.argument(info)?;
+        let content = self.take(len)?;
+        if content.is_empty() {
+            self.out.push_str("h''");
+            return Ok(());
+        }
+
+        let mut inner = Printer::new(content);
+        inner.try_item(indent).ok();
+        if inner.error.is_some() || inner.pos != content.len() {
+            // not CBOR after all, show it as plain bytes
+            write!(self.out, "h'{}'", hex::encode(content)).unwrap();
+        } else {
+            write!(self.out, "<< {} >>", inner.out).unwrap();
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8]> {
+        let end = (len as usize)
+            .checked_add(self.pos)
+            .filter(|end| len <= usize::MAX as u64 && *end <= self.bytes.len())
+            .ok_or_else(|| Malformed(format!("unexpected end of input, {} bytes expected", len)))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn head(&mut self) -> Result<(u8, u8)> {
+        let b = self.take(1)?[0];
+        Ok((b >> 5, b & 0x1f))
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64> {
+        let len = match info {
+            0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(Malformed(format!("reserved additional info {}", info))),
+        };
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn at_break(&self) -> bool {
+        self.bytes.get(self.pos) == Some(&0xff)
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        for _ in 0..indent {
+            self.out.push_str("  ");
+        }
+    }
+
+    fn try_item(&mut self, indent: usize) -> Result<()> {
+        if indent > MAX_DEPTH {
+            return Err(Malformed(String::from("nesting too deep")));
+        }
+
+        let (major, info) = self.head()?;
+        match (major, info) {
+            (0, _) => {
+                let n = self.argument(info)?;
+                write!(self.out, "{}", n).unwrap();
+            }
+            (1, _) => {
+                let n = self.argument(info)?;
+                write!(self.out, "{}", -1 - n as i128).unwrap();
+            }
+            (2, 31) | (3, 31) => {
+                self.out.push_str("(_ ");
+                let mut first = true;
+                while !self.at_break() {
+                    if !first {
+                        self.out.push_str(", ");
+                    }
+                    first = false;
+                    let (chunk_major, chunk_info) = self.head()?;
+                    if chunk_major != major || chunk_info == 31 {
+                        return Err(Malformed(String::from("invalid indefinite length chunk")));
+                    }
+                    let len = self.argument(chunk_info)?;
+                    self.string(major, len)?;
+                }
+                self.pos += 1;
+                self.out.push(')');
+            }
+            (2, _) | (3, _) => {
+                let len = self.argument(info)?;
+                self.string(major, len)?;
+            }
+            (4, _) | (5, _) => {
+                let (open, close) = if major == 4 { ('[', ']') } else { ('{', '}') };
+                self.out.push(open);
+                let count = if info == 31 {
+                    self.out.push_str("_ ");
+                    None
+                } else {
+                    Some(self.argument(info)?)
+                };
+
+                let mut i = 0u64;
+                loop {
+                    match count {
+                        Some(count) if i == count => break,
+                        None if self.at_break() => {
+                            self.pos += 1;
+                            break;
+                        }
+                        _ => {}
+                    }
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline(indent + 1);
+                    self.try_item(indent + 1)?;
+                    if major == 5 {
+                        self.out.push_str(": ");
+                        self.try_item(indent + 1)?;
+                    }
+                    i += 1;
+                }
+                if i > 0 {
+                    self.newline(indent);
+                }
+                self.out.push(close);
+            }
+            (6, _) => {
+                let tag = self.argument(info)?;
+                write!(self.out, "{}(", tag).unwrap();
+                self.try_item(indent)?;
+                self.out.push(')');
+            }
+            (7, 20) => self.out.push_str("false"),
+            (7, 21) => self.out.push_str("true"),
+            (7, 22) => self.out.push_str("null"),
+            (7, 23) => self.out.push_str("undefined"),
+            (7, 24) => {
+                let n = self.argument(info)?;
+                write!(self.out, "simple({})", n).unwrap();
+            }
+            (7, 25) => {
+                let bits = self.argument(info)? as u16;
+                self.float(half_to_f64(bits));
+            }
+            (7, 26) => {
+                let bits = self.argument(info)? as u32;
+                self.float(f32::from_bits(bits) as f64);
+            }
+            (7, 27) => {
+                let bits = self.argument(info)?;
+                self.float(f64::from_bits(bits));
+            }
+            (7, 31) => return Err(Malformed(String::from("unexpected break"))),
+            (7, 0..=19) => write!(self.out, "simple({})", info).unwrap(),
+            _ => return Err(Malformed(format!("reserved additional info {}", info))),
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, major: u8, len: u64) -> Result<()> {
+        let content = self.take(len)?;
+        if major == 2 {
+            write!(self.out, "h'{}'", hex::encode(content)).unwrap();
+            return Ok(());
+        }
+        match std::str::from_utf8(content) {
+            Ok(text) => {
+                self.out.push('"');
+                for c in text.chars() {
+                    match c {
+                        '"' => self.out.push_str("\\\""),
+                        '\\' => self.out.push_str("\\\\"),
+                        c if (c as u32) < 0x20 => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                        c => self.out.push(c),
+                    }
+                }
+                self.out.push('"');
+            }
+            Err(_) => write!(self.out, "/ invalid UTF-8 text / h'{}'", hex::encode(content)).unwrap(),
+        }
+        Ok(())
+    }
+
+    fn float(&mut self, f: f64) {
+        if f.is_nan() {
+            self.out.push_str("NaN");
+        } else if f.is_infinite() {
+            self.out.push_str(if f > 0.0 { "Infinity" } else { "-Infinity" });
+        } else {
+            // Debug keeps the fraction of integral values ("1.0") and switches to exponents
+            write!(self.out, "{:?}", f).unwrap();
+        }
+    }
+}
+
+fn half_to_f64(bits: u16) -> f64 {
+    let exp = (bits >> 10) & 0x1f;
+    let mant = (bits & 0x3ff) as f64;
+    let val = match exp {
+        0 => mant * 2f64.powi(-24),
+        31 if mant == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mant + 1024.0) * 2f64.powi(exp as i32 - 25),
+    };
+    if bits & 0x8000 != 0 {
+        -val
+    } else {
+        val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc8949_examples() {
+        // RFC 8949 appendix A
+        assert_eq!(cbor_diag(&hex::decode("1903e8").unwrap()), "1000");
+        assert_eq!(cbor_diag(&hex::decode("3863").unwrap()), "-100");
+        assert_eq!(cbor_diag(&hex::decode("f93c00").unwrap()), "1.0");
+        assert_eq!(cbor_diag(&hex::decode("f97bff").unwrap()), "65504.0");
+        assert_eq!(cbor_diag(&hex::decode("fb7e37e43c8800759c").unwrap()), "1e300");
+        assert_eq!(cbor_diag(&hex::decode("f9fc00").unwrap()), "-Infinity");
+        assert_eq!(cbor_diag(&hex::decode("c11a514b67b0").unwrap()), "1(1363896240)");
+        assert_eq!(cbor_diag(&hex::decode("4401020304").unwrap()), "h'01020304'");
+        assert_eq!(cbor_diag(&hex::decode("62225c").unwrap()), "\"\\\"\\\\\"");
+        assert_eq!(cbor_diag(&hex::decode("f6").unwrap()), "null");
+        assert_eq!(cbor_diag(&hex::decode("5f42010243030405ff").unwrap()), "(_ h'0102', h'030405')");
+        assert_eq!(
+            cbor_diag(&hex::decode("a201020304").unwrap()),
+            "{\n  1: 2,\n  3: 4\n}"
+        );
+        assert_eq!(cbor_diag(&hex::decode("9fff").unwrap()), "[_ ]");
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert_eq!(
+            cbor_diag(&hex::decode("820102").unwrap()[..2]),
+            "[\n  1,\n   / error: unexpected end of input, 1 bytes expected at offset 2 /"
+        );
+        assert!(cbor_diag(&hex::decode("0102").unwrap()).ends_with("/ error: 1 trailing bytes at offset 1 /"));
+        assert!(cbor_diag(&[0x81; 200]).contains("nesting too deep"));
+    }
+
+    #[test]
+    fn test_cose_sign1_embeds_payload() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let diag = cose_sign1_diag(ad_blob);
+
+        assert!(diag.starts_with("[\n  / protected / << {\n    1: -35\n  } >>,"));
+        assert!(diag.contains("\"module_id\": \"i-"));
+        assert!(diag.contains("\"digest\": \"SHA384\""));
+        assert!(!diag.contains("error"));
+    }
+}
@@ -0,0 +1,521 @@
+//! Rich error reports for [`NitroAdError`] through [`miette::Diagnostic`]
+//!
+//! Every error carries a stable diagnostic code, names the document field or
+//! certificate at fault and suggests a remediation.
+
+use std::fmt::Display;
+
+#[cfg(feature = "openssl")]
+use aws_nitro_enclaves_cose::error::COSEError;
+use miette::{Diagnostic, Severity};
+
+use crate::error::NitroAdError;
+use crate::policy::DocumentField;
+
+static ATTESTATION_PROCESS_URL: &str =
+    "https://github.com/aws/aws-nitro-enclaves-nsm-api/blob/main/docs/attestation_process.md";
+
+impl NitroAdError {
+    /// Name of the document field or certificate which caused the failure
+    pub fn culprit(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(_) => "COSE_Sign1 envelope",
+            NitroAdError::MalformedCoseHeader(_)
+            | NitroAdError::BadSignatureLength(_)
+            | NitroAdError::UnknownCriticalHeader(_)
+            | NitroAdError::InvalidSignature => "COSE_Sign1 envelope",
+            NitroAdError::CBORError(_) => "payload",
+            NitroAdError::IoError(_) | NitroAdError::DocumentTooLarge { .. } => "document input",
+            NitroAdError::EmptyModuleId => "payload field 'module_id'",
+            NitroAdError::UnsupportedDigest { .. } => "payload field 'digest'",
+            NitroAdError::TimestampOutOfRange { .. } => "payload field 'timestamp'",
+            NitroAdError::BadPcrCount(_)
+            | NitroAdError::MissingPcr(_)
+            | NitroAdError::BadPcrLength { .. }
+            | NitroAdError::PcrMismatch(_)
+            | NitroAdError::NoMatchingProfile
+            | NitroAdError::DebugModeRejected => "payload field 'pcrs'",
+            NitroAdError::DocumentTooOld { .. } => "payload field 'timestamp'",
+            NitroAdError::NonceMismatch | NitroAdError::MissingField(DocumentField::Nonce) => {
+                "payload field 'nonce'"
+            }
+            NitroAdError::MissingField(DocumentField::UserData) => "payload field 'user_data'",
+            NitroAdError::MissingField(DocumentField::PublicKey) => "payload field 'public_key'",
+            NitroAdError::UserDataMismatch
+            | NitroAdError::KeyBindingMismatch
+            | NitroAdError::MissingUserData
+            | NitroAdError::InvalidUserData(_) => {
+                "payload field 'user_data'"
+            }
+            NitroAdError::MissingAttestationExtension => "attested certificate",
+            NitroAdError::ChallengeExpired { .. } | NitroAdError::BadChallengeLength(_) => {
+                "challenge"
+            }
+            NitroAdError::UnsupportedPublicKey => "payload field 'public_key'",
+            NitroAdError::BadKeyLength(_) => "derived key",
+            NitroAdError::EnvelopeError => "envelope",
+            NitroAdError::EmptyCaBundle => "payload field 'cabundle'",
+            NitroAdError::VerificationError(_) => "certificate chain",
+            NitroAdError::InvalidRootCertificate(_) => "trusted root certificate",
+            NitroAdError::X509Error(_)
+            | NitroAdError::InvalidSigningKey(_)
+            | NitroAdError::TrailingCertificateData
+            | NitroAdError::BadCertificateVersion => "payload field 'certificate'",
+            NitroAdError::SerializationError(_) => "JSON output",
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(_) => "attestation result signing key",
+            NitroAdError::UnsupportedSigningKey => "attestation result signing key",
+            NitroAdError::MalformedToken(_)
+            | NitroAdError::InvalidTokenSignature
+            | NitroAdError::TokenExpired { .. } => "attestation result token",
+            NitroAdError::InvalidReportSignature => "signed verification report",
+            NitroAdError::ArchiveMismatch | NitroAdError::UnsupportedArchiveVersion(_) => {
+                "attestation archive"
+            }
+            NitroAdError::UnsupportedReportVersion(_) => "verification report",
+            NitroAdError::UnsupportedBundleVersion(_) => "attestation bundle",
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(_) => "transparency log",
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(_) => "Nitro Secure Module",
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(_) => "TLS configuration",
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(_) => "YAML output",
+            NitroAdError::HandshakeError(_) | NitroAdError::KeyConfirmationFailed => {
+                "attestation handshake"
+            }
+            NitroAdError::RotationError(_) => "payload field 'user_data'",
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(_) => "sealed message",
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(_) => "KMS CiphertextForRecipient",
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(_) => "KMS request",
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(_) => "SSH certificate",
+            NitroAdError::InvalidSpiffeId(_) => "SPIFFE ID",
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(_) => "QUIC connection",
+            NitroAdError::InvalidConfig(_) => "configuration",
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(_) => "SSM request",
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(_) => "verification service",
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { .. } => "attestation document",
+            NitroAdError::NitroCliError(_) => "nitro-cli",
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(_) => "X-Nitro-Attestation header",
+            NitroAdError::ProofError(_) => "on-chain proof",
+        }
+    }
+
+    fn diagnostic_code(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(_) => "nitro_ad::cose",
+            NitroAdError::MalformedCoseHeader(_) => "nitro_ad::cose_header",
+            NitroAdError::CBORError(_) => "nitro_ad::cbor",
+            NitroAdError::IoError(_) => "nitro_ad::io",
+            NitroAdError::DocumentTooLarge { .. } => "nitro_ad::document_size",
+            NitroAdError::VerificationError(_) => "nitro_ad::chain",
+            NitroAdError::SerializationError(_) => "nitro_ad::serialization",
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(_) => "nitro_ad::yaml",
+            NitroAdError::X509Error(_) => "nitro_ad::x509",
+            NitroAdError::BadSignatureLength(_) => "nitro_ad::signature_length",
+            NitroAdError::UnknownCriticalHeader(_) => "nitro_ad::critical_header",
+            NitroAdError::EmptyModuleId => "nitro_ad::module_id",
+            NitroAdError::UnsupportedDigest { .. } => "nitro_ad::digest",
+            NitroAdError::TimestampOutOfRange { .. } => "nitro_ad::timestamp",
+            NitroAdError::BadPcrCount(_) => "nitro_ad::pcr_count",
+            NitroAdError::MissingPcr(_) => "nitro_ad::missing_pcr",
+            NitroAdError::BadPcrLength { .. } => "nitro_ad::pcr_length",
+            NitroAdError::EmptyCaBundle => "nitro_ad::cabundle",
+            NitroAdError::TrailingCertificateData => "nitro_ad::certificate_trailing_data",
+            NitroAdError::BadCertificateVersion => "nitro_ad::certificate_version",
+            NitroAdError::InvalidSignature => "nitro_ad::signature",
+            NitroAdError::InvalidRootCertificate(_) => "nitro_ad::root_certificate",
+            NitroAdError::InvalidSigningKey(_) => "nitro_ad::signing_key",
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(_) => "nitro_ad::result_signing",
+            NitroAdError::UnsupportedSigningKey => "nitro_ad::result_key",
+            NitroAdError::MalformedToken(_) => "nitro_ad::token",
+            NitroAdError::InvalidTokenSignature => "nitro_ad::token_signature",
+            NitroAdError::TokenExpired { .. } => "nitro_ad::token_expired",
+            NitroAdError::InvalidReportSignature => "nitro_ad::report_signature",
+            NitroAdError::ArchiveMismatch => "nitro_ad::archive_mismatch",
+            NitroAdError::UnsupportedArchiveVersion(_) => "nitro_ad::archive_version",
+            NitroAdError::UnsupportedReportVersion(_) => "nitro_ad::report_version",
+            NitroAdError::UnsupportedBundleVersion(_) => "nitro_ad::bundle_version",
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(_) => "nitro_ad::transparency_log",
+            NitroAdError::PcrMismatch(_) => "nitro_ad::pcr_mismatch",
+            NitroAdError::NonceMismatch => "nitro_ad::nonce_mismatch",
+            NitroAdError::UserDataMismatch => "nitro_ad::user_data_mismatch",
+            NitroAdError::KeyBindingMismatch => "nitro_ad::key_binding",
+            NitroAdError::MissingAttestationExtension => "nitro_ad::attestation_extension",
+            NitroAdError::ChallengeExpired { .. } => "nitro_ad::challenge_expired",
+            NitroAdError::BadChallengeLength(_) => "nitro_ad::challenge_length",
+            NitroAdError::UnsupportedPublicKey => "nitro_ad::public_key",
+            NitroAdError::BadKeyLength(_) => "nitro_ad::key_length",
+            NitroAdError::EnvelopeError => "nitro_ad::envelope",
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(_) => "nitro_ad::nsm",
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(_) => "nitro_ad::tls",
+            NitroAdError::HandshakeError(_) => "nitro_ad::handshake",
+            NitroAdError::KeyConfirmationFailed => "nitro_ad::key_confirmation",
+            NitroAdError::RotationError(_) => "nitro_ad::rotation",
+            NitroAdError::NoMatchingProfile => "nitro_ad::no_matching_profile",
+            NitroAdError::DocumentTooOld { .. } => "nitro_ad::document_too_old",
+            NitroAdError::DebugModeRejected => "nitro_ad::debug_mode",
+            NitroAdError::MissingField(_) => "nitro_ad::missing_field",
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(_) => "nitro_ad::hpke",
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(_) => "nitro_ad::kms_recipient",
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(_) => "nitro_ad::kms",
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(_) => "nitro_ad::ssh",
+            NitroAdError::InvalidSpiffeId(_) => "nitro_ad::spiffe",
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(_) => "nitro_ad::quic",
+            NitroAdError::InvalidConfig(_) => "nitro_ad::config",
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(_) => "nitro_ad::ssm",
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(_) => "nitro_ad::remote",
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { .. } => "nitro_ad::remote::rejected",
+            NitroAdError::NitroCliError(_) => "nitro_ad::nitro_cli",
+            NitroAdError::MissingUserData => "nitro_ad::missing_user_data",
+            NitroAdError::InvalidUserData(_) => "nitro_ad::invalid_user_data",
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(_) => "nitro_ad::header",
+            NitroAdError::ProofError(_) => "nitro_ad::onchain_proof",
+        }
+    }
+
+    fn remediation(&self) -> String {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(COSEError::SerializationError(_)) => String::from(
+                "pass the raw attestation document bytes as returned by the NSM; \
+                 base64/hex encoded documents must be decoded first",
+            ),
+            NitroAdError::CBORError(_) => String::from(
+                "pass the raw attestation document bytes as returned by the NSM; \
+                 base64/hex encoded documents must be decoded first",
+            ),
+            NitroAdError::IoError(_) => String::from(
+                "the document could not be read from its source; retry once it is reachable",
+            ),
+            NitroAdError::DocumentTooLarge { limit } => format!(
+                "documents produced by the Nitro Secure Module are well below {} bytes; \
+                 make sure the input holds a single raw document",
+                limit
+            ),
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(_) => String::from(
+                "the document must be an untagged COSE_Sign1 structure signed with ES384",
+            ),
+            NitroAdError::MalformedCoseHeader(_) => String::from(
+                "the document must be an untagged COSE_Sign1 structure signed with ES384",
+            ),
+            NitroAdError::BadSignatureLength(_) | NitroAdError::InvalidSignature => String::from(
+                "the document was altered after signing or truncated in transit; \
+                 request a fresh document from the enclave",
+            ),
+            NitroAdError::UnknownCriticalHeader(label) => format!(
+                "the document relies on COSE header {:?} which this library does not implement",
+                label
+            ),
+            NitroAdError::EmptyModuleId => String::from(
+                "documents produced by the Nitro Secure Module always name the issuing enclave",
+            ),
+            NitroAdError::UnsupportedDigest { got } => format!(
+                "only SHA384 digests are produced by the Nitro Secure Module, got {:?}",
+                got
+            ),
+            NitroAdError::TimestampOutOfRange { .. } => String::from(
+                "check the verification time passed in; documents must be issued after \
+                 2020-01-01 and not later than one day after the verification time",
+            ),
+            NitroAdError::BadPcrCount(_) | NitroAdError::MissingPcr(_) => String::from(
+                "the 'pcrs' map must hold consecutive PCR indexes starting at 0",
+            ),
+            NitroAdError::BadPcrLength { index, .. } => format!(
+                "PCR{} must be a SHA256, SHA384 or SHA512 sized measurement",
+                index
+            ),
+            NitroAdError::EmptyCaBundle => String::from(
+                "'cabundle' must hold the chain from the AWS root down to the signing certificate",
+            ),
+            NitroAdError::VerificationError(webpki::Error::CertExpired { .. })
+            | NitroAdError::VerificationError(webpki::Error::CertNotValidYet { .. }) => String::from(
+                "signing certificates are only valid for a few hours; verify at a time within \
+                 the certificate validity period or request a fresh document",
+            ),
+            NitroAdError::VerificationError(webpki::Error::UnknownIssuer) => String::from(
+                "the chain doesn't lead to the supplied root; make sure the AWS Nitro Enclaves \
+                 root certificate is used",
+            ),
+            NitroAdError::VerificationError(_) => String::from(
+                "the 'certificate' and 'cabundle' fields don't form a valid chain to the root",
+            ),
+            NitroAdError::InvalidRootCertificate(_) => String::from(
+                "pass the DER encoded AWS Nitro Enclaves root certificate; \
+                 PEM files must be converted to DER first",
+            ),
+            NitroAdError::X509Error(_)
+            | NitroAdError::TrailingCertificateData
+            | NitroAdError::BadCertificateVersion => String::from(
+                "'certificate' must be a single DER encoded X.509 v3 certificate",
+            ),
+            NitroAdError::InvalidSigningKey(_) => String::from(
+                "the signing certificate must carry a P-384 public key",
+            ),
+            NitroAdError::SerializationError(_) => String::from(
+                "the document was verified, but could not be rendered as JSON",
+            ),
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(_) => String::from(
+                "the document was verified, but could not be rendered as YAML",
+            ),
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(_) => String::from(
+                "attestation results are signed with ES384; pass a P-384 private key",
+            ),
+            NitroAdError::UnsupportedSigningKey => String::from(
+                "attestation results are signed with ES384; pass a P-384 private key",
+            ),
+            NitroAdError::MalformedToken(_) => String::from(
+                "pass the compact serialized JWT as issued by AttestationToken::sign",
+            ),
+            NitroAdError::InvalidTokenSignature => String::from(
+                "the token was altered or issued by another verifier; check the verifier public key",
+            ),
+            NitroAdError::TokenExpired { .. } => String::from(
+                "request a fresh attestation result from the verifier",
+            ),
+            NitroAdError::InvalidReportSignature => String::from(
+                "the report was altered or signed by another verifier; check verifier_id and its key",
+            ),
+            NitroAdError::ArchiveMismatch => String::from(
+                "the archive was altered after it was written; its recorded outcome can't be trusted",
+            ),
+            NitroAdError::UnsupportedArchiveVersion(_) => String::from(
+                "the archive was written by a newer version of this library",
+            ),
+            NitroAdError::UnsupportedReportVersion(_) => String::from(
+                "the report was encoded by a newer version of this library",
+            ),
+            NitroAdError::UnsupportedBundleVersion(_) => String::from(
+                "the bundle was written by a newer version of this library",
+            ),
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(_) => String::from(
+                "the report is valid, but the log could not be reached or rejected the entry; retry later",
+            ),
+            NitroAdError::PcrMismatch(index) => format!(
+                "the enclave runs a different image or configuration than PCR{} of the policy \
+                 describes; update the policy after rebuilding the enclave image",
+                index
+            ),
+            NitroAdError::NonceMismatch => String::from(
+                "the document was not issued for this challenge; it may be replayed, \
+                 request a fresh document with the current nonce",
+            ),
+            NitroAdError::UserDataMismatch => String::from(
+                "the enclave bound different data to the document than the policy expects",
+            ),
+            NitroAdError::KeyBindingMismatch => String::from(
+                "the document was issued for another key; it may be copied from another \
+                 certificate, reconnect so the enclave presents a certificate of its own",
+            ),
+            NitroAdError::MissingAttestationExtension => String::from(
+                "the peer is not an attested enclave, or issued its certificate without \
+                 the attestation document extension",
+            ),
+            NitroAdError::ChallengeExpired { .. } => String::from(
+                "the enclave answered too late; issue a new challenge, or a longer TTL if \
+                 documents take longer to arrive",
+            ),
+            NitroAdError::BadChallengeLength(_) => String::from(
+                "challenges are 32 to 64 bytes long",
+            ),
+            NitroAdError::UnsupportedPublicKey => String::from(
+                "the enclave must request its document with the DER encoded SubjectPublicKeyInfo \
+                 of a P-384 key as public_key",
+            ),
+            NitroAdError::BadKeyLength(_) => String::from(
+                "HKDF-SHA384 derives at most 12240 bytes",
+            ),
+            NitroAdError::EnvelopeError => String::from(
+                "the envelope is truncated, was altered, or its aad differs from the sealing \
+                 side's; envelopes only open with the attested key of the document sealed to",
+            ),
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(_) => String::from(
+                "the NSM rejected the request; keep user_data, nonce and public_key within \
+                 1024 bytes each and retry",
+            ),
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(_) => String::from(
+                "rustls rejected the attested certificate or configuration; check the protocol \
+                 versions and keys passed to it",
+            ),
+            NitroAdError::HandshakeError(_) => String::from(
+                "the peer sent an unexpected or malformed handshake message; both sides must \
+                 run the same protocol version and pass messages in order",
+            ),
+            NitroAdError::KeyConfirmationFailed => String::from(
+                "the peer doesn't hold the key of its document or saw other messages; the \
+                 connection may be intercepted, restart the handshake",
+            ),
+            NitroAdError::RotationError(_) => String::from(
+                "the document isn't the successor of the latest one of the chain: a rotation was \
+                 skipped or it came from another enclave or key; fall back to a full handshake",
+            ),
+            NitroAdError::NoMatchingProfile => String::from(
+                "the enclave runs an image no profile describes; register the measurements of \
+                 its nitro-cli build-enclave output if it is trusted",
+            ),
+            NitroAdError::DocumentTooOld { .. } => String::from(
+                "request a fresh document; if enclave and verifier clocks drift, raise the \
+                 policy's maximum age",
+            ),
+            NitroAdError::DebugModeRejected => String::from(
+                "the parent instance can read the memory of debug-mode enclaves; run the enclave \
+                 without --debug-mode",
+            ),
+            NitroAdError::MissingField(field) => format!(
+                "have the enclave pass {} with its attestation request",
+                field.name()
+            ),
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(_) => String::from(
+                "the message was sealed to another document or key, or altered in transit; \
+                 pass the same aad on both sides and seal to the enclave's current document",
+            ),
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(_) => String::from(
+                "decrypt with the RecipientKey whose recipient() went into the KMS request, and \
+                 pass CiphertextForRecipient as returned, base64 decoded",
+            ),
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(_) => String::from(
+                "check the enclave's vsock proxy to KMS, its credentials and that the key \
+                 policy's kms:RecipientAttestation conditions match the enclave image",
+            ),
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(_) => String::from(
+                "give the issuer at least one principal and a CA key it can sign with, and keep \
+                 the validity window within the certificate's time range",
+            ),
+            NitroAdError::InvalidSpiffeId(_) => String::from(
+                "use a trust domain of lowercase letters, digits, '.', '-' and '_' without the \
+                 spiffe:// scheme; module IDs with other characters need PathSource::Pcr0",
+            ),
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(_) => String::from(
+                "check that the server runs quic::accept, or sends its attestation on the first \
+                 unidirectional stream, and that UDP reaches it",
+            ),
+            NitroAdError::InvalidConfig(_) => String::from(
+                "expected PCRs are hex strings keyed PCR0, PCR1, ...; check the named setting \
+                 against the documentation of the module reading it",
+            ),
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(_) => String::from(
+                "check that the parameter exists in the function's region and that its role \
+                 allows ssm:GetParameter, and kms:Decrypt for SecureString parameters",
+            ),
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(_) => String::from(
+                "check the service URL and that it answers POSTed VerifyRequest JSON with \
+                 VerifyResponse JSON",
+            ),
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { code: Some(code), .. } => format!(
+                "the service's NitroAdError code {} names the failed check; look it up in \
+                 ERROR_CODES",
+                code
+            ),
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { code: None, .. } => {
+                String::from("send the document and nonce base64 encoded")
+            }
+            NitroAdError::NitroCliError(_) => String::from(
+                "run on the parent instance, with aws-nitro-enclaves-cli installed and the user \
+                 in the ne group",
+            ),
+            NitroAdError::MissingUserData => String::from(
+                "have the enclave pass user_data with its attestation request",
+            ),
+            NitroAdError::InvalidUserData(_) => String::from(
+                "check that the enclave encodes user_data the way the verifier decodes it",
+            ),
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(_) => String::from(
+                "send the raw document base64 encoded, or gzip compressed, base64 encoded and \
+                 prefixed with 'gzip;'",
+            ),
+            NitroAdError::ProofError(_) => String::from(
+                "only documents laid out like those of the Nitro Secure Module, with ES384 \
+                 signed P-384 certificates, can be proven on-chain",
+            ),
+        }
+    }
+}
+
+impl Diagnostic for NitroAdError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(self.diagnostic_code()))
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(Severity::Error)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(format!(
+            "{} is at fault: {}",
+            self.culprit(),
+            self.remediation()
+        )))
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ATTESTATION_PROCESS_URL))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_points_at_field() {
+        let err = NitroAdError::BadPcrLength { index: 4, len: 20 };
+        assert_eq!(Diagnostic::code(&err).unwrap().to_string(), "nitro_ad::pcr_length");
+
+        let help = err.help().unwrap().to_string();
+        assert!(help.starts_with("payload field 'pcrs' is at fault"));
+        assert!(help.contains("PCR4"));
+    }
+
+    #[test]
+    fn test_diagnostic_chain_remediation() {
+        let err = NitroAdError::from(webpki::Error::CertExpired {
+            time: pki_types::UnixTime::since_unix_epoch(std::time::Duration::from_secs(1714967200)),
+            not_after: pki_types::UnixTime::since_unix_epoch(std::time::Duration::from_secs(1614974400)),
+        });
+        assert_eq!(err.culprit(), "certificate chain");
+        assert!(err.help().unwrap().to_string().contains("fresh document"));
+    }
+}
@@ -0,0 +1,167 @@
+//! Differences between two attestation documents
+//!
+//! [`NitroAdDoc::diff`] compares PCRs, `module_id`, `timestamp` and the
+//! certificate chain, e.g. to find out why a new enclave build no longer matches
+//! a policy written for an older one.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::output::CertificateOutput;
+use crate::NitroAdDoc;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DocumentDiff {
+    /// Old and new `module_id`, if they differ
+    pub module_id: Option<(String, String)>,
+    /// New minus old `timestamp`
+    pub timestamp_delta_ms: i64,
+    /// Changed, added and removed PCRs, ordered by index
+    pub pcrs: Vec<PcrChange>,
+    /// Changed certificates, `cabundle` positions followed by the signing certificate
+    pub certificates: Vec<CertificateChange>,
+}
+
+/// Hex encoded values, `None` where the document lacks the PCR
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PcrChange {
+    pub index: u8,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Certificate subjects, `None` where the chain is shorter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CertificateChange {
+    pub position: usize,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl NitroAdDoc<'_> {
+    /// Changes from `self` to `other`
+    pub fn diff(&self, other: &NitroAdDoc) -> DocumentDiff {
+        let (old, new) = (self.payload(), other.payload());
+
+        let indexes: BTreeSet<u8> = old.pcrs.keys().chain(new.pcrs.keys()).copied().collect();
+        let pcrs = indexes
+            .into_iter()
+            .filter_map(|index| {
+                let (o, n) = (old.pcrs.get(&index), new.pcrs.get(&index));
+                (o != n).then(|| PcrChange {
+                    index,
+                    old: o.map(hex::encode),
+                    new: n.map(hex::encode),
+                })
+            })
+            .collect();
+
+        let old_chain: Vec<_> = old.cabundle.iter().chain(Some(&old.certificate)).collect();
+        let new_chain: Vec<_> = new.cabundle.iter().chain(Some(&new.certificate)).collect();
+        let certificates = (0..old_chain.len().max(new_chain.len()))
+            .filter_map(|position| {
+                let (o, n) = (old_chain.get(position), new_chain.get(position));
+                (o != n).then(|| CertificateChange {
+                    position,
+                    old: o.map(|der| subject(der)),
+                    new: n.map(|der| subject(der)),
+                })
+            })
+            .collect();
+
+        DocumentDiff {
+            module_id: (old.module_id != new.module_id)
+                .then(|| (old.module_id.clone(), new.module_id.clone())),
+            timestamp_delta_ms: new.timestamp.timestamp_millis() - old.timestamp.timestamp_millis(),
+            pcrs,
+            certificates,
+        }
+    }
+}
+
+fn subject(der: &[u8]) -> String {
+    CertificateOutput::from_der(der)
+        .map(|cert| cert.subject)
+        .unwrap_or_else(|_| String::from("<unparsable certificate>"))
+}
+
+impl DocumentDiff {
+    /// Documents differ only in their timestamps
+    pub fn is_same_enclave(&self) -> bool {
+        self.module_id.is_none() && self.pcrs.is_empty()
+    }
+}
+
+impl fmt::Display for DocumentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |val: &Option<String>| val.clone().unwrap_or_else(|| String::from("-"));
+
+        if let Some((old, new)) = &self.module_id {
+            writeln!(f, "module_id: {} -> {}", old, new)?;
+        }
+        writeln!(f, "timestamp: {:+} ms", self.timestamp_delta_ms)?;
+        for pcr in &self.pcrs {
+            writeln!(f, "PCR{}: {} -> {}", pcr.index, show(&pcr.old), show(&pcr.new))?;
+        }
+        for cert in &self.certificates {
+            writeln!(
+                f,
+                "certificate {}: {} -> {}",
+                cert.position,
+                show(&cert.old),
+                show(&cert.new)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Bytes;
+
+    fn test_doc() -> NitroAdDoc<'static> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap()
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let diff = test_doc().diff(&test_doc());
+        assert!(diff.is_same_enclave());
+        assert_eq!(diff.timestamp_delta_ms, 0);
+        assert!(diff.certificates.is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_changes() {
+        let old = test_doc();
+        let mut new = test_doc();
+        new.payload_ref.pcrs.insert(2, Bytes::from(vec![1u8; 48]));
+        new.payload_ref.pcrs.remove(&15);
+        new.payload_ref.timestamp = old.payload().timestamp + chrono::Duration::seconds(5);
+        new.payload_ref.cabundle.pop();
+
+        let diff = old.diff(&new);
+        assert!(!diff.is_same_enclave());
+        assert_eq!(diff.timestamp_delta_ms, 5000);
+        assert_eq!(diff.pcrs.len(), 2);
+        assert_eq!(diff.pcrs[0].index, 2);
+        assert_eq!(diff.pcrs[1].new, None);
+
+        // the signing certificate moved up one position in the chain
+        let last = old.payload().cabundle.len();
+        assert_eq!(diff.certificates.len(), 2);
+        assert_eq!(diff.certificates[1].position, last);
+        assert_eq!(diff.certificates[1].new, None);
+
+        let text = diff.to_string();
+        assert!(text.contains("PCR15: "));
+        assert!(text.contains("timestamp: +5000 ms"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Entity Attestation Token claims (draft-ietf-rats-eat)
+//!
+//! [`EatClaims`] maps a payload to the EAT nonce, measurements, debug status and
+//! issued-at claims, in their JSON ([`EatClaims::to_json`]) or CBOR
+//! ([`EatClaims::to_cbor`], integer claim keys) form. There is no registered
+//! content format for Nitro PCRs, so each measurement is a PCR index and value
+//! pair, `{"pcr": n, "value": hex}` in JSON and `[n, bytes]` in CBOR.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value as CborValue;
+use serde_with::base64::{Base64, UrlSafe};
+use serde_with::formats::Unpadded;
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+
+use crate::{NitroAdDocPayload, NitroAdError};
+
+// CBOR claim keys
+const EAT_IAT: i128 = 6;
+const EAT_NONCE: i128 = 10;
+const EAT_DBGSTAT: i128 = 263;
+const EAT_MEASUREMENTS: i128 = 273;
+
+/// EAT `dbgstat` claim values which apply to enclaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DebugStatus {
+    Enabled,
+    /// Debug mode is chosen at enclave launch and can't be switched on afterwards
+    DisabledSinceBoot,
+}
+
+impl DebugStatus {
+    fn code(self) -> i128 {
+        match self {
+            DebugStatus::Enabled => 0,
+            DebugStatus::DisabledSinceBoot => 2,
+        }
+    }
+}
+
+/// Value of one PCR, hex encoded in JSON
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub pcr: u8,
+    #[serde_as(as = "Hex")]
+    pub value: Vec<u8>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EatClaims {
+    /// `nonce` field, base64url encoded in JSON
+    #[serde_as(as = "Option<Base64<UrlSafe, Unpadded>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eat_nonce: Option<Vec<u8>>,
+    /// Document `timestamp`, seconds since the unix epoch
+    pub iat: i64,
+    pub dbgstat: DebugStatus,
+    /// PCRs ordered by index
+    pub measurements: Vec<Measurement>,
+}
+
+impl EatClaims {
+    pub fn from_payload(payload: &NitroAdDocPayload) -> Self {
+        let pcrs: BTreeMap<_, _> = payload.pcrs.iter().collect();
+
+        EatClaims {
+            eat_nonce: payload.nonce.as_ref().map(|nonce| nonce.to_vec()),
+            iat: payload.timestamp.timestamp(),
+            dbgstat: if payload.is_debug_mode() {
+                DebugStatus::Enabled
+            } else {
+                DebugStatus::DisabledSinceBoot
+            },
+            measurements: pcrs
+                .into_iter()
+                .map(|(pcr, val)| Measurement {
+                    pcr: *pcr,
+                    value: val.to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// CBOR claims set keyed by the EAT claim integers
+    pub fn to_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        let mut map = BTreeMap::new();
+
+        if let Some(nonce) = &self.eat_nonce {
+            map.insert(CborValue::Integer(EAT_NONCE), CborValue::Bytes(nonce.clone()));
+        }
+        map.insert(CborValue::Integer(EAT_IAT), CborValue::Integer(self.iat as i128));
+        map.insert(
+            CborValue::Integer(EAT_DBGSTAT),
+            CborValue::Integer(self.dbgstat.code()),
+        );
+
+        let measurements = self
+            .measurements
+            .iter()
+            .map(|m| {
+                CborValue::Array(vec![
+                    CborValue::Integer(m.pcr as i128),
+                    CborValue::Bytes(m.value.clone()),
+                ])
+            })
+            .collect();
+        map.insert(
+            CborValue::Integer(EAT_MEASUREMENTS),
+            CborValue::Array(measurements),
+        );
+
+        Ok(serde_cbor::to_vec(&CborValue::Map(map))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::NitroAdDoc;
+
+    #[test]
+    fn test_eat_claims_from_document() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let claims = doc.to_eat_claims();
+        assert_eq!(claims.dbgstat, DebugStatus::Enabled);
+        assert_eq!(claims.iat, doc.payload().timestamp.timestamp());
+        assert_eq!(claims.measurements.len(), doc.payload().pcrs.len());
+        assert_eq!(claims.measurements[3].pcr, 3);
+
+        let js: serde_json::Value = serde_json::from_str(&claims.to_json()?)?;
+        assert_eq!(js["dbgstat"], "enabled");
+        assert!(js.get("eat_nonce").is_none());
+        assert_eq!(js["measurements"][3]["value"], hex::encode(&doc.payload().pcrs[&3]));
+
+        let with_nonce = EatClaims {
+            eat_nonce: Some(vec![0xfb, 0xff]),
+            ..claims.clone()
+        };
+        let js = with_nonce.to_json()?;
+        assert!(js.contains(r#""eat_nonce":"-_8""#));
+        assert_eq!(serde_json::from_str::<EatClaims>(&js)?, with_nonce);
+
+        let cbor: BTreeMap<i128, CborValue> = serde_cbor::from_slice(&claims.to_cbor()?)?;
+        assert_eq!(cbor[&EAT_DBGSTAT], CborValue::Integer(0));
+        match &cbor[&EAT_MEASUREMENTS] {
+            CborValue::Array(m) => assert_eq!(m.len(), claims.measurements.len()),
+            other => panic!("unexpected measurements {:?}", other),
+        }
+
+        Ok(())
+    }
+}
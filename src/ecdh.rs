@@ -0,0 +1,145 @@
+//! Keys agreed with attested enclaves
+//!
+//! A document whose `public_key` is an ephemeral P-384 key, e.g. of
+//! [`AttestedKey::generate`], lets the verifier agree on keys with the enclave holding
+//! its private key. The verifier calls [`NitroAdDoc::derive_shared_secret`] with a key
+//! pair of its own and sends its public key to the enclave, which derives the same
+//! secret with [`AttestedKey::derive_shared_secret`]. Both derive with HKDF-SHA384
+//! from the ECDH secret, salted with the SHA-384 hash of the document, so the keys are
+//! bound to that attestation.
+
+use p384::ecdh::diffie_hellman;
+use p384::pkcs8::DecodePublicKey;
+use p384::{PublicKey, SecretKey};
+use sha2::Sha384;
+
+use crate::crypto::sha384;
+#[cfg(feature = "nsm")]
+use crate::nsm::AttestedKey;
+use crate::{NitroAdDoc, NitroAdError};
+
+/// Longest output of HKDF-SHA384, 255 hashes
+pub const MAX_SECRET_LEN: usize = 255 * 48;
+
+/// Fills `okm` with HKDF-SHA384 of the ECDH secret of `my_private_key` and
+/// `peer_public_key`, with `salt` and `info`
+pub fn derive_shared_secret(
+    my_private_key: &SecretKey,
+    peer_public_key: &PublicKey,
+    salt: &[u8],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), NitroAdError> {
+    let shared = diffie_hellman(my_private_key.to_nonzero_scalar(), peer_public_key.as_affine());
+    shared
+        .extract::<Sha384>(Some(salt))
+        .expand(info, okm)
+        .map_err(|_| NitroAdError::BadKeyLength(okm.len()))
+}
+
+impl NitroAdDoc<'_> {
+    /// Fills `okm` with a secret shared with the enclave holding the private key of the
+    /// document's `public_key`, for `info` naming its purpose. Fails for documents whose
+    /// certificate chain didn't verify.
+    pub fn derive_shared_secret(
+        &self,
+        my_private_key: &SecretKey,
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), NitroAdError> {
+        let peer = attested_public_key(self)?;
+        derive_shared_secret(my_private_key, &peer, &sha384(self.as_bytes()), info, okm)
+    }
+}
+
+/// `public_key` of `doc`, provided its certificate chain verified
+pub(crate) fn attested_public_key(doc: &NitroAdDoc) -> Result<PublicKey, NitroAdError> {
+    if let Some(e) = doc.verification_error() {
+        return Err(NitroAdError::VerificationError(e));
+    }
+    doc.payload()
+        .public_key
+        .as_deref()
+        .and_then(|key| PublicKey::from_public_key_der(key).ok())
+        .ok_or(NitroAdError::UnsupportedPublicKey)
+}
+
+#[cfg(feature = "nsm")]
+impl AttestedKey {
+    /// Fills `okm` with the secret [`NitroAdDoc::derive_shared_secret`] of this key's
+    /// document derives, for the verifier's DER encoded SubjectPublicKeyInfo
+    /// `peer_public_key`
+    pub fn derive_shared_secret(
+        &self,
+        peer_public_key: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), NitroAdError> {
+        let peer = PublicKey::from_public_key_der(peer_public_key)
+            .map_err(|_| NitroAdError::UnsupportedPublicKey)?;
+        derive_shared_secret(&self.secret_key, &peer, &sha384(&self.document), info, okm)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use p384::pkcs8::EncodePublicKey;
+    use rand_core::OsRng;
+
+    use crate::testing::MockNsm;
+
+    #[test]
+    fn test_derive_shared_secret() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let enclave_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&enclave_key.document, &mock.root_cert(), now)?;
+
+        let verifier_key = SecretKey::random(&mut OsRng);
+        let verifier_public = verifier_key.public_key().to_public_key_der().unwrap();
+        let (mut verifier_secret, mut enclave_secret) = ([0; 32], [0; 32]);
+        doc.derive_shared_secret(&verifier_key, b"test", &mut verifier_secret)?;
+        enclave_key.derive_shared_secret(verifier_public.as_bytes(), b"test", &mut enclave_secret)?;
+        assert_eq!(verifier_secret, enclave_secret);
+
+        let mut other = [0; 32];
+        doc.derive_shared_secret(&verifier_key, b"other purpose", &mut other)?;
+        assert_ne!(other, verifier_secret);
+
+        let mut too_long = vec![0; MAX_SECRET_LEN + 1];
+        assert!(matches!(
+            doc.derive_shared_secret(&verifier_key, b"test", &mut too_long),
+            Err(NitroAdError::BadKeyLength(len)) if len == MAX_SECRET_LEN + 1
+        ));
+        assert!(matches!(
+            enclave_key.derive_shared_secret(b"not a key", b"test", &mut other),
+            Err(NitroAdError::UnsupportedPublicKey)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_without_public_key() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = mock.verified_doc(Default::default(), now)?;
+        let key = SecretKey::random(&mut OsRng);
+        assert!(matches!(
+            doc.derive_shared_secret(&key, b"test", &mut [0; 32]),
+            Err(NitroAdError::UnsupportedPublicKey)
+        ));
+
+        // unverified chains don't count as attested keys
+        let key_doc = AttestedKey::generate(&mock, &mut OsRng, None, None)?.document;
+        let later = now + 400 * 24 * 3600;
+        let doc = NitroAdDoc::from_bytes(&key_doc, &mock.root_cert(), later)?;
+        assert!(doc.verification_error().is_some());
+        assert!(matches!(
+            doc.derive_shared_secret(&key, b"test", &mut [0; 32]),
+            Err(NitroAdError::VerificationError(_))
+        ));
+        Ok(())
+    }
+}
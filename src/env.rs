@@ -0,0 +1,202 @@
+//! Verifier configuration from environment variables
+//!
+//! Containers and Lambda functions are configured through their environment more easily
+//! than through files. [`VerifierPolicy::from_env`] reads the policy of these variables,
+//! [`Verifier::from_env`] the root certificate too:
+//!
+//! | variable | content |
+//! |---|---|
+//! | `NITRO_AD_ROOT_CERT` | path of the DER or PEM root certificate |
+//! | `NITRO_AD_PCR<n>` | hex encoded expected value of PCR `n` |
+//! | `NITRO_AD_MAX_AGE_SECS` | maximum document age in seconds |
+//! | `NITRO_AD_DEBUG_MODE` | `allow` or `reject` documents of debug-mode enclaves |
+//! | `NITRO_AD_REQUIRED_FIELDS` | comma separated `nonce`, `user_data` or `public_key` |
+//!
+//! Unset variables leave their check out, as in the `config` module's policy files,
+//! whose values these variables take. Invalid values are errors. `NITRO_AD_PCRS_*`
+//! variables, such as the SSM parameter of the `lambda` module, aren't PCRs. Variables
+//! not starting with `NITRO_AD_` are ignored, whatever their encoding.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! let verifier = Verifier::from_env()?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+
+use crate::{NitroAdError, Verifier, VerifierPolicy};
+
+/// Prefix of the variables of the module
+static VAR_PREFIX: &str = "NITRO_AD_";
+/// Variable naming the root certificate file
+pub static ROOT_CERT_VAR: &str = "NITRO_AD_ROOT_CERT";
+/// Prefix of the expected PCR variables, followed by the PCR index
+pub static PCR_VAR_PREFIX: &str = "NITRO_AD_PCR";
+/// Variable holding the maximum document age in seconds
+pub static MAX_AGE_VAR: &str = "NITRO_AD_MAX_AGE_SECS";
+/// Variable holding the [`DebugMode`](crate::policy::DebugMode), `allow` or `reject`
+pub static DEBUG_MODE_VAR: &str = "NITRO_AD_DEBUG_MODE";
+/// Variable listing [`DocumentField`](crate::policy::DocumentField)s, comma separated
+pub static REQUIRED_FIELDS_VAR: &str = "NITRO_AD_REQUIRED_FIELDS";
+
+impl VerifierPolicy {
+    /// Policy of the variables of the [`env`](crate::env) module
+    pub fn from_env() -> Result<Self, NitroAdError> {
+        policy_from_vars(vars()?, VerifierPolicy::new())
+    }
+}
+
+impl Verifier {
+    /// Verifier trusting the root certificate of `NITRO_AD_ROOT_CERT`, with
+    /// [`VerifierPolicy::from_env`]
+    pub fn from_env() -> Result<Self, NitroAdError> {
+        let root = std::env::var_os(ROOT_CERT_VAR)
+            .ok_or_else(|| NitroAdError::InvalidConfig(format!("{} is not set", ROOT_CERT_VAR)))?;
+        let policy = VerifierPolicy::from_env()?;
+        Ok(Verifier::new(read_root_cert(root)?).with_policy(policy))
+    }
+}
+
+/// The `NITRO_AD_*` variables of the process. Fails for values which aren't Unicode,
+/// where [`std::env::vars`] panics for any variable.
+pub(crate) fn vars() -> Result<impl Iterator<Item = (String, String)>, NitroAdError> {
+    let vars = std::env::vars_os().filter_map(|(name, value)| {
+        let name = name.into_string().ok().filter(|name| name.starts_with(VAR_PREFIX))?;
+        Some(unicode(&name, value).map(|value| (name, value)))
+    });
+    Ok(vars.collect::<Result<Vec<_>, _>>()?.into_iter())
+}
+
+/// Value of the variable `name`, `None` if it is unset
+#[cfg(any(test, feature = "lambda"))]
+pub(crate) fn var(name: &str) -> Result<Option<String>, NitroAdError> {
+    std::env::var_os(name).map(|value| unicode(name, value)).transpose()
+}
+
+fn unicode(name: &str, value: OsString) -> Result<String, NitroAdError> {
+    value
+        .into_string()
+        .map_err(|_| NitroAdError::InvalidConfig(format!("{} is not valid Unicode", name)))
+}
+
+/// `policy` with the checks of the policy variables among `vars`, which take precedence
+pub(crate) fn policy_from_vars(
+    vars: impl Iterator<Item = (String, String)>,
+    mut policy: VerifierPolicy,
+) -> Result<VerifierPolicy, NitroAdError> {
+    for (name, value) in vars {
+        let invalid = || NitroAdError::InvalidConfig(format!("{}={}", name, value));
+        if name == MAX_AGE_VAR {
+            policy = policy.with_max_age(value.parse().map_err(|_| invalid())?);
+        } else if name == DEBUG_MODE_VAR {
+            policy = policy.with_debug_mode(from_name(&value).ok_or_else(invalid)?);
+        } else if name == REQUIRED_FIELDS_VAR {
+            for field in value.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+                policy = policy.with_required_field(from_name(field).ok_or_else(invalid)?);
+            }
+        } else if let Some(index) = name.strip_prefix(PCR_VAR_PREFIX) {
+            if index.starts_with("S_") {
+                continue;
+            }
+            let index = index.parse().map_err(|_| invalid())?;
+            policy = policy.with_pcr(index, hex::decode(&value).map_err(|_| invalid())?);
+        }
+    }
+    Ok(policy)
+}
+
+/// Enum variant named `name`, as in policy files
+fn from_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let deserializer: StrDeserializer<'_, ValueError> = name.into_deserializer();
+    T::deserialize(deserializer).ok()
+}
+
+/// DER root certificate of the file at `path`, converting PEM
+pub(crate) fn read_root_cert(path: impl AsRef<Path>) -> Result<Vec<u8>, NitroAdError> {
+    let path = path.as_ref();
+    let invalid = |e: &dyn std::fmt::Display| {
+        NitroAdError::InvalidConfig(format!("{}: {}", path.display(), e))
+    };
+    let root = std::fs::read(path).map_err(|e| invalid(&e))?;
+    if !root.starts_with(b"-----BEGIN") {
+        return Ok(root);
+    }
+    x509_parser::pem::parse_x509_pem(&root).map(|(_, pem)| pem.contents).map_err(|e| invalid(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::policy::{DebugMode, DocumentField};
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        let vars = vars.iter().map(|(name, value)| (name.to_string(), value.to_string()));
+        vars.collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_policy_from_vars() -> Result<(), NitroAdError> {
+        let env = vars(&[
+            ("NITRO_AD_PCR8", &"ab".repeat(48)),
+            ("NITRO_AD_MAX_AGE_SECS", "300"),
+            ("NITRO_AD_DEBUG_MODE", "reject"),
+            ("NITRO_AD_REQUIRED_FIELDS", "nonce, public_key"),
+            ("NITRO_AD_PCRS_SSM_PARAMETER", "/enclave/pcrs"),
+            ("NITRO_AD_ROOT_CERT", "root.der"),
+        ]);
+        let policy = policy_from_vars(env, VerifierPolicy::new())?;
+        let expected = VerifierPolicy::new()
+            .with_pcr(8, vec![0xab; 48])
+            .with_max_age(300)
+            .with_debug_mode(DebugMode::Reject)
+            .with_required_field(DocumentField::Nonce)
+            .with_required_field(DocumentField::PublicKey);
+        assert_eq!(policy, expected);
+
+        for env in [
+            ("NITRO_AD_MAX_AGE_SECS", "5m"),
+            ("NITRO_AD_DEBUG_MODE", "deny"),
+            ("NITRO_AD_REQUIRED_FIELDS", "nonce,pcrs"),
+        ] {
+            let policy = policy_from_vars(vars(&[env]), VerifierPolicy::new());
+            assert!(matches!(policy, Err(NitroAdError::InvalidConfig(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_vars_not_unicode() -> Result<(), NitroAdError> {
+        use std::os::unix::ffi::OsStringExt;
+
+        // the only test changing the environment
+        let not_unicode = || OsString::from_vec(vec![0xff]);
+        std::env::set_var("UNRELATED_NOT_UNICODE", not_unicode());
+        let unrelated = super::vars().map(|vars| vars.count());
+        std::env::set_var(MAX_AGE_VAR, not_unicode());
+        let own = (super::vars().err(), var(MAX_AGE_VAR).err());
+        std::env::remove_var(MAX_AGE_VAR);
+        std::env::remove_var("UNRELATED_NOT_UNICODE");
+
+        unrelated?;
+        assert!(matches!(own.0, Some(NitroAdError::InvalidConfig(e)) if e.ends_with("Unicode")));
+        assert!(matches!(own.1, Some(NitroAdError::InvalidConfig(_))));
+        assert_eq!(var("NITRO_AD_TEST_UNSET")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_root_cert() -> Result<(), NitroAdError> {
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/");
+        let der = read_root_cert(format!("{}aws_root.der", data))?;
+        assert_eq!(read_root_cert(format!("{}aws_root.pem", data))?, der);
+        assert!(matches!(read_root_cert("missing.der"), Err(NitroAdError::InvalidConfig(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! ECIES envelopes for attested enclaves
+//!
+//! A simpler alternative to the [`hpke`](crate::hpke) module for peers without an HPKE
+//! implementation. [`seal`] encrypts to the P-384 `public_key` of a verified document:
+//! it generates an ephemeral P-384 key, derives 44 bytes with HKDF-SHA384 from the
+//! ECDH secret, salted with the SHA-384 hash of the document and with [`ENVELOPE_INFO`]
+//! followed by the ephemeral public key as info, and encrypts with AES-256-GCM under
+//! the first 32 bytes as key and the last 12 as nonce. The envelope is
+//!
+//! | bytes | content |
+//! |---|---|
+//! | 97 | uncompressed SEC1 ephemeral public key |
+//! | rest | AES-256-GCM ciphertext and 16 byte tag |
+//!
+//! The enclave opens it with [`AttestedKey::open_envelope`]. The AES-GCM nonce is safe
+//! to derive since each envelope has its own key.
+
+use alloc::vec::Vec;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use p384::elliptic_curve::sec1::ToEncodedPoint;
+use p384::{PublicKey, SecretKey};
+use rand_core::OsRng;
+use zeroize::Zeroizing;
+
+use crate::crypto::sha384;
+use crate::ecdh::{attested_public_key, derive_shared_secret};
+#[cfg(feature = "nsm")]
+use crate::nsm::AttestedKey;
+use crate::{NitroAdDoc, NitroAdError};
+
+/// HKDF info prefix of envelope keys, followed by the ephemeral public key
+pub static ENVELOPE_INFO: &[u8] = b"aws-nitro-enclaves-attestation envelope v1";
+
+/// Length of the ephemeral public key starting envelopes
+pub const EPHEMERAL_KEY_LEN: usize = 97;
+
+/// AES-256-GCM cipher and nonce of the envelope with `ephemeral_key`
+fn cipher(
+    my_private_key: &SecretKey,
+    peer_public_key: &PublicKey,
+    document: &[u8],
+    ephemeral_key: &[u8],
+) -> Result<(Aes256Gcm, [u8; 12]), NitroAdError> {
+    let info = [ENVELOPE_INFO, ephemeral_key].concat();
+    let mut okm = Zeroizing::new([0; 44]);
+    derive_shared_secret(my_private_key, peer_public_key, &sha384(document), &info, &mut *okm)?;
+    let mut nonce = [0; 12];
+    nonce.copy_from_slice(&okm[32..]);
+    Ok((Aes256Gcm::new((&okm[..32]).into()), nonce))
+}
+
+/// Encrypts `plaintext` and authenticates `aad` for the enclave holding the private key
+/// of `doc`'s `public_key`. Fails for documents whose certificate chain didn't verify.
+pub fn seal(doc: &NitroAdDoc, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, NitroAdError> {
+    let recipient = attested_public_key(doc)?;
+    let ephemeral = SecretKey::random(&mut OsRng);
+    let ephemeral_key = ephemeral.public_key().to_encoded_point(false);
+    let (cipher, nonce) = cipher(&ephemeral, &recipient, doc.as_bytes(), ephemeral_key.as_bytes())?;
+    let ciphertext = cipher
+        .encrypt(&nonce.into(), Payload { msg: plaintext, aad })
+        .map_err(|_| NitroAdError::EnvelopeError)?;
+    let mut envelope = ephemeral_key.as_bytes().to_vec();
+    envelope.extend_from_slice(&ciphertext);
+    Ok(envelope)
+}
+
+#[cfg(feature = "nsm")]
+impl AttestedKey {
+    /// Decrypts an envelope of [`seal`] sealed to this key's document, checking `aad`
+    pub fn open_envelope(&self, envelope: &[u8], aad: &[u8]) -> Result<Vec<u8>, NitroAdError> {
+        if envelope.len() < EPHEMERAL_KEY_LEN {
+            return Err(NitroAdError::EnvelopeError);
+        }
+        let (ephemeral_key, ciphertext) = envelope.split_at(EPHEMERAL_KEY_LEN);
+        let peer = PublicKey::from_sec1_bytes(ephemeral_key)
+            .map_err(|_| NitroAdError::EnvelopeError)?;
+        let (cipher, nonce) = cipher(&self.secret_key, &peer, &self.document, ephemeral_key)?;
+        cipher
+            .decrypt(&nonce.into(), Payload { msg: ciphertext, aad })
+            .map_err(|_| NitroAdError::EnvelopeError)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use crate::testing::MockNsm;
+
+    #[test]
+    fn test_seal() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let enclave_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&enclave_key.document, &mock.root_cert(), now)?;
+
+        let envelope = seal(&doc, b"secret", b"header")?;
+        assert_eq!(envelope.len(), EPHEMERAL_KEY_LEN + b"secret".len() + 16);
+        assert_eq!(envelope[0], 0x04);
+        assert_eq!(enclave_key.open_envelope(&envelope, b"header")?, b"secret");
+        assert_ne!(envelope, seal(&doc, b"secret", b"header")?);
+
+        let rejected = |envelope: &[u8], aad: &[u8]| {
+            matches!(enclave_key.open_envelope(envelope, aad), Err(NitroAdError::EnvelopeError))
+        };
+        assert!(rejected(&envelope, b"other header"));
+        let mut tampered = envelope.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(rejected(&tampered, b"header"));
+        tampered = envelope.clone();
+        tampered[1] ^= 1;
+        assert!(rejected(&tampered, b"header"));
+        assert!(rejected(&envelope[..10], b"header"));
+
+        let other_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        assert!(matches!(
+            other_key.open_envelope(&envelope, b"header"),
+            Err(NitroAdError::EnvelopeError)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_without_public_key() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = mock.verified_doc(Default::default(), now)?;
+        assert!(matches!(seal(&doc, b"secret", b""), Err(NitroAdError::UnsupportedPublicKey)));
+        Ok(())
+    }
+}
@@ -0,0 +1,846 @@
+//! Attestation document parsing and validation errors
+
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+#[cfg(feature = "openssl")]
+use aws_nitro_enclaves_cose::error::COSEError;
+use chrono::{DateTime, Utc};
+
+use crate::policy::DocumentField;
+use crate::HeaderLabel;
+
+#[derive(Debug)]
+/// Aggregation of all error types returned by this library
+pub enum NitroAdError {
+    /// COSE_Sign1 structure is malformed or its signature could not be checked.
+    #[cfg(feature = "openssl")]
+    COSEError(COSEError),
+    /// Attestation document payload is not valid CBOR.
+    CBORError(serde_cbor::Error),
+    /// Certificate chain could not be verified.
+    VerificationError(webpki::Error),
+    /// JSON output could not be produced.
+    #[cfg(feature = "std")]
+    SerializationError(serde_json::Error),
+    /// YAML output could not be produced.
+    #[cfg(feature = "yaml")]
+    YamlError(serde_yaml::Error),
+    /// Certificate could not be parsed.
+    X509Error(String),
+    /// COSE signature is not a 96 bytes long ES384 r||s pair.
+    BadSignatureLength(usize),
+    /// COSE_Sign1 headers violate the specification.
+    MalformedCoseHeader(&'static str),
+    /// Document could not be read.
+    #[cfg(feature = "std")]
+    IoError(std::io::Error),
+    /// Document is longer than the given limit of bytes.
+    DocumentTooLarge { limit: usize },
+    /// Protected COSE header marks a parameter we don't understand as critical.
+    UnknownCriticalHeader(HeaderLabel),
+    /// `module_id` field is empty.
+    EmptyModuleId,
+    /// `digest` field names an algorithm other than SHA384.
+    UnsupportedDigest { got: String },
+    /// `timestamp` field lies outside of the plausible range.
+    TimestampOutOfRange { ts: DateTime<Utc> },
+    /// `pcrs` map holds no entries or more than 31 entries.
+    BadPcrCount(usize),
+    /// PCR with the given index is absent from the `pcrs` map.
+    MissingPcr(u8),
+    /// PCR value is not 32, 48 or 64 bytes long.
+    BadPcrLength { index: u8, len: usize },
+    /// `cabundle` field holds no certificates.
+    EmptyCaBundle,
+    /// Trusted root certificate could not be parsed.
+    InvalidRootCertificate(webpki::Error),
+    /// Signing certificate public key is not a valid P-384 key.
+    InvalidSigningKey(String),
+    /// Signing certificate is followed by trailing bytes.
+    TrailingCertificateData,
+    /// Signing certificate is not an X.509 v3 certificate.
+    BadCertificateVersion,
+    /// COSE signature does not match the signing certificate key.
+    InvalidSignature,
+    /// Signing an attestation result failed.
+    #[cfg(feature = "openssl")]
+    SigningError(openssl::error::ErrorStack),
+    /// Key passed for signing or checking an attestation result is not a P-384 key.
+    UnsupportedSigningKey,
+    /// Attestation result token is not a well-formed ES384 JWT.
+    MalformedToken(&'static str),
+    /// Attestation result token signature does not match the verifier key.
+    InvalidTokenSignature,
+    /// Attestation result token expired at the given unix time.
+    TokenExpired { exp: u64 },
+    /// Signed verification report signature does not match the verifier key.
+    InvalidReportSignature,
+    /// Re-verifying an archived document doesn't reproduce the archived outcome.
+    ArchiveMismatch,
+    /// Attestation archive has a format version this library doesn't know.
+    UnsupportedArchiveVersion(u32),
+    /// Compact verification report has a format version this library doesn't know.
+    UnsupportedReportVersion(u32),
+    /// Attestation bundle has a format version this library doesn't know.
+    UnsupportedBundleVersion(u32),
+    /// Transparency log request failed.
+    #[cfg(feature = "rekor")]
+    TransparencyLogError(String),
+    /// Nitro Secure Module answered a request with an error.
+    #[cfg(feature = "nsm")]
+    NsmError(aws_nitro_enclaves_nsm_api::api::ErrorCode),
+    /// TLS configuration could not be built.
+    #[cfg(feature = "tls")]
+    TlsError(rustls::Error),
+    /// PCR with the given index doesn't hold the value the policy expects.
+    PcrMismatch(u8),
+    /// `nonce` field is absent or differs from the policy's.
+    NonceMismatch,
+    /// `user_data` field is absent or differs from the policy's.
+    UserDataMismatch,
+    /// Document's `user_data` is not the SHA-384 hash of the certificate key it came with.
+    KeyBindingMismatch,
+    /// Certificate carries no attestation document extension.
+    MissingAttestationExtension,
+    /// Challenge expired at the given unix time before the document answering it arrived.
+    ChallengeExpired { expires_at: u64 },
+    /// Challenge length lies outside of the supported range.
+    BadChallengeLength(usize),
+    /// `public_key` field is absent or not a P-384 SubjectPublicKeyInfo.
+    UnsupportedPublicKey,
+    /// Derived key length lies outside of the supported range.
+    BadKeyLength(usize),
+    /// Envelope is malformed, altered or sealed to another key.
+    EnvelopeError,
+    /// Attestation handshake message is malformed or arrived out of order.
+    HandshakeError(&'static str),
+    /// Attestation handshake peer's key confirmation doesn't match the derived keys.
+    KeyConfirmationFailed,
+    /// Document doesn't continue the key rotation chain of its predecessor.
+    RotationError(&'static str),
+    /// PCRs match none of the registered measurement profiles.
+    NoMatchingProfile,
+    /// Document was older than the policy allows when verified, by its `timestamp`.
+    DocumentTooOld { age_secs: u64 },
+    /// Document comes from a debug-mode enclave, which the policy rejects.
+    DebugModeRejected,
+    /// Optional field the policy requires is absent.
+    MissingField(DocumentField),
+    /// Message could not be sealed to or opened with an attested key.
+    #[cfg(feature = "hpke")]
+    HpkeError(::hpke::HpkeError),
+    /// KMS `CiphertextForRecipient` is malformed or encrypted to another key.
+    #[cfg(feature = "kms-recipient")]
+    KmsRecipientError(openssl::error::ErrorStack),
+    /// KMS request failed or its response lacks the expected fields.
+    #[cfg(feature = "kms-client")]
+    KmsError(String),
+    /// SSH certificate could not be built or signed.
+    #[cfg(feature = "ssh")]
+    SshError(ssh_key::Error),
+    /// Trust domain or path segment is not allowed in SPIFFE IDs.
+    #[cfg(feature = "std")]
+    InvalidSpiffeId(String),
+    /// QUIC connection, stream or configuration failed.
+    #[cfg(feature = "quic")]
+    QuicError(String),
+    /// Policy or service configuration is invalid.
+    #[cfg(feature = "std")]
+    InvalidConfig(String),
+    /// SSM request failed or the parameter has no value.
+    #[cfg(feature = "ssm")]
+    SsmError(String),
+    /// Verification service is unreachable or its answer is not a verdict.
+    #[cfg(feature = "remote")]
+    RemoteError(String),
+    /// Verification service rejected the document, with the [`NitroAdError::code`] of its
+    /// error if it reported one.
+    #[cfg(feature = "remote")]
+    RemoteRejected { code: Option<u32>, message: String },
+    /// `nitro-cli` could not be run or failed.
+    #[cfg(feature = "std")]
+    NitroCliError(String),
+    /// `user_data` field is absent.
+    MissingUserData,
+    /// `user_data` field is not valid in the encoding it was decoded as.
+    InvalidUserData(String),
+    /// `X-Nitro-Attestation` header value is too long or not valid base64 or gzip.
+    #[cfg(feature = "header")]
+    MalformedHeader(String),
+    /// Document can't be expressed as an on-chain proof.
+    #[cfg(feature = "std")]
+    ProofError(&'static str),
+}
+
+/// Broad failure category of a [`NitroAdError`], see [`NitroAdError::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Document is not a well-formed attestation document. Permanent.
+    MalformedInput,
+    /// Document or token signature does not verify, or the token expired. Permanent.
+    Signature,
+    /// Certificate chain or trust anchor problem. May succeed with refreshed roots.
+    Chain,
+    /// Output could not be produced from an otherwise valid document.
+    Output,
+    /// Document verified, but doesn't carry the values a policy expects. Permanent.
+    Policy,
+    /// Document could not be read. May succeed on retry.
+    Io,
+    /// Arguments, configuration or protocol state of the caller are invalid. Permanent
+    /// until the caller changes them.
+    Usage,
+}
+
+/// Stable numeric error codes for FFI consumers, see [`NitroAdError::code`].
+/// Codes are never reused or renumbered; `0` is reserved for success.
+pub static ERROR_CODES: &[(u32, &str)] = &[
+    (1, "COSE structure error"),
+    (2, "CBOR decoding error"),
+    (3, "X.509 certificate parsing error"),
+    (4, "unknown critical COSE header"),
+    (5, "bad COSE signature length"),
+    (6, "malformed COSE header"),
+    (7, "document read error"),
+    (8, "document too large"),
+    (10, "module_id is empty"),
+    (11, "unsupported digest"),
+    (12, "timestamp out of range"),
+    (13, "wrong number of PCRs"),
+    (14, "PCR is missing"),
+    (15, "bad PCR length"),
+    (16, "cabundle is empty"),
+    (20, "certificate chain verification error"),
+    (21, "trailing data after signing certificate"),
+    (22, "signing certificate is not X.509 v3"),
+    (23, "invalid COSE signature"),
+    (24, "invalid root certificate"),
+    (25, "invalid signing key"),
+    (30, "serialization error"),
+    (31, "YAML serialization error"),
+    (40, "attestation result signing error"),
+    (41, "unsupported attestation result key"),
+    (42, "malformed attestation result token"),
+    (43, "invalid attestation result token signature"),
+    (44, "attestation result token expired"),
+    (45, "invalid verification report signature"),
+    (46, "archived outcome does not match re-verification"),
+    (47, "unsupported attestation archive version"),
+    (48, "unsupported verification report version"),
+    (49, "unsupported attestation bundle version"),
+    (50, "transparency log error"),
+    (60, "PCR does not match policy"),
+    (61, "nonce does not match policy"),
+    (62, "user_data does not match policy"),
+    (63, "document is not bound to the certificate key"),
+    (64, "certificate carries no attestation document"),
+    (65, "challenge expired"),
+    (66, "bad challenge length"),
+    (67, "public key is absent or unsupported"),
+    (68, "bad derived key length"),
+    (69, "envelope could not be opened"),
+    // reported by the C API only, see the ffi module
+    (70, "invalid argument"),
+    (71, "document field is absent"),
+    (80, "NSM request error"),
+    (90, "TLS error"),
+    (100, "attestation handshake protocol error"),
+    (101, "attestation handshake key confirmation failed"),
+    (110, "HPKE error"),
+    (120, "KMS ciphertext for recipient could not be decrypted"),
+    (121, "KMS request error"),
+    (130, "SSH certificate error"),
+    (140, "invalid SPIFFE ID"),
+    (150, "QUIC error"),
+    (160, "invalid configuration"),
+    (161, "SSM error"),
+    (170, "verification service error"),
+    (171, "rejected by the verification service"),
+    (180, "nitro-cli error"),
+    (190, "user_data is absent"),
+    (191, "user_data could not be decoded"),
+    (200, "malformed attestation header"),
+    (210, "on-chain proof error"),
+    (220, "key rotation link error"),
+    (230, "PCRs match no measurement profile"),
+    (240, "document is too old"),
+    (241, "debug-mode document rejected"),
+    (242, "required field is absent"),
+];
+
+impl NitroAdError {
+    /// Stable numeric code of the error variant, listed in [`ERROR_CODES`]
+    pub fn code(&self) -> u32 {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(_) => 1,
+            NitroAdError::CBORError(_) => 2,
+            NitroAdError::X509Error(_) => 3,
+            NitroAdError::UnknownCriticalHeader(_) => 4,
+            NitroAdError::BadSignatureLength(_) => 5,
+            NitroAdError::MalformedCoseHeader(_) => 6,
+            #[cfg(feature = "std")]
+            NitroAdError::IoError(_) => 7,
+            NitroAdError::DocumentTooLarge { .. } => 8,
+            NitroAdError::EmptyModuleId => 10,
+            NitroAdError::UnsupportedDigest { .. } => 11,
+            NitroAdError::TimestampOutOfRange { .. } => 12,
+            NitroAdError::BadPcrCount(_) => 13,
+            NitroAdError::MissingPcr(_) => 14,
+            NitroAdError::BadPcrLength { .. } => 15,
+            NitroAdError::EmptyCaBundle => 16,
+            NitroAdError::VerificationError(_) => 20,
+            NitroAdError::TrailingCertificateData => 21,
+            NitroAdError::BadCertificateVersion => 22,
+            NitroAdError::InvalidSignature => 23,
+            NitroAdError::InvalidRootCertificate(_) => 24,
+            NitroAdError::InvalidSigningKey(_) => 25,
+            #[cfg(feature = "std")]
+            NitroAdError::SerializationError(_) => 30,
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(_) => 31,
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(_) => 40,
+            NitroAdError::UnsupportedSigningKey => 41,
+            NitroAdError::MalformedToken(_) => 42,
+            NitroAdError::InvalidTokenSignature => 43,
+            NitroAdError::TokenExpired { .. } => 44,
+            NitroAdError::InvalidReportSignature => 45,
+            NitroAdError::ArchiveMismatch => 46,
+            NitroAdError::UnsupportedArchiveVersion(_) => 47,
+            NitroAdError::UnsupportedReportVersion(_) => 48,
+            NitroAdError::UnsupportedBundleVersion(_) => 49,
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(_) => 50,
+            NitroAdError::PcrMismatch(_) => 60,
+            NitroAdError::NonceMismatch => 61,
+            NitroAdError::UserDataMismatch => 62,
+            NitroAdError::KeyBindingMismatch => 63,
+            NitroAdError::MissingAttestationExtension => 64,
+            NitroAdError::ChallengeExpired { .. } => 65,
+            NitroAdError::BadChallengeLength(_) => 66,
+            NitroAdError::UnsupportedPublicKey => 67,
+            NitroAdError::BadKeyLength(_) => 68,
+            NitroAdError::EnvelopeError => 69,
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(_) => 80,
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(_) => 90,
+            NitroAdError::HandshakeError(_) => 100,
+            NitroAdError::KeyConfirmationFailed => 101,
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(_) => 110,
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(_) => 120,
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(_) => 121,
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(_) => 130,
+            #[cfg(feature = "std")]
+            NitroAdError::InvalidSpiffeId(_) => 140,
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(_) => 150,
+            #[cfg(feature = "std")]
+            NitroAdError::InvalidConfig(_) => 160,
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(_) => 161,
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(_) => 170,
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { .. } => 171,
+            #[cfg(feature = "std")]
+            NitroAdError::NitroCliError(_) => 180,
+            NitroAdError::MissingUserData => 190,
+            NitroAdError::InvalidUserData(_) => 191,
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(_) => 200,
+            #[cfg(feature = "std")]
+            NitroAdError::ProofError(_) => 210,
+            NitroAdError::RotationError(_) => 220,
+            NitroAdError::NoMatchingProfile => 230,
+            NitroAdError::DocumentTooOld { .. } => 240,
+            NitroAdError::DebugModeRejected => 241,
+            NitroAdError::MissingField(_) => 242,
+        }
+    }
+
+    /// Broad category of the failure
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(COSEError::SignatureError(_))
+            | NitroAdError::COSEError(COSEError::UnverifiedSignature) => ErrorKind::Signature,
+            NitroAdError::InvalidSignature
+            | NitroAdError::InvalidSigningKey(_)
+            | NitroAdError::InvalidTokenSignature
+            | NitroAdError::TokenExpired { .. }
+            | NitroAdError::InvalidReportSignature
+            | NitroAdError::KeyConfirmationFailed
+            | NitroAdError::RotationError(_)
+            | NitroAdError::ArchiveMismatch
+            | NitroAdError::EnvelopeError => ErrorKind::Signature,
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(::hpke::HpkeError::OpenError) => ErrorKind::Signature,
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(_) => ErrorKind::Output,
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(_) => ErrorKind::Signature,
+            NitroAdError::VerificationError(_) | NitroAdError::InvalidRootCertificate(_) => {
+                ErrorKind::Chain
+            }
+            NitroAdError::UnsupportedSigningKey => ErrorKind::Output,
+            #[cfg(feature = "std")]
+            NitroAdError::SerializationError(_) => ErrorKind::Output,
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(_) => ErrorKind::Output,
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(_) => ErrorKind::Output,
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(_) => ErrorKind::Output,
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(_) => ErrorKind::Output,
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(_) => ErrorKind::Output,
+            #[cfg(feature = "std")]
+            NitroAdError::ProofError(_) => ErrorKind::Output,
+            NitroAdError::PcrMismatch(_)
+            | NitroAdError::NonceMismatch
+            | NitroAdError::UserDataMismatch
+            | NitroAdError::KeyBindingMismatch
+            | NitroAdError::MissingAttestationExtension
+            | NitroAdError::ChallengeExpired { .. }
+            | NitroAdError::UnsupportedPublicKey
+            | NitroAdError::MissingUserData
+            | NitroAdError::InvalidUserData(_)
+            | NitroAdError::NoMatchingProfile
+            | NitroAdError::DocumentTooOld { .. }
+            | NitroAdError::DebugModeRejected
+            | NitroAdError::MissingField(_) => ErrorKind::Policy,
+            #[cfg(feature = "std")]
+            NitroAdError::IoError(_) => ErrorKind::Io,
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(_) => ErrorKind::Io,
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(_) => ErrorKind::Io,
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(_) => ErrorKind::Io,
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(_) => ErrorKind::Io,
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(_) => ErrorKind::Io,
+            // the service's verdict, whatever its reason
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { .. } => ErrorKind::Policy,
+            #[cfg(feature = "std")]
+            NitroAdError::NitroCliError(_) => ErrorKind::Io,
+            #[cfg(feature = "std")]
+            NitroAdError::InvalidConfig(_) | NitroAdError::InvalidSpiffeId(_) => ErrorKind::Usage,
+            NitroAdError::BadKeyLength(_)
+            | NitroAdError::BadChallengeLength(_)
+            | NitroAdError::HandshakeError(_) => ErrorKind::Usage,
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(_) => ErrorKind::MalformedInput,
+            NitroAdError::CBORError(_)
+            | NitroAdError::X509Error(_)
+            | NitroAdError::BadSignatureLength(_)
+            | NitroAdError::MalformedCoseHeader(_)
+            | NitroAdError::DocumentTooLarge { .. }
+            | NitroAdError::UnknownCriticalHeader(_)
+            | NitroAdError::EmptyModuleId
+            | NitroAdError::UnsupportedDigest { .. }
+            | NitroAdError::TimestampOutOfRange { .. }
+            | NitroAdError::BadPcrCount(_)
+            | NitroAdError::MissingPcr(_)
+            | NitroAdError::BadPcrLength { .. }
+            | NitroAdError::EmptyCaBundle
+            | NitroAdError::TrailingCertificateData
+            | NitroAdError::BadCertificateVersion
+            | NitroAdError::MalformedToken(_)
+            | NitroAdError::UnsupportedArchiveVersion(_)
+            | NitroAdError::UnsupportedReportVersion(_)
+            | NitroAdError::UnsupportedBundleVersion(_) => ErrorKind::MalformedInput,
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(_) => ErrorKind::MalformedInput,
+        }
+    }
+
+    /// Document signature is wrong, no retry will help
+    pub fn is_signature_failure(&self) -> bool {
+        self.kind() == ErrorKind::Signature
+    }
+
+    /// Certificate chain could not be established, worth retrying with refreshed roots
+    pub fn is_chain_failure(&self) -> bool {
+        self.kind() == ErrorKind::Chain
+    }
+
+    /// Document bytes are not a valid attestation document, no retry will help
+    pub fn is_malformed_input(&self) -> bool {
+        self.kind() == ErrorKind::MalformedInput
+    }
+
+    /// Document is genuine but not the one expected, no retry will help
+    pub fn is_policy_failure(&self) -> bool {
+        self.kind() == ErrorKind::Policy
+    }
+
+    /// Document could not be read, worth retrying
+    pub fn is_io_failure(&self) -> bool {
+        self.kind() == ErrorKind::Io
+    }
+
+    /// Caller passed invalid arguments or configuration, no retry will help
+    pub fn is_usage_failure(&self) -> bool {
+        self.kind() == ErrorKind::Usage
+    }
+
+    /// Short description of a numeric error code, `None` for unknown codes
+    pub fn code_description(code: u32) -> Option<&'static str> {
+        ERROR_CODES
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, descr)| *descr)
+    }
+}
+
+impl fmt::Display for NitroAdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(e) => write!(f, "COSE error: {}", DisplayCOSEError(e)),
+            NitroAdError::CBORError(e) => write!(f, "CBOR decoding error: {}", e),
+            NitroAdError::VerificationError(e) => {
+                write!(f, "certificate chain verification error: {}", e)
+            }
+            #[cfg(feature = "std")]
+            NitroAdError::SerializationError(e) => write!(f, "serialization error: {}", e),
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(e) => write!(f, "YAML serialization error: {}", e),
+            NitroAdError::X509Error(e) => write!(f, "X.509 certificate error: {}", e),
+            NitroAdError::BadSignatureLength(len) => {
+                write!(f, "COSE signature is {} bytes long, expected 96 bytes", len)
+            }
+            NitroAdError::MalformedCoseHeader(e) => write!(f, "malformed COSE header: {}", e),
+            #[cfg(feature = "std")]
+            NitroAdError::IoError(e) => write!(f, "document read error: {}", e),
+            NitroAdError::DocumentTooLarge { limit } => {
+                write!(f, "document is longer than {} bytes", limit)
+            }
+            NitroAdError::UnknownCriticalHeader(label) => {
+                write!(f, "unknown critical COSE header {:?}", label)
+            }
+            NitroAdError::EmptyModuleId => write!(f, "module_id is empty"),
+            NitroAdError::UnsupportedDigest { got } => {
+                write!(f, "digest {:?} is unsupported, expected SHA384", got)
+            }
+            NitroAdError::TimestampOutOfRange { ts } => {
+                write!(f, "timestamp {} is out of the valid range", ts)
+            }
+            NitroAdError::BadPcrCount(count) => {
+                write!(f, "wrong number of PCRs in the map: {}", count)
+            }
+            NitroAdError::MissingPcr(index) => write!(f, "PCR{} is missing", index),
+            NitroAdError::BadPcrLength { index, len } => write!(
+                f,
+                "PCR{} is {} bytes long, expected 32/48/64 bytes",
+                index, len
+            ),
+            NitroAdError::EmptyCaBundle => write!(f, "cabundle is empty"),
+            NitroAdError::InvalidRootCertificate(e) => {
+                write!(f, "root certificate is invalid: {}", e)
+            }
+            NitroAdError::InvalidSigningKey(e) => {
+                write!(f, "signing certificate key is invalid: {}", e)
+            }
+            NitroAdError::TrailingCertificateData => {
+                write!(f, "signing certificate is followed by trailing data")
+            }
+            NitroAdError::BadCertificateVersion => {
+                write!(f, "signing certificate is not X.509 v3")
+            }
+            NitroAdError::InvalidSignature => {
+                write!(f, "COSE signature does not match the signing certificate")
+            }
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(e) => write!(f, "attestation result signing failed: {}", e),
+            NitroAdError::UnsupportedSigningKey => {
+                write!(f, "attestation result key is not a P-384 key")
+            }
+            NitroAdError::MalformedToken(e) => write!(f, "malformed attestation result token: {}", e),
+            NitroAdError::InvalidTokenSignature => {
+                write!(f, "attestation result token signature is invalid")
+            }
+            NitroAdError::TokenExpired { exp } => {
+                write!(f, "attestation result token expired at {}", exp)
+            }
+            NitroAdError::InvalidReportSignature => {
+                write!(f, "verification report signature is invalid")
+            }
+            NitroAdError::ArchiveMismatch => {
+                write!(f, "archived outcome does not match re-verification")
+            }
+            NitroAdError::UnsupportedArchiveVersion(version) => {
+                write!(f, "attestation archive version {} is unsupported", version)
+            }
+            NitroAdError::UnsupportedReportVersion(version) => {
+                write!(f, "verification report version {} is unsupported", version)
+            }
+            NitroAdError::UnsupportedBundleVersion(version) => {
+                write!(f, "attestation bundle version {} is unsupported", version)
+            }
+            #[cfg(feature = "rekor")]
+            NitroAdError::TransparencyLogError(e) => write!(f, "transparency log error: {}", e),
+            NitroAdError::PcrMismatch(index) => {
+                write!(f, "PCR{} does not match the expected value", index)
+            }
+            NitroAdError::NonceMismatch => write!(f, "nonce does not match the expected value"),
+            NitroAdError::UserDataMismatch => {
+                write!(f, "user_data does not match the expected value")
+            }
+            NitroAdError::KeyBindingMismatch => {
+                write!(f, "attestation document is not bound to the certificate key")
+            }
+            NitroAdError::MissingAttestationExtension => {
+                write!(f, "certificate carries no attestation document")
+            }
+            NitroAdError::ChallengeExpired { expires_at } => {
+                write!(f, "challenge expired at {}", expires_at)
+            }
+            NitroAdError::BadChallengeLength(len) => {
+                write!(f, "challenge length {} is unsupported", len)
+            }
+            NitroAdError::UnsupportedPublicKey => {
+                write!(f, "public_key is absent or not a P-384 key")
+            }
+            NitroAdError::BadKeyLength(len) => {
+                write!(f, "derived key length {} is unsupported", len)
+            }
+            NitroAdError::EnvelopeError => write!(f, "envelope could not be opened"),
+            #[cfg(feature = "nsm")]
+            NitroAdError::NsmError(code) => write!(f, "NSM request failed: {:?}", code),
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(e) => write!(f, "TLS error: {}", e),
+            NitroAdError::HandshakeError(e) => write!(f, "attestation handshake error: {}", e),
+            NitroAdError::KeyConfirmationFailed => {
+                write!(f, "attestation handshake key confirmation failed")
+            }
+            NitroAdError::RotationError(e) => write!(f, "key rotation error: {}", e),
+            NitroAdError::NoMatchingProfile => write!(f, "PCRs match no measurement profile"),
+            NitroAdError::DocumentTooOld { age_secs } => {
+                write!(f, "document is {} seconds old, older than the policy allows", age_secs)
+            }
+            NitroAdError::DebugModeRejected => {
+                write!(f, "document comes from a debug-mode enclave")
+            }
+            NitroAdError::MissingField(field) => write!(f, "{} is absent", field.name()),
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(e) => write!(f, "HPKE error: {}", e),
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(e) => {
+                write!(f, "KMS ciphertext for recipient could not be decrypted: {}", e)
+            }
+            #[cfg(feature = "kms-client")]
+            NitroAdError::KmsError(e) => write!(f, "KMS request failed: {}", e),
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(e) => write!(f, "SSH certificate error: {}", e),
+            #[cfg(feature = "std")]
+            NitroAdError::InvalidSpiffeId(e) => write!(f, "invalid SPIFFE ID: {}", e),
+            #[cfg(feature = "quic")]
+            NitroAdError::QuicError(e) => write!(f, "QUIC error: {}", e),
+            #[cfg(feature = "std")]
+            NitroAdError::InvalidConfig(e) => write!(f, "invalid configuration: {}", e),
+            #[cfg(feature = "ssm")]
+            NitroAdError::SsmError(e) => write!(f, "SSM request failed: {}", e),
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteError(e) => write!(f, "verification service error: {}", e),
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { code: Some(code), message } => {
+                write!(f, "rejected by the verification service: {} (code {})", message, code)
+            }
+            #[cfg(feature = "remote")]
+            NitroAdError::RemoteRejected { code: None, message } => {
+                write!(f, "rejected by the verification service: {}", message)
+            }
+            #[cfg(feature = "std")]
+            NitroAdError::NitroCliError(e) => write!(f, "nitro-cli failed: {}", e),
+            NitroAdError::MissingUserData => write!(f, "user_data is absent"),
+            NitroAdError::InvalidUserData(e) => write!(f, "user_data could not be decoded: {}", e),
+            #[cfg(feature = "header")]
+            NitroAdError::MalformedHeader(e) => write!(f, "malformed attestation header: {}", e),
+            #[cfg(feature = "std")]
+            NitroAdError::ProofError(e) => write!(f, "on-chain proof could not be built: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for NitroAdError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(COSEError::SignatureError(e)) => Some(e),
+            #[cfg(feature = "openssl")]
+            NitroAdError::COSEError(COSEError::SerializationError(e)) => Some(e),
+            NitroAdError::CBORError(e) => Some(e),
+            NitroAdError::VerificationError(e) => Some(e),
+            NitroAdError::SerializationError(e) => Some(e),
+            NitroAdError::IoError(e) => Some(e),
+            #[cfg(feature = "yaml")]
+            NitroAdError::YamlError(e) => Some(e),
+            NitroAdError::InvalidRootCertificate(e) => Some(e),
+            #[cfg(feature = "openssl")]
+            NitroAdError::SigningError(e) => Some(e),
+            #[cfg(feature = "tls")]
+            NitroAdError::TlsError(e) => Some(e),
+            #[cfg(feature = "hpke")]
+            NitroAdError::HpkeError(e) => Some(e),
+            #[cfg(feature = "kms-recipient")]
+            NitroAdError::KmsRecipientError(e) => Some(e),
+            #[cfg(feature = "ssh")]
+            NitroAdError::SshError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// COSEError implements neither Display nor Error, so describe it here
+#[cfg(feature = "openssl")]
+struct DisplayCOSEError<'a>(&'a COSEError);
+
+#[cfg(feature = "openssl")]
+impl fmt::Display for DisplayCOSEError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            COSEError::SignatureError(e) => write!(f, "signature operation failed: {}", e),
+            COSEError::UnimplementedError => write!(f, "feature is not implemented"),
+            COSEError::UnsupportedError(e) => write!(f, "unsupported: {}", e),
+            COSEError::UnverifiedSignature => write!(f, "signature could not be verified"),
+            COSEError::SpecificationError(e) => write!(f, "specification violation: {}", e),
+            COSEError::SerializationError(e) => write!(f, "serialization failed: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl From<COSEError> for NitroAdError {
+    fn from(err: COSEError) -> NitroAdError {
+        NitroAdError::COSEError(err)
+    }
+}
+
+impl From<serde_cbor::Error> for NitroAdError {
+    fn from(err: serde_cbor::Error) -> NitroAdError {
+        NitroAdError::CBORError(err)
+    }
+}
+
+impl From<webpki::Error> for NitroAdError {
+    fn from(err: webpki::Error) -> NitroAdError {
+        NitroAdError::VerificationError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<serde_json::Error> for NitroAdError {
+    fn from(err: serde_json::Error) -> NitroAdError {
+        NitroAdError::SerializationError(err)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<serde_yaml::Error> for NitroAdError {
+    fn from(err: serde_yaml::Error) -> NitroAdError {
+        NitroAdError::YamlError(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pki_types::UnixTime;
+
+    fn cert_expired() -> webpki::Error {
+        webpki::Error::CertExpired {
+            time: UnixTime::since_unix_epoch(core::time::Duration::from_secs(1714967200)),
+            not_after: UnixTime::since_unix_epoch(core::time::Duration::from_secs(1614974400)),
+        }
+    }
+
+    #[test]
+    fn test_display_describes_cause() {
+        let err = NitroAdError::BadPcrLength { index: 3, len: 20 };
+        assert_eq!(err.to_string(), "PCR3 is 20 bytes long, expected 32/48/64 bytes");
+
+        let err = NitroAdError::from(cert_expired());
+        assert!(err.to_string().contains("CertExpired"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_source_chains_to_inner_error() {
+        let err = NitroAdError::from(cert_expired());
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), cert_expired().to_string());
+
+        assert!(NitroAdError::X509Error(String::from("bad")).source().is_none());
+    }
+
+    #[test]
+    fn test_error_codes_are_unique() {
+        // listed in ascending order, so duplicates would be neighbours
+        let codes: Vec<u32> = ERROR_CODES.iter().map(|(c, _)| *c).collect();
+        assert!(codes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(!codes.contains(&0));
+    }
+
+    #[test]
+    fn test_error_code_lookup() {
+        let err = NitroAdError::MissingPcr(2);
+        assert_eq!(err.code(), 14);
+        assert_eq!(NitroAdError::code_description(err.code()), Some("PCR is missing"));
+        assert_eq!(
+            NitroAdError::code_description(NitroAdError::InvalidSignature.code()),
+            Some("invalid COSE signature")
+        );
+        assert_eq!(NitroAdError::code_description(0), None);
+    }
+
+    #[test]
+    fn test_error_kind() {
+        assert!(NitroAdError::InvalidSignature.is_signature_failure());
+        #[cfg(feature = "openssl")]
+        assert!(NitroAdError::COSEError(COSEError::UnverifiedSignature).is_signature_failure());
+        assert!(NitroAdError::from(webpki::Error::UnknownIssuer).is_chain_failure());
+        assert!(NitroAdError::InvalidRootCertificate(webpki::Error::BadDer).is_chain_failure());
+        assert!(NitroAdError::MissingPcr(0).is_malformed_input());
+        #[cfg(feature = "openssl")]
+        assert!(NitroAdError::COSEError(COSEError::UnimplementedError).is_malformed_input());
+        assert!(NitroAdError::MalformedCoseHeader("crit").is_malformed_input());
+        assert_eq!(NitroAdError::EmptyCaBundle.kind(), ErrorKind::MalformedInput);
+        assert!(NitroAdError::PcrMismatch(8).is_policy_failure());
+        assert!(!NitroAdError::MissingPcr(8).is_policy_failure());
+        #[cfg(feature = "std")]
+        assert!(NitroAdError::IoError(std::io::ErrorKind::UnexpectedEof.into()).is_io_failure());
+        assert!(NitroAdError::DocumentTooLarge { limit: 1 }.is_malformed_input());
+        assert!(NitroAdError::BadChallengeLength(4).is_usage_failure());
+        assert!(NitroAdError::HandshakeError("unexpected message").is_usage_failure());
+        #[cfg(feature = "std")]
+        assert!(NitroAdError::InvalidConfig(String::from("PCR0")).is_usage_failure());
+        assert!(NitroAdError::ArchiveMismatch.is_signature_failure());
+        #[cfg(feature = "std")]
+        assert_eq!(NitroAdError::ProofError("no key").kind(), ErrorKind::Output);
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn test_boxed_into_dyn_error() {
+        fn fails() -> Result<(), Box<dyn Error + Send + Sync>> {
+            Err(NitroAdError::COSEError(COSEError::UnverifiedSignature))?
+        }
+        assert!(fails().unwrap_err().to_string().contains("signature"));
+    }
+}
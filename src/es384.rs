@@ -0,0 +1,59 @@
+//! ES384 signatures over attestation results, as raw r||s pairs
+
+use openssl::bn::BigNum;
+use openssl::ec::EcKeyRef;
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::{HasParams, HasPublic, Private};
+use openssl::sha::sha384;
+
+use crate::NitroAdError;
+
+// 48 bytes per factor
+const FACTOR_LEN: usize = 48;
+
+pub(crate) fn check_curve<T: HasParams>(key: &EcKeyRef<T>) -> Result<(), NitroAdError> {
+    match key.group().curve_name() {
+        Some(Nid::SECP384R1) => Ok(()),
+        _ => Err(NitroAdError::UnsupportedSigningKey),
+    }
+}
+
+/// SHA384 ECDSA signature of `data` with the P-384 `key`
+pub(crate) fn sign(data: &[u8], key: &EcKeyRef<Private>) -> Result<Vec<u8>, NitroAdError> {
+    check_curve(key)?;
+
+    let sig = EcdsaSig::sign(&sha384(data), key).map_err(NitroAdError::SigningError)?;
+    let mut raw_sig = sig
+        .r()
+        .to_vec_padded(FACTOR_LEN as i32)
+        .map_err(NitroAdError::SigningError)?;
+    raw_sig.extend(
+        sig.s()
+            .to_vec_padded(FACTOR_LEN as i32)
+            .map_err(NitroAdError::SigningError)?,
+    );
+    Ok(raw_sig)
+}
+
+/// Checks a signature made by [`sign`], malformed signatures don't verify
+pub(crate) fn verify<T: HasPublic>(
+    data: &[u8],
+    raw_sig: &[u8],
+    key: &EcKeyRef<T>,
+) -> Result<bool, NitroAdError> {
+    check_curve(key)?;
+
+    if raw_sig.len() != 2 * FACTOR_LEN {
+        return Ok(false);
+    }
+    let (r, s) = raw_sig.split_at(FACTOR_LEN);
+    let sig = BigNum::from_slice(r)
+        .and_then(|r| Ok((r, BigNum::from_slice(s)?)))
+        .and_then(|(r, s)| EcdsaSig::from_private_components(r, s));
+
+    Ok(match sig {
+        Ok(sig) => sig.verify(&sha384(data), key).unwrap_or(false),
+        Err(_) => false,
+    })
+}
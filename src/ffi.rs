@@ -0,0 +1,273 @@
+//! C API
+//!
+//! Declared in `include/nitro_ad.h`, which is generated by
+//! ```bash
+//! cbindgen --config cbindgen.toml --output include/nitro_ad.h
+//! ```
+//! Functions return `0` on success and one of the [`ERROR_CODES`] otherwise, see
+//! `nitro_ad_error_description()`. Verified documents are opaque `NitroAdDocument`
+//! handles, released with `nitro_ad_free()`. Byte strings handed out by the getters
+//! borrow from the handle; strings allocated by the library are released with
+//! `nitro_ad_string_free()`.
+//!
+//! The functions are exported by the `libnitro_ad` shared and static libraries of
+//! the `capi/` crate. Only opaque handles, integers and byte pointers cross the
+//! boundary, no struct layouts, so the ABI only changes with the functions; see
+//! [`NITRO_AD_ABI_VERSION`].
+
+use std::ffi::{c_char, CString};
+use std::ptr;
+use std::slice;
+use std::sync::OnceLock;
+
+use crate::{NitroAdDoc, NitroAdError, ERROR_CODES};
+
+/// Version of the C API. Incremented on incompatible changes, i.e. removed or changed
+/// functions; additions keep it. Shared objects are named `libnitro_ad.so.<version>`.
+pub const NITRO_AD_ABI_VERSION: u32 = 1;
+
+/// Null pointer or otherwise unusable argument
+pub const NITRO_AD_ERR_INVALID_ARGUMENT: u32 = 70;
+
+/// Requested optional document field is absent
+pub const NITRO_AD_ERR_ABSENT_FIELD: u32 = 71;
+
+/// Verified attestation document
+pub struct NitroAdDocument(NitroAdDoc<'static>);
+
+/// Slice of `len` bytes at `data`, empty slices need no valid pointer
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, len) => Some(slice::from_raw_parts(data, len)),
+    }
+}
+
+/// `NITRO_AD_ABI_VERSION` of the library, for programs checking at runtime that the
+/// loaded library matches the header they were built against
+#[no_mangle]
+pub extern "C" fn nitro_ad_abi_version() -> u32 {
+    NITRO_AD_ABI_VERSION
+}
+
+/// Verifies the document `doc` against the DER encoded `root_cert` at `unix_ts_sec`.
+/// Unlike `NitroAdDoc::from_bytes`, certificate chain failures are errors. Stores
+/// the document handle in `*out` on success.
+///
+/// # Safety
+///
+/// `doc` and `root_cert` point to `doc_len` and `root_cert_len` readable bytes,
+/// `out` is a valid pointer to write a handle to.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_verify(
+    doc: *const u8,
+    doc_len: usize,
+    root_cert: *const u8,
+    root_cert_len: usize,
+    unix_ts_sec: u64,
+    out: *mut *mut NitroAdDocument,
+) -> u32 {
+    let (doc, root_cert) = match (bytes(doc, doc_len), bytes(root_cert, root_cert_len)) {
+        (Some(doc), Some(root_cert)) if !out.is_null() => (doc, root_cert),
+        _ => return NITRO_AD_ERR_INVALID_ARGUMENT,
+    };
+    let verified = NitroAdDoc::from_bytes(doc, root_cert, unix_ts_sec).and_then(|doc| {
+        match doc.verification_error() {
+            Some(e) => Err(NitroAdError::VerificationError(e)),
+            None => Ok(doc.into_owned()),
+        }
+    });
+    match verified {
+        Ok(doc) => {
+            *out = Box::into_raw(Box::new(NitroAdDocument(doc)));
+            0
+        }
+        Err(e) => e.code(),
+    }
+}
+
+/// Releases a handle of `nitro_ad_verify()`, null handles are ignored
+///
+/// # Safety
+///
+/// `doc` is null or a handle of `nitro_ad_verify()` not released before.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_free(doc: *mut NitroAdDocument) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// JSON of `NitroAdDoc::to_json`, NUL terminated. Stores a string to release with
+/// `nitro_ad_string_free()` in `*out` on success.
+///
+/// # Safety
+///
+/// `doc` is a live handle of `nitro_ad_verify()`, `out` a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_to_json(doc: *const NitroAdDocument, out: *mut *mut c_char) -> u32 {
+    let doc = match doc.as_ref() {
+        Some(doc) if !out.is_null() => doc,
+        _ => return NITRO_AD_ERR_INVALID_ARGUMENT,
+    };
+    match doc.0.to_json() {
+        // serde_json escapes NUL characters
+        Ok(json) => {
+            *out = CString::new(json).map_or(ptr::null_mut(), CString::into_raw);
+            0
+        }
+        Err(e) => e.code(),
+    }
+}
+
+/// Releases a string of `nitro_ad_to_json()`, null strings are ignored
+///
+/// # Safety
+///
+/// `s` is null or a string of this library not released before.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Stores PCR `index` in `*data` and `*len`. The bytes live as long as `doc`.
+///
+/// # Safety
+///
+/// `doc` is a live handle of `nitro_ad_verify()`, `data` and `len` valid pointers to write to.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_pcr(
+    doc: *const NitroAdDocument,
+    index: u8,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> u32 {
+    let doc = match doc.as_ref() {
+        Some(doc) if !data.is_null() && !len.is_null() => doc,
+        _ => return NITRO_AD_ERR_INVALID_ARGUMENT,
+    };
+    match doc.0.payload().pcrs.get(&index) {
+        Some(pcr) => {
+            *data = pcr.as_ptr();
+            *len = pcr.len();
+            0
+        }
+        None => NitroAdError::MissingPcr(index).code(),
+    }
+}
+
+/// Number of PCRs of `doc`, indexes run from 0 to the count excluded. 0 for null handles.
+///
+/// # Safety
+///
+/// `doc` is null or a live handle of `nitro_ad_verify()`.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_pcr_count(doc: *const NitroAdDocument) -> usize {
+    doc.as_ref().map_or(0, |doc| doc.0.payload().pcrs.len())
+}
+
+/// Stores the document `public_key` in `*data` and `*len`, fails with
+/// `NITRO_AD_ERR_ABSENT_FIELD` for documents without one. The bytes live as long as `doc`.
+///
+/// # Safety
+///
+/// `doc` is a live handle of `nitro_ad_verify()`, `data` and `len` valid pointers to write to.
+#[no_mangle]
+pub unsafe extern "C" fn nitro_ad_public_key(
+    doc: *const NitroAdDocument,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> u32 {
+    let doc = match doc.as_ref() {
+        Some(doc) if !data.is_null() && !len.is_null() => doc,
+        _ => return NITRO_AD_ERR_INVALID_ARGUMENT,
+    };
+    match &doc.0.payload().public_key {
+        Some(public_key) => {
+            *data = public_key.as_ptr();
+            *len = public_key.len();
+            0
+        }
+        None => NITRO_AD_ERR_ABSENT_FIELD,
+    }
+}
+
+/// Static NUL terminated description of an error code, null for unknown codes
+#[no_mangle]
+pub extern "C" fn nitro_ad_error_description(code: u32) -> *const c_char {
+    static DESCRIPTIONS: OnceLock<Vec<(u32, CString)>> = OnceLock::new();
+    DESCRIPTIONS
+        .get_or_init(|| {
+            ERROR_CODES
+                .iter()
+                .map(|(code, descr)| (*code, CString::new(*descr).unwrap()))
+                .collect()
+        })
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map_or(ptr::null(), |(_, descr)| descr.as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CStr;
+
+    fn verify(doc: &[u8], unix_ts_sec: u64) -> Result<*mut NitroAdDocument, u32> {
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let mut out = ptr::null_mut();
+        match unsafe {
+            nitro_ad_verify(doc.as_ptr(), doc.len(), root_cert.as_ptr(), root_cert.len(), unix_ts_sec, &mut out)
+        } {
+            0 => Ok(out),
+            code => Err(code),
+        }
+    }
+
+    #[test]
+    fn test_verify_and_getters() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let doc = verify(ad_blob, 1614967200).unwrap();
+
+        let (mut data, mut len) = (ptr::null(), 0);
+        unsafe {
+            assert_eq!(nitro_ad_pcr_count(doc), 16);
+            assert_eq!(nitro_ad_pcr(doc, 0, &mut data, &mut len), 0);
+            assert_eq!(slice::from_raw_parts(data, len), &[0; 48][..]);
+            assert_eq!(nitro_ad_pcr(doc, 31, &mut data, &mut len), 14);
+            assert_eq!(nitro_ad_public_key(doc, &mut data, &mut len), NITRO_AD_ERR_ABSENT_FIELD);
+
+            let mut json = ptr::null_mut();
+            assert_eq!(nitro_ad_to_json(doc, &mut json), 0);
+            let parsed: serde_json::Value = serde_json::from_slice(CStr::from_ptr(json).to_bytes()).unwrap();
+            assert!(parsed["module_id"].is_string());
+            nitro_ad_string_free(json);
+
+            nitro_ad_free(doc);
+            nitro_ad_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        assert_eq!(verify(ad_blob, 1714967200), Err(20));
+        assert_eq!(verify(&ad_blob[..10], 1614967200), Err(2));
+
+        let mut out = ptr::null_mut();
+        let code = unsafe { nitro_ad_verify(ptr::null(), 10, ptr::null(), 0, 0, &mut out) };
+        assert_eq!(code, NITRO_AD_ERR_INVALID_ARGUMENT);
+        assert!(out.is_null());
+        assert_eq!(unsafe { nitro_ad_pcr_count(ptr::null()) }, 0);
+
+        let descr = unsafe { CStr::from_ptr(nitro_ad_error_description(20)) };
+        assert_eq!(descr.to_str(), Ok("certificate chain verification error"));
+        assert!(!nitro_ad_error_description(NITRO_AD_ERR_ABSENT_FIELD).is_null());
+        assert!(nitro_ad_error_description(0).is_null());
+        assert_eq!(nitro_ad_abi_version(), NITRO_AD_ABI_VERSION);
+    }
+}
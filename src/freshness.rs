@@ -0,0 +1,149 @@
+//! Attestation freshness of long-lived connections
+//!
+//! A document verified when a connection opened says little about the peer days later.
+//! A [`ConnectionAttestation`] remembers when the peer's latest document was verified
+//! on the connection and when its certificate chain expires, and
+//! [`ConnectionAttestation::check`] tells when the peer has to attest again: once the
+//! verification is older than the maximum age, or once the first certificate of the
+//! document expires within the expiry margin, whichever comes first. Protocols check
+//! before handling each message, or on a timer set to
+//! [`ConnectionAttestation::reattest_at`].
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::freshness::ConnectionAttestation;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! # let verifier = Verifier::new(aws_root_der);
+//! // re-attest at least daily, and 10 minutes before the certificates expire
+//! let mut attestation = ConnectionAttestation::new(24 * 3600).with_expiry_margin(600);
+//! attestation.record(&verifier.verify(&document, now)?);
+//! // later, for each message
+//! if attestation.check(now).requires_reattestation() {
+//!     // ask the peer for a fresh document before going on
+//! }
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use crate::NitroAdDoc;
+
+/// Outcome of [`ConnectionAttestation::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Fresh until the given unix time
+    Fresh { until: u64 },
+    /// No document was recorded for the connection yet
+    Unattested,
+    /// The latest document was verified longer than the maximum age ago
+    Expired,
+    /// A certificate of the latest document expires within the margin
+    CertificateExpiring,
+}
+
+impl Freshness {
+    /// Whether the peer has to present a new document
+    pub fn requires_reattestation(&self) -> bool {
+        !matches!(self, Freshness::Fresh { .. })
+    }
+}
+
+/// Verification times of a connection's peer, see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionAttestation {
+    max_age_secs: u64,
+    expiry_margin_secs: u64,
+    /// Time the latest document was verified at
+    verified_at: Option<u64>,
+    /// Expiry of the first certificate of the latest document's chain
+    not_after: Option<i64>,
+}
+
+impl ConnectionAttestation {
+    /// Tracker requiring re-attestation `max_age_secs` after each verification
+    pub fn new(max_age_secs: u64) -> Self {
+        ConnectionAttestation {
+            max_age_secs,
+            expiry_margin_secs: 0,
+            verified_at: None,
+            not_after: None,
+        }
+    }
+
+    /// Requires re-attestation `margin_secs` before a certificate of the latest document
+    /// expires too, rather than only once it expired
+    pub fn with_expiry_margin(mut self, margin_secs: u64) -> Self {
+        self.expiry_margin_secs = margin_secs;
+        self
+    }
+
+    /// Records `doc` as the peer's latest document, verified at
+    /// [`NitroAdDoc::verified_at`]. Pass documents whose verification succeeded only.
+    pub fn record(&mut self, doc: &NitroAdDoc) {
+        self.verified_at = Some(doc.verified_at());
+        self.not_after = doc.payload().chain_not_after();
+    }
+
+    /// Time the latest document was verified at, `None` before the first
+    pub fn verified_at(&self) -> Option<u64> {
+        self.verified_at
+    }
+
+    /// First unix time [`ConnectionAttestation::check`] requires re-attestation at,
+    /// `None` before the first document
+    pub fn reattest_at(&self) -> Option<u64> {
+        let aged = self.verified_at?.saturating_add(self.max_age_secs);
+        let expiring = self.not_after.map(|not_after| {
+            (not_after.max(0) as u64).saturating_sub(self.expiry_margin_secs)
+        });
+        Some(expiring.map_or(aged, |expiring| expiring.min(aged)))
+    }
+
+    /// Freshness of the connection's attestation at `unix_ts_sec`
+    pub fn check(&self, unix_ts_sec: u64) -> Freshness {
+        let verified_at = match self.verified_at {
+            Some(verified_at) => verified_at,
+            None => return Freshness::Unattested,
+        };
+        let expiring = self.not_after.is_none_or(|not_after| {
+            unix_ts_sec.saturating_add(self.expiry_margin_secs) as i64 >= not_after
+        });
+        if expiring {
+            Freshness::CertificateExpiring
+        } else if unix_ts_sec >= verified_at.saturating_add(self.max_age_secs) {
+            Freshness::Expired
+        } else {
+            Freshness::Fresh {
+                until: self.reattest_at().unwrap_or(unix_ts_sec),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_freshness() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+        let not_after = doc.payload().chain_not_after().unwrap() as u64;
+
+        let mut attestation = ConnectionAttestation::new(600);
+        assert_eq!(attestation.check(1614967200), Freshness::Unattested);
+        assert_eq!(attestation.reattest_at(), None);
+        attestation.record(&doc);
+        assert_eq!(attestation.verified_at(), Some(1614967200));
+        assert_eq!(attestation.check(1614967200), Freshness::Fresh { until: 1614967800 });
+        assert!(!attestation.check(1614967799).requires_reattestation());
+        assert_eq!(attestation.check(1614967800), Freshness::Expired);
+
+        // the certificates expire before the maximum age passes
+        let mut attestation = ConnectionAttestation::new(7 * 24 * 3600).with_expiry_margin(60);
+        attestation.record(&doc);
+        assert_eq!(attestation.reattest_at(), Some(not_after - 60));
+        assert_eq!(attestation.check(not_after - 61), Freshness::Fresh { until: not_after - 60 });
+        assert_eq!(attestation.check(not_after - 60), Freshness::CertificateExpiring);
+        assert!(attestation.check(not_after + 1).requires_reattestation());
+    }
+}
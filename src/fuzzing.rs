@@ -0,0 +1,154 @@
+//! Structured fuzzing support
+//!
+//! [`NitroAdDocPayload`] implements [`arbitrary::Arbitrary`], generating mostly
+//! well-formed payloads so fuzzers get past the first field checks quickly, and
+//! [`ArbitraryDocument`] wraps such a payload into a COSE_Sign1 envelope.
+//! Randomly generated documents never carry a valid signature, so
+//! [`NitroAdDoc::from_bytes_relaxed`] runs the parsing and payload checks only.
+//!
+//! See the targets in the `fuzz/` directory for usage.
+
+use std::collections::BTreeMap;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use chrono::{TimeZone, Utc};
+use serde_bytes::ByteBuf;
+use serde_cbor::Value as CborValue;
+
+use crate::{parse_and_validate_payload, Bytes, NitroAdDoc, NitroAdDocPayload, NitroAdError};
+
+// 2019-01-01 .. 2031-01-01, a bit wider than the accepted timestamp range
+const TIMESTAMP_MS_RANGE: std::ops::RangeInclusive<i64> = 1_546_300_800_000..=1_924_992_000_000;
+
+fn arbitrary_bytes(u: &mut Unstructured<'_>) -> Result<Bytes<'static>> {
+    Ok(Bytes::from(Vec::<u8>::arbitrary(u)?))
+}
+
+fn arbitrary_optional_bytes(u: &mut Unstructured<'_>) -> Result<Option<Bytes<'static>>> {
+    Ok(if u.arbitrary()? {
+        Some(arbitrary_bytes(u)?)
+    } else {
+        None
+    })
+}
+
+impl<'a> Arbitrary<'a> for NitroAdDocPayload<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let digest = if u.ratio(7, 8)? {
+            String::from("SHA384")
+        } else {
+            u.arbitrary()?
+        };
+
+        let timestamp = Utc
+            .timestamp_millis_opt(u.int_in_range(TIMESTAMP_MS_RANGE)?)
+            .unwrap();
+
+        let mut pcrs = BTreeMap::new();
+        for i in 0..u.int_in_range(0..=33u8)? {
+            // occasionally leave holes in the PCR indexes
+            if u.ratio(1, 16)? {
+                continue;
+            }
+            let len = *u.choose(&[32, 48, 64, 48, 0, 20])?;
+            pcrs.insert(i, Bytes::from(u.bytes(len)?.to_vec()));
+        }
+
+        let cabundle_len = u.int_in_range(0..=4)?;
+        let cabundle = (0..cabundle_len)
+            .map(|_| arbitrary_bytes(u))
+            .collect::<Result<_>>()?;
+
+        Ok(NitroAdDocPayload {
+            module_id: u.arbitrary()?,
+            digest,
+            timestamp,
+            pcrs,
+            certificate: arbitrary_bytes(u)?,
+            cabundle,
+            public_key: arbitrary_optional_bytes(u)?,
+            user_data: arbitrary_optional_bytes(u)?,
+            nonce: arbitrary_optional_bytes(u)?,
+        })
+    }
+}
+
+/// Arbitrary payload in a COSE_Sign1 envelope with an ES384 protected header
+#[derive(Debug, Clone)]
+pub struct ArbitraryDocument {
+    pub payload: NitroAdDocPayload<'static>,
+    pub signature: Vec<u8>,
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryDocument {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let payload = u.arbitrary()?;
+        let signature = if u.ratio(15, 16)? {
+            u.bytes(96)?.to_vec()
+        } else {
+            u.arbitrary()?
+        };
+        Ok(ArbitraryDocument { payload, signature })
+    }
+}
+
+impl ArbitraryDocument {
+    /// Encoded COSE_Sign1 document
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut protected = BTreeMap::new();
+        protected.insert(CborValue::Integer(1), CborValue::Integer(-35));
+
+        let protected = serde_cbor::to_vec(&CborValue::Map(protected)).unwrap();
+        let payload = serde_cbor::to_vec(&self.payload.to_cbor_value()).unwrap();
+
+        serde_cbor::to_vec(&(
+            ByteBuf::from(protected),
+            CborValue::Map(BTreeMap::new()),
+            ByteBuf::from(payload),
+            ByteBuf::from(self.signature.clone()),
+        ))
+        .unwrap()
+    }
+}
+
+impl<'a> NitroAdDoc<'a> {
+    /// Runs the COSE and payload checks of [`NitroAdDoc::from_bytes`], but skips
+    /// certificate chain and signature verification. Never use outside of fuzzing.
+    pub fn from_bytes_relaxed(bytes: &'a [u8]) -> std::result::Result<Self, NitroAdError> {
+        let (_, payload_ref) = parse_and_validate_payload(bytes, Utc::now())?;
+        Ok(NitroAdDoc {
+            raw: Bytes::from(bytes),
+            payload_ref,
+            // nothing was verified
+            verified_at: 0,
+            verify_err: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arbitrary_document_roundtrip() {
+        let seed: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&seed);
+
+        for _ in 0..16 {
+            let doc = match ArbitraryDocument::arbitrary(&mut u) {
+                Ok(doc) => doc,
+                Err(_) => break,
+            };
+            // must never panic
+            let _ = NitroAdDoc::from_bytes_relaxed(&doc.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_relaxed_accepts_real_document() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let doc = NitroAdDoc::from_bytes_relaxed(ad_blob).unwrap();
+        assert_eq!(doc.payload().digest, "SHA384");
+    }
+}
@@ -0,0 +1,441 @@
+//! Sans-io mutual attestation handshake
+//!
+//! A [`Verifier`] and an [`Attester`] exchange three [`Message`]s:
+//! 1. [`Message::Challenge`], verifier to attester: a random nonce and the verifier's
+//!    ephemeral P-384 key, with a document attesting that key in mutual handshakes
+//! 2. [`Message::Evidence`], attester to verifier: a document for the nonce whose
+//!    `public_key` is the attester's ephemeral key, and the attester's key confirmation
+//! 3. [`Message::Finished`], verifier to attester: the verifier's key confirmation
+//!
+//! Both sides derive their keys from the ECDH secret of the two ephemeral keys with
+//! HKDF-SHA384, salted with the hash of the challenge and the attester's document. A
+//! matching key confirmation proves that the peer holds the private key its document
+//! vouches for and saw the same messages, so a relayed document is of no use without
+//! the enclave that holds its key. The resulting [`Session`] holds a key for protecting
+//! further traffic.
+//!
+//! The state machines do no I/O: applications send [`Message::to_bytes`] over vsock,
+//! TCP, HTTP or any other transport and pass what they receive back in.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::handshake::{Message, Verifier};
+//! use aws_nitro_enclaves_attestation::Verifier as DocumentVerifier;
+//!
+//! # fn send(_: Vec<u8>) {}
+//! # fn receive() -> Vec<u8> { Vec::new() }
+//! # let aws_root_der = Vec::new();
+//! # let now = 0;
+//! let verifier = Verifier::new(DocumentVerifier::new(aws_root_der), &mut rand_core::OsRng)?;
+//! send(verifier.challenge().to_bytes()?);
+//! let (session, finished) = verifier.verify(&Message::from_bytes(&receive())?, now)?;
+//! send(finished.to_bytes()?);
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use p384::ecdh::diffie_hellman;
+use p384::pkcs8::{DecodePublicKey, EncodePublicKey};
+use p384::{PublicKey, SecretKey};
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha384};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::nsm::AttestedKey;
+use crate::NitroAdError;
+
+/// Length of challenge nonces
+pub const NONCE_LEN: usize = 32;
+
+/// Length of [`Session::key`]
+pub const SESSION_KEY_LEN: usize = 32;
+
+/// HKDF info strings of the derived values, also separating the two confirmations
+const ATTESTER_CONFIRMATION: &[u8] = b"nitro-ad handshake attester confirmation";
+const VERIFIER_CONFIRMATION: &[u8] = b"nitro-ad handshake verifier confirmation";
+const SESSION_KEY: &[u8] = b"nitro-ad handshake session key";
+
+/// Handshake message, CBOR encoded on the wire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    Challenge {
+        nonce: ByteBuf,
+        /// DER encoded SubjectPublicKeyInfo of the verifier's ephemeral key
+        public_key: ByteBuf,
+        /// Document attesting `public_key`, in mutual handshakes
+        document: Option<ByteBuf>,
+    },
+    Evidence {
+        document: ByteBuf,
+        confirmation: ByteBuf,
+    },
+    Finished {
+        confirmation: ByteBuf,
+    },
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, NitroAdError> {
+        serde_cbor::to_vec(self).map_err(NitroAdError::CBORError)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NitroAdError> {
+        serde_cbor::from_slice(bytes).map_err(NitroAdError::CBORError)
+    }
+}
+
+/// Keys both sides derive once the attester's document is known
+struct Keys {
+    attester_confirmation: Zeroizing<[u8; 48]>,
+    verifier_confirmation: Zeroizing<[u8; 48]>,
+    session: Zeroizing<[u8; SESSION_KEY_LEN]>,
+}
+
+impl Keys {
+    fn derive(
+        secret_key: &SecretKey,
+        peer: &PublicKey,
+        challenge: &Message,
+        document: &[u8],
+    ) -> Result<Self, NitroAdError> {
+        let challenge = challenge.to_bytes()?;
+        let mut transcript = Sha384::new();
+        transcript.update((challenge.len() as u64).to_be_bytes());
+        transcript.update(&challenge);
+        transcript.update(document);
+        let transcript = transcript.finalize();
+
+        let shared = diffie_hellman(secret_key.to_nonzero_scalar(), peer.as_affine());
+        let hkdf = shared.extract::<Sha384>(Some(&transcript));
+        let mut keys = Keys {
+            attester_confirmation: Zeroizing::new([0; 48]),
+            verifier_confirmation: Zeroizing::new([0; 48]),
+            session: Zeroizing::new([0; SESSION_KEY_LEN]),
+        };
+        // lengths far below HKDF's limit of 255 hashes
+        hkdf.expand(ATTESTER_CONFIRMATION, &mut keys.attester_confirmation[..]).unwrap();
+        hkdf.expand(VERIFIER_CONFIRMATION, &mut keys.verifier_confirmation[..]).unwrap();
+        hkdf.expand(SESSION_KEY, &mut keys.session[..]).unwrap();
+        Ok(keys)
+    }
+}
+
+/// Fails with [`NitroAdError::KeyConfirmationFailed`] unless `received` is `expected`
+fn confirm(received: &[u8], expected: &[u8]) -> Result<(), NitroAdError> {
+    if bool::from(received.ct_eq(expected)) {
+        Ok(())
+    } else {
+        Err(NitroAdError::KeyConfirmationFailed)
+    }
+}
+
+/// DER encoded SubjectPublicKeyInfo of `secret_key`
+fn spki(secret_key: &SecretKey) -> Result<Vec<u8>, NitroAdError> {
+    Ok(secret_key
+        .public_key()
+        .to_public_key_der()
+        .map_err(|e| NitroAdError::InvalidSigningKey(e.to_string()))?
+        .into_vec())
+}
+
+/// Established handshake
+pub struct Session {
+    key: Zeroizing<[u8; SESSION_KEY_LEN]>,
+    peer_document: Option<Vec<u8>>,
+}
+
+impl Session {
+    /// Key shared with the peer, e.g. for an AEAD protecting further messages
+    pub fn key(&self) -> &[u8; SESSION_KEY_LEN] {
+        &self.key
+    }
+
+    /// Verified document of the peer: the attester's on the verifier side, and the
+    /// verifier's on the attester side of mutual handshakes
+    pub fn peer_document(&self) -> Option<&[u8]> {
+        self.peer_document.as_deref()
+    }
+}
+
+/// Verifying side of the handshake, sending the challenge
+pub struct Verifier {
+    verifier: crate::Verifier,
+    secret_key: SecretKey,
+    challenge: Message,
+}
+
+impl Verifier {
+    /// Handshake verifying the attester's document with `verifier`
+    pub fn new(
+        verifier: crate::Verifier,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self, NitroAdError> {
+        let secret_key = SecretKey::random(rng);
+        let public_key = spki(&secret_key)?;
+        Ok(Self::with_key(verifier, rng, secret_key, public_key, None))
+    }
+
+    /// Mutual handshake, attesting the verifier's key with a document of `attester`
+    /// too. The attester must verify it, see [`Attester::respond`].
+    pub fn mutual<A: crate::nsm::Attester + ?Sized>(
+        verifier: crate::Verifier,
+        attester: &A,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self, NitroAdError> {
+        let key = AttestedKey::generate(attester, rng, None, None)?;
+        let document = Some(ByteBuf::from(key.document));
+        Ok(Self::with_key(verifier, rng, key.secret_key, key.public_key, document))
+    }
+
+    fn with_key(
+        verifier: crate::Verifier,
+        rng: &mut impl CryptoRngCore,
+        secret_key: SecretKey,
+        public_key: Vec<u8>,
+        document: Option<ByteBuf>,
+    ) -> Self {
+        let mut nonce = vec![0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let challenge = Message::Challenge {
+            nonce: ByteBuf::from(nonce),
+            public_key: ByteBuf::from(public_key),
+            document,
+        };
+        Verifier {
+            verifier,
+            secret_key,
+            challenge,
+        }
+    }
+
+    /// First message, to send to the attester
+    pub fn challenge(&self) -> &Message {
+        &self.challenge
+    }
+
+    /// Verifies the attester's [`Message::Evidence`] at `unix_ts_sec` and its key
+    /// confirmation. Returns the session together with the [`Message::Finished`] to
+    /// send back.
+    pub fn verify(
+        self,
+        evidence: &Message,
+        unix_ts_sec: u64,
+    ) -> Result<(Session, Message), NitroAdError> {
+        let (document, confirmation) = match evidence {
+            Message::Evidence {
+                document,
+                confirmation,
+            } => (document, confirmation),
+            _ => return Err(NitroAdError::HandshakeError("expected evidence")),
+        };
+        let nonce = match &self.challenge {
+            Message::Challenge { nonce, .. } => nonce,
+            _ => unreachable!("verifiers only send challenges"),
+        };
+
+        let doc = self.verifier.verify(document, unix_ts_sec)?;
+        match doc.payload().nonce.as_deref() {
+            Some(got) if bool::from(got.ct_eq(nonce)) => (),
+            _ => return Err(NitroAdError::NonceMismatch),
+        }
+        let peer = doc
+            .payload()
+            .public_key
+            .as_deref()
+            .and_then(|key| PublicKey::from_public_key_der(key).ok())
+            .ok_or(NitroAdError::KeyBindingMismatch)?;
+
+        let keys = Keys::derive(&self.secret_key, &peer, &self.challenge, document)?;
+        confirm(confirmation, &keys.attester_confirmation[..])?;
+        let finished = Message::Finished {
+            confirmation: ByteBuf::from(keys.verifier_confirmation.to_vec()),
+        };
+        let session = Session {
+            key: keys.session,
+            peer_document: Some(document.to_vec()),
+        };
+        Ok((session, finished))
+    }
+}
+
+/// Attesting side of the handshake, answering the challenge
+pub struct Attester {
+    keys: Keys,
+    peer_document: Option<Vec<u8>>,
+}
+
+impl Attester {
+    /// Answers `challenge` with a document of `attester` for a fresh ephemeral key.
+    /// With a `verifier`, the handshake is mutual: the challenge must carry a document
+    /// for the verifier's key which passes it at `unix_ts_sec`.
+    pub fn respond<A: crate::nsm::Attester + ?Sized>(
+        attester: &A,
+        verifier: Option<&crate::Verifier>,
+        rng: &mut impl CryptoRngCore,
+        challenge: &Message,
+        unix_ts_sec: u64,
+    ) -> Result<(Self, Message), NitroAdError> {
+        let (nonce, public_key, document) = match challenge {
+            Message::Challenge {
+                nonce,
+                public_key,
+                document,
+            } => (nonce, public_key, document),
+            _ => return Err(NitroAdError::HandshakeError("expected challenge")),
+        };
+        if nonce.len() != NONCE_LEN {
+            return Err(NitroAdError::HandshakeError("challenge nonce has a bad length"));
+        }
+        let peer = PublicKey::from_public_key_der(public_key)
+            .map_err(|_| NitroAdError::HandshakeError("challenge key is not a P-384 key"))?;
+
+        let peer_document = match (verifier, document) {
+            (Some(verifier), Some(document)) => {
+                let doc = verifier.verify(document, unix_ts_sec)?;
+                if doc.payload().public_key.as_deref() != Some(&public_key[..]) {
+                    return Err(NitroAdError::KeyBindingMismatch);
+                }
+                Some(document.to_vec())
+            }
+            (Some(_), None) => {
+                return Err(NitroAdError::HandshakeError("challenge carries no document"))
+            }
+            (None, _) => None,
+        };
+
+        let key = AttestedKey::generate(attester, rng, None, Some(nonce))?;
+        let keys = Keys::derive(&key.secret_key, &peer, challenge, &key.document)?;
+        let evidence = Message::Evidence {
+            confirmation: ByteBuf::from(keys.attester_confirmation.to_vec()),
+            document: ByteBuf::from(key.document),
+        };
+        Ok((Attester { keys, peer_document }, evidence))
+    }
+
+    /// Checks the verifier's [`Message::Finished`] and establishes the session
+    pub fn finish(self, finished: &Message) -> Result<Session, NitroAdError> {
+        match finished {
+            Message::Finished { confirmation } => {
+                confirm(confirmation, &self.keys.verifier_confirmation[..])?
+            }
+            _ => return Err(NitroAdError::HandshakeError("expected finished")),
+        }
+        Ok(Session {
+            key: self.keys.session,
+            peer_document: self.peer_document,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use rand_core::OsRng;
+
+    use crate::testing::MockNsm;
+    use crate::VerifierPolicy;
+
+    fn now() -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    /// Message after a round trip through its encoding
+    fn wire(message: &Message) -> Message {
+        Message::from_bytes(&message.to_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_handshake() -> Result<(), NitroAdError> {
+        let enclave = MockNsm::new()?.with_pcr(0, &[1; 48]);
+        let policy = VerifierPolicy::new().with_pcr(0, vec![1; 48]);
+        let verifier = Verifier::new(
+            crate::Verifier::new(enclave.root_cert()).with_policy(policy),
+            &mut OsRng,
+        )?;
+
+        let challenge = wire(verifier.challenge());
+        let (attester, evidence) =
+            Attester::respond(&enclave, None, &mut OsRng, &challenge, now())?;
+        let (verifier_session, finished) = verifier.verify(&wire(&evidence), now())?;
+        let attester_session = attester.finish(&wire(&finished))?;
+
+        assert_eq!(verifier_session.key(), attester_session.key());
+        assert!(verifier_session.peer_document().is_some());
+        assert!(attester_session.peer_document().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutual_handshake() -> Result<(), NitroAdError> {
+        let client = MockNsm::new()?;
+        let server = MockNsm::new()?;
+        let trust_server = crate::Verifier::new(server.root_cert());
+        let trust_client = crate::Verifier::new(client.root_cert());
+
+        let verifier = Verifier::mutual(trust_server, &client, &mut OsRng)?;
+        let challenge = verifier.challenge().clone();
+        let (attester, evidence) =
+            Attester::respond(&server, Some(&trust_client), &mut OsRng, &challenge, now())?;
+        let (client_session, finished) = verifier.verify(&evidence, now())?;
+        let server_session = attester.finish(&finished)?;
+        assert_eq!(client_session.key(), server_session.key());
+        assert!(server_session.peer_document().is_some());
+
+        // the server requires the client's document, and one passing its verifier
+        let plain = Verifier::new(crate::Verifier::new(server.root_cert()), &mut OsRng)?;
+        assert!(matches!(
+            Attester::respond(&server, Some(&trust_client), &mut OsRng, plain.challenge(), now()),
+            Err(NitroAdError::HandshakeError(_))
+        ));
+        let untrusted = crate::Verifier::new(server.root_cert());
+        assert!(
+            Attester::respond(&server, Some(&untrusted), &mut OsRng, &challenge, now()).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_failures() -> Result<(), NitroAdError> {
+        let enclave = MockNsm::new()?;
+        let trust = || crate::Verifier::new(enclave.root_cert());
+
+        // evidence answering another challenge
+        let first = Verifier::new(trust(), &mut OsRng)?;
+        let second = Verifier::new(trust(), &mut OsRng)?;
+        let (_, evidence) =
+            Attester::respond(&enclave, None, &mut OsRng, first.challenge(), now())?;
+        assert!(matches!(second.verify(&evidence, now()), Err(NitroAdError::NonceMismatch)));
+
+        // tampered confirmations
+        let verifier = Verifier::new(trust(), &mut OsRng)?;
+        let (attester, evidence) =
+            Attester::respond(&enclave, None, &mut OsRng, verifier.challenge(), now())?;
+        let tampered = match evidence.clone() {
+            Message::Evidence { document, .. } => Message::Evidence {
+                document,
+                confirmation: ByteBuf::from(vec![0; 48]),
+            },
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            verifier.verify(&tampered, now()),
+            Err(NitroAdError::KeyConfirmationFailed)
+        ));
+        let finished = Message::Finished {
+            confirmation: ByteBuf::from(vec![0; 48]),
+        };
+        assert!(matches!(attester.finish(&finished), Err(NitroAdError::KeyConfirmationFailed)));
+
+        // messages out of order
+        let verifier = Verifier::new(trust(), &mut OsRng)?;
+        let challenge = verifier.challenge().clone();
+        assert!(matches!(verifier.verify(&challenge, now()), Err(NitroAdError::HandshakeError(_))));
+        assert!(matches!(
+            Attester::respond(&enclave, None, &mut OsRng, &evidence, now()),
+            Err(NitroAdError::HandshakeError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Attestation documents in HTTP headers
+//!
+//! REST services pass documents to each other in an [`HEADER_NAME`] request or response
+//! header whose value is the standard, padded base64 of the raw document, optionally
+//! gzip compressed first and then prefixed with `gzip;`:
+//! ```text
+//! X-Nitro-Attestation: hEShATgioFkRX6lpbW9kdWxlX2lkeCdpLTAyNmFl...
+//! X-Nitro-Attestation: gzip;H4sIAAAAAAAA/+y6ZVRbXbcw...
+//! ```
+//! Neither form contains characters that need escaping in a header. Certificates
+//! dominate documents and hardly compress, so gzip mostly pays off for documents with
+//! textual `user_data`. [`decode_header`] rejects values longer than
+//! [`MAX_HEADER_VALUE_LEN`] before decoding them, and documents longer than
+//! [`MAX_DOCUMENT_SIZE`] however well they compressed.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::header::{self, HeaderEncoding};
+//!
+//! # let document = Vec::new();
+//! let value = header::encode_header(&document, HeaderEncoding::Gzip)?;
+//! assert_eq!(header::decode_header(&value)?, document);
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::{NitroAdError, MAX_DOCUMENT_SIZE};
+
+pub static HEADER_NAME: &str = "X-Nitro-Attestation";
+
+/// Prefix of values holding gzip compressed documents
+pub static GZIP_PREFIX: &str = "gzip;";
+
+/// Longest value [`decode_header`] accepts, enough for the uncompressed base64 of a
+/// [`MAX_DOCUMENT_SIZE`] document. Proxies and servers often limit headers to 8 KiB,
+/// which NSM documents fit unless they carry large `user_data`.
+pub const MAX_HEADER_VALUE_LEN: usize = 22 * 1024;
+
+/// Form of the document in a header value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderEncoding {
+    Base64,
+    /// base64 of the gzip compressed document, after [`GZIP_PREFIX`]
+    Gzip,
+}
+
+fn malformed(message: String) -> NitroAdError {
+    NitroAdError::MalformedHeader(message)
+}
+
+/// Header value carrying `document`. Fails for documents longer than
+/// [`MAX_DOCUMENT_SIZE`] and if the value would be longer than [`MAX_HEADER_VALUE_LEN`].
+pub fn encode_header(document: &[u8], encoding: HeaderEncoding) -> Result<String, NitroAdError> {
+    if document.len() > MAX_DOCUMENT_SIZE {
+        return Err(NitroAdError::DocumentTooLarge {
+            limit: MAX_DOCUMENT_SIZE,
+        });
+    }
+    let value = match encoding {
+        HeaderEncoding::Base64 => base64::encode(document),
+        HeaderEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            let compressed = encoder.write_all(document).and_then(|()| encoder.finish());
+            let compressed = compressed.map_err(|e| malformed(format!("gzip failed: {}", e)))?;
+            format!("{}{}", GZIP_PREFIX, base64::encode(compressed))
+        }
+    };
+    if value.len() > MAX_HEADER_VALUE_LEN {
+        return Err(malformed(format!(
+            "value would be {} bytes long, longer than {}",
+            value.len(),
+            MAX_HEADER_VALUE_LEN
+        )));
+    }
+    Ok(value)
+}
+
+/// Document carried by a header value of either encoding
+pub fn decode_header(value: &str) -> Result<Vec<u8>, NitroAdError> {
+    let value = value.trim();
+    if value.len() > MAX_HEADER_VALUE_LEN {
+        return Err(malformed(format!(
+            "value is {} bytes long, longer than {}",
+            value.len(),
+            MAX_HEADER_VALUE_LEN
+        )));
+    }
+    let (encoding, encoded) = match value.strip_prefix(GZIP_PREFIX) {
+        Some(encoded) => (HeaderEncoding::Gzip, encoded),
+        None => (HeaderEncoding::Base64, value),
+    };
+    let decoded = base64::decode(encoded).map_err(|e| malformed(format!("bad base64: {}", e)))?;
+    let document = match encoding {
+        HeaderEncoding::Base64 => decoded,
+        HeaderEncoding::Gzip => {
+            // one byte beyond the limit tells too long documents from those of its size
+            let mut document = Vec::new();
+            GzDecoder::new(&decoded[..])
+                .take(MAX_DOCUMENT_SIZE as u64 + 1)
+                .read_to_end(&mut document)
+                .map_err(|e| malformed(format!("bad gzip data: {}", e)))?;
+            document
+        }
+    };
+    if document.len() > MAX_DOCUMENT_SIZE {
+        return Err(NitroAdError::DocumentTooLarge {
+            limit: MAX_DOCUMENT_SIZE,
+        });
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Verifier;
+
+    #[test]
+    fn test_header_round_trip() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        let plain = encode_header(ad_blob, HeaderEncoding::Base64)?;
+        let gzip = encode_header(ad_blob, HeaderEncoding::Gzip)?;
+        assert!(gzip.starts_with(GZIP_PREFIX));
+        assert!(gzip.len() < plain.len());
+        for value in &[plain, gzip] {
+            assert!(value.bytes().all(|b| b.is_ascii_graphic()));
+            assert_eq!(decode_header(value)?, &ad_blob[..]);
+        }
+
+        let value = format!(" {}\r\n", encode_header(ad_blob, HeaderEncoding::Base64)?);
+        let document = decode_header(&value)?;
+        Verifier::new(&root_cert[..]).verify(&document, 1614967200)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_size_guards() {
+        let too_large = vec![0u8; MAX_DOCUMENT_SIZE + 1];
+        for encoding in &[HeaderEncoding::Base64, HeaderEncoding::Gzip] {
+            let result = encode_header(&too_large, *encoding);
+            assert!(matches!(result, Err(NitroAdError::DocumentTooLarge { .. })));
+        }
+        let largest = vec![0xa5; MAX_DOCUMENT_SIZE];
+        let value = encode_header(&largest, HeaderEncoding::Base64).unwrap();
+        assert_eq!(decode_header(&value).unwrap(), largest);
+
+        // a megabyte of zeros compresses to about a kilobyte
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0u8; 1 << 20]).unwrap();
+        let bomb = format!("{}{}", GZIP_PREFIX, base64::encode(encoder.finish().unwrap()));
+        assert!(bomb.len() < MAX_HEADER_VALUE_LEN);
+        let result = decode_header(&bomb);
+        assert!(matches!(result, Err(NitroAdError::DocumentTooLarge { .. })));
+
+        let long = "A".repeat(MAX_HEADER_VALUE_LEN + 4);
+        assert!(matches!(decode_header(&long), Err(NitroAdError::MalformedHeader(_))));
+        assert!(matches!(decode_header("not base64!"), Err(NitroAdError::MalformedHeader(_))));
+        let not_gzip = format!("{}{}", GZIP_PREFIX, base64::encode(b"plain"));
+        assert!(matches!(decode_header(&not_gzip), Err(NitroAdError::MalformedHeader(_))));
+    }
+}
@@ -0,0 +1,142 @@
+//! Encrypting to attested enclaves with HPKE
+//!
+//! [`encrypt_to_attested_key`] seals a message with HPKE (RFC 9180) in base mode to the
+//! `public_key` of a verified document, e.g. of [`AttestedKey::generate`], so only the
+//! enclave holding the private key opens it, with [`AttestedKey::decrypt`]. The suite
+//! is DHKEM(P-384, HKDF-SHA384), HKDF-SHA384 and AES-256-GCM, and the HPKE `info` is
+//! the SHA-384 hash of the document, so a message only opens for the attestation it
+//! was sealed to. Sealed messages are the encapsulated key followed by the ciphertext.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::hpke::encrypt_to_attested_key;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! let doc = Verifier::new(aws_root_der).verify(&document, now)?;
+//! let sealed = encrypt_to_attested_key(&doc, b"database password", b"")?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use alloc::vec::Vec;
+
+use ::hpke::aead::AesGcm256;
+use ::hpke::kdf::HkdfSha384;
+use ::hpke::kem::DhP384HkdfSha384;
+#[cfg(feature = "nsm")]
+use ::hpke::{single_shot_open, OpModeR};
+use ::hpke::{single_shot_seal, Deserializable, Kem, OpModeS, Serializable};
+use p384::elliptic_curve::sec1::ToEncodedPoint;
+use rand_core::OsRng;
+
+use crate::crypto::sha384;
+use crate::ecdh::attested_public_key;
+#[cfg(feature = "nsm")]
+use crate::nsm::AttestedKey;
+use crate::{NitroAdDoc, NitroAdError};
+
+/// Length of the encapsulated key starting sealed messages, an uncompressed P-384 point
+pub const ENCAPPED_KEY_LEN: usize = 97;
+
+type PublicKey = <DhP384HkdfSha384 as Kem>::PublicKey;
+#[cfg(feature = "nsm")]
+type EncappedKey = <DhP384HkdfSha384 as Kem>::EncappedKey;
+
+/// Seals `plaintext` and authenticates `aad` for the enclave holding the private key of
+/// `doc`'s `public_key`. Fails for documents whose certificate chain didn't verify.
+pub fn encrypt_to_attested_key(
+    doc: &NitroAdDoc,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, NitroAdError> {
+    let point = attested_public_key(doc)?.to_encoded_point(false);
+    let recipient =
+        PublicKey::from_bytes(point.as_bytes()).map_err(|_| NitroAdError::UnsupportedPublicKey)?;
+    let (encapped_key, ciphertext) =
+        single_shot_seal::<AesGcm256, HkdfSha384, DhP384HkdfSha384, _>(
+            &OpModeS::Base,
+            &recipient,
+            &sha384(doc.as_bytes()),
+            plaintext,
+            aad,
+            &mut OsRng,
+        )
+        .map_err(NitroAdError::HpkeError)?;
+    let mut sealed = encapped_key.to_bytes().to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+#[cfg(feature = "nsm")]
+impl AttestedKey {
+    /// Opens a message of [`encrypt_to_attested_key`] sealed to this key's document,
+    /// checking `aad`
+    pub fn decrypt(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, NitroAdError> {
+        if sealed.len() < ENCAPPED_KEY_LEN {
+            return Err(NitroAdError::HpkeError(::hpke::HpkeError::OpenError));
+        }
+        let (encapped_key, ciphertext) = sealed.split_at(ENCAPPED_KEY_LEN);
+        let encapped_key =
+            EncappedKey::from_bytes(encapped_key).map_err(NitroAdError::HpkeError)?;
+        let private_key = <DhP384HkdfSha384 as Kem>::PrivateKey::from_bytes(
+            &self.secret_key.to_bytes(),
+        )
+        .map_err(NitroAdError::HpkeError)?;
+        single_shot_open::<AesGcm256, HkdfSha384, DhP384HkdfSha384>(
+            &OpModeR::Base,
+            &private_key,
+            &encapped_key,
+            &sha384(&self.document),
+            ciphertext,
+            aad,
+        )
+        .map_err(NitroAdError::HpkeError)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use crate::testing::MockNsm;
+
+    #[test]
+    fn test_encrypt_to_attested_key() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let enclave_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&enclave_key.document, &mock.root_cert(), now)?;
+
+        let sealed = encrypt_to_attested_key(&doc, b"secret", b"header")?;
+        assert_eq!(sealed.len(), ENCAPPED_KEY_LEN + b"secret".len() + 16);
+        assert_eq!(enclave_key.decrypt(&sealed, b"header")?, b"secret");
+        assert_ne!(sealed, encrypt_to_attested_key(&doc, b"secret", b"header")?);
+
+        let rejected = |sealed: &[u8], aad: &[u8]| {
+            matches!(
+                enclave_key.decrypt(sealed, aad),
+                Err(NitroAdError::HpkeError(::hpke::HpkeError::OpenError))
+            )
+        };
+        assert!(rejected(&sealed, b"other header"));
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(rejected(&tampered, b"header"));
+        assert!(rejected(&sealed[..10], b"header"));
+
+        // a second key of the same enclave doesn't open it
+        let other_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        assert!(other_key.decrypt(&sealed, b"header").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_without_public_key() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = mock.verified_doc(Default::default(), now)?;
+        assert!(matches!(
+            encrypt_to_attested_key(&doc, b"secret", b""),
+            Err(NitroAdError::UnsupportedPublicKey)
+        ));
+        Ok(())
+    }
+}
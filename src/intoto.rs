@@ -0,0 +1,142 @@
+//! in-toto attestation statements
+//!
+//! [`Statement`] wraps a verified document into an in-toto Statement v1 whose
+//! subject is the enclave image, identified by its PCR0 measurement, and whose
+//! predicate records the verification result, in the spirit of a SLSA
+//! verification summary.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{NitroAdDoc, NitroAdError};
+
+pub static STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+pub static PREDICATE_TYPE: &str =
+    "https://github.com/ppmag/aws-nitro-enclaves-attestation/verification/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub predicate: VerificationPredicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subject {
+    pub name: String,
+    /// Algorithm name to hex encoded digest
+    pub digest: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationPredicate {
+    pub verifier: Verifier,
+    /// RFC 3339
+    pub time_verified: String,
+    /// Only documents which passed verification are wrapped, so always `"PASSED"`
+    pub verification_result: String,
+    pub module_id: String,
+    /// Document `timestamp`, RFC 3339
+    pub document_timestamp: String,
+    /// PCR index to hex encoded value
+    pub pcrs: BTreeMap<u8, String>,
+    pub debug_mode: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verifier {
+    pub id: String,
+    pub version: String,
+}
+
+impl Statement {
+    /// Statement about a document verified at `now_sec`. Fails for documents whose
+    /// certificate chain didn't verify, or which lack PCR0.
+    pub fn from_doc(doc: &NitroAdDoc, now_sec: u64) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+
+        let payload = doc.payload();
+        let pcr0 = payload.pcrs.get(&0).ok_or(NitroAdError::MissingPcr(0))?;
+        let algorithm = match pcr0.len() {
+            32 => "sha256",
+            48 => "sha384",
+            64 => "sha512",
+            len => return Err(NitroAdError::BadPcrLength { index: 0, len }),
+        };
+
+        // chrono can't represent times past the year 262143
+        let time_verified = i64::try_from(now_sec)
+            .ok()
+            .and_then(|sec| Utc.timestamp_opt(sec, 0).single())
+            .ok_or(NitroAdError::TimestampOutOfRange {
+                ts: DateTime::<Utc>::MAX_UTC,
+            })?;
+
+        Ok(Statement {
+            statement_type: String::from(STATEMENT_TYPE),
+            subject: vec![Subject {
+                name: payload.module_id.clone(),
+                digest: std::iter::once((String::from(algorithm), hex::encode(pcr0))).collect(),
+            }],
+            predicate_type: String::from(PREDICATE_TYPE),
+            predicate: VerificationPredicate {
+                verifier: Verifier {
+                    id: String::from(env!("CARGO_PKG_NAME")),
+                    version: String::from(env!("CARGO_PKG_VERSION")),
+                },
+                time_verified: time_verified.to_rfc3339_opts(SecondsFormat::Secs, true),
+                verification_result: String::from("PASSED"),
+                module_id: payload.module_id.clone(),
+                document_timestamp: payload
+                    .timestamp
+                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+                pcrs: payload
+                    .pcrs
+                    .iter()
+                    .map(|(i, val)| (*i, hex::encode(val)))
+                    .collect(),
+                debug_mode: payload.is_debug_mode(),
+            },
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_from_document() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let js: serde_json::Value =
+            serde_json::from_str(&Statement::from_doc(&doc, 1614967200)?.to_json()?)?;
+        assert_eq!(js["_type"], STATEMENT_TYPE);
+        assert_eq!(js["subject"][0]["digest"]["sha384"], hex::encode(&doc.payload().pcrs[&0]));
+        assert_eq!(js["predicateType"], PREDICATE_TYPE);
+        assert_eq!(js["predicate"]["timeVerified"], "2021-03-05T18:00:00Z");
+        assert_eq!(js["predicate"]["verificationResult"], "PASSED");
+        assert_eq!(js["predicate"]["debugMode"], true);
+
+        let expired = NitroAdDoc::from_bytes(ad_blob, root_cert, 1714967200)?;
+        assert!(Statement::from_doc(&expired, 1714967200).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+//! AWS KMS key policy condition keys and recipients
+//!
+//! KMS matches the attestation document of `Decrypt`, `GenerateDataKey` and
+//! `GenerateRandom` requests against `kms:RecipientAttestation:*` condition keys.
+//! [`KmsConditionKeys`] derives their values from a verified document, for writing
+//! or auditing key policies.
+//!
+//! With the `kms-recipient` feature, enclaves attach the document to their requests as
+//! the `Recipient` parameter: [`RecipientKey::generate`] creates the RSA key KMS
+//! encrypts its response to and requests a document carrying it, and
+//! [`RecipientKey::recipient`] is the parameter's value. KMS returns the plaintext,
+//! data key or random bytes as `CiphertextForRecipient`, a CMS EnvelopedData structure
+//! encrypted to that key, which [`RecipientKey::decrypt`] opens.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "kms-recipient")]
+use openssl::cms::CmsContentInfo;
+#[cfg(feature = "kms-recipient")]
+use openssl::error::ErrorStack;
+#[cfg(feature = "kms-recipient")]
+use openssl::md::Md;
+#[cfg(feature = "kms-recipient")]
+use openssl::pkey::{PKey, Private};
+#[cfg(feature = "kms-recipient")]
+use openssl::pkey_ctx::PkeyCtx;
+#[cfg(feature = "kms-recipient")]
+use openssl::rsa::{Padding, Rsa};
+#[cfg(feature = "kms-recipient")]
+use serde::Serialize;
+use serde_json::json;
+#[cfg(feature = "kms-recipient")]
+use zeroize::Zeroizing;
+
+#[cfg(feature = "kms-recipient")]
+use crate::crypto::{der_any, der_element};
+#[cfg(feature = "kms-recipient")]
+use crate::nsm::{AttestationRequest, Attester};
+use crate::{NitroAdDoc, NitroAdError};
+
+pub static IMAGE_SHA384_KEY: &str = "kms:RecipientAttestation:ImageSha384";
+
+/// PCRs with a documented meaning, see [`output::pcr_meaning`](crate::output::pcr_meaning)
+pub static DOCUMENTED_PCRS: &[u8] = &[0, 1, 2, 3, 4, 8];
+
+/// Condition key name to lowercase hex encoded value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KmsConditionKeys(pub BTreeMap<String, String>);
+
+impl KmsConditionKeys {
+    /// `ImageSha384` and the documented PCRs present in the document
+    pub fn from_doc(doc: &NitroAdDoc) -> Result<Self, NitroAdError> {
+        let present: Vec<u8> = DOCUMENTED_PCRS
+            .iter()
+            .copied()
+            .filter(|i| doc.payload().pcrs.contains_key(i))
+            .collect();
+        Self::with_pcrs(doc, &present)
+    }
+
+    /// `ImageSha384` and the given PCRs, all of which must be present in the document.
+    /// Fails for documents whose certificate chain didn't verify.
+    pub fn with_pcrs(doc: &NitroAdDoc, pcrs: &[u8]) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+
+        let payload = doc.payload();
+        let pcr = |i: u8| {
+            payload
+                .pcrs
+                .get(&i)
+                .map(hex::encode)
+                .ok_or(NitroAdError::MissingPcr(i))
+        };
+
+        let mut keys = BTreeMap::new();
+        keys.insert(String::from(IMAGE_SHA384_KEY), pcr(0)?);
+        for i in pcrs {
+            keys.insert(format!("kms:RecipientAttestation:PCR{}", i), pcr(*i)?);
+        }
+
+        Ok(KmsConditionKeys(keys))
+    }
+
+    /// `Condition` element of a key policy statement
+    pub fn to_policy_condition_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string_pretty(&json!({
+            "StringEqualsIgnoreCase": self.0,
+        }))?)
+    }
+}
+
+/// Size of [`RecipientKey::generate`] keys in bits
+#[cfg(feature = "kms-recipient")]
+pub const RECIPIENT_KEY_BITS: u32 = 2048;
+
+/// Algorithm KMS encrypts the response key to the recipient's public key with
+#[cfg(feature = "kms-recipient")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum KeyEncryptionAlgorithm {
+    /// RSAES-OAEP with SHA-256, the only algorithm KMS supports
+    #[serde(rename = "RSAES_OAEP_SHA_256")]
+    RsaesOaepSha256,
+}
+
+#[cfg(feature = "kms-recipient")]
+impl KeyEncryptionAlgorithm {
+    /// Name in KMS requests
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyEncryptionAlgorithm::RsaesOaepSha256 => "RSAES_OAEP_SHA_256",
+        }
+    }
+}
+
+/// `Recipient` parameter of KMS `Decrypt`, `GenerateDataKey` and `GenerateRandom`
+/// requests. Serializes to the JSON of the KMS API, with the document in base64.
+#[cfg(feature = "kms-recipient")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Recipient {
+    #[serde(serialize_with = "serialize_base64")]
+    pub attestation_document: Vec<u8>,
+    pub key_encryption_algorithm: KeyEncryptionAlgorithm,
+}
+
+#[cfg(feature = "kms-recipient")]
+fn serialize_base64<S: serde::Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&base64::encode(bytes))
+}
+
+#[cfg(feature = "kms-recipient")]
+impl Recipient {
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Ephemeral RSA key of a KMS recipient with the document vouching for it
+#[cfg(feature = "kms-recipient")]
+pub struct RecipientKey {
+    private_key: PKey<Private>,
+    recipient: Recipient,
+}
+
+#[cfg(feature = "kms-recipient")]
+impl RecipientKey {
+    /// Generates a [`RECIPIENT_KEY_BITS`] RSA key and requests a document from
+    /// `attester` carrying its public key along with `user_data` and `nonce`
+    pub fn generate<A: Attester + ?Sized>(
+        attester: &A,
+        user_data: Option<&[u8]>,
+        nonce: Option<&[u8]>,
+    ) -> Result<Self, NitroAdError> {
+        let private_key = Rsa::generate(RECIPIENT_KEY_BITS)
+            .and_then(PKey::from_rsa)
+            .map_err(NitroAdError::SigningError)?;
+        let public_key = private_key.public_key_to_der().map_err(NitroAdError::SigningError)?;
+        let attestation_document = attester.attest(AttestationRequest {
+            user_data,
+            nonce,
+            public_key: Some(&public_key),
+        })?;
+        Ok(RecipientKey {
+            private_key,
+            recipient: Recipient {
+                attestation_document,
+                key_encryption_algorithm: KeyEncryptionAlgorithm::RsaesOaepSha256,
+            },
+        })
+    }
+
+    /// `Recipient` parameter of requests whose response is encrypted to this key
+    pub fn recipient(&self) -> &Recipient {
+        &self.recipient
+    }
+
+    pub fn private_key(&self) -> &PKey<Private> {
+        &self.private_key
+    }
+
+    /// Decrypts the `CiphertextForRecipient` of a KMS response to a request carrying
+    /// [`RecipientKey::recipient`]: the BER or DER encoded CMS EnvelopedData whose
+    /// content key is encrypted to this key with RSAES-OAEP. Fails for envelopes to
+    /// other keys and with other key encryption algorithms.
+    pub fn decrypt(
+        &self,
+        ciphertext_for_recipient: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, NitroAdError> {
+        let cms = CmsContentInfo::from_der(ciphertext_for_recipient)
+            .map_err(NitroAdError::KmsRecipientError)?;
+        // Without a recipient certificate, OpenSSL decrypts the content with a random key
+        // if the content key doesn't decrypt, which may return garbage instead of an
+        // error. Envelopes without an OAEP encrypted key fail to unwrap an empty one.
+        let der = cms.to_der().map_err(NitroAdError::KmsRecipientError)?;
+        self.unwrap_oaep(oaep_encrypted_key(&der).unwrap_or_default())
+            .and_then(|_| cms.decrypt_without_cert_check(&self.private_key))
+            .map(Zeroizing::new)
+            .map_err(NitroAdError::KmsRecipientError)
+    }
+
+    /// Content key of `encrypted_key`, decrypted with RSAES-OAEP and SHA-256 as by KMS
+    fn unwrap_oaep(&self, encrypted_key: &[u8]) -> Result<Zeroizing<Vec<u8>>, ErrorStack> {
+        let mut ctx = PkeyCtx::new(&self.private_key)?;
+        ctx.decrypt_init()?;
+        ctx.set_rsa_padding(Padding::PKCS1_OAEP)?;
+        ctx.set_rsa_oaep_md(Md::sha256())?;
+        ctx.set_rsa_mgf1_md(Md::sha256())?;
+        let mut key = Zeroizing::new(Vec::new());
+        ctx.decrypt_to_vec(encrypted_key, &mut key)?;
+        Ok(key)
+    }
+}
+
+/// RSAES-OAEP, 1.2.840.113549.1.1.7
+#[cfg(feature = "kms-recipient")]
+static RSAES_OAEP_OID_DER: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x07];
+
+/// Encrypted content key of the DER encoded EnvelopedData `der`, if it has a single
+/// recipient and its key is encrypted with RSAES-OAEP, see
+/// <https://tools.ietf.org/html/rfc5652#section-6>
+#[cfg(feature = "kms-recipient")]
+fn oaep_encrypted_key(der: &[u8]) -> Option<&[u8]> {
+    let (content_info, _) = der_element(der, 0x30)?;
+    let (_, content_info) = der_element(content_info, 0x06)?;
+    let (enveloped_data, _) = der_element(content_info, 0xa0)?;
+    let (enveloped_data, _) = der_element(enveloped_data, 0x30)?;
+    let (_, mut enveloped_data) = der_element(enveloped_data, 0x02)?;
+    if let Some((_, rest)) = der_element(enveloped_data, 0xa0) {
+        // originatorInfo
+        enveloped_data = rest;
+    }
+    let (recipient_infos, _) = der_element(enveloped_data, 0x31)?;
+    let (recipient_info, rest) = der_element(recipient_infos, 0x30)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (_, recipient_info) = der_element(recipient_info, 0x02)?;
+    let (_, _, recipient_info) = der_any(recipient_info)?;
+    let (algorithm, recipient_info) = der_element(recipient_info, 0x30)?;
+    let (oid, _) = der_element(algorithm, 0x06)?;
+    let (encrypted_key, _) = der_element(recipient_info, 0x04)?;
+    (oid == RSAES_OAEP_OID_DER).then_some(encrypted_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_keys() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let pcrs = &doc.payload().pcrs;
+
+        let keys = KmsConditionKeys::from_doc(&doc)?;
+        assert_eq!(keys.0.len(), 7);
+        assert_eq!(keys.0[IMAGE_SHA384_KEY], hex::encode(&pcrs[&0]));
+        assert_eq!(keys.0["kms:RecipientAttestation:PCR8"], hex::encode(&pcrs[&8]));
+
+        let condition: serde_json::Value = serde_json::from_str(&keys.to_policy_condition_json()?)?;
+        assert_eq!(
+            condition["StringEqualsIgnoreCase"]["kms:RecipientAttestation:PCR3"],
+            hex::encode(&pcrs[&3])
+        );
+
+        assert!(matches!(
+            KmsConditionKeys::with_pcrs(&doc, &[31]),
+            Err(NitroAdError::MissingPcr(31))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "kms-recipient", feature = "testing"))]
+    fn test_recipient_key() -> Result<(), NitroAdError> {
+        use crate::testing::MockNsm;
+
+        let mock = MockNsm::new()?;
+        let key = RecipientKey::generate(&mock, None, Some(b"nonce"))?;
+        let recipient = key.recipient();
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&recipient.attestation_document, &mock.root_cert(), now)?;
+        assert_eq!(
+            doc.payload().public_key.as_deref(),
+            Some(&key.private_key().public_key_to_der().unwrap()[..])
+        );
+        assert_eq!(key.private_key().bits(), RECIPIENT_KEY_BITS);
+
+        let json: serde_json::Value = serde_json::from_str(&recipient.to_json()?)?;
+        assert_eq!(json["KeyEncryptionAlgorithm"], "RSAES_OAEP_SHA_256");
+        assert_eq!(
+            json["AttestationDocument"],
+            base64::encode(&recipient.attestation_document)
+        );
+        assert_eq!(recipient.key_encryption_algorithm.as_str(), "RSAES_OAEP_SHA_256");
+        Ok(())
+    }
+
+    /// Recipient key of `private_key`, without a document
+    #[cfg(feature = "kms-recipient")]
+    fn recipient_key(private_key: PKey<Private>) -> RecipientKey {
+        RecipientKey {
+            private_key,
+            recipient: Recipient {
+                attestation_document: Vec::new(),
+                key_encryption_algorithm: KeyEncryptionAlgorithm::RsaesOaepSha256,
+            },
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "kms-recipient")]
+    fn test_decrypt_ciphertext_for_recipient() -> Result<(), NitroAdError> {
+        use openssl::asn1::Asn1Time;
+        use openssl::cms::CMSOptions;
+        use openssl::hash::MessageDigest;
+        use openssl::stack::Stack;
+        use openssl::symm::Cipher;
+        use openssl::x509::X509;
+
+        /// EnvelopedData to the recipient certificate of `key`. OpenSSL encrypts the
+        /// content key with PKCS#1 v1.5 rather than KMS's OAEP.
+        fn envelope(key: &RecipientKey, plaintext: &[u8]) -> Vec<u8> {
+            let mut cert = X509::builder().unwrap();
+            cert.set_version(2).unwrap();
+            cert.set_pubkey(key.private_key()).unwrap();
+            cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+            cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+            cert.sign(key.private_key(), MessageDigest::sha256()).unwrap();
+            let mut certs = Stack::new().unwrap();
+            certs.push(cert.build()).unwrap();
+            CmsContentInfo::encrypt(&certs, plaintext, Cipher::aes_256_cbc(), CMSOptions::BINARY)
+                .unwrap()
+                .to_der()
+                .unwrap()
+        }
+
+        // 32 bytes 0x07 encrypted like KMS does, by `openssl cms -encrypt -binary -aes256
+        // -keyopt rsa_padding_mode:oaep -keyopt rsa_oaep_md:sha256 -outform DER`
+        let ciphertext = include_bytes!("../tests/data/kms_ciphertext_for_recipient.der");
+        let private_key = include_bytes!("../tests/data/kms_recipient_key.der");
+        let key = recipient_key(PKey::private_key_from_der(private_key).unwrap());
+        assert_eq!(&key.decrypt(ciphertext)?[..], &[7; 32]);
+
+        let other = Rsa::generate(RECIPIENT_KEY_BITS).and_then(PKey::from_rsa).unwrap();
+        let other = recipient_key(other);
+        assert!(matches!(other.decrypt(ciphertext), Err(NitroAdError::KmsRecipientError(_))));
+        assert!(matches!(key.decrypt(b"not cms"), Err(NitroAdError::KmsRecipientError(_))));
+        assert!(matches!(
+            key.decrypt(&envelope(&key, &[7; 32])),
+            Err(NitroAdError::KmsRecipientError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Attested AWS KMS requests from enclaves
+//!
+//! [`AttestedKmsClient`] wraps an `aws_sdk_kms::Client`. Each `Decrypt`,
+//! `GenerateDataKey` and `GenerateRandom` call generates a fresh
+//! [`RecipientKey`](crate::kms::RecipientKey), attaches its document as the request's
+//! `Recipient` and decrypts the `CiphertextForRecipient` of the response, so the
+//! plaintext never leaves the enclave unencrypted.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::kms_client::AttestedKmsClient;
+//! use aws_nitro_enclaves_attestation::nsm::Nsm;
+//!
+//! # async fn run(sdk_client: aws_sdk_kms::Client, ciphertext_blob: Vec<u8>)
+//! #     -> Result<(), aws_nitro_enclaves_attestation::NitroAdError> {
+//! let client = AttestedKmsClient::new(sdk_client, Nsm::open()?);
+//! let plaintext = client.decrypt(&ciphertext_blob, None).await?;
+//! # Ok(())
+//! # }
+//! ```
+//! The RSA key generation and NSM request run on the calling task; they take in the
+//! order of tens of milliseconds.
+
+use aws_sdk_kms::error::DisplayErrorContext;
+use aws_sdk_kms::primitives::Blob;
+use aws_sdk_kms::types::{DataKeySpec, KeyEncryptionMechanism, RecipientInfo};
+use aws_sdk_kms::Client;
+use zeroize::Zeroizing;
+
+use crate::kms::RecipientKey;
+use crate::nsm::{Attester, Nsm};
+use crate::NitroAdError;
+
+/// Data key of [`AttestedKmsClient::generate_data_key`]
+#[derive(Debug)]
+pub struct DataKey {
+    /// ARN of the KMS key that encrypted the data key
+    pub key_id: Option<String>,
+    /// Plaintext data key, zeroized on drop
+    pub plaintext: Zeroizing<Vec<u8>>,
+    /// Data key encrypted under the KMS key, for storing next to the data
+    pub ciphertext_blob: Vec<u8>,
+}
+
+/// KMS client attaching attestation documents of `attester` to its requests
+#[derive(Debug)]
+pub struct AttestedKmsClient<A = Nsm> {
+    client: Client,
+    attester: A,
+}
+
+fn kms_error(e: impl std::error::Error) -> NitroAdError {
+    NitroAdError::KmsError(DisplayErrorContext(e).to_string())
+}
+
+fn recipient_info(key: &RecipientKey) -> RecipientInfo {
+    RecipientInfo::builder()
+        .key_encryption_algorithm(KeyEncryptionMechanism::RsaesOaepSha256)
+        .attestation_document(Blob::new(key.recipient().attestation_document.clone()))
+        .build()
+}
+
+/// Decrypts the `CiphertextForRecipient` of a response to a request for `key`
+fn open(
+    key: &RecipientKey,
+    ciphertext: Option<&Blob>,
+) -> Result<Zeroizing<Vec<u8>>, NitroAdError> {
+    let ciphertext = ciphertext.ok_or_else(|| {
+        NitroAdError::KmsError(String::from("response carries no CiphertextForRecipient"))
+    })?;
+    key.decrypt(ciphertext.as_ref())
+}
+
+impl<A: Attester> AttestedKmsClient<A> {
+    pub fn new(client: Client, attester: A) -> Self {
+        AttestedKmsClient { client, attester }
+    }
+
+    /// Wrapped client, for requests without a recipient
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn recipient_key(&self) -> Result<RecipientKey, NitroAdError> {
+        RecipientKey::generate(&self.attester, None, None)
+    }
+
+    /// Decrypts `ciphertext_blob`, with the KMS key `key_id` if given, for this enclave
+    pub async fn decrypt(
+        &self,
+        ciphertext_blob: &[u8],
+        key_id: Option<&str>,
+    ) -> Result<Zeroizing<Vec<u8>>, NitroAdError> {
+        let key = self.recipient_key()?;
+        let output = self
+            .client
+            .decrypt()
+            .ciphertext_blob(Blob::new(ciphertext_blob))
+            .set_key_id(key_id.map(String::from))
+            .recipient(recipient_info(&key))
+            .send()
+            .await
+            .map_err(kms_error)?;
+        open(&key, output.ciphertext_for_recipient())
+    }
+
+    /// Generates a data key of `key_spec` under the KMS key `key_id`
+    pub async fn generate_data_key(
+        &self,
+        key_id: &str,
+        key_spec: DataKeySpec,
+    ) -> Result<DataKey, NitroAdError> {
+        let key = self.recipient_key()?;
+        let output = self
+            .client
+            .generate_data_key()
+            .key_id(key_id)
+            .key_spec(key_spec)
+            .recipient(recipient_info(&key))
+            .send()
+            .await
+            .map_err(kms_error)?;
+        Ok(DataKey {
+            key_id: output.key_id().map(String::from),
+            plaintext: open(&key, output.ciphertext_for_recipient())?,
+            ciphertext_blob: output
+                .ciphertext_blob()
+                .map(|blob| blob.as_ref().to_vec())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// `number_of_bytes` random bytes from the KMS CSPRNG, 1 to 1024
+    pub async fn generate_random(
+        &self,
+        number_of_bytes: i32,
+    ) -> Result<Zeroizing<Vec<u8>>, NitroAdError> {
+        let key = self.recipient_key()?;
+        let output = self
+            .client
+            .generate_random()
+            .number_of_bytes(number_of_bytes)
+            .recipient(recipient_info(&key))
+            .send()
+            .await
+            .map_err(kms_error)?;
+        open(&key, output.ciphertext_for_recipient())
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use aws_sdk_kms::config::{BehaviorVersion, Credentials, Region};
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+    use openssl::md::Md;
+    use openssl::pkey::PKey;
+    use openssl::pkey_ctx::PkeyCtx;
+    use openssl::rand::rand_bytes;
+    use openssl::rsa::Padding;
+    use openssl::symm::{encrypt, Cipher};
+    use serde_json::{json, Value};
+
+    use crate::crypto::der_header;
+    use crate::testing::MockNsm;
+    use crate::NitroAdDoc;
+
+    /// DER element of `tag` with the concatenated `contents`
+    fn der(tag: u8, contents: &[&[u8]]) -> Vec<u8> {
+        let contents = contents.concat();
+        let mut element = Vec::with_capacity(contents.len() + 6);
+        der_header(&mut element, tag, contents.len());
+        element.extend_from_slice(&contents);
+        element
+    }
+
+    /// CMS EnvelopedData of `plaintext` like KMS returns: encrypted with AES-256-CBC,
+    /// the content key with RSAES-OAEP and SHA-256 to `public_key`, which OpenSSL's CMS
+    /// encryption doesn't support
+    fn envelope(public_key: &PKey<openssl::pkey::Public>, plaintext: &[u8]) -> Vec<u8> {
+        let (mut content_key, mut iv) = ([0; 32], [0; 16]);
+        rand_bytes(&mut content_key).unwrap();
+        rand_bytes(&mut iv).unwrap();
+        let mut ctx = PkeyCtx::new(public_key).unwrap();
+        ctx.encrypt_init().unwrap();
+        ctx.set_rsa_padding(Padding::PKCS1_OAEP).unwrap();
+        ctx.set_rsa_oaep_md(Md::sha256()).unwrap();
+        ctx.set_rsa_mgf1_md(Md::sha256()).unwrap();
+        let mut encrypted_key = Vec::new();
+        ctx.encrypt_to_vec(&content_key, &mut encrypted_key).unwrap();
+        let content = encrypt(Cipher::aes_256_cbc(), &content_key, Some(&iv), plaintext).unwrap();
+
+        let oid = |arcs: &[u8]| der(0x06, &[arcs]);
+        let sha256 = der(0x30, &[&oid(&[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01])]);
+        let rsa_pkcs1 = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01];
+        let mgf1 = der(0x30, &[&oid(&[&rsa_pkcs1[..], &[0x01, 0x08]].concat()), &sha256]);
+        let oaep_params = der(0x30, &[&der(0xa0, &[&sha256]), &der(0xa1, &[&mgf1])]);
+        let oaep = der(0x30, &[&oid(&[&rsa_pkcs1[..], &[0x01, 0x07]].concat()), &oaep_params]);
+        // version 2, identified by a subjectKeyIdentifier as the key has no certificate
+        let recipient_info = der(0x30, &[
+            &der(0x02, &[&[2]]),
+            &der(0x80, &[b"recipient"]),
+            &oaep,
+            &der(0x04, &[&encrypted_key]),
+        ]);
+        let aes256_cbc = [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+        let encrypted_content_info = der(0x30, &[
+            &oid(&[&rsa_pkcs1[..], &[0x07, 0x01]].concat()),
+            &der(0x30, &[&oid(&aes256_cbc), &der(0x04, &[&iv])]),
+            &der(0x80, &[&content]),
+        ]);
+        let enveloped_data = der(0x30, &[
+            &der(0x02, &[&[2]]),
+            &der(0x31, &[&recipient_info]),
+            &encrypted_content_info,
+        ]);
+        let enveloped_data_oid = oid(&[&rsa_pkcs1[..], &[0x07, 0x03]].concat());
+        der(0x30, &[&enveloped_data_oid, &der(0xa0, &[&enveloped_data])])
+    }
+
+    /// `CiphertextForRecipient` of `plaintext` for the `Recipient` of `request`, after
+    /// checking its document against `root_cert`
+    fn ciphertext_for_recipient(request: &Value, root_cert: &[u8], plaintext: &[u8]) -> String {
+        let recipient = &request["Recipient"];
+        assert_eq!(recipient["KeyEncryptionAlgorithm"], "RSAES_OAEP_SHA_256");
+        let document = recipient["AttestationDocument"].as_str().unwrap();
+        let document = base64::decode(document).unwrap();
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&document, root_cert, now).unwrap();
+        assert!(doc.verification_error().is_none());
+        let public_key = PKey::public_key_from_der(doc.payload().public_key.as_ref().unwrap());
+        base64::encode(envelope(&public_key.unwrap(), plaintext))
+    }
+
+    /// Client of `mock` whose KMS answers each request with `respond`'s JSON, given the
+    /// operation name and request JSON
+    fn client(
+        mock: MockNsm,
+        respond: impl Fn(&str, Value) -> Value + Send + Sync + 'static,
+    ) -> AttestedKmsClient<MockNsm> {
+        let http_client = infallible_client_fn(move |request| {
+            let target = request.headers()["x-amz-target"].to_str().unwrap();
+            let target = target.trim_start_matches("TrentService.").to_string();
+            let body = serde_json::from_slice(request.body().bytes().unwrap()).unwrap();
+            http::Response::builder()
+                .status(200)
+                .body(respond(&target, body).to_string())
+                .unwrap()
+        });
+        let config = aws_sdk_kms::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("id", "secret", None, None, "test"))
+            .http_client(http_client)
+            .build();
+        AttestedKmsClient::new(Client::from_conf(config), mock)
+    }
+
+    #[test]
+    fn test_attested_requests() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let root_cert = mock.root_cert();
+        let kms = client(mock, move |target, request| {
+            let plaintext = match target {
+                "Decrypt" => {
+                    assert_eq!(request["CiphertextBlob"], base64::encode(b"blob"));
+                    b"plain".to_vec()
+                }
+                "GenerateDataKey" => {
+                    assert_eq!(request["KeySpec"], "AES_256");
+                    vec![1; 32]
+                }
+                "GenerateRandom" => vec![2; request["NumberOfBytes"].as_u64().unwrap() as usize],
+                _ => panic!("unexpected request {}", target),
+            };
+            let ciphertext = ciphertext_for_recipient(&request, &root_cert, &plaintext);
+            json!({
+                "KeyId": "arn:key",
+                "CiphertextBlob": base64::encode(b"encrypted data key"),
+                "CiphertextForRecipient": ciphertext,
+            })
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            assert_eq!(&kms.decrypt(b"blob", None).await?[..], b"plain");
+
+            let data_key = kms.generate_data_key("alias/app", DataKeySpec::Aes256).await?;
+            assert_eq!(data_key.key_id.as_deref(), Some("arn:key"));
+            assert_eq!(&data_key.plaintext[..], &[1; 32]);
+            assert_eq!(data_key.ciphertext_blob, b"encrypted data key");
+
+            assert_eq!(&kms.generate_random(16).await?[..], &[2; 16]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_response_without_recipient_ciphertext() {
+        let kms = client(MockNsm::new().unwrap(), |_, _| json!({ "KeyId": "arn:key" }));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let result = runtime.block_on(kms.decrypt(b"blob", None));
+        assert!(matches!(result, Err(NitroAdError::KmsError(_))));
+    }
+}
@@ -0,0 +1,134 @@
+//! AWS Lambda verification endpoint
+//!
+//! [`run`] serves [`VerifyRequest`]s, a base64 encoded document and optionally the
+//! nonce it must carry, with a [`Verifier`] and answers each with a [`VerifyResponse`]
+//! of [`handle`]. Clients invoke the function with the SDK or, behind an HTTP gateway
+//! passing the body through, with a [`RemoteVerifier`](crate::remote::RemoteVerifier).
+//! [`verifier_from_env`] configures that verifier from the function's environment:
+//!
+//! | variable | content |
+//! |---|---|
+//! | `NITRO_AD_ROOT_CERT` | path of the DER or PEM root certificate, e.g. bundled with the function |
+//! | `NITRO_AD_PCR<n>` | hex encoded expected value of PCR `n` |
+//! | `NITRO_AD_PCRS_SSM_PARAMETER` | name of an SSM parameter holding expected PCRs as JSON |
+//!
+//! and the other policy variables of the [`env`](crate::env) module. The SSM parameter
+//! holds an object of hex values keyed `PCR0`, `PCR1`, ..., see
+//! [`VerifierPolicy::with_measurements_json`]; PCRs in the environment take precedence.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::lambda;
+//!
+//! # async fn main_(ssm: aws_sdk_ssm::Client) -> Result<(), lambda_runtime::Error> {
+//! let verifier = lambda::verifier_from_env(Some(&ssm)).await?;
+//! lambda::run(verifier).await
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use lambda_runtime::{service_fn, LambdaEvent};
+
+use crate::env::{policy_from_vars, read_root_cert, var, vars};
+pub use crate::env::{PCR_VAR_PREFIX, ROOT_CERT_VAR};
+pub use crate::remote::{handle, VerifyRequest, VerifyResponse};
+use crate::ssm::SsmMeasurementSource;
+use crate::{NitroAdError, Verifier, VerifierPolicy};
+
+/// Variable naming the SSM parameter with expected PCRs
+pub static PCRS_SSM_PARAMETER_VAR: &str = "NITRO_AD_PCRS_SSM_PARAMETER";
+
+/// Expected PCRs of the SSM parameter `name`, decrypted if it is a `SecureString`
+async fn ssm_policy(
+    ssm: &aws_sdk_ssm::Client,
+    name: &str,
+) -> Result<VerifierPolicy, NitroAdError> {
+    SsmMeasurementSource::new(ssm.clone(), name).policy().await
+}
+
+/// Verifier configured by the environment variables of the module documentation. `ssm`
+/// is needed only if `NITRO_AD_PCRS_SSM_PARAMETER` is set.
+pub async fn verifier_from_env(
+    ssm: Option<&aws_sdk_ssm::Client>,
+) -> Result<Verifier, NitroAdError> {
+    let root = std::env::var_os(ROOT_CERT_VAR)
+        .ok_or_else(|| NitroAdError::InvalidConfig(format!("{} is not set", ROOT_CERT_VAR)))?;
+    let policy = match (var(PCRS_SSM_PARAMETER_VAR)?, ssm) {
+        (Some(name), Some(ssm)) => ssm_policy(ssm, &name).await?,
+        (Some(_), None) => {
+            let message = format!("{} is set without an SSM client", PCRS_SSM_PARAMETER_VAR);
+            return Err(NitroAdError::InvalidConfig(message));
+        }
+        (None, _) => VerifierPolicy::new(),
+    };
+    let policy = policy_from_vars(vars()?, policy)?;
+    Ok(Verifier::new(read_root_cert(&root)?).with_policy(policy))
+}
+
+/// Runs the Lambda function answering [`VerifyRequest`]s with `verifier` until the
+/// runtime shuts it down
+pub async fn run(verifier: Verifier) -> Result<(), lambda_runtime::Error> {
+    let verifier = Arc::new(verifier);
+    lambda_runtime::run(service_fn(move |event: LambdaEvent<VerifyRequest>| {
+        let verifier = Arc::clone(&verifier);
+        async move {
+            let now = chrono::Utc::now().timestamp() as u64;
+            Ok::<_, lambda_runtime::Error>(handle(&verifier, &event.payload, now))
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_ssm::config::{BehaviorVersion, Credentials, Region};
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+
+    #[test]
+    fn test_policy_from_vars() -> Result<(), NitroAdError> {
+        let vars = |vars: &[(&str, &str)]| {
+            let vars = vars.iter().map(|(name, value)| (name.to_string(), value.to_string()));
+            vars.collect::<Vec<_>>()
+        };
+        let pcr = "ab".repeat(48);
+        let base = VerifierPolicy::new().with_pcr(0, vec![0; 48]).with_pcr(1, vec![1; 48]);
+        let env =
+            vars(&[("NITRO_AD_PCR0", &pcr), ("NITRO_AD_ROOT_CERT", "root.der"), ("HOME", "/")]);
+        let policy = policy_from_vars(env.into_iter(), base)?;
+        assert_eq!(policy.pcrs[&0], vec![0xab; 48]);
+        assert_eq!(policy.pcrs[&1], vec![1; 48]);
+
+        for env in &[vars(&[("NITRO_AD_PCRx", &pcr)]), vars(&[("NITRO_AD_PCR2", "zz")])] {
+            let policy = policy_from_vars(env.clone().into_iter(), VerifierPolicy::new());
+            assert!(matches!(policy, Err(NitroAdError::InvalidConfig(_))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ssm_policy() -> Result<(), NitroAdError> {
+        let http_client = infallible_client_fn(|request| {
+            assert_eq!(request.headers()["x-amz-target"], "AmazonSSM.GetParameter");
+            let body: serde_json::Value =
+                serde_json::from_slice(request.body().bytes().unwrap()).unwrap();
+            assert_eq!(body["Name"], "/enclave/pcrs");
+            let value = serde_json::json!({ "PCR0": "cd".repeat(48) }).to_string();
+            let response =
+                serde_json::json!({ "Parameter": { "Name": "/enclave/pcrs", "Value": value } });
+            http::Response::builder().status(200).body(response.to_string()).unwrap()
+        });
+        let config = aws_sdk_ssm::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("id", "secret", None, None, "test"))
+            .http_client(http_client)
+            .build();
+        let ssm = aws_sdk_ssm::Client::from_conf(config);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let policy = runtime.block_on(ssm_policy(&ssm, "/enclave/pcrs"))?;
+        assert_eq!(policy.pcrs[&0], vec![0xcd; 48]);
+        Ok(())
+    }
+}
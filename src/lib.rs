@@ -5,313 +5,787 @@
 //! with custom functionality like enclave-to-enclave
 //! secure communication and mutual attestation.
 //!
-//!
+//! Without the default `std` feature the crate is `no_std` and needs only `alloc`:
+//! [`NitroAdDoc::from_bytes`] parses and verifies documents, output formats,
+//! reports and everything reading the system clock are left out.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use std::fmt;
-use std::string::String;
+extern crate alloc;
 
-use aws_cose::error::COSEError;
-use aws_nitro_enclaves_cose as aws_cose;
-use hex;
-use webpki;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
-use serde_json;
+#[cfg(feature = "std")]
+use serde_cbor::Value as CborValue;
+#[cfg(feature = "std")]
+use std::io::Read;
 
 use chrono::prelude::*;
 use chrono::serde::ts_milliseconds;
 use chrono::{DateTime, Duration, Utc};
 
-use itertools::Itertools;
-use std::collections::HashMap;
-
-use x509_parser::prelude::*;
-
-use openssl::bn::BigNumContext;
-use openssl::ec::*;
-use openssl::nid::Nid;
-
-use json::{object, JsonValue};
-
-static ALL_SIGALGS: &[&webpki::SignatureAlgorithm] = &[
-    &webpki::ECDSA_P256_SHA256,
-    &webpki::ECDSA_P256_SHA384,
-    &webpki::ECDSA_P384_SHA256,
-    &webpki::ECDSA_P384_SHA384,
-    &webpki::ED25519,
-    #[cfg(feature = "alloc")]
-    &webpki::RSA_PKCS1_2048_8192_SHA256,
-    #[cfg(feature = "alloc")]
-    &webpki::RSA_PKCS1_2048_8192_SHA384,
-    #[cfg(feature = "alloc")]
-    &webpki::RSA_PKCS1_2048_8192_SHA512,
-    #[cfg(feature = "alloc")]
-    &webpki::RSA_PKCS1_3072_8192_SHA384,
+use pki_types::{CertificateDer, SubjectPublicKeyInfoDer, TrustAnchor, UnixTime};
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(feature = "std")]
+pub mod archive;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod bundle;
+mod bytes;
+#[cfg(feature = "std")]
+pub mod cache;
+mod chain;
+#[cfg(feature = "std")]
+pub mod challenge;
+#[cfg(feature = "config")]
+pub mod config;
+mod cose;
+pub mod crypto;
+#[cfg(feature = "std")]
+pub mod diag;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "ecdh")]
+pub mod ecdh;
+#[cfg(feature = "std")]
+pub mod env;
+#[cfg(feature = "envelope")]
+pub mod envelope;
+#[cfg(feature = "std")]
+pub mod eat;
+pub mod error;
+#[cfg(feature = "openssl")]
+mod es384;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod freshness;
+#[cfg(feature = "handshake")]
+pub mod handshake;
+#[cfg(feature = "header")]
+pub mod header;
+#[cfg(feature = "hpke")]
+pub mod hpke;
+#[cfg(feature = "std")]
+pub mod intoto;
+#[cfg(feature = "std")]
+pub mod kms;
+#[cfg(feature = "kms-client")]
+pub mod kms_client;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod nitro_cli;
+#[cfg(feature = "nsm")]
+pub mod nsm;
+#[cfg(feature = "std")]
+pub mod onchain;
+#[cfg(feature = "std")]
+pub mod output;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "nsm")]
+pub mod provider;
+#[cfg(feature = "provisioning")]
+pub mod provisioning;
+#[cfg(feature = "std")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "quic")]
+pub mod quic;
+#[cfg(feature = "reattest")]
+pub mod reattest;
+#[cfg(feature = "rekor")]
+pub mod rekor;
+#[cfg(feature = "rotation")]
+pub mod rotation;
+#[cfg(feature = "std")]
+pub mod spiffe;
+#[cfg(feature = "ssh")]
+pub mod ssh;
+#[cfg(feature = "ssm")]
+pub mod ssm;
+#[cfg(feature = "strategies")]
+pub mod strategies;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "openssl")]
+pub mod token;
+#[cfg(feature = "std")]
+pub mod verifier;
+#[cfg(feature = "vsock")]
+pub mod vsock;
+#[cfg(feature = "std")]
+pub mod x509;
+pub use bytes::Bytes;
+pub use cose::HeaderLabel;
+pub use error::{ErrorKind, NitroAdError, ERROR_CODES};
+pub use policy::VerifierPolicy;
+#[cfg(feature = "std")]
+pub use verifier::Verifier;
+
+use cose::{HeaderMap, HeaderValue};
+
+/// COSE 'alg' header label, see https://tools.ietf.org/html/rfc8152#section-3.1
+const COSE_HEADER_ALG: i128 = 1;
+
+/// COSE 'crit' header label, see https://tools.ietf.org/html/rfc8152#section-3.1
+const COSE_HEADER_CRIT: i128 = 2;
+
+/// ECDSA w/ SHA-384, see https://tools.ietf.org/html/rfc8152#section-8.1
+const COSE_ALG_ES384: i128 = -35;
+
+/// ES384 signature is r||s with 48 bytes per factor
+pub(crate) const COSE_ES384_SIGNATURE_LEN: usize = 2 * 48;
+
+/// Largest document [`NitroAdDoc::from_reader`] accepts. NSM documents are about
+/// 4.5 KiB, plus at most 1 KiB each of `user_data`, `nonce` and `public_key`.
+pub const MAX_DOCUMENT_SIZE: usize = 16 * 1024;
+
+/// Header labels this library processes itself and so may be marked critical
+static COSE_UNDERSTOOD_HEADERS: &[i128] = &[
+    COSE_HEADER_ALG,
 ];
 
-#[derive(Debug, Serialize, Deserialize)]
-struct NitroAdDocPayload {
-    module_id: String,
-    digest: String,
+/// Attestation document payload, as produced by the Nitro Secure Module. Byte string
+/// fields borrow from the document they were decoded from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NitroAdDocPayload<'a> {
+    pub module_id: String,
+    pub digest: String,
 
     #[serde(with = "ts_milliseconds")]
-    timestamp: DateTime<Utc>,
+    pub timestamp: DateTime<Utc>,
 
-    #[serde(serialize_with = "ser_peer_public")]
-    pcrs: HashMap<u8, ByteBuf>,
+    #[serde(serialize_with = "ser_peer_public", borrow)]
+    pub pcrs: BTreeMap<u8, Bytes<'a>>,
 
-    #[serde(skip_serializing)]
-    certificate: ByteBuf,
+    #[serde(skip_serializing, borrow)]
+    pub certificate: Bytes<'a>,
 
-    #[serde(skip_serializing)]
-    cabundle: Vec<ByteBuf>,
+    #[serde(skip_serializing, borrow)]
+    pub cabundle: Vec<Bytes<'a>>,
 
     // optional
-    #[serde(skip_serializing_if = "Option::is_none")]
-    public_key: Option<ByteBuf>,
+    #[serde(skip_serializing_if = "Option::is_none", borrow)]
+    pub public_key: Option<Bytes<'a>>,
 
     // optional
-    #[serde(skip_serializing_if = "Option::is_none")]
-    user_data: Option<ByteBuf>,
+    #[serde(skip_serializing_if = "Option::is_none", borrow)]
+    pub user_data: Option<Bytes<'a>>,
 
     // optional
-    #[serde(skip_serializing_if = "Option::is_none")]
-    nonce: Option<ByteBuf>,
+    #[serde(skip_serializing_if = "Option::is_none", borrow)]
+    pub nonce: Option<Bytes<'a>>,
 }
 
-fn ser_peer_public<S>(peer_public: &HashMap<u8, ByteBuf>, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    let map = peer_public
-        .iter()
-        .sorted()
-        .map(|(k, v)| (k, hex::encode(v.to_vec())));
-    serializer.collect_map(map)
-}
+impl NitroAdDocPayload<'_> {
+    /// CBOR value of the payload in the Nitro Secure Module wire format
+    #[cfg(feature = "std")]
+    pub(crate) fn to_cbor_value(&self) -> CborValue {
+        let text = |s: &str| CborValue::Text(String::from(s));
+        let bytes = |b: &Bytes| CborValue::Bytes(b.to_vec());
+        let optional = |b: &Option<Bytes>| b.as_ref().map(bytes).unwrap_or(CborValue::Null);
+
+        let pcrs = self
+            .pcrs
+            .iter()
+            .map(|(i, val)| (CborValue::Integer(*i as i128), bytes(val)))
+            .collect();
+
+        let mut map = BTreeMap::new();
+        map.insert(text("module_id"), text(&self.module_id));
+        map.insert(text("digest"), text(&self.digest));
+        map.insert(
+            text("timestamp"),
+            CborValue::Integer(self.timestamp.timestamp_millis() as i128),
+        );
+        map.insert(text("pcrs"), CborValue::Map(pcrs));
+        map.insert(text("certificate"), bytes(&self.certificate));
+        map.insert(
+            text("cabundle"),
+            CborValue::Array(self.cabundle.iter().map(bytes).collect()),
+        );
+        map.insert(text("public_key"), optional(&self.public_key));
+        map.insert(text("user_data"), optional(&self.user_data));
+        map.insert(text("nonce"), optional(&self.nonce));
 
-#[derive(Debug)]
-pub enum NitroAdError {
-    COSEError(COSEError),
-    CBORError(serde_cbor::Error),
-    VerificationError(webpki::Error),
-    SerializationError(serde_json::Error),
-    X509Error(String),
-    Error(String),
-}
+        CborValue::Map(map)
+    }
 
-impl fmt::Display for NitroAdError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "NitroAdError: ")
+    /// Deterministically encoded CBOR of the payload in the Nitro Secure Module wire format.
+    /// Map keys follow the canonical ordering of RFC 7049 section 3.9, which for the text and
+    /// small integer keys of the payload is the deterministic ordering of RFC 8949 section 4.2.1.
+    /// Absent optional fields are encoded as null.
+    #[cfg(feature = "std")]
+    pub fn to_canonical_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(serde_cbor::to_vec(&self.to_cbor_value())?)
     }
-}
 
-impl From<COSEError> for NitroAdError {
-    fn from(err: COSEError) -> NitroAdError {
-        NitroAdError::COSEError(err)
+    /// Enclave was started in debug mode. Its PCR0, PCR1 and PCR2 are all zeros
+    /// then, so the document attests nothing about the enclave image.
+    pub fn is_debug_mode(&self) -> bool {
+        (0..3).all(|i| {
+            self.pcrs
+                .get(&i)
+                .is_some_and(|val| val.iter().all(|b| *b == 0))
+        })
+    }
+
+    /// `user_data` as UTF-8 text
+    pub fn user_data_str(&self) -> Result<&str, NitroAdError> {
+        core::str::from_utf8(self.user_data_bytes()?)
+            .map_err(|e| NitroAdError::InvalidUserData(alloc::format!("not UTF-8: {}", e)))
+    }
+
+    /// `user_data` as a JSON document, deserialized to `T`, e.g. a [`serde_json::Value`]
+    #[cfg(feature = "std")]
+    pub fn user_data_json<T: DeserializeOwned>(&self) -> Result<T, NitroAdError> {
+        serde_json::from_slice(self.user_data_bytes()?)
+            .map_err(|e| NitroAdError::InvalidUserData(format!("not JSON of the type: {}", e)))
+    }
+
+    /// `user_data` as a single CBOR data item, deserialized to `T`. Trailing bytes after
+    /// the item are an error.
+    pub fn user_data_cbor<T: DeserializeOwned>(&self) -> Result<T, NitroAdError> {
+        serde_cbor::from_slice(self.user_data_bytes()?).map_err(|e| {
+            NitroAdError::InvalidUserData(alloc::format!("not CBOR of the type: {}", e))
+        })
+    }
+
+    fn user_data_bytes(&self) -> Result<&[u8], NitroAdError> {
+        self.user_data.as_deref().ok_or(NitroAdError::MissingUserData)
+    }
+
+    /// Checks the payload fields against the specification, taking the system clock as
+    /// the current time
+    #[cfg(feature = "std")]
+    pub fn validate(&self) -> Result<(), NitroAdError> {
+        self.validate_at(Utc::now())
+    }
+
+    /// `NitroAdDocPayload::validate` at time `now`, without reading the system clock
+    pub fn validate_at(&self, now: DateTime<Utc>) -> Result<(), NitroAdError> {
+        (!self.module_id.is_empty())
+            .then_some(())
+            .ok_or(NitroAdError::EmptyModuleId)?;
+
+        (self.digest == "SHA384")
+            .then_some(())
+            .ok_or_else(|| NitroAdError::UnsupportedDigest {
+                got: self.digest.clone(),
+            })?;
+
+        // validate timestamp range
+        let ts_start = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let ts_end = now + Duration::days(1);
+        (self.timestamp > ts_start && self.timestamp < ts_end)
+            .then_some(())
+            .ok_or(NitroAdError::TimestampOutOfRange {
+                ts: self.timestamp,
+            })?;
+
+        // validate pcr map length
+        let pcrs_len = self.pcrs.len();
+        ((1..32).contains(&pcrs_len))
+            .then_some(())
+            .ok_or(NitroAdError::BadPcrCount(pcrs_len))?;
+
+        // validate pcr items
+        for i in 0..pcrs_len as u8 {
+            (self.pcrs.contains_key(&i))
+                .then_some(())
+                .ok_or(NitroAdError::MissingPcr(i))?;
+
+            let pcr_len = self.pcrs[&i].len();
+            ([32, 48, 64].contains(&pcr_len))
+                .then_some(())
+                .ok_or(NitroAdError::BadPcrLength {
+                    index: i,
+                    len: pcr_len,
+                })?;
+            //println!("prc{:2}:  {}", i, hex::encode( self.pcrs[&i].to_vec() ) );
+        }
+
+        Ok(())
     }
-}
 
-impl From<serde_cbor::Error> for NitroAdError {
-    fn from(err: serde_cbor::Error) -> NitroAdError {
-        NitroAdError::CBORError(err)
+    /// Seconds since the unix epoch at which the first certificate of `certificate` and
+    /// `cabundle` expires, usually the hours-lived `certificate`. `None` if none parses.
+    #[cfg(feature = "std")]
+    pub fn chain_not_after(&self) -> Option<i64> {
+        use x509_parser::prelude::*;
+
+        core::iter::once(&self.certificate)
+            .chain(&self.cabundle)
+            .filter_map(|der| X509Certificate::from_der(der).ok())
+            .map(|(_, cert)| cert.validity().not_after.timestamp())
+            .min()
     }
 }
 
-impl From<webpki::Error> for NitroAdError {
-    fn from(err: webpki::Error) -> NitroAdError {
-        NitroAdError::VerificationError(err)
+impl<'a> NitroAdDocPayload<'a> {
+    /// Same payload, owning its byte strings
+    pub fn into_owned(mut self) -> NitroAdDocPayload<'static> {
+        NitroAdDocPayload {
+            module_id: core::mem::take(&mut self.module_id),
+            digest: core::mem::take(&mut self.digest),
+            timestamp: self.timestamp,
+            pcrs: core::mem::take(&mut self.pcrs)
+                .into_iter()
+                .map(|(i, val)| (i, val.into_owned()))
+                .collect(),
+            certificate: core::mem::take(&mut self.certificate).into_owned(),
+            cabundle: core::mem::take(&mut self.cabundle)
+                .into_iter()
+                .map(Bytes::into_owned)
+                .collect(),
+            public_key: self.public_key.take().map(Bytes::into_owned),
+            user_data: self.user_data.as_deref().map(|b| Bytes::from(b.to_vec())),
+            nonce: self.nonce.take().map(Bytes::into_owned),
+        }
     }
 }
 
-impl From<serde_json::Error> for NitroAdError {
-    fn from(err: serde_json::Error) -> NitroAdError {
-        NitroAdError::SerializationError(err)
+/// `user_data` may carry secrets the enclave bound to the document, e.g. wrapped
+/// session keys, so an owned copy is scrubbed from memory with the payload.
+/// Borrowed `user_data` is left to the owner of the document buffer.
+impl Drop for NitroAdDocPayload<'_> {
+    fn drop(&mut self) {
+        if let Some(user_data) = self.user_data.as_mut().and_then(Bytes::owned_mut) {
+            user_data.zeroize();
+        }
     }
 }
 
-pub struct NitroAdDoc {
-    payload_ref: NitroAdDocPayload,
+fn ser_peer_public<S>(peer_public: &BTreeMap<u8, Bytes>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let map = peer_public.iter().map(|(k, v)| (k, hex::encode(v)));
+    serializer.collect_map(map)
+}
+
+/// Verified attestation document, borrowing from the buffer it was decoded from.
+/// See [`NitroAdDoc::into_owned`] for keeping it beyond that buffer.
+pub struct NitroAdDoc<'a> {
+    raw: Bytes<'a>,
+    payload_ref: NitroAdDocPayload<'a>,
+    verified_at: u64,
     verify_err: Option<webpki::Error>,
 }
 
-impl NitroAdDoc {
+impl<'a> NitroAdDoc<'a> {
     pub fn from_bytes(
-        bytes: &[u8],
+        bytes: &'a [u8],
         root_cert: &[u8],
         unix_ts_sec: u64,
     ) -> Result<Self, NitroAdError> {
-        let ad_doc_cose = aws_cose::COSESign1::from_bytes(bytes)?;
+        Self::from_bytes_with(bytes, root_cert, unix_ts_sec, &crypto::DefaultBackend::default())
+    }
 
-        // for validation flow details see here:
-        // https://github.com/aws/aws-nitro-enclaves-nsm-api/blob/main/docs/attestation_process.md
+    /// [`NitroAdDoc::from_bytes`] checking the document signature with `backend`
+    pub fn from_bytes_with<B: crypto::CryptoBackend>(
+        bytes: &'a [u8],
+        root_cert: &[u8],
+        unix_ts_sec: u64,
+        backend: &B,
+    ) -> Result<Self, NitroAdError> {
+        let root_cert = CertificateDer::from(root_cert);
+        let anchor = webpki::anchor_from_trusted_cert(&root_cert)
+            .map_err(NitroAdError::InvalidRootCertificate)?;
+        Self::from_bytes_with_anchor(bytes, anchor, unix_ts_sec, backend)
+    }
 
-        // no Signature checks for now - no key specified 
-        let ad_payload = ad_doc_cose.get_payload(None)?;
-        let ad_parsed: NitroAdDocPayload = serde_cbor::from_slice(&ad_payload)?;
+    /// [`NitroAdDoc::from_bytes_with`] for an already parsed root certificate
+    pub(crate) fn from_bytes_with_anchor<B: crypto::CryptoBackend>(
+        bytes: &'a [u8],
+        anchor: TrustAnchor<'_>,
+        unix_ts_sec: u64,
+        backend: &B,
+    ) -> Result<Self, NitroAdError> {
+        let (ad_parsed, verify_err) = verify_payload(bytes, anchor, unix_ts_sec, backend)?;
+        Ok(NitroAdDoc {
+            raw: Bytes::from(bytes),
+            payload_ref: ad_parsed,
+            verified_at: unix_ts_sec,
+            verify_err,
+        })
+    }
 
-        (ad_parsed.module_id.len() > 0)
-            .then(|| ())
-            .ok_or(NitroAdError::Error(String::from("module_id is empty")))?;
+    #[cfg(feature = "std")]
+    pub fn to_output(&self) -> Result<output::DocumentOutput, NitroAdError> {
+        output::DocumentOutput::from_doc(self)
+    }
 
-        (ad_parsed.digest == "SHA384")
-            .then(|| ())
-            .ok_or(NitroAdError::Error(String::from(
-                "digest signature is unknown",
-            )))?;
+    #[cfg(feature = "std")]
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(&self.to_output()?)?)
+    }
 
-        // validate timestamp range
-        let ts_start = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
-        let ts_end = Utc::now() + Duration::days(1);
-        (ad_parsed.timestamp > ts_start && ad_parsed.timestamp < ts_end)
-            .then(|| ())
-            .ok_or(NitroAdError::Error(String::from(
-                "timestamp field has wrong value",
-            )))?;
+    #[cfg(feature = "std")]
+    pub fn to_json_pretty(&self) -> Result<String, NitroAdError> {
+        self.to_json_with(&output::JsonOptions::pretty())
+    }
 
-        // validate pcr map length
-        let pcrs_len = ad_parsed.pcrs.len() as u8;
-        ((1..32).contains(&pcrs_len))
-            .then(|| ())
-            .ok_or(NitroAdError::Error(String::from(
-                "wrong number of PCRs in the map",
-            )))?;
+    #[cfg(feature = "std")]
+    pub fn to_json_with(&self, options: &output::JsonOptions) -> Result<String, NitroAdError> {
+        output::DocumentOutput::from_doc_with(self, options)?.to_json(options)
+    }
 
-        // validate pcr items
-        for i in 0..pcrs_len {
-            (ad_parsed.pcrs.contains_key(&i))
-                .then(|| ())
-                .ok_or(NitroAdError::Error(format!("PCR{} is missing", i)))?;
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String, NitroAdError> {
+        self.to_output()?.to_yaml()
+    }
 
-            let pcr_len = ad_parsed.pcrs[&i].len();
-            ([32, 48, 64].contains(&pcr_len))
-                .then(|| ())
-                .ok_or(NitroAdError::Error(format!(
-                    "PCR{} len is other than 32/48/64 bytes",
-                    i
-                )))?;
-            //println!("prc{:2}:  {}", i, hex::encode( ad_parsed.pcrs[&i].to_vec() ) );
+    pub fn verification_error(&self) -> Option<webpki::Error> {
+        self.verify_err.clone()
+    }
+
+    pub fn payload(&self) -> &NitroAdDocPayload<'a> {
+        &self.payload_ref
+    }
+
+    /// Same document, owning copies of the document bytes and payload
+    pub fn into_owned(mut self) -> NitroAdDoc<'static> {
+        NitroAdDoc {
+            raw: Bytes::from(self.raw.to_vec()),
+            payload_ref: self.payload_ref.clone().into_owned(),
+            verified_at: self.verified_at,
+            verify_err: self.verify_err.take(),
         }
+    }
 
-        // validate 'certificate' member against
-        // 'cabundle' with root cert replaced with our trusted hardcoded one
-        let ee: &[u8] = &ad_parsed.certificate;
+    /// Raw COSE_Sign1 document
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
 
-        let interm: Vec<ByteBuf> = ad_parsed.cabundle.clone();
-        let interm = &interm[1..]; // skip first (claimed root) cert
+    /// Exact bytes the document signature covers, the COSE_Sign1 Sig_structure of
+    /// RFC 8152 section 4.4. With them, [`NitroAdDoc::signature_der`] and the key of the
+    /// payload `certificate`, HSMs and other libraries can check the signature on their own.
+    pub fn signed_bytes(&self) -> Result<Vec<u8>, NitroAdError> {
+        let cose = self.cose()?;
+        let (protected, _, payload, _) = &cose.0;
+        sig_structure(protected, payload)
+    }
 
-        let interm_slices: Vec<_> = interm.iter().map(|x| x.as_slice()).collect();
-        let interm_slices: &[&[u8]] = &interm_slices.to_vec();
+    /// ES384 document signature as COSE carries it, r||s with 48 bytes each
+    pub fn signature_raw(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(self.cose()?.0 .3.to_vec())
+    }
 
-        let anchors = vec![webpki::trust_anchor_util::cert_der_as_trust_anchor(root_cert).unwrap()];
-        let anchors = webpki::TLSServerTrustAnchors(&anchors);
+    /// Document signature as a DER encoded ECDSA-Sig-Value, the form X.509, OpenSSL and
+    /// PKCS #11 callers usually expect
+    pub fn signature_der(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(crypto::ecdsa_raw_to_der(&self.signature_raw()?))
+    }
 
-        let time = webpki::Time::from_seconds_since_unix_epoch(unix_ts_sec);
+    fn cose(&self) -> Result<CoseSign1<'_>, NitroAdError> {
+        Ok(CoseSign1(serde_cbor::from_slice(self.as_bytes())?))
+    }
 
-        let cert = webpki::EndEntityCert::from(ee)?;
-        let verify_err = cert.verify_is_valid_tls_server_cert(ALL_SIGALGS, &anchors, interm_slices, time).err();
+    /// Time the certificate chain was checked at, `unix_ts_sec` of [`NitroAdDoc::from_bytes`]
+    pub fn verified_at(&self) -> u64 {
+        self.verified_at
+    }
 
-        let res = parse_x509_certificate(ee);
-        match res {
-            Ok((rem, cert)) => {
-                (rem.is_empty())
-                    .then(|| ())
-                    .ok_or(NitroAdError::Error(String::from("rem isnot empty")))?;
+    #[cfg(feature = "std")]
+    pub fn report(&self) -> report::VerificationReport {
+        report::VerificationReport::from_doc(self)
+    }
 
-                (cert.tbs_certificate.version == X509Version::V3)
-                    .then(|| ())
-                    .ok_or(NitroAdError::Error(String::from("wrong cert version")))?;
+    /// See [`NitroAdDocPayload::to_canonical_cbor`]
+    #[cfg(feature = "std")]
+    pub fn to_canonical_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        self.payload_ref.to_canonical_cbor()
+    }
 
-                let ee_pub_key = cert.tbs_certificate.subject_pki.subject_public_key.data;
+    /// See [`eat::EatClaims`]
+    #[cfg(feature = "std")]
+    pub fn to_eat_claims(&self) -> eat::EatClaims {
+        eat::EatClaims::from_payload(&self.payload_ref)
+    }
 
-                let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
-                let mut ctx = BigNumContext::new().unwrap();
-                let point = EcPoint::from_bytes(&group, &ee_pub_key, &mut ctx).unwrap();
-                let key = EcKey::from_public_key(&group, &point).unwrap();
+    /// RFC 8949 diagnostic notation of the COSE_Sign1 document and its payload.
+    /// Use [`diag::cose_sign1_diag`] directly for documents which fail to parse.
+    #[cfg(feature = "std")]
+    pub fn to_cbor_diag(&self) -> String {
+        diag::cose_sign1_diag(&self.raw)
+    }
+}
 
-                // [TODO] remove all above parse_x509_certificate() stuff and extract public key with webpki after issue
-                // https://github.com/briansmith/webpki/issues/85
-                // become fixed
+#[cfg(feature = "std")]
+impl NitroAdDoc<'static> {
+    /// [`NitroAdDoc::from_bytes`] of a document read from `reader` until end of file.
+    /// Fails without reading further once more than [`MAX_DOCUMENT_SIZE`] bytes arrived.
+    pub fn from_reader<R: Read>(
+        reader: R,
+        root_cert: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<Self, NitroAdError> {
+        let mut bytes = Zeroizing::new(Vec::new());
+        reader
+            .take(MAX_DOCUMENT_SIZE as u64 + 1)
+            .read_to_end(&mut bytes)
+            .map_err(NitroAdError::IoError)?;
+        if bytes.len() > MAX_DOCUMENT_SIZE {
+            return Err(NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE });
+        }
+        let doc = NitroAdDoc::from_bytes(&bytes, root_cert, unix_ts_sec)?.into_owned();
+        Ok(doc)
+    }
 
-                if !ad_doc_cose.verify_signature(&key)? {
-                    return Err(NitroAdError::COSEError(COSEError::UnimplementedError));  //should be SignatureError(openssl::error::ErrorStack)
-                }
-            }
-            _ => {
-                return Err(NitroAdError::Error(format!(
-                    "x509 parsing failed: {:?}",
-                    res
-                )))
-            }
+    /// [`NitroAdDoc::from_bytes`] of the next document on `reader`, framed by a big
+    /// endian `u32` byte length. Lengths above [`MAX_DOCUMENT_SIZE`] fail before the
+    /// document is read, the stream is left positioned after it otherwise.
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R>(
+        reader: &mut R,
+        root_cert: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<Self, NitroAdError>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        use tokio::io::AsyncReadExt;
+
+        let len = reader.read_u32().await.map_err(NitroAdError::IoError)? as usize;
+        if len > MAX_DOCUMENT_SIZE {
+            return Err(NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE });
         }
+        let mut bytes = Zeroizing::new(vec![0; len]);
+        reader.read_exact(&mut bytes).await.map_err(NitroAdError::IoError)?;
+        let doc = NitroAdDoc::from_bytes(&bytes, root_cert, unix_ts_sec)?.into_owned();
+        Ok(doc)
+    }
+}
 
-        Ok(NitroAdDoc {
-            payload_ref: ad_parsed,
-            verify_err: verify_err,
-        })
+/// Runs all checks of [`NitroAdDoc::from_bytes_with_anchor`], returns the payload and
+/// the certificate chain error, if any.
+pub(crate) fn verify_payload<'a, B: crypto::CryptoBackend>(
+    bytes: &'a [u8],
+    anchor: TrustAnchor<'_>,
+    unix_ts_sec: u64,
+    backend: &B,
+) -> Result<(NitroAdDocPayload<'a>, Option<webpki::Error>), NitroAdError> {
+    // seconds beyond the chrono range don't bound the timestamp at all
+    let now = i64::try_from(unix_ts_sec)
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let (ad_doc_cose, ad_parsed) = parse_and_validate_payload(bytes, now)?;
+    let (verify_err, ee_spki) = verify_chain(&ad_parsed, anchor, unix_ts_sec)?;
+
+    let ee_pub_key = backend.parse_spki(&ee_spki)?;
+
+    if !verify_cose_signature(&ad_doc_cose.0, &ee_pub_key, backend)? {
+        return Err(NitroAdError::InvalidSignature);
     }
 
-    pub fn to_json(&self) -> Result<String, NitroAdError> {
-        let json_ad = object!{
-            "module_id": self.payload_ref.module_id.clone(),
-            "digest": self.payload_ref.digest.clone(),
-            "timestamp": self.payload_ref.timestamp.to_string(),
-            "pcrs": pcrs_to_json(&self.payload_ref.pcrs),
-            "certs": x509s_to_json(&self.payload_ref.certificate, &self.payload_ref.cabundle)?,
-            "public_key": self.payload_ref.public_key.as_ref().map(|pk| base64::encode(pk)),
-            "user_data": self.payload_ref.user_data.as_ref().map(|ud| base64::encode(ud)),
-            "nonce": self.payload_ref.nonce.as_ref().map(|nc| base64::encode(nc)),
-            "verification_error": self.verify_err.map(|e| e.to_string()),
-        };
+    Ok((ad_parsed, verify_err))
+}
 
-        Ok(json::stringify(json_ad))
-    }
+/// Decodes the COSE_Sign1 envelope and its payload and checks the payload fields
+/// against the specification at time `now`. Neither the signature nor the certificates
+/// are verified.
+fn parse_and_validate_payload(
+    bytes: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(CoseSign1<'_>, NitroAdDocPayload<'_>), NitroAdError> {
+    let ad_doc_cose = CoseSign1(serde_cbor::from_slice(bytes)?);
+    let (protected, unprotected, ad_payload, signature) = &ad_doc_cose.0;
+
+    // protected headers are a serialized map, even when not listing any
+    let _: HeaderMap = serde_cbor::from_slice(protected)?;
+    check_critical_headers(protected, unprotected)?;
+
+    (signature.len() == COSE_ES384_SIGNATURE_LEN)
+        .then_some(())
+        .ok_or(NitroAdError::BadSignatureLength(signature.len()))?;
+
+    // for validation flow details see here:
+    // https://github.com/aws/aws-nitro-enclaves-nsm-api/blob/main/docs/attestation_process.md
+
+    // no Signature checks for now - no key specified 
+    let ad_parsed = match ad_payload.as_borrowed() {
+        Some(ad_payload) => serde_cbor::from_slice(ad_payload)?,
+        // indefinite length payload, copied out of the document
+        None => serde_cbor::from_slice::<NitroAdDocPayload>(ad_payload)?.into_owned(),
+    };
+
+    ad_parsed.validate_at(now)?;
+
+    Ok((ad_doc_cose, ad_parsed))
+}
 
-    pub fn verification_error(&self) -> Option<webpki::Error> {
-        self.verify_err.clone()
+/// COSE_Sign1 array: protected headers, unprotected headers, payload, signature
+pub(crate) type CoseSign1Raw<'a> = (Bytes<'a>, HeaderMap, Bytes<'a>, Bytes<'a>);
+
+/// Decoded COSE_Sign1 envelope, a payload copy is scrubbed on drop
+pub(crate) struct CoseSign1<'a>(CoseSign1Raw<'a>);
+
+impl Drop for CoseSign1<'_> {
+    fn drop(&mut self) {
+        if let Some(payload) = self.0 .2.owned_mut() {
+            payload.zeroize();
+        }
     }
 }
 
-fn pcrs_to_json(pcrs: &HashMap<u8, ByteBuf>) -> JsonValue {
-    let mapped = pcrs.iter()
-        .map(|(i, val)| (i.to_string(), hex::encode(&val)));
+/// Checks the payload `certificate` through `cabundle` up to `anchor` at `unix_ts_sec`.
+/// Returns the chain error, if any, and the DER encoded SubjectPublicKeyInfo of the
+/// certificate.
+fn verify_chain(
+    payload: &NitroAdDocPayload<'_>,
+    anchor: TrustAnchor<'_>,
+    unix_ts_sec: u64,
+) -> Result<(Option<webpki::Error>, SubjectPublicKeyInfoDer<'static>), NitroAdError> {
+    // validate 'certificate' member against
+    // 'cabundle' with root cert replaced with our trusted hardcoded one
+    let ee: &[u8] = &payload.certificate;
+
+    let interm = payload
+        .cabundle
+        .get(1..) // skip first (claimed root) cert
+        .ok_or(NitroAdError::EmptyCaBundle)?;
+
+    let interm: Vec<_> = interm.iter().map(|x| CertificateDer::from(&**x)).collect();
+
+    let anchors = [anchor];
+
+    let time = UnixTime::since_unix_epoch(core::time::Duration::from_secs(unix_ts_sec));
+
+    let ee = CertificateDer::from(ee);
+    let cert = webpki::EndEntityCert::try_from(&ee).map_err(|e| match e {
+        webpki::Error::TrailingData(webpki::DerTypeId::Certificate) => {
+            NitroAdError::TrailingCertificateData
+        }
+        webpki::Error::UnsupportedCertVersion => NitroAdError::BadCertificateVersion,
+        e => NitroAdError::VerificationError(e),
+    })?;
+    let verify_err = cert
+        .verify_for_usage(
+            chain::ALL_SIGALGS,
+            &anchors,
+            &interm,
+            time,
+            chain::AttestationProfile,
+            None,
+            None,
+        )
+        .err();
+
+    Ok((verify_err, cert.subject_public_key_info()))
+}
+
+/// Checks the ES384 signature of the COSE_Sign1 Sig_structure against `public_key`,
+/// see https://tools.ietf.org/html/rfc8152#section-4.4
+fn verify_cose_signature<B: crypto::CryptoBackend>(
+    cose: &CoseSign1Raw<'_>,
+    public_key: &B::PublicKey,
+    backend: &B,
+) -> Result<bool, NitroAdError> {
+    let (protected, _, payload, signature) = cose;
+
+    // 'alg' must be integrity protected, a mismatch fails verification
+    let headers: HeaderMap = serde_cbor::from_slice(protected)?;
+    match headers.get(&HeaderLabel::Int(COSE_HEADER_ALG)) {
+        Some(HeaderValue::Int(COSE_ALG_ES384)) => {}
+        Some(HeaderValue::Int(_)) => return Ok(false),
+        _ => {
+            return Err(NitroAdError::MalformedCoseHeader(
+                "protected header lacks a valid alg",
+            ))
+        }
+    }
 
-    use std::iter::FromIterator;
-    JsonValue::Object(json::object::Object::from_iter(mapped))
+    let sig_structure = Zeroizing::new(sig_structure(protected, payload)?);
+    Ok(backend.verify_es384(public_key, &sig_structure, signature))
 }
 
-fn x509_to_json(der: &ByteBuf) -> Result<JsonValue, NitroAdError> {
-    let (_, cert) = X509Certificate::from_der(&der)
-        .map_err(|e| NitroAdError::X509Error(e.to_string()))?;
-
-    Ok(object!{
-        "issuer": cert.issuer().to_string(),
-        "subject": cert.subject().to_string(),
-        "validity": {
-            "not_before": cert.validity().not_before.to_string(),
-            "not_after": cert.validity().not_after.to_string(),
-        },
-    })
+/// COSE_Sign1 Sig_structure of the `protected` headers and `payload`, the bytes the
+/// document signature covers
+pub(crate) fn sig_structure(protected: &Bytes, payload: &Bytes) -> Result<Vec<u8>, NitroAdError> {
+    Ok(serde_cbor::to_vec(&("Signature1", protected, ByteBuf::new(), payload))?)
 }
 
-fn x509s_to_json<'a>(cert: &ByteBuf, cabundle: &Vec<ByteBuf>) -> Result<Vec<JsonValue>, NitroAdError> {
-    let mut result: Vec<JsonValue> = Vec::new();
+/// Fails if the COSE_Sign1 headers list critical parameters we don't understand.
+fn check_critical_headers(protected: &[u8], unprotected: &HeaderMap) -> Result<(), NitroAdError> {
+    let crit_label = HeaderLabel::Int(COSE_HEADER_CRIT);
 
-    for der in cabundle {
-        result.push(x509_to_json(der)?);
+    // 'crit' is only meaningful when integrity protected
+    if unprotected.get(&crit_label).is_some() {
+        return Err(NitroAdError::MalformedCoseHeader(
+            "crit header is not in the protected bucket",
+        ));
     }
 
-    result.push(x509_to_json(cert)?);
+    if protected.is_empty() {
+        return Ok(());
+    }
 
-    Ok(result)
+    let protected: HeaderMap = serde_cbor::from_slice(protected)?;
+    let labels = match protected.get(&crit_label) {
+        None => return Ok(()),
+        Some(HeaderValue::Array(labels)) if !labels.is_empty() => labels,
+        Some(_) => {
+            return Err(NitroAdError::MalformedCoseHeader(
+                "crit header must be a non-empty array of labels",
+            ))
+        }
+    };
+
+    for label in labels {
+        match label.as_label() {
+            Some(HeaderLabel::Int(l)) if COSE_UNDERSTOOD_HEADERS.contains(&l) => {}
+            Some(label) => return Err(NitroAdError::UnknownCriticalHeader(label)),
+            None => {
+                return Err(NitroAdError::MalformedCoseHeader(
+                    "crit header contains invalid label",
+                ))
+            }
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use serde_cbor::Value as CborValue;
+
     #[test]
+    #[cfg(feature = "std")]
     fn test_payload_to_valid_json() -> Result<(), NitroAdError> {
 
         // current ee cert baked into the ../tests/data/nitro_ad_debug.bin attestation document has next time limits
@@ -337,15 +811,228 @@ mod tests {
 
         let _: serde::de::IgnoredAny = serde_json::from_str(&js)?;  // test js is valid JSON string (by trying to parse it)
 
+        let parsed: output::DocumentOutput = serde_json::from_str(&js)?;
+        assert_eq!(parsed, nitro_addoc.to_output()?);
+        assert_eq!(parsed.pcrs.len(), nitro_addoc.payload().pcrs.len());
+        assert_eq!(parsed.certs.len(), nitro_addoc.payload().cabundle.len() + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_json_options() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let pretty = nitro_addoc.to_json_pretty()?;
+        assert!(pretty.contains("\n  \"module_id\""));
+        let parsed: output::DocumentOutput = serde_json::from_str(&pretty)?;
+        assert_eq!(parsed, nitro_addoc.to_output()?);
+
+        let minimal = nitro_addoc.to_json_with(&output::JsonOptions::minimal())?;
+        assert!(!minimal.contains('\n'));
+        assert!(!minimal.contains("null"));
+        assert!(!minimal.contains("\"certs\""));
+        assert!(minimal.len() < nitro_addoc.to_json()?.len());
+
+        assert!(!pretty.contains("pcr_meanings"));
+        let options = output::JsonOptions {
+            pcr_meanings: true,
+            ..Default::default()
+        };
+        let annotated: output::DocumentOutput =
+            serde_json::from_str(&nitro_addoc.to_json_with(&options)?)?;
+        let meanings = annotated.pcr_meanings.unwrap();
+        assert_eq!(meanings[&0], "enclave image file");
+        assert_eq!(meanings[&4], "instance ID of the parent instance");
+        assert!(!meanings.contains_key(&5));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_json_raw_certs() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let payload = nitro_addoc.payload();
+
+        assert!(!nitro_addoc.to_json()?.contains("\"der\""));
+
+        let options = output::JsonOptions {
+            raw_certs: Some(output::CertEncoding::Der),
+            ..Default::default()
+        };
+        let parsed: output::DocumentOutput =
+            serde_json::from_str(&nitro_addoc.to_json_with(&options)?)?;
+        let leaf = parsed.certs.last().unwrap().der.as_ref().unwrap();
+        assert_eq!(base64::decode(leaf).unwrap(), payload.certificate.to_vec());
+        assert_eq!(parsed.certs.len(), payload.cabundle.len() + 1);
+
+        let parsed: output::DocumentOutput =
+            serde_json::from_str(&nitro_addoc.to_json_with(&output::JsonOptions::audit())?)?;
+        let root = parsed.certs[0].pem.as_ref().unwrap();
+        assert!(root.starts_with("-----BEGIN CERTIFICATE-----\n"));
+        assert!(root.lines().all(|line| line.len() <= 64));
+        let (_, pem) = x509_parser::pem::parse_x509_pem(root.as_bytes()).unwrap();
+        assert_eq!(pem.contents, payload.cabundle[0].to_vec());
+        assert!(parsed.certs.iter().all(|cert| cert.der.is_none()));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_payload_to_yaml() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let yaml = nitro_addoc.to_yaml()?;
+        let parsed: output::DocumentOutput = serde_yaml::from_str(&yaml)?;
+        assert_eq!(parsed, nitro_addoc.to_output()?);
+
         Ok(())
     }
 
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema_matches_output() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let schema = serde_json::to_value(output::json_schema())?;
+        let properties = schema["properties"].as_object().unwrap();
+
+        let js: serde_json::Value = serde_json::from_str(&nitro_addoc.to_json()?)?;
+        for field in js.as_object().unwrap().keys() {
+            assert!(properties.contains_key(field), "{} missing from schema", field);
+        }
+        assert!(schema["$defs"]["CertificateOutput"].is_object());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_canonical_cbor() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+
+        let cbor = nitro_addoc.to_canonical_cbor()?;
+
+        // re-parsing and re-encoding yields identical bytes
+        let reparsed: NitroAdDocPayload = serde_cbor::from_slice(&cbor)?;
+        assert_eq!(reparsed.to_canonical_cbor()?, cbor);
+
+        // map keys are sorted length first, then bytewise
+        let keys = ["pcrs", "nonce", "digest", "cabundle", "module_id", "timestamp", "user_data", "public_key", "certificate"];
+        let positions: Vec<usize> = keys
+            .iter()
+            .map(|k| {
+                let encoded = serde_cbor::to_vec(k).unwrap();
+                cbor.windows(encoded.len()).position(|w| w == encoded.as_slice()).unwrap()
+            })
+            .collect();
+        assert!(positions.windows(2).all(|p| p[0] < p[1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_borrowed_payload() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+
+        let certificate = nitro_addoc.payload().certificate.as_borrowed().unwrap();
+        let blob = ad_blob.as_ptr_range();
+        assert!(blob.contains(&certificate.as_ptr()));
+        assert!(nitro_addoc.payload().pcrs.values().all(|pcr| pcr.as_borrowed().is_some()));
+
+
+        // outlives the buffer it was decoded from
+        let buf = ad_blob.to_vec();
+        let owned: NitroAdDoc<'static> = NitroAdDoc::from_bytes(&buf, root_cert, 1614967200).unwrap().into_owned();
+        drop(buf);
+        assert_eq!(owned.payload().certificate.as_borrowed(), None);
+        assert_eq!(owned.payload().certificate, nitro_addoc.payload().certificate);
+        assert_eq!(owned.payload().pcrs, nitro_addoc.payload().pcrs);
+        assert_eq!(owned.as_bytes(), &ad_blob[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_from_reader() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        let nitro_addoc = NitroAdDoc::from_reader(&ad_blob[..], root_cert, 1614967200).unwrap();
+        assert_eq!(nitro_addoc.as_bytes(), &ad_blob[..]);
+
+        // the limit bounds what is read, not just what is parsed
+        let mut endless = std::io::repeat(0);
+        assert!(matches!(
+            NitroAdDoc::from_reader(&mut endless, root_cert, 1614967200),
+            Err(NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE })
+        ));
+
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::ConnectionReset.into())
+            }
+        }
+        assert!(matches!(
+            NitroAdDoc::from_reader(Failing, root_cert, 1614967200),
+            Err(e) if e.is_io_failure()
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_from_async_reader() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        // two framed documents back to back, then a truncated one
+        let mut stream = Vec::new();
+        for _ in 0..2 {
+            stream.extend_from_slice(&(ad_blob.len() as u32).to_be_bytes());
+            stream.extend_from_slice(ad_blob);
+        }
+        stream.extend_from_slice(&(ad_blob.len() as u32).to_be_bytes());
+        stream.extend_from_slice(&ad_blob[..100]);
+
+        let mut reader = &stream[..];
+        runtime.block_on(async {
+            for _ in 0..2 {
+                let doc = NitroAdDoc::from_async_reader(&mut reader, root_cert, 1614967200).await;
+                assert_eq!(doc.unwrap().as_bytes(), &ad_blob[..]);
+            }
+            let doc = NitroAdDoc::from_async_reader(&mut reader, root_cert, 1614967200).await;
+            assert!(matches!(doc, Err(e) if e.is_io_failure()));
+
+            let mut oversized = &(MAX_DOCUMENT_SIZE as u32 + 1).to_be_bytes()[..];
+            assert!(matches!(
+                NitroAdDoc::from_async_reader(&mut oversized, root_cert, 1614967200).await,
+                Err(NitroAdError::DocumentTooLarge { .. })
+            ));
+        });
+    }
+
     #[test]
     fn test_broken_root_cert() { 
 
         let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
         let root_cert = include_bytes!("../tests/data/aws_root.der");
-        let mut root_cert_copy = root_cert.clone();
+        let mut root_cert_copy = *root_cert;
 
         root_cert_copy[200] = 0xff;
         let nitro_addoc = NitroAdDoc::from_bytes(ad_blob, &root_cert_copy, 1614967200).unwrap(); // Mar 5 18:00:00 2021 GMT
@@ -370,13 +1057,101 @@ mod tests {
         assert!(nitro_addoc.verification_error().is_some());
     }
 
+    #[test]
+    fn test_user_data() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+        let mut payload = doc.payload().clone();
+        assert!(matches!(payload.user_data_str(), Err(NitroAdError::MissingUserData)));
+
+        payload.user_data = Some(Bytes::from(br#"{"session":42}"#.to_vec()));
+        assert_eq!(payload.user_data_str().unwrap(), r#"{"session":42}"#);
+        #[cfg(feature = "std")]
+        {
+            let json: serde_json::Value = payload.user_data_json().unwrap();
+            assert_eq!(json["session"], 42);
+            let wrong_type = payload.user_data_json::<Vec<u8>>();
+            assert!(matches!(wrong_type, Err(NitroAdError::InvalidUserData(_))));
+        }
+        assert!(matches!(
+            payload.user_data_cbor::<BTreeMap<String, u32>>(),
+            Err(NitroAdError::InvalidUserData(_))
+        ));
+
+        let mut session = BTreeMap::new();
+        session.insert(String::from("session"), 42u32);
+        payload.user_data = Some(Bytes::from(serde_cbor::to_vec(&session).unwrap()));
+        assert_eq!(payload.user_data_cbor::<BTreeMap<String, u32>>().unwrap(), session);
+        assert!(matches!(payload.user_data_str(), Err(NitroAdError::InvalidUserData(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_signature_export() {
+        use crypto::CryptoBackend;
+        use x509_parser::prelude::*;
+
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+        let signed = doc.signed_bytes().unwrap();
+        let raw = doc.signature_raw().unwrap();
+        let der = doc.signature_der().unwrap();
+        assert_eq!(raw.len(), COSE_ES384_SIGNATURE_LEN);
+        assert_eq!(der[0], 0x30);
+
+        let (_, cert) = X509Certificate::from_der(&doc.payload().certificate).unwrap();
+        let spki = cert.public_key().raw;
+        let backend = crypto::DefaultBackend::default();
+        assert!(backend.verify_es384(&backend.parse_spki(spki).unwrap(), &signed, &raw));
+
+        #[cfg(feature = "openssl")]
+        {
+            let key = openssl::pkey::PKey::public_key_from_der(spki).unwrap();
+            let key = key.ec_key().unwrap();
+            let sig = openssl::ecdsa::EcdsaSig::from_der(&der).unwrap();
+            assert!(sig.verify(&crypto::sha384(&signed), &key).unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_chain_not_after() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+        let not_after = doc.payload().chain_not_after().unwrap() as u64;
+        assert!(not_after > 1614967200);
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, not_after - 1).unwrap();
+        assert!(doc.verification_error().is_none());
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, not_after + 1).unwrap();
+        assert!(doc.verification_error().is_some());
+    }
+
+    #[test]
+    fn test_timestamp_after_verification_time() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        // a year before the document was issued
+        match NitroAdDoc::from_bytes(ad_blob, root_cert, 1583431200) {
+            Err(NitroAdError::TimestampOutOfRange { .. }) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        }
+
+        let payload = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap().payload().clone();
+        assert!(payload.validate_at(payload.timestamp - Duration::hours(23)).is_ok());
+        assert!(payload.validate_at(payload.timestamp - Duration::hours(25)).is_err());
+    }
+
     #[test]
     #[should_panic]
     fn test_broken_some_cert_in_ad() { 
 
         let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
         let root_cert = include_bytes!("../tests/data/aws_root.der");
-        let mut ad_blob_copy = ad_blob.clone();
+        let mut ad_blob_copy = *ad_blob;
 
         ad_blob_copy[0x99f] = 0xff;
         let _nitro_addoc = NitroAdDoc::from_bytes(&ad_blob_copy, root_cert, 1614967200).unwrap();
@@ -388,7 +1163,7 @@ mod tests {
 
         let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
         let root_cert = include_bytes!("../tests/data/aws_root.der");
-        let mut ad_blob_copy = ad_blob.clone();
+        let mut ad_blob_copy = *ad_blob;
 
         ad_blob_copy[0x13b] = 0xff;
         let _nitro_addoc = NitroAdDoc::from_bytes(&ad_blob_copy, root_cert, 1614967200).unwrap();
@@ -400,20 +1175,148 @@ mod tests {
 
         let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
         let root_cert = include_bytes!("../tests/data/aws_root.der");
-        let mut ad_blob_copy = ad_blob.clone();
+        let mut ad_blob_copy = *ad_blob;
 
         ad_blob_copy[0x281] = 0xff;
         let _nitro_addoc = NitroAdDoc::from_bytes(&ad_blob_copy, root_cert, 1614967200).unwrap();
     }
 
     #[test]
+    fn test_unknown_critical_header() {
+        let mut protected = CborMap::new();
+        protected.insert(CborValue::Integer(1), CborValue::Integer(-35));
+        protected.insert(CborValue::Integer(2), CborValue::Array(vec![CborValue::Integer(-65537)]));
+        let cose_doc = cose_sign1_with_headers(&protected, &CborMap::new());
+
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        match NitroAdDoc::from_bytes(&cose_doc, root_cert, 1614967200) {
+            Err(NitroAdError::UnknownCriticalHeader(HeaderLabel::Int(-65537))) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        };
+    }
+
+    #[test]
+    fn test_understood_critical_header() {
+        let mut protected = CborMap::new();
+        protected.insert(CborValue::Integer(1), CborValue::Integer(-35));
+        protected.insert(CborValue::Integer(2), CborValue::Array(vec![CborValue::Integer(1)]));
+        let protected = serde_cbor::to_vec(&protected).unwrap();
+
+        assert!(check_critical_headers(&protected, &HeaderMap::default()).is_ok());
+    }
+
+    #[test]
+    fn test_unprotected_critical_header() {
+        let mut protected = CborMap::new();
+        protected.insert(CborValue::Integer(1), CborValue::Integer(-35));
+        let mut unprotected = CborMap::new();
+        unprotected.insert(CborValue::Integer(2), CborValue::Array(vec![CborValue::Integer(1)]));
+        let protected = serde_cbor::to_vec(&protected).unwrap();
+        let unprotected: HeaderMap = serde_cbor::from_slice(&serde_cbor::to_vec(&unprotected).unwrap()).unwrap();
+
+        assert!(check_critical_headers(&protected, &unprotected).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_header_label() {
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let duplicates: [&[u8]; 2] = [
+            // {1: -7, 1: -35}, ES384 only if the last alg wins
+            &[0xa2, 0x01, 0x26, 0x01, 0x38, 0x22],
+            // {1: -35, 2: [1], 2: [-65537]}, unknown critical header only in the last crit
+            &[0xa3, 0x01, 0x38, 0x22, 0x02, 0x81, 0x01, 0x02, 0x81, 0x3a, 0x00, 0x01, 0x00, 0x00],
+        ];
+        for protected in duplicates.iter() {
+            let cose_doc = serde_cbor::to_vec(&(
+                ByteBuf::from(protected.to_vec()),
+                CborMap::new(),
+                ByteBuf::from(vec![0xa0]),
+                ByteBuf::from(vec![0u8; 96]),
+            ))
+            .unwrap();
+            match NitroAdDoc::from_bytes(&cose_doc, root_cert, 1614967200) {
+                Err(NitroAdError::CBORError(_)) => {}
+                res => panic!("unexpected result: {:?}", res.err()),
+            };
+            assert!(check_critical_headers(protected, &HeaderMap::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_unsupported_digest() {
+        let mut payload = test_payload();
+        payload.insert(CborValue::Text("digest".into()), CborValue::Text("SHA256".into()));
+
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        match NitroAdDoc::from_bytes(&cose_sign1_with_payload(payload), root_cert, 1614967200) {
+            Err(NitroAdError::UnsupportedDigest { got }) => assert_eq!(got, "SHA256"),
+            res => panic!("unexpected result: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn test_empty_cabundle() {
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        match NitroAdDoc::from_bytes(&cose_sign1_with_payload(test_payload()), root_cert, 1614967200) {
+            Err(NitroAdError::EmptyCaBundle) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn test_trailing_certificate_data() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let (_, _, payload, _): (ByteBuf, CborMap, ByteBuf, ByteBuf) =
+            serde_cbor::from_slice(ad_blob).unwrap();
+        let mut payload: CborMap = serde_cbor::from_slice(&payload).unwrap();
+
+        let certificate = payload.get_mut(&CborValue::Text("certificate".into())).unwrap();
+        if let CborValue::Bytes(der) = certificate {
+            der.push(0);
+        }
+
+        match NitroAdDoc::from_bytes(&cose_sign1_with_payload(payload), root_cert, 1614967200) {
+            Err(NitroAdError::TrailingCertificateData) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn test_malformed_root_cert() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        match NitroAdDoc::from_bytes(ad_blob, &root_cert[..100], 1614967200) {
+            Err(NitroAdError::InvalidRootCertificate(_)) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn test_short_signature() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        let (protected, unprotected, payload, _): (ByteBuf, CborMap, ByteBuf, ByteBuf) =
+            serde_cbor::from_slice(ad_blob).unwrap();
+        let cose_doc = serde_cbor::to_vec(&(protected, unprotected, payload, ByteBuf::from(vec![0u8; 10]))).unwrap();
+
+        match NitroAdDoc::from_bytes(&cose_doc, root_cert, 1614967200) {
+            Err(NitroAdError::BadSignatureLength(10)) => {}
+            res => panic!("unexpected result: {:?}", res.err()),
+        };
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
     fn cose_sign1_ec384_validate() {
         let (_, ec_public) = get_ec384_test_key();
 
         const TEXT: &[u8] = b"It is a truth universally acknowledged, that a single man in possession of a good fortune, must be in want of a wife.";
 
         // This output was validated against COSE-C implementation
-        let cose_doc = aws_cose::COSESign1::from_bytes(&[
+        let cose_doc = aws_nitro_enclaves_cose::COSESign1::from_bytes(&[
             0x84, /* Protected: {1: -35} */
             0x44, 0xA1, 0x01, 0x38, 0x22, /* Unprotected: {4: '11'} */
             0xA1, 0x04, 0x42, 0x31, 0x31, /* payload: */
@@ -443,27 +1346,84 @@ mod tests {
 
     #[test]
     fn aws_root_cert_used_as_end_entity_cert() {
-        let ee: &[u8] = include_bytes!("../tests/data/aws_root.der");
-        let ca = include_bytes!("../tests/data/aws_root.der");
+        let ee = CertificateDer::from(&include_bytes!("../tests/data/aws_root.der")[..]);
+        let ca = CertificateDer::from(&include_bytes!("../tests/data/aws_root.der")[..]);
 
-        let anchors = vec![webpki::trust_anchor_util::cert_der_as_trust_anchor(ca).unwrap()];
-        let anchors = webpki::TLSServerTrustAnchors(&anchors);
+        let anchors = [webpki::anchor_from_trusted_cert(&ca).unwrap()];
 
-        let time = webpki::Time::from_seconds_since_unix_epoch(1616094379); // 18 March 2021
+        let time = UnixTime::since_unix_epoch(core::time::Duration::from_secs(1616094379)); // 18 March 2021
 
-        let cert = webpki::EndEntityCert::from(ee).unwrap();
+        let cert = webpki::EndEntityCert::try_from(&ee).unwrap();
         assert_eq!(
-            Err(webpki::Error::CAUsedAsEndEntity),
-            cert.verify_is_valid_tls_server_cert(ALL_SIGALGS, &anchors, &[], time)
+            Err(webpki::Error::CaUsedAsEndEntity),
+            cert.verify_for_usage(
+                chain::ALL_SIGALGS,
+                &anchors,
+                &[],
+                time,
+                chain::AttestationProfile,
+                None,
+                None,
+            )
+            .map(|_| ())
         );
     }
 
     ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
+    #[cfg(feature = "openssl")]
     use openssl::pkey::{Private, Public};
+    use std::collections::BTreeMap;
+
+    type CborMap = BTreeMap<CborValue, CborValue>;
+
+    /// COSE_Sign1 blob with custom headers, dummy payload and signature
+    fn cose_sign1_with_headers(
+        protected: &CborMap,
+        unprotected: &CborMap,
+    ) -> Vec<u8> {
+        let protected = serde_cbor::to_vec(protected).unwrap();
+        serde_cbor::to_vec(&(
+            ByteBuf::from(protected),
+            unprotected,
+            ByteBuf::from(vec![0xa0]),
+            ByteBuf::from(vec![0u8; 96]),
+        ))
+        .unwrap()
+    }
+
+    /// COSE_Sign1 blob with ES384 protected header, custom payload and dummy signature
+    fn cose_sign1_with_payload(payload: CborMap) -> Vec<u8> {
+        let mut protected = CborMap::new();
+        protected.insert(CborValue::Integer(1), CborValue::Integer(-35));
+        let protected = serde_cbor::to_vec(&protected).unwrap();
+        serde_cbor::to_vec(&(
+            ByteBuf::from(protected),
+            CborMap::new(),
+            ByteBuf::from(serde_cbor::to_vec(&CborValue::Map(payload)).unwrap()),
+            ByteBuf::from(vec![0u8; 96]),
+        ))
+        .unwrap()
+    }
+
+    /// Well-formed payload fields with a single PCR and no certificates
+    fn test_payload() -> CborMap {
+        let mut pcrs = BTreeMap::new();
+        pcrs.insert(CborValue::Integer(0), CborValue::Bytes(vec![0u8; 48]));
+
+        let mut payload = BTreeMap::new();
+        payload.insert(CborValue::Text("module_id".into()), CborValue::Text("i-0-enc0".into()));
+        payload.insert(CborValue::Text("digest".into()), CborValue::Text("SHA384".into()));
+        payload.insert(CborValue::Text("timestamp".into()), CborValue::Integer(1614967200000));
+        payload.insert(CborValue::Text("pcrs".into()), CborValue::Map(pcrs));
+        payload.insert(CborValue::Text("certificate".into()), CborValue::Bytes(vec![]));
+        payload.insert(CborValue::Text("cabundle".into()), CborValue::Array(vec![]));
+        payload
+    }
 
     /// Static SECP384R1/P-384 key to be used when cross-validating the implementation
-    fn get_ec384_test_key() -> (EcKey<Private>, EcKey<Public>) {
+    #[cfg(feature = "openssl")]
+    fn get_ec384_test_key() -> (openssl::ec::EcKey<Private>, openssl::ec::EcKey<Public>) {
         let alg = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::SECP384R1).unwrap();
         let x = openssl::bn::BigNum::from_hex_str(
             "5a829f62f2f4f095c0e922719285b4b981c677912870a413137a5d7319916fa8\
@@ -483,7 +1443,7 @@ mod tests {
         let ec_public =
             openssl::ec::EcKey::from_public_key_affine_coordinates(&alg, &x, &y).unwrap();
         let ec_private =
-            openssl::ec::EcKey::from_private_components(&alg, &d, &ec_public.public_key()).unwrap();
+            openssl::ec::EcKey::from_private_components(&alg, &d, ec_public.public_key()).unwrap();
         (
             //PKey::from_ec_key(ec_private).unwrap(),
             //PKey::from_ec_key(ec_public).unwrap(),
@@ -0,0 +1,125 @@
+//! Verification metrics
+//!
+//! With the `metrics` feature, [`Verifier::verify`](crate::Verifier::verify) and
+//! [`Verifier::verify_fast`](crate::Verifier::verify_fast) report to the
+//! [`metrics`](::metrics) facade, so any installed recorder, e.g. the Prometheus
+//! exporter of `metrics-exporter-prometheus`, collects:
+//!
+//! | metric | type | labels |
+//! |---|---|---|
+//! | [`VERIFICATIONS_TOTAL`] | counter | `result`: `accepted` or `rejected` |
+//! | [`FAILURES_TOTAL`] | counter | `kind`, see [`kind_label`], and `code`, see [`NitroAdError::code`] |
+//! | [`VERIFY_DURATION_SECONDS`] | histogram | |
+//! | [`CERT_EXPIRY_HORIZON_SECONDS`] | gauge | |
+//!
+//! The expiry horizon is the time from verification until the first certificate of the
+//! last document accepted by `verify` expires, usually the hours-lived leaf; alert when
+//! it turns negative for documents that should be fresh, or when an approaching CA
+//! certificate expiry shrinks it. Nothing is recorded without a recorder installed.
+
+use std::time::Duration;
+
+use ::metrics::{counter, gauge, histogram};
+
+use crate::{ErrorKind, NitroAdDoc, NitroAdError};
+
+pub static VERIFICATIONS_TOTAL: &str = "nitro_ad_verifications_total";
+pub static FAILURES_TOTAL: &str = "nitro_ad_verification_failures_total";
+pub static VERIFY_DURATION_SECONDS: &str = "nitro_ad_verify_duration_seconds";
+pub static CERT_EXPIRY_HORIZON_SECONDS: &str = "nitro_ad_cert_expiry_horizon_seconds";
+
+/// Value of the `kind` label of [`FAILURES_TOTAL`]
+pub fn kind_label(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::MalformedInput => "malformed_input",
+        ErrorKind::Signature => "signature",
+        ErrorKind::Chain => "chain",
+        ErrorKind::Output => "output",
+        ErrorKind::Policy => "policy",
+        ErrorKind::Io => "io",
+        ErrorKind::Usage => "usage",
+    }
+}
+
+/// Records the outcome and duration of a verification
+pub(crate) fn record_verification(result: Result<(), &NitroAdError>, elapsed: Duration) {
+    histogram!(VERIFY_DURATION_SECONDS).record(elapsed.as_secs_f64());
+    match result {
+        Ok(()) => counter!(VERIFICATIONS_TOTAL, "result" => "accepted").increment(1),
+        Err(e) => {
+            counter!(VERIFICATIONS_TOTAL, "result" => "rejected").increment(1);
+            let kind = kind_label(e.kind());
+            counter!(FAILURES_TOTAL, "kind" => kind, "code" => e.code().to_string()).increment(1);
+        }
+    }
+}
+
+/// Records the [`CERT_EXPIRY_HORIZON_SECONDS`] of the accepted `doc`
+pub(crate) fn record_expiry_horizon(doc: &NitroAdDoc, unix_ts_sec: u64) {
+    if let Some(not_after) = doc.payload().chain_not_after() {
+        gauge!(CERT_EXPIRY_HORIZON_SECONDS).set((not_after - unix_ts_sec as i64) as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use crate::{Verifier, VerifierPolicy};
+
+    #[test]
+    fn test_verification_metrics() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..]);
+        let nonce_verifier = Verifier::new(&root_cert[..])
+            .with_policy(VerifierPolicy::new().with_nonce(&b"challenge"[..]));
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, || {
+            verifier.verify(ad_blob, 1614967200).unwrap();
+            verifier.verify_fast(ad_blob, 1614967200).unwrap();
+            assert!(nonce_verifier.verify(ad_blob, 1614967200).is_err());
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let metric = |name: &str, labels: &[(&str, &str)]| {
+            snapshot.iter().find_map(|(key, _, _, value)| {
+                let found = key.key().name() == name
+                    && key.key().labels().count() == labels.len()
+                    && key.key().labels().all(|l| labels.contains(&(l.key(), l.value())));
+                Some((key.kind(), value)).filter(|_| found)
+            })
+        };
+        assert_eq!(
+            metric(VERIFICATIONS_TOTAL, &[("result", "accepted")]),
+            Some((MetricKind::Counter, &DebugValue::Counter(2)))
+        );
+        assert_eq!(
+            metric(VERIFICATIONS_TOTAL, &[("result", "rejected")]),
+            Some((MetricKind::Counter, &DebugValue::Counter(1)))
+        );
+        let code = NitroAdError::NonceMismatch.code().to_string();
+        assert_eq!(
+            metric(FAILURES_TOTAL, &[("kind", "policy"), ("code", &code)]),
+            Some((MetricKind::Counter, &DebugValue::Counter(1)))
+        );
+        match metric(VERIFY_DURATION_SECONDS, &[]) {
+            Some((MetricKind::Histogram, DebugValue::Histogram(values))) => {
+                assert_eq!(values.len(), 3)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // the leaf certificate of the document expires three hours after issuance
+        match metric(CERT_EXPIRY_HORIZON_SECONDS, &[]) {
+            Some((MetricKind::Gauge, DebugValue::Gauge(horizon))) => {
+                assert!(horizon.into_inner() > 0.0 && horizon.into_inner() <= 3.0 * 3600.0)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
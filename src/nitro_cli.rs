@@ -0,0 +1,172 @@
+//! Running enclaves as reported by `nitro-cli`
+//!
+//! Host-side tooling often knows the enclaves it launched better than their image
+//! files: [`describe_enclaves`] runs `nitro-cli describe-enclaves` on the parent
+//! instance and returns each enclave's EnclaveID, which documents carry as
+//! `module_id`, and measurements, ready to become the expected PCRs of a
+//! [`VerifierPolicy`]. [`parse_describe_enclaves`] parses output captured elsewhere.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::{nitro_cli, Verifier};
+//!
+//! # let aws_root_der = Vec::new();
+//! let enclaves = nitro_cli::describe_enclaves()?;
+//! let verifier = Verifier::new(aws_root_der).with_policy(enclaves[0].policy());
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::{NitroAdError, VerifierPolicy};
+
+/// `Flags` of enclaves started with `--debug-mode`
+pub static DEBUG_MODE_FLAG: &str = "DEBUG_MODE";
+
+/// Running enclave of `nitro-cli describe-enclaves`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnclaveDescription {
+    /// Name given with `--enclave-name`, absent from older nitro-cli output
+    pub enclave_name: Option<String>,
+    /// `module_id` of the enclave's documents
+    pub enclave_id: String,
+    pub process_id: u32,
+    /// vsock CID of the enclave
+    pub enclave_cid: u64,
+    /// e.g. `RUNNING`
+    pub state: String,
+    /// `NONE` or [`DEBUG_MODE_FLAG`]
+    pub flags: String,
+    /// Measurements of the enclave image, empty if nitro-cli didn't report them
+    pub pcrs: BTreeMap<u8, Vec<u8>>,
+}
+
+impl EnclaveDescription {
+    pub fn is_debug_mode(&self) -> bool {
+        self.flags == DEBUG_MODE_FLAG
+    }
+
+    /// Policy requiring the measured PCRs. Debug mode enclaves attest all-zero PCRs, so
+    /// their documents only pass this policy if no PCRs were measured.
+    pub fn policy(&self) -> VerifierPolicy {
+        let pcrs = self.pcrs.iter();
+        pcrs.fold(VerifierPolicy::new(), |policy, (i, value)| policy.with_pcr(*i, value.clone()))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribedEnclave {
+    #[serde(default)]
+    enclave_name: Option<String>,
+    #[serde(rename = "EnclaveID")]
+    enclave_id: String,
+    #[serde(rename = "ProcessID")]
+    process_id: u32,
+    #[serde(rename = "EnclaveCID")]
+    enclave_cid: u64,
+    state: String,
+    flags: String,
+    #[serde(default)]
+    measurements: Option<serde_json::Value>,
+}
+
+/// Enclaves of the JSON output of `nitro-cli describe-enclaves`
+pub fn parse_describe_enclaves(json: &str) -> Result<Vec<EnclaveDescription>, NitroAdError> {
+    let enclaves: Vec<DescribedEnclave> = serde_json::from_str(json)?;
+    enclaves
+        .into_iter()
+        .map(|enclave| {
+            let pcrs = match &enclave.measurements {
+                Some(measurements) => VerifierPolicy::new().with_measurements(measurements)?.pcrs,
+                None => BTreeMap::new(),
+            };
+            Ok(EnclaveDescription {
+                enclave_name: enclave.enclave_name,
+                enclave_id: enclave.enclave_id,
+                process_id: enclave.process_id,
+                enclave_cid: enclave.enclave_cid,
+                state: enclave.state,
+                flags: enclave.flags,
+                pcrs,
+            })
+        })
+        .collect()
+}
+
+/// Enclaves running on this instance, by `nitro-cli describe-enclaves`. Fails with
+/// [`NitroAdError::NitroCliError`] if nitro-cli is not installed or fails.
+pub fn describe_enclaves() -> Result<Vec<EnclaveDescription>, NitroAdError> {
+    let output = Command::new("nitro-cli")
+        .arg("describe-enclaves")
+        .output()
+        .map_err(|e| NitroAdError::NitroCliError(format!("can't run nitro-cli: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(NitroAdError::NitroCliError(format!("{}: {}", output.status, stderr.trim())));
+    }
+    parse_describe_enclaves(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_describe_enclaves() -> Result<(), NitroAdError> {
+        let json = format!(
+            r#"[
+              {{
+                "EnclaveName": "signer",
+                "EnclaveID": "i-05f6ed443a1b2c3d4-enc173dfe3e1b2c3d4",
+                "ProcessID": 7077,
+                "EnclaveCID": 16,
+                "NumberOfCPUs": 2,
+                "CPUIDs": [1, 3],
+                "MemoryMiB": 512,
+                "State": "RUNNING",
+                "Flags": "NONE",
+                "Measurements": {{
+                  "HashAlgorithm": "Sha384 {{ ... }}",
+                  "PCR0": "{}",
+                  "PCR1": "{}",
+                  "PCR2": "{}"
+                }}
+              }},
+              {{
+                "EnclaveID": "i-05f6ed443a1b2c3d4-enc173dfe3e5e6f7a8",
+                "ProcessID": 7123,
+                "EnclaveCID": 17,
+                "NumberOfCPUs": 2,
+                "CPUIDs": [5, 7],
+                "MemoryMiB": 512,
+                "State": "RUNNING",
+                "Flags": "DEBUG_MODE"
+              }}
+            ]"#,
+            "aa".repeat(48),
+            "bb".repeat(48),
+            "cc".repeat(48)
+        );
+        let enclaves = parse_describe_enclaves(&json)?;
+        assert_eq!(enclaves.len(), 2);
+        assert_eq!(enclaves[0].enclave_name.as_deref(), Some("signer"));
+        assert_eq!(enclaves[0].enclave_id, "i-05f6ed443a1b2c3d4-enc173dfe3e1b2c3d4");
+        assert_eq!(enclaves[0].enclave_cid, 16);
+        assert!(!enclaves[0].is_debug_mode());
+        let policy = enclaves[0].policy();
+        assert_eq!(policy.pcrs.keys().collect::<Vec<_>>(), [&0, &1, &2]);
+        assert_eq!(policy.pcrs[&2], vec![0xcc; 48]);
+
+        assert_eq!(enclaves[1].enclave_name, None);
+        assert!(enclaves[1].is_debug_mode());
+        assert!(enclaves[1].pcrs.is_empty());
+
+        assert!(parse_describe_enclaves("[]")?.is_empty());
+        let invalid = r#"[{ "EnclaveID": "i", "ProcessID": 1, "EnclaveCID": 4, "State": "RUNNING",
+            "Flags": "NONE", "Measurements": { "PCR0": "zz" } }]"#;
+        assert!(matches!(parse_describe_enclaves(invalid), Err(NitroAdError::InvalidConfig(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Attestation documents from the Nitro Secure Module
+//!
+//! Inside an enclave, [`Nsm`] asks the `/dev/nsm` device for documents through the
+//! ioctl interface of the aws-nitro-enclaves-nsm-api crate:
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::nsm::{AttestationRequest, Attester, Nsm};
+//!
+//! let nsm = Nsm::open()?;
+//! let document = nsm.attest(AttestationRequest {
+//!     nonce: Some(b"challenge"),
+//!     ..Default::default()
+//! })?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//! The NSM rejects `user_data`, `nonce` and `public_key` longer than 1024 bytes with
+//! [`ErrorCode::InputTooLarge`].
+//!
+//! PCRs 0 to 15 hold the boot measurements and are locked. Applications measure their
+//! runtime configuration into PCRs 16 and up with [`Nsm::extend_pcr`], then lock them
+//! with [`Nsm::lock_pcr`] so later documents attest the final value.
+//!
+//! [`Nsm`] is also an entropy source: [`Nsm::fill_random`] draws from the NSM's
+//! hardware RNG, and its [`RngCore`] and [`CryptoRng`] impls plug into key generation,
+//! e.g. `p384::SecretKey::random(&mut nsm)`.
+//!
+//! [`AttestedKey::generate`] takes the first step of attested key exchange protocols:
+//! it generates an ephemeral P-384 key pair and requests a document vouching for its
+//! public key.
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+
+pub use aws_nitro_enclaves_nsm_api::api::ErrorCode;
+use aws_nitro_enclaves_nsm_api::api::{Request, Response};
+use aws_nitro_enclaves_nsm_api::driver::nsm_process_request;
+use p384::pkcs8::EncodePublicKey;
+use rand_core::{CryptoRng, CryptoRngCore, RngCore};
+use serde_bytes::ByteBuf;
+use zeroize::Zeroizing;
+
+use crate::NitroAdError;
+
+/// NSM device of the enclave
+const DEVICE: &str = "/dev/nsm";
+
+/// Optional fields to include in a requested document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttestationRequest<'a> {
+    pub user_data: Option<&'a [u8]>,
+    pub nonce: Option<&'a [u8]>,
+    pub public_key: Option<&'a [u8]>,
+}
+
+/// PCR state reported by [`Nsm::describe_pcr`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcrDescription {
+    /// PCR can't be extended anymore
+    pub locked: bool,
+    pub value: Vec<u8>,
+}
+
+/// Source of signed attestation documents
+pub trait Attester {
+    /// COSE_Sign1 document carrying the fields of `request`, for
+    /// [`NitroAdDoc::from_bytes`](crate::NitroAdDoc::from_bytes)
+    fn attest(&self, request: AttestationRequest<'_>) -> Result<Vec<u8>, NitroAdError>;
+}
+
+/// Open `/dev/nsm` device, closed on drop
+pub struct Nsm {
+    device: File,
+}
+
+impl Nsm {
+    /// Opens the NSM device, fails with [`NitroAdError::IoError`] outside of enclaves
+    pub fn open() -> Result<Self, NitroAdError> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(DEVICE)
+            .map_err(NitroAdError::IoError)?;
+        Ok(Nsm { device })
+    }
+
+    /// Sends `request` to the device. Error responses become [`NitroAdError::NsmError`].
+    fn request(&self, request: Request) -> Result<Response, NitroAdError> {
+        match nsm_process_request(self.device.as_raw_fd(), request) {
+            Response::Error(code) => Err(NitroAdError::NsmError(code)),
+            response => Ok(response),
+        }
+    }
+
+    /// Current value of PCR `index` and whether it is locked
+    pub fn describe_pcr(&self, index: u16) -> Result<PcrDescription, NitroAdError> {
+        match self.request(Request::DescribePCR { index })? {
+            Response::DescribePCR { lock, data } => Ok(PcrDescription {
+                locked: lock,
+                value: data,
+            }),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+
+    /// Extends PCR `index` with `data`, i.e. sets it to SHA384(value || data), and
+    /// returns the new value. Locked PCRs fail with [`ErrorCode::ReadOnlyIndex`].
+    pub fn extend_pcr(&self, index: u16, data: &[u8]) -> Result<Vec<u8>, NitroAdError> {
+        let request = Request::ExtendPCR {
+            index,
+            data: data.to_vec(),
+        };
+        match self.request(request)? {
+            Response::ExtendPCR { data } => Ok(data),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+
+    /// Locks PCR `index` against further extension until the enclave terminates
+    pub fn lock_pcr(&self, index: u16) -> Result<(), NitroAdError> {
+        match self.request(Request::LockPCR { index })? {
+            Response::LockPCR => Ok(()),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+
+    /// Random bytes of the NSM hardware RNG, as many as the NSM hands out per request
+    pub fn get_random(&self) -> Result<Vec<u8>, NitroAdError> {
+        match self.request(Request::GetRandom)? {
+            Response::GetRandom { random } if !random.is_empty() => Ok(random),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+
+    /// Fills `dest` with random bytes, requesting as often as needed
+    pub fn fill_random(&self, dest: &mut [u8]) -> Result<(), NitroAdError> {
+        let mut filled = 0;
+        while filled < dest.len() {
+            let random = Zeroizing::new(self.get_random()?);
+            let n = random.len().min(dest.len() - filled);
+            dest[filled..filled + n].copy_from_slice(&random[..n]);
+            filled += n;
+        }
+        Ok(())
+    }
+
+    /// Locks PCRs 0 to `range` excluded against further extension
+    pub fn lock_pcrs(&self, range: u16) -> Result<(), NitroAdError> {
+        match self.request(Request::LockPCRs { range })? {
+            Response::LockPCRs => Ok(()),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+}
+
+/// Panics in `fill_bytes` and friends when the NSM fails, like `rand_core::OsRng`
+impl RngCore for Nsm {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = self.fill_random(dest) {
+            panic!("NSM entropy request failed: {}", e);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_random(dest).map_err(rand_core::Error::new)
+    }
+}
+
+impl CryptoRng for Nsm {}
+
+impl Attester for Nsm {
+    fn attest(&self, request: AttestationRequest<'_>) -> Result<Vec<u8>, NitroAdError> {
+        let buf = |field: Option<&[u8]>| field.map(ByteBuf::from);
+        let response = self.request(Request::Attestation {
+            user_data: buf(request.user_data),
+            nonce: buf(request.nonce),
+            public_key: buf(request.public_key),
+        })?;
+        match response {
+            Response::Attestation { document } => Ok(document),
+            _ => Err(NitroAdError::NsmError(ErrorCode::InvalidResponse)),
+        }
+    }
+}
+
+/// Ephemeral P-384 key pair with a document attesting its public key
+pub struct AttestedKey {
+    /// Private key, zeroized on drop
+    pub secret_key: p384::SecretKey,
+    /// DER encoded SubjectPublicKeyInfo of the key, the `public_key` of the document
+    pub public_key: Vec<u8>,
+    /// Attestation document
+    pub document: Vec<u8>,
+}
+
+impl AttestedKey {
+    /// Generates a key pair with `rng` and requests a document from `attester`, carrying
+    /// the public key along with `user_data` and `nonce`. Inside an enclave the NSM can
+    /// serve as both: `AttestedKey::generate(&nsm, &mut Nsm::open()?, None, nonce)`.
+    pub fn generate<A: Attester + ?Sized>(
+        attester: &A,
+        rng: &mut impl CryptoRngCore,
+        user_data: Option<&[u8]>,
+        nonce: Option<&[u8]>,
+    ) -> Result<Self, NitroAdError> {
+        let secret_key = p384::SecretKey::random(rng);
+        let public_key = public_key_der(&secret_key)?;
+        let document = attester.attest(AttestationRequest {
+            user_data,
+            nonce,
+            public_key: Some(&public_key),
+        })?;
+        Ok(AttestedKey {
+            secret_key,
+            public_key,
+            document,
+        })
+    }
+}
+
+/// DER encoded SubjectPublicKeyInfo of the public key of `secret_key`
+pub(crate) fn public_key_der(secret_key: &p384::SecretKey) -> Result<Vec<u8>, NitroAdError> {
+    let der = secret_key.public_key().to_public_key_der();
+    Ok(der.map_err(|e| NitroAdError::InvalidSigningKey(e.to_string()))?.into_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    #[test]
+    fn test_open_outside_enclave() {
+        if Path::new(DEVICE).exists() {
+            return;
+        }
+        let err = Nsm::open().err().unwrap();
+        assert!(err.is_io_failure());
+        assert_eq!(err.code(), 7);
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_attested_key() -> Result<(), NitroAdError> {
+        use p384::pkcs8::DecodePublicKey;
+
+        use crate::testing::MockNsm;
+        use crate::NitroAdDoc;
+
+        let mock = MockNsm::new()?;
+        let key = AttestedKey::generate(&mock, &mut rand_core::OsRng, None, Some(b"challenge"))?;
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&key.document, &mock.root_cert(), now)?;
+        assert!(doc.verification_error().is_none());
+        let public_key = doc.payload().public_key.as_deref().unwrap();
+        assert_eq!(public_key, &key.public_key[..]);
+        assert_eq!(p384::PublicKey::from_public_key_der(public_key).unwrap(), key.secret_key.public_key());
+        assert_eq!(doc.payload().nonce.as_deref(), Some(&b"challenge"[..]));
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+//! Proofs for verifying documents in smart contracts
+//!
+//! Contracts can't afford to parse COSE, CBOR and X.509 on-chain, so a [`ProofBundle`]
+//! carries exactly the bytes the signatures cover, plus offsets into them that let a
+//! contract read the values it cares about without parsing:
+//!
+//! * the COSE Sig_structure, whose ES384 signature by the leaf certificate key is the
+//!   document signature
+//! * for each certificate, root first, its TBSCertificate and issuer signature, both
+//!   ECDSA P-384 with SHA-384, and where its uncompressed public key lies in the TBS
+//! * where the PCRs, `timestamp`, `user_data`, `nonce` and `public_key` of the payload
+//!   lie in the Sig_structure
+//!
+//! The contract pins the root, checks each signature with the key of the certificate
+//! before, the root's own included, and the Sig_structure signature with the leaf key,
+//! then slices claims out of the Sig_structure. Oracles relaying enclave results
+//! typically bind a signing key in `public_key` and check their results against it
+//! afterwards. [`ProofBundle::to_abi`] encodes bundles for Solidity's `abi.decode`; all
+//! keys and signatures are fixed size, so they map onto SSZ vectors as well.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::onchain::ProofBundle;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! let doc = Verifier::new(aws_root_der).verify(&document, now)?;
+//! let calldata = ProofBundle::from_doc(&doc)?.to_abi();
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//! Contracts must check certificate validity against the block time themselves, and
+//! should require a recent `timestamp`; the bundle proves what the enclave attested, not
+//! when it is relayed.
+
+use core::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+use x509_parser::oid_registry::OID_SIG_ECDSA_WITH_SHA384;
+use x509_parser::prelude::*;
+
+use crate::crypto::ecdsa_der_to_raw;
+use crate::{CoseSign1, NitroAdDoc, NitroAdDocPayload, NitroAdError};
+
+/// Length of P-384 scalars and coordinates
+const P384_LEN: usize = 48;
+
+/// Encoded `timestamp` payload key, followed by the value in every NSM document
+static TIMESTAMP_KEY: &[u8] = b"\x69timestamp";
+
+/// Initial byte of CBOR unsigned integers with 8 bytes big-endian values
+const CBOR_UINT64: u8 = 0x1b;
+
+/// Bytes `offset..offset + len` of [`ProofBundle::sig_structure`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// Certificate of the document chain, with hex encoded bytes in JSON
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateProof {
+    /// DER encoded TBSCertificate, the bytes the issuer signed
+    #[serde_as(as = "Hex")]
+    pub tbs_certificate: Vec<u8>,
+    /// r||s ECDSA signature of the issuer over the SHA-384 of `tbs_certificate`
+    #[serde_as(as = "Hex")]
+    pub signature: Vec<u8>,
+    /// x||y of the certificate's P-384 key
+    #[serde_as(as = "Hex")]
+    pub public_key: Vec<u8>,
+    /// Offset of `public_key` in `tbs_certificate`
+    pub public_key_offset: u32,
+    /// Validity in seconds since the unix epoch
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+/// PCR of the payload
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcrProof {
+    pub index: u8,
+    #[serde_as(as = "Hex")]
+    pub value: Vec<u8>,
+    /// Offset of `value` in [`ProofBundle::sig_structure`]
+    pub offset: u32,
+}
+
+/// Data for verifying a document on-chain, see the [module documentation](self)
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofBundle {
+    /// COSE_Sign1 Sig_structure of the document
+    #[serde_as(as = "Hex")]
+    pub sig_structure: Vec<u8>,
+    /// r||s ES384 signature of the leaf certificate key over `sig_structure`
+    #[serde_as(as = "Hex")]
+    pub signature: Vec<u8>,
+    /// `cabundle`, root first, followed by the leaf `certificate`
+    pub chain: Vec<CertificateProof>,
+    /// Offset of the CBOR payload in `sig_structure`
+    pub payload_offset: u32,
+    /// Document `timestamp`, milliseconds since the unix epoch
+    pub timestamp_ms: u64,
+    /// Offset of the 8 bytes big-endian `timestamp_ms` in `sig_structure`
+    pub timestamp_offset: u32,
+    pub pcrs: Vec<PcrProof>,
+    pub user_data: Option<Span>,
+    pub nonce: Option<Span>,
+    pub public_key: Option<Span>,
+}
+
+impl ProofBundle {
+    /// Proof of the verified `doc`. Fails for documents whose certificate chain didn't
+    /// verify, and with [`NitroAdError::ProofError`] for documents the NSM wouldn't
+    /// produce, e.g. with indefinite length byte strings or other signature algorithms.
+    pub fn from_doc(doc: &NitroAdDoc) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+        let cose = CoseSign1(serde_cbor::from_slice(doc.as_bytes())?);
+        let (protected, _, payload, signature) = &cose.0;
+        let sig_structure = crate::sig_structure(protected, payload)?;
+        let payload_offset = sig_structure.len() - payload.len();
+
+        // byte strings borrow from the Sig_structure, locating them in it
+        let fields: NitroAdDocPayload = serde_cbor::from_slice(&sig_structure[payload_offset..])?;
+        let span = |value: &[u8]| {
+            let offset = (value.as_ptr() as usize).wrapping_sub(sig_structure.as_ptr() as usize);
+            match offset.checked_add(value.len()) {
+                Some(end) if end <= sig_structure.len() => Ok(Span {
+                    offset: offset as u32,
+                    len: value.len() as u32,
+                }),
+                _ => Err(NitroAdError::ProofError("payload holds indefinite length byte strings")),
+            }
+        };
+        let optional = |value: &Option<crate::Bytes>| value.as_deref().map(span).transpose();
+
+        let timestamp_ms = u64::try_from(fields.timestamp.timestamp_millis())
+            .map_err(|_| NitroAdError::ProofError("timestamp is negative"))?;
+        let timestamp_offset = sig_structure[payload_offset..]
+            .windows(TIMESTAMP_KEY.len() + 9)
+            .position(|window| {
+                window.starts_with(TIMESTAMP_KEY)
+                    && window[TIMESTAMP_KEY.len()] == CBOR_UINT64
+                    && window[TIMESTAMP_KEY.len() + 1..] == timestamp_ms.to_be_bytes()
+            })
+            .map(|position| payload_offset + position + TIMESTAMP_KEY.len() + 1)
+            .ok_or(NitroAdError::ProofError("timestamp is not a 64-bit unsigned integer"))?;
+
+        let user_data = optional(&fields.user_data)?;
+        let nonce = optional(&fields.nonce)?;
+        let public_key = optional(&fields.public_key)?;
+        let pcrs = fields
+            .pcrs
+            .iter()
+            .map(|(index, value)| {
+                Ok(PcrProof {
+                    index: *index,
+                    value: value.to_vec(),
+                    offset: span(value)?.offset,
+                })
+            })
+            .collect::<Result<_, NitroAdError>>()?;
+        let chain = fields
+            .cabundle
+            .iter()
+            .chain(core::iter::once(&fields.certificate))
+            .map(|der| certificate_proof(der))
+            .collect::<Result<_, _>>()?;
+        drop(fields);
+
+        Ok(ProofBundle {
+            sig_structure,
+            signature: signature.to_vec(),
+            chain,
+            payload_offset: payload_offset as u32,
+            timestamp_ms,
+            timestamp_offset: timestamp_offset as u32,
+            pcrs,
+            user_data,
+            nonce,
+            public_key,
+        })
+    }
+
+    /// x||y of the key signing `sig_structure`
+    pub fn leaf_public_key(&self) -> &[u8] {
+        self.chain.last().map_or(&[], |leaf| &leaf.public_key)
+    }
+
+    /// Solidity ABI encoding of the bundle as the parameters
+    /// ```solidity
+    /// (bytes sigStructure, bytes signature, bytes[] tbsCertificates,
+    ///  bytes[] certSignatures, uint256[] publicKeyOffsets, uint256 payloadOffset,
+    ///  uint256 timestampOffset, uint256[] pcrIndices, uint256[] pcrOffsets,
+    ///  uint256[] spans)
+    /// ```
+    /// where `spans` holds offset and length of `user_data`, `nonce` and `public_key`,
+    /// both zero for absent fields. PCR values are 48 bytes long, and public keys 96.
+    pub fn to_abi(&self) -> Vec<u8> {
+        let spans = [self.user_data, self.nonce, self.public_key]
+            .iter()
+            .flat_map(|span| span.map_or([0, 0], |span| [span.offset as u64, span.len as u64]))
+            .collect();
+        let chain = |field: fn(&CertificateProof) -> &[u8]| self.chain.iter().map(field).collect();
+        abi_encode(&[
+            Token::Bytes(&self.sig_structure),
+            Token::Bytes(&self.signature),
+            Token::BytesArray(chain(|cert| &cert.tbs_certificate)),
+            Token::BytesArray(chain(|cert| &cert.signature)),
+            Token::UintArray(self.chain.iter().map(|cert| cert.public_key_offset as u64).collect()),
+            Token::Uint(self.payload_offset as u64),
+            Token::Uint(self.timestamp_offset as u64),
+            Token::UintArray(self.pcrs.iter().map(|pcr| pcr.index as u64).collect()),
+            Token::UintArray(self.pcrs.iter().map(|pcr| pcr.offset as u64).collect()),
+            Token::UintArray(spans),
+        ])
+    }
+}
+
+fn certificate_proof(der: &[u8]) -> Result<CertificateProof, NitroAdError> {
+    let (_, cert) =
+        X509Certificate::from_der(der).map_err(|e| NitroAdError::X509Error(e.to_string()))?;
+    if cert.signature_algorithm.algorithm != OID_SIG_ECDSA_WITH_SHA384 {
+        return Err(NitroAdError::ProofError("certificate is not signed with ECDSA SHA-384"));
+    }
+    let signature = ecdsa_der_to_raw(&cert.signature_value.data, P384_LEN)
+        .ok_or(NitroAdError::ProofError("certificate signature is not a P-384 signature"))?;
+
+    let tbs = cert.tbs_certificate.as_ref();
+    let point = &cert.public_key().subject_public_key.data;
+    let public_key = match point.split_first() {
+        // uncompressed SEC1 point
+        Some((0x04, public_key)) if public_key.len() == 2 * P384_LEN => public_key,
+        _ => return Err(NitroAdError::ProofError("certificate key is not a P-384 key")),
+    };
+    let public_key_offset = (public_key.as_ptr() as usize).wrapping_sub(tbs.as_ptr() as usize);
+    if public_key_offset + public_key.len() > tbs.len() {
+        return Err(NitroAdError::ProofError("certificate key is not part of the TBS"));
+    }
+
+    Ok(CertificateProof {
+        tbs_certificate: tbs.to_vec(),
+        signature,
+        public_key: public_key.to_vec(),
+        public_key_offset: public_key_offset as u32,
+        not_before: cert.validity().not_before.timestamp(),
+        not_after: cert.validity().not_after.timestamp(),
+    })
+}
+
+enum Token<'a> {
+    Uint(u64),
+    Bytes(&'a [u8]),
+    BytesArray(Vec<&'a [u8]>),
+    UintArray(Vec<u64>),
+}
+
+fn abi_word(value: u64) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// ABI encoding of `tokens` as a tuple: one head word per token, static values inline
+/// and offsets of dynamic ones, counted from the start of the tuple, into the tail
+fn abi_encode(tokens: &[Token]) -> Vec<u8> {
+    let mut head = Vec::with_capacity(32 * tokens.len());
+    let mut tail = Vec::new();
+    for token in tokens {
+        let offset = 32 * tokens.len() + tail.len();
+        match token {
+            Token::Uint(value) => head.extend_from_slice(&abi_word(*value)),
+            Token::Bytes(bytes) => {
+                head.extend_from_slice(&abi_word(offset as u64));
+                tail.extend_from_slice(&abi_word(bytes.len() as u64));
+                tail.extend_from_slice(bytes);
+                tail.resize(tail.len() + (32 - bytes.len() % 32) % 32, 0);
+            }
+            Token::BytesArray(items) => {
+                head.extend_from_slice(&abi_word(offset as u64));
+                tail.extend_from_slice(&abi_word(items.len() as u64));
+                let items: Vec<_> = items.iter().map(|bytes| Token::Bytes(bytes)).collect();
+                tail.extend_from_slice(&abi_encode(&items));
+            }
+            Token::UintArray(values) => {
+                head.extend_from_slice(&abi_word(offset as u64));
+                tail.extend_from_slice(&abi_word(values.len() as u64));
+                for value in values {
+                    tail.extend_from_slice(&abi_word(*value));
+                }
+            }
+        }
+    }
+    head.extend_from_slice(&tail);
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+
+    use crate::crypto::{CryptoBackend, DefaultBackend};
+    use crate::CoseSign1Raw;
+
+    /// DER SubjectPublicKeyInfo of P-384 keys up to the uncompressed point's x||y
+    static P384_SPKI_PREFIX: &[u8] = &[
+        0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06,
+        0x05, 0x2b, 0x81, 0x04, 0x00, 0x22, 0x03, 0x62, 0x00, 0x04,
+    ];
+
+    fn verifies(public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+        let backend = DefaultBackend::default();
+        let key = backend.parse_spki(&[P384_SPKI_PREFIX, public_key].concat()).unwrap();
+        backend.verify_es384(&key, data, signature)
+    }
+
+    #[test]
+    fn test_proof_bundle() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let proof = ProofBundle::from_doc(&doc)?;
+
+        // what a contract checks, from the bundle only
+        let sig = &proof.sig_structure;
+        let root = &proof.chain[0];
+        let (_, pinned) = X509Certificate::from_der(root_cert).unwrap();
+        assert_eq!(root.tbs_certificate, pinned.tbs_certificate.as_ref());
+        for (issuer, cert) in proof.chain.iter().zip(&proof.chain[1..]) {
+            let offset = cert.public_key_offset as usize;
+            assert_eq!(&cert.tbs_certificate[offset..offset + 96], &cert.public_key[..]);
+            assert!(verifies(&issuer.public_key, &cert.tbs_certificate, &cert.signature));
+        }
+        assert!(verifies(&root.public_key, &root.tbs_certificate, &root.signature));
+        assert!(verifies(proof.leaf_public_key(), sig, &proof.signature));
+        for pcr in &proof.pcrs {
+            let offset = pcr.offset as usize;
+            assert_eq!(&sig[offset..offset + 48], &doc.payload().pcrs[&pcr.index][..]);
+        }
+        let offset = proof.timestamp_offset as usize;
+        let timestamp = u64::from_be_bytes(sig[offset..offset + 8].try_into().unwrap());
+        assert_eq!(timestamp as i64, doc.payload().timestamp.timestamp_millis());
+        let (_, _, payload, _): CoseSign1Raw = serde_cbor::from_slice(ad_blob)?;
+        assert_eq!(&sig[proof.payload_offset as usize..], &payload[..]);
+        assert_eq!(proof.user_data, None);
+
+        let mut tampered = proof.sig_structure.clone();
+        tampered[proof.pcrs[0].offset as usize] ^= 0x01;
+        assert!(!verifies(proof.leaf_public_key(), &tampered, &proof.signature));
+
+        let json = serde_json::to_string(&proof)?;
+        assert_eq!(serde_json::from_str::<ProofBundle>(&json)?, proof);
+
+        let expired = NitroAdDoc::from_bytes(ad_blob, root_cert, 1714967200)?;
+        assert!(matches!(ProofBundle::from_doc(&expired), Err(NitroAdError::VerificationError(_))));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_proof_spans() -> Result<(), NitroAdError> {
+        use crate::nsm::{AttestationRequest, Attester};
+        use crate::testing::MockNsm;
+
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let document = mock.attest(AttestationRequest {
+            user_data: Some(b"result digest"),
+            nonce: Some(b""),
+            public_key: Some(b"oracle key"),
+        })?;
+        let doc = NitroAdDoc::from_bytes(&document, &mock.root_cert(), now)?;
+        let proof = ProofBundle::from_doc(&doc)?;
+
+        let slice = |span: Option<Span>| {
+            let span = span.unwrap();
+            &proof.sig_structure[span.offset as usize..(span.offset + span.len) as usize]
+        };
+        assert_eq!(slice(proof.user_data), b"result digest");
+        assert_eq!(slice(proof.nonce), b"");
+        assert_eq!(slice(proof.public_key), b"oracle key");
+        let not_after = proof.chain.iter().map(|cert| cert.not_after).min();
+        assert_eq!(not_after, doc.payload().chain_not_after());
+        Ok(())
+    }
+
+    #[test]
+    fn test_abi_encode() {
+        let encoded = abi_encode(&[
+            Token::Uint(1),
+            Token::Bytes(b"ab"),
+            Token::BytesArray(vec![b"c", b""]),
+            Token::UintArray(vec![7]),
+        ]);
+        let words: Vec<_> = encoded.chunks(32).map(hex::encode).collect();
+        let word = |value: u64| hex::encode(abi_word(value));
+        let padded = |bytes: &[u8]| hex::encode([bytes, &[0; 32][bytes.len()..]].concat());
+        assert_eq!(
+            words,
+            [
+                word(1),
+                word(0x80),
+                word(0xc0),
+                word(0x180),
+                // "ab"
+                word(2),
+                padded(b"ab"),
+                // ["c", ""]: length, offsets from after the length, then each bytes
+                word(2),
+                word(0x40),
+                word(0x80),
+                word(1),
+                padded(b"c"),
+                word(0),
+                // [7]
+                word(1),
+                word(7),
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Typed output model of a parsed attestation document
+//!
+//! [`NitroAdDoc::to_json`](crate::NitroAdDoc::to_json) serializes a [`DocumentOutput`],
+//! which can be deserialized back from that JSON. With the `schemars` feature,
+//! [`json_schema`] describes that JSON for validation and type generation.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use x509_parser::prelude::*;
+
+use crate::{NitroAdDoc, NitroAdDocPayload, NitroAdError};
+
+/// Formatting and field selection for JSON output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonOptions {
+    /// Spaces per indentation level, `None` for single line output
+    pub indent: Option<usize>,
+    /// Include the `pcrs` map
+    pub pcrs: bool,
+    /// Include the `certs` chain summary
+    pub certs: bool,
+    /// Include `public_key`, `user_data` and `nonce`
+    pub optional_fields: bool,
+    /// Leave out fields which have no value instead of emitting `null`
+    pub skip_nulls: bool,
+    /// Embed each certificate of the `certs` chain in the given encoding
+    pub raw_certs: Option<CertEncoding>,
+    /// Add `pcr_meanings`, describing what each documented PCR measures
+    pub pcr_meanings: bool,
+}
+
+/// Encoding of certificates embedded with [`JsonOptions::raw_certs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertEncoding {
+    /// base64 encoded DER in the `der` field
+    Der,
+    /// PEM in the `pem` field
+    Pem,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        JsonOptions {
+            indent: None,
+            pcrs: true,
+            certs: true,
+            optional_fields: true,
+            skip_nulls: false,
+            raw_certs: None,
+            pcr_meanings: false,
+        }
+    }
+}
+
+impl JsonOptions {
+    /// Human readable output, indented by two spaces
+    pub fn pretty() -> Self {
+        JsonOptions {
+            indent: Some(2),
+            ..Default::default()
+        }
+    }
+
+    /// Smallest output: single line, no certificate chain, no nulls
+    pub fn minimal() -> Self {
+        JsonOptions {
+            certs: false,
+            skip_nulls: true,
+            ..Default::default()
+        }
+    }
+
+    /// Complete, self-describing audit record: indented output with PCR meanings
+    /// and the PEM encoded certificate chain
+    pub fn audit() -> Self {
+        JsonOptions {
+            raw_certs: Some(CertEncoding::Pem),
+            pcr_meanings: true,
+            ..JsonOptions::pretty()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct DocumentOutput {
+    pub module_id: String,
+    pub digest: String,
+    pub timestamp: String,
+    /// PCR index to hex encoded value
+    pub pcrs: BTreeMap<u8, String>,
+    /// PCR index to its documented meaning, see [`JsonOptions::pcr_meanings`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pcr_meanings: Option<BTreeMap<u8, String>>,
+    /// `cabundle` certificates followed by the signing certificate
+    pub certs: Vec<CertificateOutput>,
+    /// base64 encoded
+    pub public_key: Option<String>,
+    /// base64 encoded
+    pub user_data: Option<String>,
+    /// base64 encoded
+    pub nonce: Option<String>,
+    pub verification_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct CertificateOutput {
+    pub issuer: String,
+    pub subject: String,
+    pub validity: ValidityOutput,
+    /// base64 encoded DER, see [`JsonOptions::raw_certs`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub der: Option<String>,
+    /// PEM, see [`JsonOptions::raw_certs`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pem: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ValidityOutput {
+    pub not_before: String,
+    pub not_after: String,
+}
+
+impl DocumentOutput {
+    pub fn from_doc(doc: &NitroAdDoc) -> Result<Self, NitroAdError> {
+        Self::from_doc_with(doc, &JsonOptions::default())
+    }
+
+    /// Like [`from_doc`](Self::from_doc), embedding certificates as selected by `options.raw_certs`
+    pub fn from_doc_with(doc: &NitroAdDoc, options: &JsonOptions) -> Result<Self, NitroAdError> {
+        let mut output = Self::from_payload(doc.payload(), options)?;
+        output.verification_error = doc.verification_error().map(|e| e.to_string());
+        Ok(output)
+    }
+
+    /// Output of a payload which may not have been verified, e.g. for inspecting
+    /// rejected documents. Its `verification_error` is `None`.
+    pub fn from_payload(
+        payload: &NitroAdDocPayload,
+        options: &JsonOptions,
+    ) -> Result<Self, NitroAdError> {
+        let certs = payload
+            .cabundle
+            .iter()
+            .chain(std::iter::once(&payload.certificate))
+            .map(|der| {
+                let cert = CertificateOutput::from_der(der)?;
+                Ok(match options.raw_certs {
+                    None => cert,
+                    Some(encoding) => cert.with_encoded(der, encoding),
+                })
+            })
+            .collect::<Result<Vec<_>, NitroAdError>>()?;
+
+        let pcr_meanings = options.pcr_meanings.then(|| {
+            payload
+                .pcrs
+                .keys()
+                .filter_map(|i| pcr_meaning(*i).map(|meaning| (*i, String::from(meaning))))
+                .collect()
+        });
+
+        Ok(DocumentOutput {
+            module_id: payload.module_id.clone(),
+            digest: payload.digest.clone(),
+            timestamp: payload.timestamp.to_string(),
+            pcrs: payload
+                .pcrs
+                .iter()
+                .map(|(i, val)| (*i, hex::encode(val)))
+                .collect(),
+            pcr_meanings,
+            certs,
+            public_key: payload.public_key.as_ref().map(base64::encode),
+            user_data: payload.user_data.as_ref().map(base64::encode),
+            nonce: payload.nonce.as_ref().map(base64::encode),
+            verification_error: None,
+        })
+    }
+
+    pub fn to_json(&self, options: &JsonOptions) -> Result<String, NitroAdError> {
+        let mut value = serde_json::to_value(self)?;
+
+        if let serde_json::Value::Object(fields) = &mut value {
+            if !options.pcrs {
+                fields.remove("pcrs");
+                fields.remove("pcr_meanings");
+            }
+            if !options.certs {
+                fields.remove("certs");
+            }
+            if !options.optional_fields {
+                for name in &["public_key", "user_data", "nonce"] {
+                    fields.remove(*name);
+                }
+            }
+            if options.skip_nulls {
+                fields.retain(|_, v| !v.is_null());
+            }
+        }
+
+        match options.indent {
+            None => Ok(serde_json::to_string(&value)?),
+            Some(indent) => {
+                let indent = vec![b' '; indent];
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+                let mut out = Vec::new();
+                let mut ser = serde_json::Serializer::with_formatter(&mut out, formatter);
+                value.serialize(&mut ser)?;
+                // serde_json only emits valid UTF-8
+                Ok(String::from_utf8(out).unwrap())
+            }
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> Result<String, NitroAdError> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// What the PCR at `index` measures, as documented in
+/// https://docs.aws.amazon.com/enclaves/latest/user/set-up-attestation.html.
+/// `None` for PCRs without a documented meaning.
+pub fn pcr_meaning(index: u8) -> Option<&'static str> {
+    match index {
+        0 => Some("enclave image file"),
+        1 => Some("Linux kernel and bootstrap"),
+        2 => Some("application"),
+        3 => Some("IAM role assigned to the parent instance"),
+        4 => Some("instance ID of the parent instance"),
+        8 => Some("enclave image file signing certificate"),
+        _ => None,
+    }
+}
+
+/// JSON Schema (draft 2020-12) of the [`NitroAdDoc::to_json`] output
+#[cfg(feature = "schemars")]
+pub fn json_schema() -> schemars::Schema {
+    schemars::schema_for!(DocumentOutput)
+}
+
+impl CertificateOutput {
+    pub fn from_der(der: &[u8]) -> Result<Self, NitroAdError> {
+        let (_, cert) =
+            X509Certificate::from_der(der).map_err(|e| NitroAdError::X509Error(e.to_string()))?;
+
+        Ok(CertificateOutput {
+            issuer: cert.issuer().to_string(),
+            subject: cert.subject().to_string(),
+            validity: ValidityOutput {
+                not_before: cert.validity().not_before.to_string(),
+                not_after: cert.validity().not_after.to_string(),
+            },
+            der: None,
+            pem: None,
+        })
+    }
+
+    fn with_encoded(mut self, der: &[u8], encoding: CertEncoding) -> Self {
+        let b64 = base64::encode(der);
+        match encoding {
+            CertEncoding::Der => self.der = Some(b64),
+            CertEncoding::Pem => {
+                let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+                // RFC 7468 lines are at most 64 characters
+                for line in b64.as_bytes().chunks(64) {
+                    // base64 output is ASCII
+                    pem.push_str(std::str::from_utf8(line).unwrap());
+                    pem.push('\n');
+                }
+                pem.push_str("-----END CERTIFICATE-----\n");
+                self.pem = Some(pem);
+            }
+        }
+        self
+    }
+}
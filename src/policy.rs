@@ -0,0 +1,495 @@
+//! Checks of verified documents against expected values
+//!
+//! A [`VerifierPolicy`] lists the PCR values, `nonce` and `user_data` a document
+//! must carry. Every comparison goes through [`ct_eq`], so the response time of a
+//! verification service doesn't tell a caller how much of an expected value it
+//! guessed right.
+//!
+//! Protocols keeping structured claims in `user_data` register their type with
+//! [`VerifierPolicy::with_user_data_schema`] or [`VerifierPolicy::with_user_data_claims`],
+//! so documents whose claims don't decode, or fail the application's checks, are
+//! rejected by verification like any other policy failure.
+//!
+//! Beyond expected values, a policy can bound the age of documents, reject those of
+//! debug-mode enclaves and require optional fields to be present. With the `config`
+//! feature, all of it loads from a TOML or YAML file, see the `config` module.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::{NitroAdDoc, NitroAdDocPayload, NitroAdError};
+
+/// Constant time equality of `a` and `b`. Only their lengths, which are public for
+/// all values compared here, affect the time taken.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Serialization of structured `user_data`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserDataEncoding {
+    /// A single CBOR data item
+    Cbor,
+    /// A JSON document
+    #[cfg(feature = "std")]
+    Json,
+}
+
+type UserDataCheck = dyn Fn(&NitroAdDocPayload) -> Result<(), NitroAdError> + Send + Sync;
+
+/// Expected type of `user_data`, see [`VerifierPolicy::with_user_data_claims`]
+#[derive(Clone)]
+pub struct UserDataSchema {
+    type_name: &'static str,
+    encoding: UserDataEncoding,
+    check: Arc<UserDataCheck>,
+}
+
+impl UserDataSchema {
+    /// Rust type name of the claims, for diagnostics only
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn encoding(&self) -> UserDataEncoding {
+        self.encoding
+    }
+}
+
+impl fmt::Debug for UserDataSchema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UserDataSchema")
+            .field("type_name", &self.type_name)
+            .field("encoding", &self.encoding)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Schemas are equal if cloned from the same registration
+impl PartialEq for UserDataSchema {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.check, &other.check)
+    }
+}
+
+impl Eq for UserDataSchema {}
+
+/// Handling of documents of enclaves started with `nitro-cli run-enclave --debug-mode`,
+/// whose memory the parent instance can read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugMode {
+    /// Accepts debug-mode documents like any other
+    #[default]
+    Allow,
+    /// Rejects debug-mode documents with [`NitroAdError::DebugModeRejected`]
+    Reject,
+}
+
+/// Optional document field a policy can require, see
+/// [`VerifierPolicy::with_required_field`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentField {
+    Nonce,
+    UserData,
+    PublicKey,
+}
+
+impl DocumentField {
+    /// Name of the field in the document payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            DocumentField::Nonce => "nonce",
+            DocumentField::UserData => "user_data",
+            DocumentField::PublicKey => "public_key",
+        }
+    }
+
+    fn is_present(&self, payload: &NitroAdDocPayload) -> bool {
+        match self {
+            DocumentField::Nonce => payload.nonce.is_some(),
+            DocumentField::UserData => payload.user_data.is_some(),
+            DocumentField::PublicKey => payload.public_key.is_some(),
+        }
+    }
+}
+
+/// Values a document must carry, fields left empty are not checked
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifierPolicy {
+    /// PCR index to expected value
+    pub pcrs: BTreeMap<u8, Vec<u8>>,
+    /// Expected `nonce`, typically the challenge sent to the enclave
+    pub nonce: Option<Vec<u8>>,
+    /// Expected `user_data`
+    pub user_data: Option<Vec<u8>>,
+    /// Expected type of the claims in `user_data`
+    pub user_data_schema: Option<UserDataSchema>,
+    /// Longest time in seconds between the document `timestamp` and verification
+    pub max_age_secs: Option<u64>,
+    pub debug_mode: DebugMode,
+    /// Optional fields which must be present, whatever their value
+    pub required_fields: BTreeSet<DocumentField>,
+}
+
+impl VerifierPolicy {
+    /// Policy accepting every document
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires PCR `index` to hold `value`
+    pub fn with_pcr(mut self, index: u8, value: impl Into<Vec<u8>>) -> Self {
+        self.pcrs.insert(index, value.into());
+        self
+    }
+
+    /// Requires the document `nonce` to be `nonce`
+    pub fn with_nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Requires the document `user_data` to be `user_data`
+    pub fn with_user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    /// Requires documents to be at most `max_age_secs` old when verified
+    pub fn with_max_age(mut self, max_age_secs: u64) -> Self {
+        self.max_age_secs = Some(max_age_secs);
+        self
+    }
+
+    pub fn with_debug_mode(mut self, debug_mode: DebugMode) -> Self {
+        self.debug_mode = debug_mode;
+        self
+    }
+
+    /// Requires the optional `field` to be present
+    pub fn with_required_field(mut self, field: DocumentField) -> Self {
+        self.required_fields.insert(field);
+        self
+    }
+
+    /// Requires the document `user_data` to decode from `encoding` to a `T`
+    pub fn with_user_data_schema<T: DeserializeOwned + 'static>(
+        self,
+        encoding: UserDataEncoding,
+    ) -> Self {
+        self.with_user_data_claims(encoding, |_: &T| Ok(()))
+    }
+
+    /// Requires the document `user_data` to decode from `encoding` to a `T` accepted by
+    /// `check`, whose error message ends up in [`NitroAdError::InvalidUserData`]. Absent
+    /// `user_data` fails with [`NitroAdError::MissingUserData`].
+    pub fn with_user_data_claims<T, F>(mut self, encoding: UserDataEncoding, check: F) -> Self
+    where
+        T: DeserializeOwned + 'static,
+        F: Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    {
+        let check = move |payload: &NitroAdDocPayload| {
+            let claims: T = match encoding {
+                UserDataEncoding::Cbor => payload.user_data_cbor()?,
+                #[cfg(feature = "std")]
+                UserDataEncoding::Json => payload.user_data_json()?,
+            };
+            check(&claims).map_err(|e| {
+                NitroAdError::InvalidUserData(alloc::format!("claims rejected: {}", e))
+            })
+        };
+        self.user_data_schema = Some(UserDataSchema {
+            type_name: core::any::type_name::<T>(),
+            encoding,
+            check: Arc::new(check),
+        });
+        self
+    }
+
+    /// Requires the PCRs of `json`, an object of hex values keyed `PCR0`, `PCR1`, ...,
+    /// such as the `Measurements` of `nitro-cli build-enclave` output, which is accepted
+    /// as is. Other keys, like nitro-cli's `HashAlgorithm`, are ignored.
+    #[cfg(feature = "std")]
+    pub fn with_measurements_json(self, json: &str) -> Result<Self, NitroAdError> {
+        self.with_measurements(&serde_json::from_str(json)?)
+    }
+
+    /// [`VerifierPolicy::with_measurements_json`] of parsed JSON
+    #[cfg(feature = "std")]
+    pub(crate) fn with_measurements(
+        mut self,
+        json: &serde_json::Value,
+    ) -> Result<Self, NitroAdError> {
+        let measurements = json.get("Measurements").unwrap_or(json).as_object();
+        let invalid = |message: String| NitroAdError::InvalidConfig(message);
+        let measurements =
+            measurements.ok_or_else(|| invalid(String::from("measurements aren't an object")))?;
+        for (key, value) in measurements {
+            let index = match key.strip_prefix("PCR") {
+                Some(index) => index.parse().map_err(|_| invalid(format!("invalid PCR {}", key)))?,
+                None => continue,
+            };
+            let value = value
+                .as_str()
+                .and_then(|value| hex::decode(value).ok())
+                .ok_or_else(|| invalid(format!("{} is not a hex string", key)))?;
+            self.pcrs.insert(index, value);
+        }
+        Ok(self)
+    }
+
+    /// Checks the payload of `doc`, its age at [`NitroAdDoc::verified_at`]. Fails for
+    /// documents whose certificate chain didn't verify.
+    pub fn check(&self, doc: &NitroAdDoc) -> Result<(), NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+        self.check_payload_at(doc.payload(), doc.verified_at())
+    }
+
+    /// First unix time [`VerifierPolicy::check_payload_at`] rejects `payload` as too old
+    /// at, `None` without a maximum age
+    #[cfg(feature = "std")]
+    pub(crate) fn too_old_at(&self, payload: &NitroAdDocPayload) -> Option<u64> {
+        let issued = payload.timestamp.timestamp().max(0) as u64;
+        Some(issued.saturating_add(self.max_age_secs?).saturating_add(1))
+    }
+
+    /// Checks `payload` only, which must come from a document verified at
+    /// `unix_ts_sec`
+    pub fn check_payload_at(
+        &self,
+        payload: &NitroAdDocPayload,
+        unix_ts_sec: u64,
+    ) -> Result<(), NitroAdError> {
+        if let Some(max_age_secs) = self.max_age_secs {
+            // i128 holds every difference of the u64 and i64 times
+            let age_secs = unix_ts_sec as i128 - payload.timestamp.timestamp() as i128;
+            if age_secs > max_age_secs as i128 {
+                return Err(NitroAdError::DocumentTooOld {
+                    age_secs: u64::try_from(age_secs).unwrap_or(u64::MAX),
+                });
+            }
+        }
+        self.check_payload(payload)
+    }
+
+    /// Checks `payload` only, which must come from a verified document, except for its
+    /// age, which needs the verification time of [`VerifierPolicy::check_payload_at`]
+    pub fn check_payload(&self, payload: &NitroAdDocPayload) -> Result<(), NitroAdError> {
+        if self.debug_mode == DebugMode::Reject && payload.is_debug_mode() {
+            return Err(NitroAdError::DebugModeRejected);
+        }
+
+        if let Some(field) = self.required_fields.iter().find(|field| !field.is_present(payload)) {
+            return Err(NitroAdError::MissingField(*field));
+        }
+
+        for (index, expected) in &self.pcrs {
+            let value = payload.pcrs.get(index).ok_or(NitroAdError::MissingPcr(*index))?;
+            if !ct_eq(value, expected) {
+                return Err(NitroAdError::PcrMismatch(*index));
+            }
+        }
+
+        if let Some(expected) = &self.nonce {
+            match &payload.nonce {
+                Some(nonce) if ct_eq(nonce, expected) => {}
+                _ => return Err(NitroAdError::NonceMismatch),
+            }
+        }
+
+        if let Some(expected) = &self.user_data {
+            match &payload.user_data {
+                Some(user_data) if ct_eq(user_data, expected) => {}
+                _ => return Err(NitroAdError::UserDataMismatch),
+            }
+        }
+
+        if let Some(schema) = &self.user_data_schema {
+            (schema.check)(payload)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Bytes;
+
+    fn test_doc() -> NitroAdDoc<'static> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap()
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(b"nonce", b"nonce"));
+        assert!(!ct_eq(b"nonce", b"nonca"));
+        assert!(!ct_eq(b"nonce", b"nonce!"));
+    }
+
+    #[test]
+    fn test_check_pcrs() {
+        let doc = test_doc();
+        let pcr0 = doc.payload().pcrs[&0].to_vec();
+
+        assert!(VerifierPolicy::new().check(&doc).is_ok());
+        assert!(VerifierPolicy::new().with_pcr(0, pcr0.clone()).check(&doc).is_ok());
+
+        let mut wrong = pcr0.clone();
+        wrong[47] ^= 0x01;
+        let policy = VerifierPolicy::new().with_pcr(0, pcr0.clone()).with_pcr(1, wrong);
+        assert!(matches!(policy.check(&doc), Err(NitroAdError::PcrMismatch(1))));
+
+        let policy = VerifierPolicy::new().with_pcr(31, pcr0);
+        assert!(matches!(policy.check(&doc), Err(NitroAdError::MissingPcr(31))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_with_measurements_json() -> Result<(), NitroAdError> {
+        let pcr0 = "00".repeat(48);
+        let flat = format!(r#"{{ "PCR0": "{}", "PCR8": "{}" }}"#, pcr0, "ab".repeat(48));
+        let policy = VerifierPolicy::new().with_measurements_json(&flat)?;
+        assert_eq!(policy.pcrs.keys().collect::<Vec<_>>(), [&0, &8]);
+        assert_eq!(policy.pcrs[&8], vec![0xab; 48]);
+
+        let nitro_cli = format!(
+            r#"{{ "Measurements": {{ "HashAlgorithm": "Sha384 {{ ... }}", "PCR0": "{}" }} }}"#,
+            pcr0
+        );
+        let policy = VerifierPolicy::new().with_measurements_json(&nitro_cli)?;
+        assert_eq!(policy.pcrs[&0], vec![0; 48]);
+
+        let invalid = |json: &str| VerifierPolicy::new().with_measurements_json(json).err();
+        assert!(matches!(invalid(r#"{ "PCRx": "00" }"#), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid(r#"{ "PCR0": "zz" }"#), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid(r#"["PCR0"]"#), Some(NitroAdError::InvalidConfig(_))));
+        assert!(matches!(invalid("{"), Some(NitroAdError::SerializationError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_nonce_and_user_data() {
+        let mut payload = test_doc().payload().clone();
+        payload.nonce = Some(Bytes::from(b"challenge".to_vec()));
+        payload.user_data = None;
+
+        let policy = VerifierPolicy::new().with_nonce(&b"challenge"[..]);
+        assert!(policy.check_payload(&payload).is_ok());
+
+        let policy = VerifierPolicy::new().with_nonce(&b"challengf"[..]);
+        assert!(matches!(policy.check_payload(&payload), Err(NitroAdError::NonceMismatch)));
+
+        let policy = VerifierPolicy::new().with_user_data(Vec::new());
+        assert!(matches!(policy.check_payload(&payload), Err(NitroAdError::UserDataMismatch)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_check_user_data_schema() {
+        #[derive(Debug, serde::Serialize, serde::Deserialize)]
+        struct Claims {
+            tenant: String,
+            version: u32,
+        }
+
+        let mut payload = test_doc().payload().clone();
+        let claims = Claims {
+            tenant: String::from("acme"),
+            version: 2,
+        };
+        payload.user_data = Some(Bytes::from(serde_cbor::to_vec(&claims).unwrap()));
+
+        let policy = VerifierPolicy::new().with_user_data_schema::<Claims>(UserDataEncoding::Cbor);
+        assert!(policy.check_payload(&payload).is_ok());
+        assert_eq!(policy, policy.clone());
+        assert_ne!(policy, VerifierPolicy::new());
+        let schema = policy.user_data_schema.as_ref().unwrap();
+        assert!(schema.type_name().ends_with("Claims"));
+
+        let current = |claims: &Claims| match claims.version {
+            3 => Ok(()),
+            version => Err(format!("version {} is outdated", version)),
+        };
+        let policy = VerifierPolicy::new().with_user_data_claims(UserDataEncoding::Cbor, current);
+        match policy.check_payload(&payload) {
+            Err(NitroAdError::InvalidUserData(e)) => assert!(e.contains("version 2 is outdated")),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let policy = VerifierPolicy::new().with_user_data_schema::<Claims>(UserDataEncoding::Json);
+        assert!(matches!(policy.check_payload(&payload), Err(NitroAdError::InvalidUserData(_))));
+        payload.user_data = Some(Bytes::from(br#"{"tenant":"acme","version":2}"#.to_vec()));
+        assert!(policy.check_payload(&payload).is_ok());
+        payload.user_data = None;
+        assert!(matches!(policy.check_payload(&payload), Err(NitroAdError::MissingUserData)));
+    }
+
+    #[test]
+    fn test_check_age_debug_mode_and_required_fields() {
+        let doc = test_doc();
+        let issued = doc.payload().timestamp.timestamp() as u64;
+        assert!(doc.payload().is_debug_mode());
+
+        let policy = VerifierPolicy::new().with_max_age(300);
+        assert!(policy.check_payload_at(doc.payload(), issued + 300).is_ok());
+        assert!(matches!(
+            policy.check_payload_at(doc.payload(), issued + 301),
+            Err(NitroAdError::DocumentTooOld { age_secs: 301 })
+        ));
+        // documents from the future are the certificate validity's concern
+        assert!(policy.check_payload_at(doc.payload(), issued - 10).is_ok());
+        assert!(policy.check_payload(doc.payload()).is_ok());
+        // times and ages beyond i64::MAX don't wrap around
+        assert!(matches!(
+            policy.check_payload_at(doc.payload(), u64::MAX),
+            Err(NitroAdError::DocumentTooOld { age_secs }) if age_secs == u64::MAX - issued
+        ));
+        let unbounded = VerifierPolicy::new().with_max_age(u64::MAX);
+        assert!(unbounded.check_payload_at(doc.payload(), issued).is_ok());
+        assert!(unbounded.check_payload_at(doc.payload(), u64::MAX).is_ok());
+
+        let policy = VerifierPolicy::new().with_debug_mode(DebugMode::Reject);
+        assert!(matches!(policy.check(&doc), Err(NitroAdError::DebugModeRejected)));
+        assert!(VerifierPolicy::new().with_debug_mode(DebugMode::Allow).check(&doc).is_ok());
+
+        let mut payload = doc.payload().clone();
+        payload.nonce = Some(Bytes::from(b"challenge".to_vec()));
+        payload.public_key = None;
+        let policy = VerifierPolicy::new().with_required_field(DocumentField::Nonce);
+        assert!(policy.check_payload(&payload).is_ok());
+        let policy = policy.with_required_field(DocumentField::PublicKey);
+        assert!(matches!(
+            policy.check_payload(&payload),
+            Err(NitroAdError::MissingField(DocumentField::PublicKey))
+        ));
+    }
+
+    #[test]
+    fn test_check_rejects_unverified_chain() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1714967200).unwrap();
+        assert!(matches!(
+            VerifierPolicy::new().check(&doc),
+            Err(NitroAdError::VerificationError(webpki::Error::CertExpired { .. }))
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+//! Expected measurements of many enclave images
+//!
+//! Operators running several enclave applications, each in several versions, describe
+//! the PCRs of every trusted image with a [`MeasurementProfile`] and register them in a
+//! [`ProfileRegistry`]. [`ProfileRegistry::verify`] accepts documents whose PCRs match
+//! one of the profiles and names it in the [`profile`](VerificationReport::profile) of
+//! the report, so services tell which application and version they talk to.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::profile::{MeasurementProfile, ProfileRegistry};
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! # let (build_v1, build_v2) = ("{}", "{}");
+//! let v1 = MeasurementProfile::new("payments", "1.4.0").with_measurements_json(build_v1)?;
+//! let v2 = MeasurementProfile::new("payments", "1.5.0").with_measurements_json(build_v2)?;
+//! let registry = ProfileRegistry::new().with_profile(v1)?.with_profile(v2)?;
+//! let report = registry.verify(&Verifier::new(aws_root_der), &document, now)?;
+//! println!("{:?}", report.profile);
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+
+use crate::crypto::CryptoBackend;
+use crate::policy::ct_eq;
+use crate::report::{ProfileId, VerificationReport};
+use crate::{NitroAdDocPayload, NitroAdError, Verifier, VerifierPolicy};
+
+/// Name, version and PCRs of an enclave image, hex encoded in JSON
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeasurementProfile {
+    pub name: String,
+    pub version: String,
+    /// PCR index to expected value
+    #[serde_as(as = "BTreeMap<_, Hex>")]
+    pub pcrs: BTreeMap<u8, Vec<u8>>,
+}
+
+impl MeasurementProfile {
+    /// Profile without PCRs yet
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        MeasurementProfile {
+            name: name.into(),
+            version: version.into(),
+            pcrs: BTreeMap::new(),
+        }
+    }
+
+    /// Expects PCR `index` to hold `value`
+    pub fn with_pcr(mut self, index: u8, value: impl Into<Vec<u8>>) -> Self {
+        self.pcrs.insert(index, value.into());
+        self
+    }
+
+    /// Expects the PCRs of `json`, in the format of
+    /// [`VerifierPolicy::with_measurements_json`], e.g. `nitro-cli build-enclave` output
+    pub fn with_measurements_json(mut self, json: &str) -> Result<Self, NitroAdError> {
+        self.pcrs.extend(VerifierPolicy::new().with_measurements_json(json)?.pcrs);
+        Ok(self)
+    }
+
+    pub fn id(&self) -> ProfileId {
+        ProfileId {
+            name: self.name.clone(),
+            version: self.version.clone(),
+        }
+    }
+
+    /// Whether `payload` carries all PCRs of the profile
+    pub fn matches(&self, payload: &NitroAdDocPayload) -> bool {
+        self.pcrs.iter().all(|(index, expected)| {
+            payload.pcrs.get(index).is_some_and(|value| ct_eq(value, expected))
+        })
+    }
+}
+
+/// Profiles of all trusted images, looked up in registration order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileRegistry {
+    profiles: Vec<MeasurementProfile>,
+}
+
+impl ProfileRegistry {
+    /// Registry without profiles, matching no document
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `profile`. Fails for profiles without PCRs, which would match every
+    /// document, and for a name and version registered already.
+    pub fn register(&mut self, profile: MeasurementProfile) -> Result<(), NitroAdError> {
+        let invalid = |message: String| Err(NitroAdError::InvalidConfig(message));
+        if profile.pcrs.is_empty() {
+            return invalid(format!("profile {} {} has no PCRs", profile.name, profile.version));
+        }
+        if self.get(&profile.name, &profile.version).is_some() {
+            return invalid(format!("profile {} {} exists", profile.name, profile.version));
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// [`ProfileRegistry::register`] for chaining
+    pub fn with_profile(mut self, profile: MeasurementProfile) -> Result<Self, NitroAdError> {
+        self.register(profile)?;
+        Ok(self)
+    }
+
+    /// Removes the profile of `name` and `version`, e.g. of a retired image
+    pub fn remove(&mut self, name: &str, version: &str) -> Option<MeasurementProfile> {
+        let index = self.position(name, version)?;
+        Some(self.profiles.remove(index))
+    }
+
+    pub fn get(&self, name: &str, version: &str) -> Option<&MeasurementProfile> {
+        self.position(name, version).map(|index| &self.profiles[index])
+    }
+
+    pub fn profiles(&self) -> &[MeasurementProfile] {
+        &self.profiles
+    }
+
+    /// First registered profile `payload` matches
+    pub fn lookup(&self, payload: &NitroAdDocPayload) -> Option<&MeasurementProfile> {
+        self.profiles.iter().find(|profile| profile.matches(payload))
+    }
+
+    /// Report of [`Verifier::verify`] of `bytes` naming the profile its PCRs match.
+    /// Fails with [`NitroAdError::NoMatchingProfile`] if they match none; the audit sink
+    /// and metrics of `verifier` still count such documents as accepted.
+    pub fn verify<B: CryptoBackend>(
+        &self,
+        verifier: &Verifier<B>,
+        bytes: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<VerificationReport, NitroAdError> {
+        let doc = verifier.verify(bytes, unix_ts_sec)?;
+        let profile = self.lookup(doc.payload()).ok_or(NitroAdError::NoMatchingProfile)?;
+        let mut report = doc.report();
+        report.profile = Some(profile.id());
+        Ok(report)
+    }
+
+    fn position(&self, name: &str, version: &str) -> Option<usize> {
+        self.profiles.iter().position(|p| p.name == name && p.version == version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::NitroAdDoc;
+
+    #[test]
+    fn test_profile_registry() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let pcr0 = doc.payload().pcrs[&0].to_vec();
+        let verifier = Verifier::new(&root_cert[..]);
+
+        let old = MeasurementProfile::new("app", "1.0").with_pcr(0, vec![0xab; 48]);
+        let current = MeasurementProfile::new("app", "1.1").with_pcr(0, pcr0.clone());
+        let mut registry = ProfileRegistry::new().with_profile(old.clone())?;
+        assert!(matches!(
+            registry.verify(&verifier, ad_blob, 1614967200),
+            Err(NitroAdError::NoMatchingProfile)
+        ));
+        registry.register(current.clone())?;
+        assert_eq!(registry.lookup(doc.payload()), Some(&current));
+
+        let report = registry.verify(&verifier, ad_blob, 1614967200)?;
+        assert_eq!(report.profile, Some(current.id()));
+        assert_eq!(VerificationReport::from_cbor(&report.to_cbor()?)?, report);
+        let json = serde_json::to_string(&report)?;
+        assert!(json.contains(r#""profile":{"name":"app","version":"1.1"}"#));
+        assert_ne!(report.to_canonical_cbor()?, doc.report().to_canonical_cbor()?);
+        assert!(!serde_json::to_string(&doc.report())?.contains("profile"));
+
+        assert!(matches!(registry.register(old), Err(NitroAdError::InvalidConfig(_))));
+        let empty = MeasurementProfile::new("app", "2.0");
+        assert!(matches!(registry.register(empty), Err(NitroAdError::InvalidConfig(_))));
+        assert_eq!(registry.remove("app", "1.1"), Some(current));
+        assert!(registry.lookup(doc.payload()).is_none());
+        assert_eq!(registry.profiles().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_json() -> Result<(), NitroAdError> {
+        let build = format!(r#"{{ "Measurements": {{ "PCR0": "{}" }} }}"#, "00".repeat(48));
+        let profile = MeasurementProfile::new("app", "1.0").with_measurements_json(&build)?;
+        assert_eq!(profile.pcrs[&0], vec![0; 48]);
+        let json = serde_json::to_string(&profile)?;
+        assert_eq!(serde_json::from_str::<MeasurementProfile>(&json)?, profile);
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! Protocol Buffers encoding of documents and verification reports
+//!
+//! The messages mirror `proto/attestation.proto`; services with their own
+//! generated code decode the output of [`Document::encode_to_vec`] directly.
+
+use std::collections::BTreeMap;
+
+pub use prost::Message;
+
+use crate::report::VerificationReport as Report;
+use crate::{NitroAdDoc, NitroAdDocPayload};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Document {
+    #[prost(string, tag = "1")]
+    pub module_id: String,
+    #[prost(string, tag = "2")]
+    pub digest: String,
+    #[prost(int64, tag = "3")]
+    pub timestamp_ms: i64,
+    #[prost(btree_map = "uint32, bytes", tag = "4")]
+    pub pcrs: BTreeMap<u32, Vec<u8>>,
+    #[prost(bytes = "vec", tag = "5")]
+    pub certificate: Vec<u8>,
+    #[prost(bytes = "vec", repeated, tag = "6")]
+    pub cabundle: Vec<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "7")]
+    pub public_key: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "8")]
+    pub user_data: Option<Vec<u8>>,
+    #[prost(bytes = "vec", optional, tag = "9")]
+    pub nonce: Option<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct VerificationReport {
+    #[prost(bytes = "vec", tag = "1")]
+    pub document_sha384: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub module_id: String,
+    #[prost(int64, tag = "3")]
+    pub document_timestamp_ms: i64,
+    #[prost(uint64, tag = "4")]
+    pub verified_at: u64,
+    #[prost(btree_map = "uint32, bytes", tag = "5")]
+    pub pcrs: BTreeMap<u32, Vec<u8>>,
+    #[prost(bool, tag = "6")]
+    pub debug_mode: bool,
+    #[prost(string, optional, tag = "7")]
+    pub chain_error: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub profile_name: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub profile_version: Option<String>,
+}
+
+impl From<&NitroAdDocPayload<'_>> for Document {
+    fn from(payload: &NitroAdDocPayload) -> Self {
+        Document {
+            module_id: payload.module_id.clone(),
+            digest: payload.digest.clone(),
+            timestamp_ms: payload.timestamp.timestamp_millis(),
+            pcrs: payload
+                .pcrs
+                .iter()
+                .map(|(i, val)| (*i as u32, val.to_vec()))
+                .collect(),
+            certificate: payload.certificate.to_vec(),
+            cabundle: payload.cabundle.iter().map(|cert| cert.to_vec()).collect(),
+            public_key: payload.public_key.as_ref().map(|b| b.to_vec()),
+            user_data: payload.user_data.as_ref().map(|b| b.to_vec()),
+            nonce: payload.nonce.as_ref().map(|b| b.to_vec()),
+        }
+    }
+}
+
+impl From<&Report> for VerificationReport {
+    fn from(report: &Report) -> Self {
+        VerificationReport {
+            document_sha384: report.document_sha384.clone(),
+            module_id: report.module_id.clone(),
+            document_timestamp_ms: report.document_timestamp_ms,
+            verified_at: report.verified_at,
+            pcrs: report
+                .pcrs
+                .iter()
+                .map(|(i, val)| (*i as u32, val.clone()))
+                .collect(),
+            debug_mode: report.debug_mode,
+            chain_error: report.chain_error.clone(),
+            profile_name: report.profile.as_ref().map(|profile| profile.name.clone()),
+            profile_version: report.profile.as_ref().map(|profile| profile.version.clone()),
+        }
+    }
+}
+
+impl NitroAdDoc<'_> {
+    /// Payload as an encoded [`Document`] message
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        Document::from(self.payload()).encode_to_vec()
+    }
+}
+
+impl Report {
+    /// Encoded [`VerificationReport`] message
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        VerificationReport::from(self).encode_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protobuf_roundtrip() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+
+        let decoded = Document::decode(doc.to_protobuf().as_slice()).unwrap();
+        assert_eq!(decoded, Document::from(doc.payload()));
+        assert_eq!(decoded.cabundle.len(), doc.payload().cabundle.len());
+        assert_eq!(decoded.nonce, None);
+
+        let report = doc.report();
+        let decoded = VerificationReport::decode(report.to_protobuf().as_slice()).unwrap();
+        assert_eq!(decoded.verified_at, 1614967200);
+        assert_eq!(decoded.chain_error, None);
+        assert_eq!(decoded.profile_name, None);
+        assert_eq!(decoded.pcrs.len(), report.pcrs.len());
+    }
+}
@@ -0,0 +1,220 @@
+//! Cached documents for enclave servers
+//!
+//! Servers presenting their document with every response don't need a new one each
+//! time: an [`AttestationProvider`] keeps the latest document and requests the next one
+//! only once the document's signing certificate expires within the refresh margin,
+//! [`DEFAULT_REFRESH_MARGIN`] unless [`AttestationProvider::with_refresh_margin`] says
+//! otherwise. The NSM issues these certificates for about three hours. Documents come
+//! straight from the attester and aren't verified, only decoded for their expiry.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::nsm::Nsm;
+//! use aws_nitro_enclaves_attestation::provider::AttestationProvider;
+//!
+//! # let (session_key, now) = (Vec::new(), 0);
+//! let provider = AttestationProvider::new(Nsm::open()?).with_public_key(session_key);
+//! // for every request
+//! let document = provider.document(now)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//! For refreshes off the request path, see the tokio task of the `reattest` module.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{TimeZone, Utc};
+
+use crate::nsm::{AttestationRequest, Attester};
+use crate::{parse_and_validate_payload, NitroAdError};
+
+/// Seconds before the certificate expiry documents are refreshed at, half an hour
+pub const DEFAULT_REFRESH_MARGIN: u64 = 30 * 60;
+
+/// Document of an attester and its expiry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FreshDocument {
+    /// Raw COSE_Sign1 document
+    pub document: Vec<u8>,
+    /// Unix time the first certificate of the document's chain expires at, the
+    /// signing certificate for NSM documents
+    pub not_after: i64,
+}
+
+/// Attester requests with the same fields, answered with the latest document
+pub struct AttestationProvider<A> {
+    attester: A,
+    user_data: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+    public_key: Option<Vec<u8>>,
+    refresh_margin: u64,
+    latest: Mutex<Option<Arc<FreshDocument>>>,
+}
+
+impl<A: Attester> AttestationProvider<A> {
+    /// Provider of documents of `attester`, without optional fields
+    pub fn new(attester: A) -> Self {
+        AttestationProvider {
+            attester,
+            user_data: None,
+            nonce: None,
+            public_key: None,
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            latest: Mutex::new(None),
+        }
+    }
+
+    pub fn with_user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    pub fn with_public_key(mut self, public_key: impl Into<Vec<u8>>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// Refreshes documents `margin_secs` before their certificate expires
+    pub fn with_refresh_margin(mut self, margin_secs: u64) -> Self {
+        self.refresh_margin = margin_secs;
+        self
+    }
+
+    /// Latest document at `unix_ts_sec`, requested from the attester if there is none
+    /// yet or its certificate expires within the refresh margin. If that request fails,
+    /// the previous document is returned while its certificate is valid.
+    pub fn document(&self, unix_ts_sec: u64) -> Result<Arc<FreshDocument>, NitroAdError> {
+        let now = unix_ts_sec as i64;
+        let mut latest = self.latest();
+        let refresh_at = |doc: &FreshDocument| doc.not_after - self.refresh_margin as i64;
+        if let Some(doc) = latest.as_ref().filter(|doc| now < refresh_at(doc)) {
+            return Ok(Arc::clone(doc));
+        }
+        match self.request(unix_ts_sec) {
+            Ok(fresh) => {
+                let fresh = Arc::new(fresh);
+                *latest = Some(Arc::clone(&fresh));
+                Ok(fresh)
+            }
+            Err(e) => match latest.as_ref().filter(|doc| now < doc.not_after) {
+                Some(doc) => Ok(Arc::clone(doc)),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Forgets the latest document, so the next [`AttestationProvider::document`] call
+    /// requests one
+    pub fn invalidate(&self) {
+        *self.latest() = None;
+    }
+
+    fn request(&self, unix_ts_sec: u64) -> Result<FreshDocument, NitroAdError> {
+        let document = self.attester.attest(AttestationRequest {
+            user_data: self.user_data.as_deref(),
+            nonce: self.nonce.as_deref(),
+            public_key: self.public_key.as_deref(),
+        })?;
+        let not_after = {
+            let now = Utc.timestamp_opt(unix_ts_sec as i64, 0).unwrap();
+            let (_cose, payload) = parse_and_validate_payload(&document, now)?;
+            payload.chain_not_after()
+        };
+        let not_after = not_after
+            .ok_or_else(|| NitroAdError::X509Error(String::from("no certificate parses")))?;
+        Ok(FreshDocument {
+            document,
+            not_after,
+        })
+    }
+
+    fn latest(&self) -> MutexGuard<'_, Option<Arc<FreshDocument>>> {
+        // the document is replaced whole, so that of a panicked thread is still valid
+        self.latest.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use crate::nsm::ErrorCode;
+    use crate::testing::MockNsm;
+    use crate::NitroAdDoc;
+
+    /// Mock counting its requests, failing them while `failing` is set
+    struct CountingNsm {
+        mock: MockNsm,
+        requests: AtomicUsize,
+        failing: AtomicBool,
+    }
+
+    impl Attester for &CountingNsm {
+        fn attest(&self, request: AttestationRequest<'_>) -> Result<Vec<u8>, NitroAdError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(NitroAdError::NsmError(ErrorCode::InternalError));
+            }
+            self.mock.attest(request)
+        }
+    }
+
+    #[test]
+    fn test_provider() -> Result<(), NitroAdError> {
+        let nsm = CountingNsm {
+            mock: MockNsm::new()?,
+            requests: AtomicUsize::new(0),
+            failing: AtomicBool::new(false),
+        };
+        let provider = AttestationProvider::new(&nsm)
+            .with_nonce(&b"challenge"[..])
+            .with_refresh_margin(600);
+        let now = Utc::now().timestamp() as u64;
+
+        let first = provider.document(now)?;
+        assert!(Arc::ptr_eq(&provider.document(now + 1)?, &first));
+        assert_eq!(nsm.requests.load(Ordering::SeqCst), 1);
+        let doc = NitroAdDoc::from_bytes(&first.document, &nsm.mock.root_cert(), now)?;
+        assert_eq!(doc.payload().nonce.as_deref(), Some(&b"challenge"[..]));
+        assert_eq!(doc.payload().chain_not_after(), Some(first.not_after));
+
+        // within the margin the document is refreshed
+        let refresh_at = first.not_after as u64 - 600;
+        assert!(Arc::ptr_eq(&provider.document(refresh_at - 1)?, &first));
+        let second = provider.document(refresh_at)?;
+        assert_ne!(second.document, first.document);
+        assert_eq!(nsm.requests.load(Ordering::SeqCst), 2);
+
+        provider.invalidate();
+        let third = provider.document(now)?;
+        assert_ne!(third.document, second.document);
+        assert_eq!(nsm.requests.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_refresh() -> Result<(), NitroAdError> {
+        let nsm = CountingNsm {
+            mock: MockNsm::new()?,
+            requests: AtomicUsize::new(0),
+            failing: AtomicBool::new(true),
+        };
+        let provider = AttestationProvider::new(&nsm);
+        let now = Utc::now().timestamp() as u64;
+        assert!(matches!(provider.document(now), Err(NitroAdError::NsmError(_))));
+
+        nsm.failing.store(false, Ordering::SeqCst);
+        let first = provider.document(now)?;
+        nsm.failing.store(true, Ordering::SeqCst);
+        // the previous document serves until it expires
+        let refresh_at = first.not_after as u64 - DEFAULT_REFRESH_MARGIN;
+        assert!(Arc::ptr_eq(&provider.document(refresh_at)?, &first));
+        assert!(provider.document(first.not_after as u64).is_err());
+        assert_eq!(nsm.requests.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Provisioning secrets to attested enclaves
+//!
+//! The enclave creates a [`Recipient`], an ephemeral [`AttestedKey`], and sends its
+//! document to the service holding the secrets. The service's [`Provisioner`] verifies
+//! the document with its [`Verifier`], whose policy decides which enclave images get
+//! the secrets, and seals the named secrets to the document's key with
+//! [`hpke`](crate::hpke). Back in the enclave, [`Recipient::open`] turns the sealed
+//! bundle into [`Secrets`], zeroized on drop.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::provisioning::Provisioner;
+//! use aws_nitro_enclaves_attestation::{Verifier, VerifierPolicy};
+//!
+//! # let (aws_root_der, pcr0, document, now) = (Vec::new(), Vec::new(), Vec::new(), 0);
+//! let policy = VerifierPolicy::new().with_pcr(0, pcr0);
+//! let verifier = Verifier::new(aws_root_der).with_policy(policy);
+//! let provisioner = Provisioner::new(verifier).with_secret("database-password", "hunter2");
+//! let bundle = provisioner.provision(&document, now)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//! Secrets are bound to the recipient's document, so a bundle opens only with the key
+//! that was attested. Applications wanting freshness on top send a
+//! [`Challenge`](crate::challenge::Challenge) to the enclave and require it as the
+//! policy's `nonce`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rand_core::CryptoRngCore;
+use serde_bytes::{ByteBuf, Bytes};
+use zeroize::Zeroizing;
+
+use crate::hpke::encrypt_to_attested_key;
+use crate::nsm::{AttestedKey, Attester};
+use crate::{NitroAdError, Verifier};
+
+/// HPKE associated data of sealed secret bundles
+pub static PROVISIONING_AAD: &[u8] = b"aws-nitro-enclaves-attestation secrets v1";
+
+/// Service side: seals named secrets to documents its verifier accepts
+pub struct Provisioner {
+    verifier: Verifier,
+    secrets: BTreeMap<String, Zeroizing<Vec<u8>>>,
+}
+
+impl Provisioner {
+    /// Provisioner without secrets, accepting the documents `verifier` accepts
+    pub fn new(verifier: Verifier) -> Self {
+        Provisioner {
+            verifier,
+            secrets: BTreeMap::new(),
+        }
+    }
+
+    /// Adds secret `name`, replacing an earlier one of that name
+    pub fn with_secret(mut self, name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        self.secrets.insert(name.into(), Zeroizing::new(value.into()));
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.secrets.keys().map(String::as_str)
+    }
+
+    /// Verifies `document` at `unix_ts_sec`, including the verifier's policy, and seals
+    /// the secrets to its `public_key`
+    pub fn provision(&self, document: &[u8], unix_ts_sec: u64) -> Result<Vec<u8>, NitroAdError> {
+        let doc = self.verifier.verify(document, unix_ts_sec)?;
+        let secrets: BTreeMap<&str, &Bytes> = self
+            .secrets
+            .iter()
+            .map(|(name, value)| (name.as_str(), Bytes::new(value)))
+            .collect();
+        let plaintext =
+            Zeroizing::new(serde_cbor::to_vec(&secrets).map_err(NitroAdError::CBORError)?);
+        encrypt_to_attested_key(&doc, &plaintext, PROVISIONING_AAD)
+    }
+}
+
+impl fmt::Debug for Provisioner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Provisioner")
+            .field("verifier", &self.verifier)
+            .field("secrets", &self.secrets.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Enclave side: the attested key secrets are sealed to
+pub struct Recipient {
+    key: AttestedKey,
+}
+
+impl Recipient {
+    /// Generates the key with `rng` and requests its document from `attester`, with
+    /// the verifier's challenge as `nonce` if there is one
+    pub fn new<A: Attester + ?Sized>(
+        attester: &A,
+        rng: &mut impl CryptoRngCore,
+        nonce: Option<&[u8]>,
+    ) -> Result<Self, NitroAdError> {
+        AttestedKey::generate(attester, rng, None, nonce).map(|key| Recipient { key })
+    }
+
+    /// Document to send to the [`Provisioner`]
+    pub fn document(&self) -> &[u8] {
+        &self.key.document
+    }
+
+    /// Opens the `bundle` of [`Provisioner::provision`]. The key is dropped afterwards,
+    /// each recipient receives one bundle.
+    pub fn open(self, bundle: &[u8]) -> Result<Secrets, NitroAdError> {
+        let plaintext = Zeroizing::new(self.key.decrypt(bundle, PROVISIONING_AAD)?);
+        let secrets: BTreeMap<String, ByteBuf> =
+            serde_cbor::from_slice(&plaintext).map_err(NitroAdError::CBORError)?;
+        Ok(Secrets(
+            secrets
+                .into_iter()
+                .map(|(name, value)| (name, Zeroizing::new(value.into_vec())))
+                .collect(),
+        ))
+    }
+}
+
+/// Named secrets received by a [`Recipient`]. `Debug` shows the names only.
+pub struct Secrets(BTreeMap<String, Zeroizing<Vec<u8>>>);
+
+impl Secrets {
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.0.get(name).map(|value| value.as_slice())
+    }
+
+    /// Removes secret `name`, for handing it over to the application
+    pub fn take(&mut self, name: &str) -> Option<Zeroizing<Vec<u8>>> {
+        self.0.remove(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Secrets").field(&self.0.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use rand_core::OsRng;
+
+    use crate::testing::MockNsm;
+    use crate::VerifierPolicy;
+
+    #[test]
+    fn test_provision() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let provisioner = Provisioner::new(Verifier::new(mock.root_cert()))
+            .with_secret("database-password", "hunter2")
+            .with_secret("api-token", vec![0, 1, 2]);
+        assert_eq!(provisioner.names().collect::<Vec<_>>(), ["api-token", "database-password"]);
+        assert!(!format!("{:?}", provisioner).contains("hunter2"));
+
+        let recipient = Recipient::new(&mock, &mut OsRng, None)?;
+        let bundle = provisioner.provision(recipient.document(), now)?;
+        let mut secrets = recipient.open(&bundle)?;
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets.get("database-password"), Some(&b"hunter2"[..]));
+        assert!(!format!("{:?}", secrets).contains("hunter2"));
+        assert_eq!(secrets.take("api-token").as_deref().map(Vec::as_slice), Some(&[0, 1, 2][..]));
+        assert_eq!(secrets.names().collect::<Vec<_>>(), ["database-password"]);
+        assert!(secrets.get("api-token").is_none());
+
+        // bundles only open with the key they were sealed to
+        let other = Recipient::new(&mock, &mut OsRng, None)?;
+        assert!(other.open(&bundle).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_provision_checks_policy() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let verifier = Verifier::new(mock.root_cert())
+            .with_policy(VerifierPolicy::new().with_nonce(b"challenge".to_vec()));
+        let provisioner = Provisioner::new(verifier).with_secret("name", "value");
+
+        let stale = Recipient::new(&mock, &mut OsRng, None)?;
+        assert!(matches!(
+            provisioner.provision(stale.document(), now),
+            Err(NitroAdError::NonceMismatch)
+        ));
+        let fresh = Recipient::new(&mock, &mut OsRng, Some(b"challenge"))?;
+        let bundle = provisioner.provision(fresh.document(), now)?;
+        let secrets = fresh.open(&bundle)?;
+        assert_eq!(secrets.get("name"), Some(&b"value"[..]));
+
+        let plain = crate::nsm::Attester::attest(&mock, Default::default())?;
+        let provisioner = Provisioner::new(Verifier::new(mock.root_cert()));
+        assert!(matches!(
+            provisioner.provision(&plain, now),
+            Err(NitroAdError::UnsupportedPublicKey)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Python API through pyo3
+//!
+//! ```bash
+//! maturin build --manifest-path python/Cargo.toml
+//! ```
+//! builds the `python/` crate into the `nitro_attestation` extension module:
+//! ```python
+//! doc = nitro_attestation.verify(document, aws_root_der, time.time())
+//! ```
+//! [`verify`] raises a subclass of `AttestationError` per [`ErrorKind`], with the
+//! message and error code as `args`.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::output::CertificateOutput;
+use crate::{ErrorKind, NitroAdDoc, NitroAdError};
+
+create_exception!(nitro_attestation, AttestationError, PyException, "Document failed verification");
+create_exception!(
+    nitro_attestation,
+    MalformedDocumentError,
+    AttestationError,
+    "Document is not a well-formed attestation document"
+);
+create_exception!(
+    nitro_attestation,
+    SignatureError,
+    AttestationError,
+    "Document signature does not verify"
+);
+create_exception!(
+    nitro_attestation,
+    ChainError,
+    AttestationError,
+    "Certificate chain or trust anchor problem"
+);
+create_exception!(
+    nitro_attestation,
+    PolicyError,
+    AttestationError,
+    "Document doesn't carry the expected values"
+);
+
+fn to_py_err(e: NitroAdError) -> PyErr {
+    let args = (e.to_string(), e.code());
+    match e.kind() {
+        ErrorKind::MalformedInput => MalformedDocumentError::new_err(args),
+        ErrorKind::Signature => SignatureError::new_err(args),
+        ErrorKind::Chain => ChainError::new_err(args),
+        ErrorKind::Policy => PolicyError::new_err(args),
+        ErrorKind::Output | ErrorKind::Io | ErrorKind::Usage => AttestationError::new_err(args),
+    }
+}
+
+fn cert_dict<'py>(py: Python<'py>, cert: &CertificateOutput) -> PyResult<Bound<'py, PyDict>> {
+    let validity = PyDict::new(py);
+    validity.set_item("not_before", &cert.validity.not_before)?;
+    validity.set_item("not_after", &cert.validity.not_after)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("issuer", &cert.issuer)?;
+    dict.set_item("subject", &cert.subject)?;
+    dict.set_item("validity", validity)?;
+    Ok(dict)
+}
+
+/// Verifies the `document` bytes against the DER encoded `root_der` at `time`, seconds
+/// since the unix epoch. Returns a dict of the fields of `NitroAdDoc::to_json`, with
+/// integer PCR indexes and bytes rather than encoded strings. Certificate chain
+/// failures are reported in `verification_error` rather than raised.
+#[pyfunction]
+pub fn verify<'py>(
+    py: Python<'py>,
+    document: &[u8],
+    root_der: &[u8],
+    time: f64,
+) -> PyResult<Bound<'py, PyDict>> {
+    let doc = NitroAdDoc::from_bytes(document, root_der, time as u64).map_err(to_py_err)?;
+    let output = doc.to_output().map_err(to_py_err)?;
+    let payload = doc.payload();
+    let bytes = |b: &[u8]| PyBytes::new(py, b);
+
+    let pcrs = PyDict::new(py);
+    for (index, value) in &payload.pcrs {
+        pcrs.set_item(index, bytes(value))?;
+    }
+    let certs = output
+        .certs
+        .iter()
+        .map(|cert| cert_dict(py, cert))
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("module_id", &payload.module_id)?;
+    dict.set_item("digest", &payload.digest)?;
+    dict.set_item("timestamp", output.timestamp)?;
+    dict.set_item("pcrs", pcrs)?;
+    dict.set_item("certs", certs)?;
+    dict.set_item("public_key", payload.public_key.as_deref().map(bytes))?;
+    dict.set_item("user_data", payload.user_data.as_deref().map(bytes))?;
+    dict.set_item("nonce", payload.nonce.as_deref().map(bytes))?;
+    dict.set_item("verification_error", doc.verification_error().map(|e| e.to_string()))?;
+    Ok(dict)
+}
+
+#[pymodule]
+pub fn nitro_attestation(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add("AttestationError", py.get_type::<AttestationError>())?;
+    m.add("MalformedDocumentError", py.get_type::<MalformedDocumentError>())?;
+    m.add("SignatureError", py.get_type::<SignatureError>())?;
+    m.add("ChainError", py.get_type::<ChainError>())?;
+    m.add("PolicyError", py.get_type::<PolicyError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let doc = verify(py, ad_blob, root_cert, 1614967200.0).unwrap();
+            let pcr0 = doc.get_item("pcrs").unwrap().unwrap().get_item(0).unwrap();
+            assert_eq!(pcr0.extract::<Vec<u8>>().unwrap(), vec![0; 48]);
+            assert!(doc.get_item("nonce").unwrap().unwrap().is_none());
+            assert!(doc.get_item("verification_error").unwrap().unwrap().is_none());
+
+            let err = verify(py, &ad_blob[..10], root_cert, 1614967200.0).unwrap_err();
+            assert!(err.is_instance_of::<MalformedDocumentError>(py));
+            assert!(err.is_instance_of::<AttestationError>(py));
+            let (_, code): (String, u32) = err.value(py).getattr("args").unwrap().extract().unwrap();
+            assert_eq!(code, 2);
+        });
+    }
+}
@@ -0,0 +1,224 @@
+//! Attested QUIC with quinn
+//!
+//! QUIC runs TLS 1.3, so the [`tls`](crate::tls) module's configurations carry over:
+//! [`server_config`] presents a fresh [`AttestedCertificate`](crate::tls::AttestedCertificate)
+//! and [`client_config`] accepts only servers whose certificate's document passes a
+//! [`Verifier`]. On top, once a connection is established, [`accept`] has the server
+//! send a document whose `nonce` is the [`exporter_binding`] of that connection on
+//! its first unidirectional stream, and [`connect`] verifies it before handing the
+//! connection out. Streams and datagrams of the connection are then known to reach the
+//! attested enclave, not a relay holding a copied certificate.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::quic::{client_config, connect};
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # async fn run(aws_root_der: Vec<u8>, now: u64)
+//! #     -> Result<(), aws_nitro_enclaves_attestation::NitroAdError> {
+//! let mut endpoint = quinn::Endpoint::client("0.0.0.0:0".parse().unwrap()).unwrap();
+//! endpoint.set_default_client_config(client_config(Verifier::new(aws_root_der.clone()))?);
+//! let verifier = Verifier::new(aws_root_der);
+//! let addr = "10.0.0.1:4433".parse().unwrap();
+//! let (connection, doc) = connect(&endpoint, addr, "enclave", &verifier, now).await?;
+//! # Ok(())
+//! # }
+//! ```
+//! [`send_attestation`] and [`receive_attestation`] run the same exchange in either
+//! direction, e.g. for clients attesting to servers.
+
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{Connection, Endpoint, Incoming, ReadToEndError};
+
+use crate::nsm::{AttestationRequest, Attester};
+use crate::tls::{check_binding, CHANNEL_BINDING_LABEL};
+use crate::{tls, NitroAdDoc, NitroAdError, Verifier, MAX_DOCUMENT_SIZE};
+
+fn quic_error(e: impl std::fmt::Display) -> NitroAdError {
+    NitroAdError::QuicError(e.to_string())
+}
+
+/// Server configuration of [`tls::server_config`] for QUIC
+pub fn server_config<A: Attester + ?Sized>(
+    attester: &A,
+    dns_names: &[&str],
+) -> Result<quinn::ServerConfig, NitroAdError> {
+    let config = QuicServerConfig::try_from(tls::server_config(attester, dns_names)?)
+        .map_err(quic_error)?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(config)))
+}
+
+/// Client configuration of [`tls::client_config`] for QUIC
+pub fn client_config(verifier: Verifier) -> Result<quinn::ClientConfig, NitroAdError> {
+    let config = QuicClientConfig::try_from(tls::client_config(verifier)?).map_err(quic_error)?;
+    Ok(quinn::ClientConfig::new(Arc::new(config)))
+}
+
+/// `tls-exporter` channel binding of the established `connection`, equal at both of its
+/// ends
+pub fn exporter_binding(connection: &Connection) -> Result<[u8; 32], NitroAdError> {
+    let mut binding = [0; 32];
+    connection
+        .export_keying_material(&mut binding, CHANNEL_BINDING_LABEL, &[])
+        .map_err(|_| quic_error("keying material export failed"))?;
+    Ok(binding)
+}
+
+/// Sends a document from `attester`, whose `nonce` is the [`exporter_binding`] of
+/// `connection`, on a new unidirectional stream
+pub async fn send_attestation<A: Attester + ?Sized>(
+    attester: &A,
+    connection: &Connection,
+) -> Result<(), NitroAdError> {
+    let binding = exporter_binding(connection)?;
+    let document = attester.attest(AttestationRequest {
+        nonce: Some(&binding),
+        ..Default::default()
+    })?;
+    let mut stream = connection.open_uni().await.map_err(quic_error)?;
+    stream.write_all(&document).await.map_err(quic_error)?;
+    stream.finish().map_err(quic_error)
+}
+
+/// Receives the document of the peer's [`send_attestation`] on the next unidirectional
+/// stream, verifies it with `verifier` at `unix_ts_sec` and checks its binding to
+/// `connection`
+pub async fn receive_attestation(
+    verifier: &Verifier,
+    connection: &Connection,
+    unix_ts_sec: u64,
+) -> Result<NitroAdDoc<'static>, NitroAdError> {
+    let mut stream = connection.accept_uni().await.map_err(quic_error)?;
+    let document = stream.read_to_end(MAX_DOCUMENT_SIZE).await.map_err(|e| match e {
+        ReadToEndError::TooLong => NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE },
+        e => quic_error(e),
+    })?;
+    let binding = exporter_binding(connection)?;
+    let doc = verifier.verify(&document, unix_ts_sec)?;
+    check_binding(&doc, &binding)?;
+    Ok(doc.into_owned())
+}
+
+/// Completes the handshake of `incoming` and sends the attestation of `attester` to the
+/// client
+pub async fn accept<A: Attester + ?Sized>(
+    attester: &A,
+    incoming: Incoming,
+) -> Result<Connection, NitroAdError> {
+    let connection = incoming.await.map_err(quic_error)?;
+    send_attestation(attester, &connection).await?;
+    Ok(connection)
+}
+
+/// Connects `endpoint` to the server of [`accept`] at `addr` and verifies its
+/// attestation with `verifier` at `unix_ts_sec`, closing the connection on failure
+pub async fn connect(
+    endpoint: &Endpoint,
+    addr: SocketAddr,
+    server_name: &str,
+    verifier: &Verifier,
+    unix_ts_sec: u64,
+) -> Result<(Connection, NitroAdDoc<'static>), NitroAdError> {
+    let connection = endpoint
+        .connect(addr, server_name)
+        .map_err(quic_error)?
+        .await
+        .map_err(quic_error)?;
+    match receive_attestation(verifier, &connection, unix_ts_sec).await {
+        Ok(doc) => Ok((connection, doc)),
+        Err(e) => {
+            connection.close(0u32.into(), b"attestation rejected");
+            Err(e)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use crate::testing::MockNsm;
+    use crate::VerifierPolicy;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    fn endpoints(mock: &MockNsm) -> Result<(Endpoint, Endpoint), NitroAdError> {
+        let localhost = "127.0.0.1:0".parse().unwrap();
+        let server = Endpoint::server(server_config(mock, &["enclave"])?, localhost).unwrap();
+        let mut client = Endpoint::client(localhost).unwrap();
+        client.set_default_client_config(client_config(Verifier::new(mock.root_cert()))?);
+        Ok((server, client))
+    }
+
+    #[test]
+    fn test_attested_connection() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let verifier = Verifier::new(mock.root_cert());
+        let now = chrono::Utc::now().timestamp() as u64;
+        runtime().block_on(async {
+            let (server, client) = endpoints(&mock)?;
+            let addr = server.local_addr().unwrap();
+            let serve = async {
+                let connection = accept(&mock, server.accept().await.unwrap()).await?;
+                let mut stream = connection.accept_uni().await.map_err(quic_error)?;
+                stream.read_to_end(64).await.map_err(quic_error)
+            };
+            let talk = async {
+                let (connection, doc) = connect(&client, addr, "enclave", &verifier, now).await?;
+                let binding = exporter_binding(&connection)?;
+                assert_eq!(doc.payload().nonce.as_deref(), Some(&binding[..]));
+                let mut stream = connection.open_uni().await.map_err(quic_error)?;
+                stream.write_all(b"hello").await.map_err(quic_error)?;
+                stream.finish().map_err(quic_error)?;
+                stream.stopped().await.map_err(quic_error)?;
+                Ok::<_, NitroAdError>(connection)
+            };
+            let (received, connection) = tokio::try_join!(serve, talk)?;
+            assert_eq!(received, b"hello");
+            connection.close(0u32.into(), b"");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_unbound_document_rejected() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        runtime().block_on(async {
+            let (server, client) = endpoints(&mock)?;
+            let addr = server.local_addr().unwrap();
+            // a document attested for another connection
+            let relay = async {
+                let connection = server.accept().await.unwrap().await.map_err(quic_error)?;
+                let document = mock.attest(AttestationRequest {
+                    nonce: Some(&[0; 32]),
+                    ..Default::default()
+                })?;
+                let mut stream = connection.open_uni().await.map_err(quic_error)?;
+                stream.write_all(&document).await.map_err(quic_error)?;
+                stream.finish().map_err(quic_error)?;
+                connection.closed().await;
+                Ok::<_, NitroAdError>(())
+            };
+            let verifier = Verifier::new(mock.root_cert());
+            let (relayed, rejected) =
+                tokio::join!(relay, connect(&client, addr, "enclave", &verifier, now));
+            relayed?;
+            assert!(matches!(rejected, Err(NitroAdError::NonceMismatch)));
+
+            let verifier = Verifier::new(mock.root_cert())
+                .with_policy(VerifierPolicy::new().with_pcr(0, vec![0xab; 48]));
+            let (accepted, rejected) = tokio::join!(
+                async { accept(&mock, server.accept().await.unwrap()).await },
+                connect(&client, addr, "enclave", &verifier, now)
+            );
+            accepted?;
+            assert!(matches!(rejected, Err(NitroAdError::PcrMismatch(0))));
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,250 @@
+//! Continuous re-attestation
+//!
+//! The NSM issues the signing certificate of each document for about three hours, so a
+//! server presenting its document to clients needs a new one every few hours.
+//! [`Reattester::start`] requests the first document, then spawns a tokio task which
+//! requests the next one [`DEFAULT_REFRESH_BEFORE`] before the first certificate of the
+//! current one expires. Each document is verified before it replaces the current one;
+//! failed requests are repeated every [`DEFAULT_RETRY_INTERVAL`], and the current
+//! document stays in place until a request succeeds.
+//!
+//! [`ReattestHandle::current`] returns the latest document, and the `watch` receivers
+//! of [`ReattestHandle::subscribe`] are notified of each new one. Requests run on the
+//! blocking thread pool since NSM calls are blocking ioctls. To refresh on the request
+//! path instead, without a task, see [`AttestationProvider`](crate::provider::AttestationProvider).
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::nsm::Nsm;
+//! use aws_nitro_enclaves_attestation::reattest::Reattester;
+//! use aws_nitro_enclaves_attestation::{NitroAdError, Verifier};
+//!
+//! # async fn run(aws_root_der: Vec<u8>) -> Result<(), NitroAdError> {
+//! let handle = Reattester::new(Nsm::open()?, Verifier::new(aws_root_der)).start().await?;
+//! let mut fresh = handle.subscribe();
+//! while fresh.changed().await.is_ok() {
+//!     let document = &fresh.borrow_and_update().document;
+//!     // present the new document from now on
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::nsm::{AttestationRequest, Attester};
+pub use crate::provider::FreshDocument;
+use crate::{NitroAdError, Verifier};
+
+/// Time before the certificate expiry documents are requested at, unless
+/// [`Reattester::with_refresh_before`] says otherwise
+pub const DEFAULT_REFRESH_BEFORE: Duration = Duration::from_secs(30 * 60);
+
+/// Time between failed requests, unless [`Reattester::with_retry_interval`] says
+/// otherwise
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Fields of the repeated attestation requests and their schedule
+pub struct Reattester<A> {
+    attester: A,
+    verifier: Verifier,
+    user_data: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+    public_key: Option<Vec<u8>>,
+    refresh_before: Duration,
+    retry_interval: Duration,
+}
+
+impl<A: Attester + Send + Sync + 'static> Reattester<A> {
+    /// Requests documents from `attester`, accepting those of `verifier`
+    pub fn new(attester: A, verifier: Verifier) -> Self {
+        Reattester {
+            attester,
+            verifier,
+            user_data: None,
+            nonce: None,
+            public_key: None,
+            refresh_before: DEFAULT_REFRESH_BEFORE,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+        }
+    }
+
+    pub fn with_user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    pub fn with_public_key(mut self, public_key: impl Into<Vec<u8>>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// Requests documents `refresh_before` the certificate expiry of the current one
+    pub fn with_refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    /// Waits `retry_interval` after failed requests. Documents are never requested
+    /// more often than that.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// Requests the first document and spawns the task requesting the next ones on the
+    /// current tokio runtime. Fails if the first request does.
+    pub async fn start(self) -> Result<ReattestHandle, NitroAdError> {
+        let reattester = Arc::new(self);
+        let first = Arc::clone(&reattester).request().await?;
+        let (sender, receiver) = watch::channel(Arc::new(first));
+        let task = tokio::spawn(reattester.run(sender));
+        Ok(ReattestHandle { receiver, task })
+    }
+
+    async fn run(self: Arc<Self>, sender: watch::Sender<Arc<FreshDocument>>) {
+        while !sender.is_closed() {
+            let refresh_at = sender.borrow().not_after - self.refresh_before.as_secs() as i64;
+            let due = Duration::from_secs((refresh_at - Utc::now().timestamp()).max(0) as u64);
+            tokio::time::sleep(due.max(self.retry_interval)).await;
+            // the current document stays in place until a request succeeds
+            if let Ok(fresh) = Arc::clone(&self).request().await {
+                sender.send_replace(Arc::new(fresh));
+            }
+        }
+    }
+
+    async fn request(self: Arc<Self>) -> Result<FreshDocument, NitroAdError> {
+        let requested = tokio::task::spawn_blocking(move || self.attest()).await;
+        requested.map_err(|e| NitroAdError::IoError(io::Error::other(e)))?
+    }
+
+    fn attest(&self) -> Result<FreshDocument, NitroAdError> {
+        let document = self.attester.attest(AttestationRequest {
+            user_data: self.user_data.as_deref(),
+            nonce: self.nonce.as_deref(),
+            public_key: self.public_key.as_deref(),
+        })?;
+        let doc = self.verifier.verify(&document, Utc::now().timestamp() as u64)?;
+        let not_after = doc.payload().chain_not_after().unwrap_or_default();
+        drop(doc);
+        Ok(FreshDocument {
+            document,
+            not_after,
+        })
+    }
+}
+
+/// Latest document of a running [`Reattester`], whose task stops when the handle is
+/// dropped
+pub struct ReattestHandle {
+    receiver: watch::Receiver<Arc<FreshDocument>>,
+    task: JoinHandle<()>,
+}
+
+impl ReattestHandle {
+    pub fn current(&self) -> Arc<FreshDocument> {
+        Arc::clone(&self.receiver.borrow())
+    }
+
+    /// Receiver notified of every document requested from now on
+    pub fn subscribe(&self) -> watch::Receiver<Arc<FreshDocument>> {
+        self.receiver.clone()
+    }
+}
+
+impl Drop for ReattestHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::time::timeout;
+
+    use crate::nsm::ErrorCode;
+    use crate::testing::MockNsm;
+    use crate::NitroAdDoc;
+
+    /// Mock failing the requests numbered `failing`
+    struct FlakyNsm {
+        mock: MockNsm,
+        failing: Range<usize>,
+        requests: AtomicUsize,
+    }
+
+    impl Attester for FlakyNsm {
+        fn attest(&self, request: AttestationRequest<'_>) -> Result<Vec<u8>, NitroAdError> {
+            if self.failing.contains(&self.requests.fetch_add(1, Ordering::SeqCst)) {
+                return Err(NitroAdError::NsmError(ErrorCode::InternalError));
+            }
+            self.mock.attest(request)
+        }
+    }
+
+    fn flaky(failing: Range<usize>) -> Result<(FlakyNsm, Verifier), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let verifier = Verifier::new(mock.root_cert());
+        let requests = AtomicUsize::new(0);
+        Ok((FlakyNsm { mock, failing, requests }, verifier))
+    }
+
+    #[tokio::test]
+    async fn test_reattest() -> Result<(), NitroAdError> {
+        let (nsm, verifier) = flaky(1..3)?;
+        let root_cert = verifier.root_cert().to_vec();
+        // the mock's certificates outlive the test, so every document is due right away
+        let handle = Reattester::new(nsm, verifier)
+            .with_nonce(&b"challenge"[..])
+            .with_refresh_before(Duration::from_secs(100 * 365 * 24 * 3600))
+            .with_retry_interval(Duration::from_millis(10))
+            .start()
+            .await?;
+        let first = handle.current();
+        assert!(first.not_after > Utc::now().timestamp());
+
+        let mut fresh = handle.subscribe();
+        timeout(Duration::from_secs(10), fresh.changed()).await.unwrap().unwrap();
+        let second = Arc::clone(&fresh.borrow_and_update());
+        assert_ne!(second.document, first.document);
+        let now = Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&second.document, &root_cert, now)?;
+        assert_eq!(doc.payload().nonce.as_deref(), Some(&b"challenge"[..]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_requests() -> Result<(), NitroAdError> {
+        let (nsm, verifier) = flaky(0..1)?;
+        let result = Reattester::new(nsm, verifier).start().await;
+        assert!(matches!(result, Err(NitroAdError::NsmError(ErrorCode::InternalError))));
+
+        // failures keep the current document until a request succeeds
+        let (nsm, verifier) = flaky(1..usize::MAX)?;
+        let handle = Reattester::new(nsm, verifier)
+            .with_refresh_before(Duration::from_secs(100 * 365 * 24 * 3600))
+            .with_retry_interval(Duration::from_millis(10))
+            .start()
+            .await?;
+        let first = handle.current();
+        let mut fresh = handle.subscribe();
+        assert!(timeout(Duration::from_millis(100), fresh.changed()).await.is_err());
+        assert_eq!(handle.current(), first);
+        Ok(())
+    }
+}
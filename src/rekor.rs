@@ -0,0 +1,233 @@
+//! Transparency log submissions
+//!
+//! [`RekorClient`] records a [`SignedReport`] in a Rekor transparency log as a
+//! `hashedrekord` entry: the SHA384 of the report's signing input, which covers
+//! the document hash and the verification result, along with the verifier
+//! signature and public key. The returned [`LogEntry`] keeps the inclusion proof,
+//! so the audit trail can later be checked against the log.
+
+use std::time::Duration;
+
+use openssl::bn::BigNum;
+use openssl::ec::EcKeyRef;
+use openssl::ecdsa::EcdsaSig;
+use openssl::pkey::HasPublic;
+use openssl::sha::sha384;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::report::{signing_input, SignedReport};
+use crate::NitroAdError;
+
+/// Public Sigstore instance
+pub static PUBLIC_REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Log entry of a submitted report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub uuid: String,
+    pub log_index: u64,
+    /// Seconds since the unix epoch
+    pub integrated_time: u64,
+    /// base64 encoded signature of the log over the entry
+    pub signed_entry_timestamp: Option<String>,
+    pub inclusion_proof: Option<InclusionProof>,
+}
+
+/// Merkle tree inclusion proof of a [`LogEntry`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub log_index: u64,
+    /// hex encoded
+    pub root_hash: String,
+    pub tree_size: u64,
+    /// hex encoded sibling hashes, leaf to root
+    pub hashes: Vec<String>,
+    pub checkpoint: Option<String>,
+}
+
+pub struct RekorClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RekorClient {
+    /// Client of the log at `url`, e.g. [`PUBLIC_REKOR_URL`]
+    pub fn new(url: &str) -> Self {
+        RekorClient {
+            url: String::from(url.trim_end_matches('/')),
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        }
+    }
+
+    /// Submits `report`, signed with the private counterpart of `key`
+    pub fn submit<T: HasPublic>(
+        &self,
+        report: &SignedReport,
+        key: &EcKeyRef<T>,
+    ) -> Result<LogEntry, NitroAdError> {
+        // don't publish entries the log (or anyone else) can't verify
+        report.verify(key)?;
+
+        let body = entry_request(report, key)?;
+        let response = self
+            .agent
+            .post(&format!("{}/api/v1/log/entries", self.url))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+            .map_err(|e| NitroAdError::TransparencyLogError(e.to_string()))?
+            .into_string()
+            .map_err(|e| NitroAdError::TransparencyLogError(e.to_string()))?;
+
+        parse_entry(&response)
+    }
+}
+
+/// `hashedrekord` entry proposal of a signed report
+fn entry_request<T: HasPublic>(
+    report: &SignedReport,
+    key: &EcKeyRef<T>,
+) -> Result<serde_json::Value, NitroAdError> {
+    let data = signing_input(&report.verifier_id, &report.report)?;
+
+    // Rekor expects DER encoded ECDSA signatures rather than r||s
+    let (r, s) = report.signature.split_at(report.signature.len() / 2);
+    let der_sig = BigNum::from_slice(r)
+        .and_then(|r| Ok((r, BigNum::from_slice(s)?)))
+        .and_then(|(r, s)| EcdsaSig::from_private_components(r, s))
+        .and_then(|sig| sig.to_der())
+        .map_err(NitroAdError::SigningError)?;
+    let pem = key.public_key_to_pem().map_err(NitroAdError::SigningError)?;
+
+    Ok(json!({
+        "apiVersion": "0.0.1",
+        "kind": "hashedrekord",
+        "spec": {
+            "signature": {
+                "content": base64::encode(der_sig),
+                "publicKey": { "content": base64::encode(pem) },
+            },
+            "data": {
+                "hash": { "algorithm": "sha384", "value": hex::encode(sha384(&data)) },
+            },
+        },
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RekorEntry {
+    integrated_time: u64,
+    log_index: u64,
+    verification: Option<RekorVerification>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RekorVerification {
+    signed_entry_timestamp: Option<String>,
+    inclusion_proof: Option<RekorInclusionProof>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RekorInclusionProof {
+    log_index: u64,
+    root_hash: String,
+    tree_size: u64,
+    hashes: Vec<String>,
+    checkpoint: Option<String>,
+}
+
+/// Rekor answers with a map from the entry UUID to the entry
+fn parse_entry(response: &str) -> Result<LogEntry, NitroAdError> {
+    let entries: std::collections::BTreeMap<String, RekorEntry> = serde_json::from_str(response)?;
+    let (uuid, entry) = entries
+        .into_iter()
+        .next()
+        .ok_or_else(|| NitroAdError::TransparencyLogError(String::from("empty log response")))?;
+
+    let (signed_entry_timestamp, inclusion_proof) = match entry.verification {
+        Some(v) => (v.signed_entry_timestamp, v.inclusion_proof),
+        None => (None, None),
+    };
+
+    Ok(LogEntry {
+        uuid,
+        log_index: entry.log_index,
+        integrated_time: entry.integrated_time,
+        signed_entry_timestamp,
+        inclusion_proof: inclusion_proof.map(|p| InclusionProof {
+            log_index: p.log_index,
+            root_hash: p.root_hash,
+            tree_size: p.tree_size,
+            hashes: p.hashes,
+            checkpoint: p.checkpoint,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+
+    use crate::NitroAdDoc;
+
+    #[test]
+    fn test_entry_request() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let report = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?.report();
+
+        let key = EcKey::generate(&EcGroup::from_curve_name(Nid::SECP384R1).unwrap()).unwrap();
+        let signed = report.sign("verifier-a", &key)?;
+        let body = entry_request(&signed, &key)?;
+
+        assert_eq!(body["kind"], "hashedrekord");
+        let der_sig = base64::decode(body["spec"]["signature"]["content"].as_str().unwrap()).unwrap();
+        let data = signing_input(&signed.verifier_id, &signed.report)?;
+        assert!(EcdsaSig::from_der(&der_sig)
+            .unwrap()
+            .verify(&sha384(&data), &key)
+            .unwrap());
+        assert_eq!(body["spec"]["data"]["hash"]["value"], hex::encode(sha384(&data)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_entry() -> Result<(), NitroAdError> {
+        let response = r#"{"24296fb24b8ad77a": {
+            "body": "eyJ...",
+            "integratedTime": 1700000000,
+            "logID": "c0d23d6ad406973f",
+            "logIndex": 42,
+            "verification": {
+                "inclusionProof": {
+                    "checkpoint": "rekor.sigstore.dev - 2605736670972794746\n43\n...",
+                    "hashes": ["aa", "bb"],
+                    "logIndex": 41,
+                    "rootHash": "cc",
+                    "treeSize": 43
+                },
+                "signedEntryTimestamp": "MEUCIQ..."
+            }
+        }}"#;
+
+        let entry = parse_entry(response)?;
+        assert_eq!(entry.uuid, "24296fb24b8ad77a");
+        assert_eq!(entry.log_index, 42);
+        let proof = entry.inclusion_proof.unwrap();
+        assert_eq!(proof.tree_size, 43);
+        assert_eq!(proof.hashes, vec!["aa", "bb"]);
+
+        assert!(parse_entry("{}").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+//! Verification as a service
+//!
+//! Thin clients can leave verification to a central service holding the root
+//! certificate and policy. The service answers a JSON [`VerifyRequest`] with a JSON
+//! [`VerifyResponse`], computed by [`handle`], e.g. in the [`lambda`](crate::lambda)
+//! function. With the `remote` feature, [`RemoteVerifier`] POSTs requests to such a
+//! service over HTTP and implements [`DocumentVerifier`] like the local [`Verifier`],
+//! so callers switch between the two without other changes.
+
+#[cfg(feature = "remote")]
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::DocumentOutput;
+#[cfg(feature = "remote")]
+use crate::verifier::DocumentVerifier;
+use crate::{NitroAdError, Verifier, VerifierPolicy};
+
+/// Verification request of a document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    /// base64 encoded document
+    pub document: String,
+    /// base64 encoded `nonce` the document must carry, e.g. the caller's challenge
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Verification time in seconds since the unix epoch, the service's clock if absent
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+impl VerifyRequest {
+    /// Request to verify `document` at the service's current time
+    pub fn new(document: &[u8]) -> Self {
+        VerifyRequest {
+            document: base64::encode(document),
+            nonce: None,
+            timestamp: None,
+        }
+    }
+
+    pub fn with_nonce(mut self, nonce: &[u8]) -> Self {
+        self.nonce = Some(base64::encode(nonce));
+        self
+    }
+
+    pub fn with_timestamp(mut self, unix_ts_sec: u64) -> Self {
+        self.timestamp = Some(unix_ts_sec);
+        self
+    }
+}
+
+/// Outcome of a [`VerifyRequest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    pub verified: bool,
+    /// Verified document, `None` if verification failed
+    pub document: Option<DocumentOutput>,
+    pub error: Option<String>,
+    /// [`NitroAdError::code`] of `error`, `None` for malformed requests
+    pub error_code: Option<u32>,
+}
+
+/// Error message and code of a rejected request
+type Rejection = (String, Option<u32>);
+
+fn verify_request(
+    verifier: &Verifier,
+    request: &VerifyRequest,
+    unix_ts_sec: u64,
+) -> Result<DocumentOutput, Rejection> {
+    let rejected = |e: NitroAdError| (e.to_string(), Some(e.code()));
+    let decode = |field: &str, value: &str| {
+        base64::decode(value).map_err(|_| (format!("{} is not base64", field), None))
+    };
+    let document = decode("document", &request.document)?;
+    let nonce = request.nonce.as_deref().map(|nonce| decode("nonce", nonce)).transpose()?;
+    let unix_ts_sec = request.timestamp.unwrap_or(unix_ts_sec);
+    let doc = verifier.verify(&document, unix_ts_sec).map_err(rejected)?;
+    if let Some(nonce) = nonce {
+        VerifierPolicy::new().with_nonce(nonce).check(&doc).map_err(rejected)?;
+    }
+    DocumentOutput::from_doc(&doc).map_err(rejected)
+}
+
+/// Answers `request` with `verifier`, at the request's `timestamp` or else `unix_ts_sec`
+pub fn handle(verifier: &Verifier, request: &VerifyRequest, unix_ts_sec: u64) -> VerifyResponse {
+    let (document, (error, error_code)) = match verify_request(verifier, request, unix_ts_sec) {
+        Ok(document) => (Some(document), (None, None)),
+        Err((error, error_code)) => (None, (Some(error), error_code)),
+    };
+    VerifyResponse {
+        verified: document.is_some(),
+        document,
+        error,
+        error_code,
+    }
+}
+
+#[cfg(feature = "remote")]
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client of a verification service
+/// ```no_run
+/// use aws_nitro_enclaves_attestation::remote::RemoteVerifier;
+/// use aws_nitro_enclaves_attestation::verifier::DocumentVerifier;
+///
+/// fn module_id(verifier: &impl DocumentVerifier, document: &[u8], now: u64)
+///     -> Result<String, aws_nitro_enclaves_attestation::NitroAdError> {
+///     Ok(verifier.verify_document(document, now)?.module_id)
+/// }
+/// # let (document, now) = (Vec::new(), 0);
+/// let verifier = RemoteVerifier::new("https://verifier.example.org/verify");
+/// let id = module_id(&verifier, &document, now)?;
+/// # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+/// ```
+#[cfg(feature = "remote")]
+pub struct RemoteVerifier {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "remote")]
+impl RemoteVerifier {
+    /// Client POSTing requests to `url`, giving up on responses after 10 seconds
+    pub fn new(url: &str) -> Self {
+        Self::with_timeout(url, DEFAULT_TIMEOUT)
+    }
+
+    pub fn with_timeout(url: &str, timeout: Duration) -> Self {
+        RemoteVerifier {
+            url: String::from(url),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    /// Sends `request` and returns the verified document. Verdicts other than
+    /// `verified` fail with [`NitroAdError::RemoteRejected`], the service being
+    /// unreachable or answering otherwise with [`NitroAdError::RemoteError`].
+    pub fn send(&self, request: &VerifyRequest) -> Result<DocumentOutput, NitroAdError> {
+        let remote_error = |e: &dyn std::fmt::Display| NitroAdError::RemoteError(e.to_string());
+        let response = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::to_string(request)?)
+            .map_err(|e| remote_error(&e))?
+            .into_string()
+            .map_err(|e| remote_error(&e))?;
+        let response: VerifyResponse =
+            serde_json::from_str(&response).map_err(|e| remote_error(&e))?;
+        match response {
+            VerifyResponse {
+                verified: true,
+                document: Some(document),
+                ..
+            } => Ok(document),
+            VerifyResponse { error, error_code, .. } => Err(NitroAdError::RemoteRejected {
+                code: error_code,
+                message: error.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+impl DocumentVerifier for RemoteVerifier {
+    fn verify_document(
+        &self,
+        document: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<DocumentOutput, NitroAdError> {
+        self.send(&VerifyRequest::new(document).with_timestamp(unix_ts_sec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..]);
+
+        let response = handle(&verifier, &VerifyRequest::new(ad_blob), 1614967200);
+        assert!(response.verified);
+        let document = response.document.unwrap();
+        assert_eq!(document.module_id, "i-026ae32a18c80f866-enc01780356441553dc");
+
+        let request = VerifyRequest::new(ad_blob).with_nonce(b"challenge");
+        let response = handle(&verifier, &request, 1614967200);
+        assert!(!response.verified);
+        assert_eq!(response.error_code, Some(NitroAdError::NonceMismatch.code()));
+        // expired certificates, at the request's time rather than the service's
+        let request = VerifyRequest::new(ad_blob).with_timestamp(1914967200);
+        let response = handle(&verifier, &request, 1614967200);
+        assert!(!response.verified);
+        assert!(response.error_code.is_some());
+
+        let malformed = VerifyRequest {
+            document: String::from("not base64!"),
+            nonce: None,
+            timestamp: None,
+        };
+        let response = handle(&verifier, &malformed, 1614967200);
+        assert_eq!(response.error.as_deref(), Some("document is not base64"));
+        assert_eq!(response.error_code, None);
+
+        let json = serde_json::json!({ "document": base64::encode(ad_blob) });
+        let request: VerifyRequest = serde_json::from_value(json).unwrap();
+        assert_eq!(request, VerifyRequest::new(ad_blob));
+    }
+
+    /// Address of a single threaded HTTP server answering `requests` requests with
+    /// `handle`
+    #[cfg(feature = "remote")]
+    fn serve(verifier: Verifier, requests: usize) -> std::net::SocketAddr {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        Some(value) => length = value.trim().parse().unwrap(),
+                        None if line == "\r\n" => break,
+                        None => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request = serde_json::from_slice(&body).unwrap();
+                let response = serde_json::to_string(&handle(&verifier, &request, 0)).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn test_remote_verifier() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let local = Verifier::new(&root_cert[..]);
+        let addr = serve(Verifier::new(&root_cert[..]), 3);
+        let remote = RemoteVerifier::new(&format!("http://{}/verify", addr));
+
+        assert_eq!(
+            remote.verify_document(ad_blob, 1614967200)?,
+            local.verify_document(ad_blob, 1614967200)?
+        );
+        let request =
+            VerifyRequest::new(ad_blob).with_nonce(b"challenge").with_timestamp(1614967200);
+        let code = NitroAdError::NonceMismatch.code();
+        assert!(matches!(
+            remote.send(&request),
+            Err(NitroAdError::RemoteRejected { code: Some(c), .. }) if c == code
+        ));
+        assert!(matches!(
+            remote.verify_document(&ad_blob[..100], 1614967200),
+            Err(NitroAdError::RemoteRejected { .. })
+        ));
+
+        let unreachable = RemoteVerifier::new("http://127.0.0.1:1/verify");
+        assert!(matches!(
+            unreachable.verify_document(ad_blob, 1614967200),
+            Err(NitroAdError::RemoteError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! Verification outcome of a single document
+//!
+//! A [`VerificationReport`] records which document was checked, when, and with
+//! which result, independently of the document bytes themselves. Signed by the
+//! verifier ([`VerificationReport::sign`]), it is portable evidence that the
+//! verifier accepted the document at that time. [`VerificationReport::to_cbor`] is a
+//! compact, versioned binary form for passing reports between services and storing them.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+#[cfg(feature = "openssl")]
+use openssl::ec::EcKeyRef;
+#[cfg(feature = "openssl")]
+use openssl::pkey::{HasPublic, Private};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use serde_cbor::Value as CborValue;
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+
+use crate::crypto::sha384;
+#[cfg(feature = "openssl")]
+use crate::es384;
+use crate::{NitroAdDoc, NitroAdError};
+
+/// Domain separation of report signatures from other uses of the verifier key
+#[cfg(feature = "openssl")]
+static SIGNATURE_CONTEXT: &str = "nitro-attestation-report-v1";
+
+/// Format version of [`VerificationReport::to_cbor`], its first array element
+pub const REPORT_VERSION: u32 = 1;
+
+/// Version and fields of a [`VerificationReport`], in declaration order
+#[derive(Serialize, Deserialize)]
+struct CompactReport(
+    u32,
+    ByteBuf,
+    String,
+    i64,
+    u64,
+    BTreeMap<u8, ByteBuf>,
+    bool,
+    Option<String>,
+    Option<(String, String)>,
+);
+
+/// Name and version of the [`MeasurementProfile`](crate::profile::MeasurementProfile) a
+/// document's PCRs matched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileId {
+    pub name: String,
+    pub version: String,
+}
+
+/// Hex encoded in JSON
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationReport {
+    /// SHA384 of the raw COSE_Sign1 document
+    #[serde_as(as = "Hex")]
+    pub document_sha384: Vec<u8>,
+    pub module_id: String,
+    /// Document `timestamp`, milliseconds since the unix epoch
+    pub document_timestamp_ms: i64,
+    /// Time the certificate chain was checked at, seconds since the unix epoch
+    pub verified_at: u64,
+    #[serde_as(as = "BTreeMap<_, Hex>")]
+    pub pcrs: BTreeMap<u8, Vec<u8>>,
+    pub debug_mode: bool,
+    /// Certificate chain error, `None` when the document was accepted
+    pub chain_error: Option<String>,
+    /// Profile the PCRs matched, for reports of
+    /// [`ProfileRegistry::verify`](crate::profile::ProfileRegistry::verify)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ProfileId>,
+}
+
+impl VerificationReport {
+    pub fn from_doc(doc: &NitroAdDoc) -> Self {
+        let payload = doc.payload();
+        VerificationReport {
+            document_sha384: sha384(doc.as_bytes()).to_vec(),
+            module_id: payload.module_id.clone(),
+            document_timestamp_ms: payload.timestamp.timestamp_millis(),
+            verified_at: doc.verified_at(),
+            pcrs: payload
+                .pcrs
+                .iter()
+                .map(|(i, val)| (*i, val.to_vec()))
+                .collect(),
+            debug_mode: payload.is_debug_mode(),
+            chain_error: doc.verification_error().map(|e| e.to_string()),
+            profile: None,
+        }
+    }
+
+    /// Signature and certificate chain of the document verified
+    pub fn is_accepted(&self) -> bool {
+        self.chain_error.is_none()
+    }
+
+    /// Deterministically encoded CBOR map of the report fields, keys sorted as
+    /// for [`NitroAdDocPayload::to_canonical_cbor`](crate::NitroAdDocPayload::to_canonical_cbor)
+    pub fn to_canonical_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        Ok(serde_cbor::to_vec(&self.to_cbor_value())?)
+    }
+
+    /// CBOR array of [`REPORT_VERSION`] and the report fields in declaration order, with
+    /// hashes and PCRs as byte strings. About half the size of the JSON encoding.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, NitroAdError> {
+        let pcrs = self.pcrs.iter().map(|(i, val)| (*i, ByteBuf::from(val.clone()))).collect();
+        Ok(serde_cbor::to_vec(&CompactReport(
+            REPORT_VERSION,
+            ByteBuf::from(self.document_sha384.clone()),
+            self.module_id.clone(),
+            self.document_timestamp_ms,
+            self.verified_at,
+            pcrs,
+            self.debug_mode,
+            self.chain_error.clone(),
+            self.profile.clone().map(|profile| (profile.name, profile.version)),
+        ))?)
+    }
+
+    /// Report of [`VerificationReport::to_cbor`] output. Other versions fail with
+    /// [`NitroAdError::UnsupportedReportVersion`] whatever their fields.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, NitroAdError> {
+        let fields: Vec<CborValue> = serde_cbor::from_slice(bytes)?;
+        if let Some(CborValue::Integer(version)) = fields.first() {
+            if *version != REPORT_VERSION as i128 {
+                let version = u32::try_from(*version).unwrap_or(u32::MAX);
+                return Err(NitroAdError::UnsupportedReportVersion(version));
+            }
+        }
+        let CompactReport(
+            _,
+            document_sha384,
+            module_id,
+            document_timestamp_ms,
+            verified_at,
+            pcrs,
+            debug_mode,
+            chain_error,
+            profile,
+        ) = serde_cbor::value::from_value(CborValue::Array(fields))?;
+        Ok(VerificationReport {
+            document_sha384: document_sha384.into_vec(),
+            module_id,
+            document_timestamp_ms,
+            verified_at,
+            pcrs: pcrs.into_iter().map(|(i, val)| (i, val.into_vec())).collect(),
+            debug_mode,
+            chain_error,
+            profile: profile.map(|(name, version)| ProfileId { name, version }),
+        })
+    }
+
+    fn to_cbor_value(&self) -> CborValue {
+        let text = |s: &str| CborValue::Text(String::from(s));
+
+        let pcrs = self
+            .pcrs
+            .iter()
+            .map(|(i, val)| (CborValue::Integer(*i as i128), CborValue::Bytes(val.clone())))
+            .collect();
+
+        let mut map = BTreeMap::new();
+        map.insert(text("document_sha384"), CborValue::Bytes(self.document_sha384.clone()));
+        map.insert(text("module_id"), text(&self.module_id));
+        map.insert(
+            text("document_timestamp_ms"),
+            CborValue::Integer(self.document_timestamp_ms as i128),
+        );
+        map.insert(text("verified_at"), CborValue::Integer(self.verified_at as i128));
+        map.insert(text("pcrs"), CborValue::Map(pcrs));
+        map.insert(text("debug_mode"), CborValue::Bool(self.debug_mode));
+        map.insert(
+            text("chain_error"),
+            self.chain_error.as_deref().map_or(CborValue::Null, text),
+        );
+        // absent rather than null, so reports without one keep their signatures
+        if let Some(profile) = &self.profile {
+            let mut id = BTreeMap::new();
+            id.insert(text("name"), text(&profile.name));
+            id.insert(text("version"), text(&profile.version));
+            map.insert(text("profile"), CborValue::Map(id));
+        }
+
+        CborValue::Map(map)
+    }
+
+    /// Signs the report as verifier `verifier_id` with its P-384 `key`
+    #[cfg(feature = "openssl")]
+    pub fn sign(&self, verifier_id: &str, key: &EcKeyRef<Private>) -> Result<SignedReport, NitroAdError> {
+        let signature = es384::sign(&signing_input(verifier_id, self)?, key)?;
+        Ok(SignedReport {
+            verifier_id: String::from(verifier_id),
+            report: self.clone(),
+            signature,
+        })
+    }
+}
+
+/// [`VerificationReport`] with the ES384 signature of the verifier which produced it
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedReport {
+    pub verifier_id: String,
+    pub report: VerificationReport,
+    /// r||s over the CBOR array of a context string, `verifier_id` and the canonical report
+    #[serde_as(as = "Hex")]
+    pub signature: Vec<u8>,
+}
+
+#[cfg(feature = "openssl")]
+impl SignedReport {
+    /// Checks the signature against the P-384 public `key` of the verifier
+    pub fn verify<T: HasPublic>(&self, key: &EcKeyRef<T>) -> Result<&VerificationReport, NitroAdError> {
+        let data = signing_input(&self.verifier_id, &self.report)?;
+        if !es384::verify(&data, &self.signature, key)? {
+            return Err(NitroAdError::InvalidReportSignature);
+        }
+        Ok(&self.report)
+    }
+}
+
+#[cfg(feature = "openssl")]
+pub(crate) fn signing_input(verifier_id: &str, report: &VerificationReport) -> Result<Vec<u8>, NitroAdError> {
+    Ok(serde_cbor::to_vec(&CborValue::Array(vec![
+        CborValue::Text(String::from(SIGNATURE_CONTEXT)),
+        CborValue::Text(String::from(verifier_id)),
+        report.to_cbor_value(),
+    ]))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "openssl")]
+    use openssl::ec::{EcGroup, EcKey};
+    #[cfg(feature = "openssl")]
+    use openssl::nid::Nid;
+
+    #[test]
+    fn test_report_from_document() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        let report = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap().report();
+        assert!(report.is_accepted());
+        assert_eq!(report.verified_at, 1614967200);
+        assert_eq!(report.document_sha384, sha384(ad_blob).to_vec());
+
+        let js = serde_json::to_string(&report).unwrap();
+        assert!(js.contains(&hex::encode(sha384(ad_blob))));
+        assert_eq!(serde_json::from_str::<VerificationReport>(&js).unwrap(), report);
+
+        let expired = NitroAdDoc::from_bytes(ad_blob, root_cert, 1714967200).unwrap().report();
+        assert!(!expired.is_accepted());
+    }
+
+    #[test]
+    fn test_compact_report() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+
+        for ts in &[1614967200, 1714967200] {
+            let report = NitroAdDoc::from_bytes(ad_blob, root_cert, *ts)?.report();
+            let cbor = report.to_cbor()?;
+            assert_eq!(VerificationReport::from_cbor(&cbor)?, report);
+            assert!(cbor.len() * 2 < serde_json::to_vec(&report)?.len());
+        }
+
+        let report = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?.report();
+        let mut fields: Vec<CborValue> = serde_cbor::from_slice(&report.to_cbor()?)?;
+        fields[0] = CborValue::Integer(2);
+        fields.truncate(3);
+        let future = serde_cbor::to_vec(&fields)?;
+        assert!(matches!(
+            VerificationReport::from_cbor(&future),
+            Err(NitroAdError::UnsupportedReportVersion(2))
+        ));
+        fields[0] = CborValue::Integer(REPORT_VERSION as i128);
+        let truncated = serde_cbor::to_vec(&fields)?;
+        let result = VerificationReport::from_cbor(&truncated);
+        assert!(matches!(result, Err(NitroAdError::CBORError(_))));
+        assert!(VerificationReport::from_cbor(&report.to_canonical_cbor()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "openssl")]
+    fn test_signed_report() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let report = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?.report();
+
+        let key = EcKey::generate(&EcGroup::from_curve_name(Nid::SECP384R1).unwrap()).unwrap();
+        let signed = report.sign("verifier-a", &key)?;
+        assert_eq!(signed.verify(&key)?, &report);
+
+        // encoding is stable, so the report survives a JSON round trip
+        let js = serde_json::to_string(&signed)?;
+        let parsed: SignedReport = serde_json::from_str(&js)?;
+        assert!(parsed.verify(&key).is_ok());
+
+        let mut forged = parsed.clone();
+        forged.verifier_id = String::from("verifier-b");
+        assert!(matches!(forged.verify(&key), Err(NitroAdError::InvalidReportSignature)));
+
+        let mut forged = parsed;
+        forged.report.verified_at += 1;
+        assert!(matches!(forged.verify(&key), Err(NitroAdError::InvalidReportSignature)));
+
+        Ok(())
+    }
+}
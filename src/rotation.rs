@@ -0,0 +1,313 @@
+//! Key rotation chains of attested documents
+//!
+//! Sessions outliving the key of their handshake, or the hours-lived certificate of its
+//! document, rotate keys without handshaking again. The enclave calls
+//! [`AttestedKey::rotate`] to generate a new key pair and request a document for it
+//! whose `user_data` is a [`ROTATION_LINK_LEN`] byte link to the previous document:
+//!
+//! | bytes | content |
+//! |---|---|
+//! | 48 | SHA384 of the previous document |
+//! | 96 | r\|\|s ES384 signature by the previous key |
+//!
+//! The previous key signs [`ROTATION_CONTEXT`], followed by the hash and the new
+//! `public_key`.
+//!
+//! The peer keeps the latest document of the chain in a [`RotationChain`] and passes
+//! each new one to [`RotationChain::rotate`], which verifies it and checks that the
+//! holder of the previous key vouched for it and that it is of the same enclave. Only
+//! the new document is verified, at the time of the rotation, so the chain stays valid
+//! after the certificates of its first documents expired.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::rotation::RotationChain;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # let (aws_root_der, first_document, next_document, now) = (vec![], vec![], vec![], 0);
+//! let verifier = Verifier::new(aws_root_der);
+//! let mut chain = RotationChain::new(&verifier, first_document, now)?;
+//! chain.rotate(&verifier, next_document, now)?;
+//! let current_key = chain.public_key();
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "nsm")]
+use p384::ecdsa::signature::Signer;
+use p384::ecdsa::signature::Verifier as _;
+#[cfg(feature = "nsm")]
+use p384::ecdsa::SigningKey;
+use p384::ecdsa::{Signature, VerifyingKey};
+use p384::pkcs8::DecodePublicKey;
+#[cfg(feature = "nsm")]
+use rand_core::CryptoRngCore;
+
+use crate::crypto::{sha384, CryptoBackend};
+#[cfg(feature = "nsm")]
+use crate::nsm::{public_key_der, AttestationRequest, AttestedKey, Attester};
+use crate::{NitroAdDoc, NitroAdDocPayload, NitroAdError, Verifier};
+
+/// Prefix of the messages signed by rotation links
+pub static ROTATION_CONTEXT: &[u8] = b"aws-nitro-enclaves-attestation key rotation v1";
+
+/// Length of the `user_data` of rotated documents
+pub const ROTATION_LINK_LEN: usize = 48 + 96;
+
+/// Message the previous key signs for `next_public_key`
+fn link_message(previous_sha384: &[u8], next_public_key: &[u8]) -> Vec<u8> {
+    [ROTATION_CONTEXT, previous_sha384, next_public_key].concat()
+}
+
+#[cfg(feature = "nsm")]
+impl AttestedKey {
+    /// Successor of this key: a new key pair of `rng` with a document from `attester`
+    /// linking it to this key's document, see the [module documentation](self)
+    pub fn rotate<A: Attester + ?Sized>(
+        &self,
+        attester: &A,
+        rng: &mut impl CryptoRngCore,
+        nonce: Option<&[u8]>,
+    ) -> Result<AttestedKey, NitroAdError> {
+        let secret_key = p384::SecretKey::random(rng);
+        let public_key = public_key_der(&secret_key)?;
+        let previous_sha384 = sha384(&self.document);
+        let signature: Signature = SigningKey::from(&self.secret_key)
+            .sign(&link_message(&previous_sha384, &public_key));
+        let link = [&previous_sha384[..], &signature.to_bytes()[..]].concat();
+        let document = attester.attest(AttestationRequest {
+            user_data: Some(&link),
+            nonce,
+            public_key: Some(&public_key),
+        })?;
+        Ok(AttestedKey {
+            secret_key,
+            public_key,
+            document,
+        })
+    }
+}
+
+/// What a document's successor is checked against
+#[derive(Debug, Clone)]
+struct Predecessor {
+    document_sha384: [u8; 48],
+    key: VerifyingKey,
+    module_id: String,
+    timestamp: DateTime<Utc>,
+}
+
+impl Predecessor {
+    fn of(doc: &NitroAdDoc) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+        let payload = doc.payload();
+        let key = payload
+            .public_key
+            .as_deref()
+            .and_then(|key| VerifyingKey::from_public_key_der(key).ok())
+            .ok_or(NitroAdError::UnsupportedPublicKey)?;
+        Ok(Predecessor {
+            document_sha384: sha384(doc.as_bytes()),
+            key,
+            module_id: payload.module_id.clone(),
+            timestamp: payload.timestamp,
+        })
+    }
+
+    fn check(&self, next: &NitroAdDocPayload) -> Result<(), NitroAdError> {
+        let link = next.user_data.as_deref().filter(|link| link.len() == ROTATION_LINK_LEN);
+        let link = link.ok_or(NitroAdError::RotationError("user_data is not a rotation link"))?;
+        let (previous_sha384, signature) = link.split_at(48);
+        if previous_sha384 != self.document_sha384 {
+            return Err(NitroAdError::RotationError("link is to another document"));
+        }
+        let next_key = next.public_key.as_deref().ok_or(NitroAdError::UnsupportedPublicKey)?;
+        Signature::from_slice(signature)
+            .and_then(|signature| {
+                self.key.verify(&link_message(previous_sha384, next_key), &signature)
+            })
+            .map_err(|_| NitroAdError::RotationError("link signature is invalid"))?;
+        if next.module_id != self.module_id {
+            return Err(NitroAdError::RotationError("module_id changed"));
+        }
+        if next.timestamp < self.timestamp {
+            return Err(NitroAdError::RotationError("document predates the previous one"));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `next` continues the key rotation of `previous`. Both documents' chains
+/// must have verified; policies are left to the caller.
+pub fn verify_rotation(previous: &NitroAdDoc, next: &NitroAdDoc) -> Result<(), NitroAdError> {
+    if let Some(e) = next.verification_error() {
+        return Err(NitroAdError::VerificationError(e));
+    }
+    Predecessor::of(previous)?.check(next.payload())
+}
+
+/// Latest document of a key rotation chain
+#[derive(Debug, Clone)]
+pub struct RotationChain {
+    document: Vec<u8>,
+    public_key: Vec<u8>,
+    predecessor: Predecessor,
+    rotations: usize,
+}
+
+impl RotationChain {
+    /// Chain starting at `document`, whose `public_key` must be a P-384 key, verified by
+    /// `verifier` at `unix_ts_sec`
+    pub fn new<B: CryptoBackend>(
+        verifier: &Verifier<B>,
+        document: impl Into<Vec<u8>>,
+        unix_ts_sec: u64,
+    ) -> Result<Self, NitroAdError> {
+        let document = document.into();
+        let doc = verifier.verify(&document, unix_ts_sec)?;
+        let (public_key, predecessor) = Self::latest(&doc)?;
+        drop(doc);
+        Ok(RotationChain {
+            document,
+            public_key,
+            predecessor,
+            rotations: 0,
+        })
+    }
+
+    /// Verifies `next` with `verifier` at `unix_ts_sec` and makes it the latest document
+    /// if it continues the chain. The chain is unchanged on errors.
+    pub fn rotate<B: CryptoBackend>(
+        &mut self,
+        verifier: &Verifier<B>,
+        next: impl Into<Vec<u8>>,
+        unix_ts_sec: u64,
+    ) -> Result<(), NitroAdError> {
+        let next = next.into();
+        let doc = verifier.verify(&next, unix_ts_sec)?;
+        self.predecessor.check(doc.payload())?;
+        let (public_key, predecessor) = Self::latest(&doc)?;
+        drop(doc);
+        self.document = next;
+        self.public_key = public_key;
+        self.predecessor = predecessor;
+        self.rotations += 1;
+        Ok(())
+    }
+
+    fn latest(doc: &NitroAdDoc) -> Result<(Vec<u8>, Predecessor), NitroAdError> {
+        let predecessor = Predecessor::of(doc)?;
+        let public_key = doc.payload().public_key.as_deref().unwrap_or_default().to_vec();
+        Ok((public_key, predecessor))
+    }
+
+    /// Raw latest document
+    pub fn document(&self) -> &[u8] {
+        &self.document
+    }
+
+    /// DER encoded SubjectPublicKeyInfo of the current key, the latest document's
+    /// `public_key`
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Number of rotations since the first document
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use rand_core::OsRng;
+
+    use crate::testing::{DocumentBuilder, MockNsm};
+
+    #[test]
+    fn test_rotation_chain() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let verifier = Verifier::new(mock.root_cert());
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        let first = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let second = first.rotate(&mock, &mut OsRng, None)?;
+        let third = second.rotate(&mock, &mut OsRng, Some(b"challenge"))?;
+        let doc = NitroAdDoc::from_bytes(&second.document, &mock.root_cert(), now)?;
+        assert_eq!(doc.payload().user_data.as_deref().map(<[u8]>::len), Some(ROTATION_LINK_LEN));
+
+        let mut chain = RotationChain::new(&verifier, first.document.clone(), now)?;
+        chain.rotate(&verifier, second.document.clone(), now)?;
+        // skipping a document or going back breaks the chain, and leaves it as it was
+        for document in &[&first.document, &second.document] {
+            assert!(matches!(
+                chain.rotate(&verifier, document.to_vec(), now),
+                Err(NitroAdError::RotationError(_))
+            ));
+        }
+        assert_eq!(chain.document(), &second.document[..]);
+        chain.rotate(&verifier, third.document.clone(), now)?;
+        assert_eq!(chain.public_key(), &third.public_key[..]);
+        assert_eq!(chain.rotations(), 2);
+
+        // a fresh key of the same enclave isn't vouched for by the previous one
+        let fresh = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        assert!(matches!(
+            chain.rotate(&verifier, fresh.document, now),
+            Err(NitroAdError::RotationError("user_data is not a rotation link"))
+        ));
+        let forked = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let forked = forked.rotate(&mock, &mut OsRng, None)?;
+        // claims to continue the chain, but signed with another key
+        let forged = AttestedKey {
+            document: third.document.clone(),
+            ..AttestedKey::generate(&mock, &mut OsRng, None, None)?
+        }
+        .rotate(&mock, &mut OsRng, None)?;
+        assert!(matches!(
+            chain.rotate(&verifier, forked.document, now),
+            Err(NitroAdError::RotationError("link is to another document"))
+        ));
+        assert!(matches!(
+            chain.rotate(&verifier, forged.document, now),
+            Err(NitroAdError::RotationError("link signature is invalid"))
+        ));
+        assert_eq!(chain.rotations(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_rotation() -> Result<(), NitroAdError> {
+        let builder = DocumentBuilder::new()?;
+        let mock = MockNsm::from_builder(builder.clone());
+        let moved_builder = builder.with_module_id("i-1111111111111111-enc1111111111111111");
+        let other_enclave = MockNsm::from_builder(moved_builder);
+        let now = chrono::Utc::now().timestamp() as u64;
+        let root_cert = mock.root_cert();
+        let verify = |document| NitroAdDoc::from_bytes(document, &root_cert, now);
+
+        let first = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let second = first.rotate(&mock, &mut OsRng, None)?;
+        let moved = first.rotate(&other_enclave, &mut OsRng, None)?;
+        verify_rotation(&verify(&first.document)?, &verify(&second.document)?)?;
+        assert!(matches!(
+            verify_rotation(&verify(&first.document)?, &verify(&moved.document)?),
+            Err(NitroAdError::RotationError("module_id changed"))
+        ));
+
+        let without_key = crate::nsm::Attester::attest(&mock, Default::default())?;
+        assert!(matches!(
+            verify_rotation(&verify(&without_key)?, &verify(&second.document)?),
+            Err(NitroAdError::UnsupportedPublicKey)
+        ));
+        let later = now + 400 * 24 * 3600;
+        let expired = NitroAdDoc::from_bytes(&second.document, &root_cert, later)?;
+        assert!(matches!(
+            verify_rotation(&verify(&first.document)?, &expired),
+            Err(NitroAdError::VerificationError(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! SPIFFE identities for attested enclaves
+//!
+//! [`SpiffeClaims::from_doc`] names a verified document's enclave with a SPIFFE ID in a
+//! given trust domain, its path derived from the `module_id` or PCR0 as chosen by a
+//! [`PathSource`], along with SPIRE style [`Selector`]s for registration entries to
+//! match on. A [`NodeAttestor`] does what a SPIRE server node attestor plugin does with
+//! the document an agent inside the enclave presents: verifies it, policy included,
+//! and returns the agent ID `spiffe://<trust domain>/spire/agent/aws_nitro/<module_id>`
+//! with the selectors.
+
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::kms::DOCUMENTED_PCRS;
+use crate::{NitroAdDoc, NitroAdError, Verifier};
+
+/// Type of the selectors, and name of the node attestor in agent IDs
+pub static SELECTOR_TYPE: &str = "aws_nitro";
+
+/// Document field the path of [`SpiffeClaims::from_doc`] IDs derives from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSource {
+    /// `/nitro-enclave/<module_id>`, one ID per enclave instance
+    ModuleId,
+    /// `/nitro-enclave/pcr0/<hex>`, one ID per enclave image
+    Pcr0,
+}
+
+/// Property of an enclave a SPIRE registration entry can match on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Selector {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+}
+
+impl Selector {
+    fn new(value: String) -> Self {
+        Selector {
+            kind: String::from(SELECTOR_TYPE),
+            value,
+        }
+    }
+}
+
+/// `aws_nitro:<value>`, the form of `spire-server entry create -selector`
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.kind, self.value)
+    }
+}
+
+/// SPIFFE ID and selectors of an attested enclave
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpiffeClaims {
+    /// `spiffe://<trust_domain><path>`
+    pub spiffe_id: String,
+    pub trust_domain: String,
+    pub path: String,
+    /// `module_id`, `digest` and the documented PCRs present in the document
+    pub selectors: Vec<Selector>,
+}
+
+/// Checks the characters SPIFFE allows in trust domain names
+fn check_trust_domain(trust_domain: &str) -> Result<(), NitroAdError> {
+    let valid = |c: char| matches!(c, 'a'..='z' | '0'..='9' | '.' | '-' | '_');
+    if trust_domain.is_empty() || !trust_domain.chars().all(valid) {
+        return Err(NitroAdError::InvalidSpiffeId(format!("trust domain '{}'", trust_domain)));
+    }
+    Ok(())
+}
+
+/// Checks the characters SPIFFE allows in path segments
+fn check_segment(segment: &str) -> Result<&str, NitroAdError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+    if segment.is_empty() || segment == "." || segment == ".." || !segment.chars().all(valid) {
+        return Err(NitroAdError::InvalidSpiffeId(format!("path segment '{}'", segment)));
+    }
+    Ok(segment)
+}
+
+impl SpiffeClaims {
+    /// Claims of `doc` in `trust_domain`, e.g. `example.org`, with the ID path of
+    /// `source`. Fails for documents whose certificate chain didn't verify.
+    pub fn from_doc(
+        doc: &NitroAdDoc,
+        trust_domain: &str,
+        source: PathSource,
+    ) -> Result<Self, NitroAdError> {
+        let payload = doc.payload();
+        let path = match source {
+            PathSource::ModuleId => {
+                format!("/nitro-enclave/{}", check_segment(&payload.module_id)?)
+            }
+            PathSource::Pcr0 => {
+                let pcr0 = payload.pcrs.get(&0).ok_or(NitroAdError::MissingPcr(0))?;
+                format!("/nitro-enclave/pcr0/{}", hex::encode(pcr0))
+            }
+        };
+        Self::with_path(doc, trust_domain, path)
+    }
+
+    fn with_path(doc: &NitroAdDoc, trust_domain: &str, path: String) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+        check_trust_domain(trust_domain)?;
+
+        let payload = doc.payload();
+        let mut selectors = vec![
+            Selector::new(format!("module_id:{}", payload.module_id)),
+            Selector::new(format!("digest:{}", payload.digest)),
+        ];
+        for i in DOCUMENTED_PCRS {
+            if let Some(value) = payload.pcrs.get(i) {
+                selectors.push(Selector::new(format!("pcr{}:{}", i, hex::encode(value))));
+            }
+        }
+
+        Ok(SpiffeClaims {
+            spiffe_id: format!("spiffe://{}{}", trust_domain, path),
+            trust_domain: String::from(trust_domain),
+            path,
+            selectors,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, NitroAdError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Server side of SPIRE style node attestation for agents running in enclaves
+#[derive(Debug)]
+pub struct NodeAttestor {
+    verifier: Verifier,
+    trust_domain: String,
+}
+
+impl NodeAttestor {
+    /// Attestor for `trust_domain` accepting the documents `verifier` accepts
+    pub fn new(verifier: Verifier, trust_domain: impl Into<String>) -> Result<Self, NitroAdError> {
+        let trust_domain = trust_domain.into();
+        check_trust_domain(&trust_domain)?;
+        Ok(NodeAttestor {
+            verifier,
+            trust_domain,
+        })
+    }
+
+    /// Verifies the agent's `document` at `unix_ts_sec` and returns its agent ID and
+    /// selectors. The verifier's policy should require a fresh `nonce`, SPIRE's
+    /// challenge, so documents can't be replayed.
+    pub fn attest(&self, document: &[u8], unix_ts_sec: u64) -> Result<SpiffeClaims, NitroAdError> {
+        let doc = self.verifier.verify(document, unix_ts_sec)?;
+        let module_id = check_segment(&doc.payload().module_id)?;
+        let path = format!("/spire/agent/{}/{}", SELECTOR_TYPE, module_id);
+        SpiffeClaims::with_path(&doc, &self.trust_domain, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        let payload = doc.payload();
+        let pcr0 = hex::encode(&payload.pcrs[&0]);
+
+        let claims = SpiffeClaims::from_doc(&doc, "example.org", PathSource::ModuleId)?;
+        assert_eq!(
+            claims.spiffe_id,
+            format!("spiffe://example.org/nitro-enclave/{}", payload.module_id)
+        );
+        assert_eq!(claims.selectors.len(), 2 + DOCUMENTED_PCRS.len());
+        let module_id = format!("aws_nitro:module_id:{}", payload.module_id);
+        assert_eq!(claims.selectors[0].to_string(), module_id);
+        assert_eq!(claims.selectors[1].to_string(), "aws_nitro:digest:SHA384");
+        assert_eq!(claims.selectors[2].to_string(), format!("aws_nitro:pcr0:{}", pcr0));
+
+        let claims = SpiffeClaims::from_doc(&doc, "example.org", PathSource::Pcr0)?;
+        assert_eq!(claims.path, format!("/nitro-enclave/pcr0/{}", pcr0));
+        let json: serde_json::Value = serde_json::from_str(&claims.to_json()?)?;
+        assert_eq!(json["spiffe_id"], claims.spiffe_id);
+        assert_eq!(json["selectors"][0]["type"], "aws_nitro");
+
+        for trust_domain in &["", "Example.org", "example.org/path", "spiffe://example.org"] {
+            assert!(matches!(
+                SpiffeClaims::from_doc(&doc, trust_domain, PathSource::Pcr0),
+                Err(NitroAdError::InvalidSpiffeId(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_attestor() -> Result<(), NitroAdError> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let attestor = NodeAttestor::new(Verifier::new(&root_cert[..]), "example.org")?;
+        let claims = attestor.attest(ad_blob, 1614967200)?;
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200)?;
+        assert_eq!(
+            claims.spiffe_id,
+            format!("spiffe://example.org/spire/agent/aws_nitro/{}", doc.payload().module_id)
+        );
+        let workload = SpiffeClaims::from_doc(&doc, "example.org", PathSource::Pcr0)?;
+        assert_eq!(claims.selectors, workload.selectors);
+
+        assert!(attestor.attest(&ad_blob[..100], 1614967200).is_err());
+        assert!(matches!(
+            NodeAttestor::new(Verifier::new(&root_cert[..]), "bad domain"),
+            Err(NitroAdError::InvalidSpiffeId(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! SSH certificates for attested enclaves
+//!
+//! An [`SshCertificateIssuer`] holds an SSH certificate authority key and signs
+//! short-lived certificates for the `public_key` of documents its [`Verifier`] accepts,
+//! policy included, so only enclaves running the expected image get SSH access to, or
+//! as, the principals it names. The document's key is either the SSH wire encoding of
+//! any SSH public key or a DER encoded P-384 SubjectPublicKeyInfo, e.g. of
+//! [`AttestedKey::generate`](crate::nsm::AttestedKey::generate). Certificates carry
+//! the document's `module_id` and PCR0 as key ID, which `sshd` logs.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::ssh::SshCertificateIssuer;
+//! use aws_nitro_enclaves_attestation::Verifier;
+//!
+//! # fn load_ca_key() -> aws_nitro_enclaves_attestation::ssh::PrivateKey { unimplemented!() }
+//! # let (aws_root_der, document, now) = (Vec::new(), Vec::new(), 0);
+//! let ca_key = load_ca_key();
+//! let issuer = SshCertificateIssuer::new(Verifier::new(aws_root_der), ca_key)
+//!     .with_principal("deploy");
+//! let certificate = issuer.issue(&document, now)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+
+use p384::pkcs8::DecodePublicKey;
+use rand_core::{OsRng, RngCore};
+use ssh_key::certificate::Builder;
+pub use ssh_key::certificate::CertType;
+use ssh_key::public::{EcdsaPublicKey, KeyData};
+use ssh_key::PublicKey;
+pub use ssh_key::{Certificate, PrivateKey};
+
+use crate::{NitroAdDoc, NitroAdError, Verifier};
+
+/// Validity of certificates unless [`SshCertificateIssuer::with_validity_secs`] says
+/// otherwise
+pub const DEFAULT_SSH_CERT_VALIDITY_SECS: u64 = 300;
+
+/// SSH public key in the `public_key` field of a verified document
+pub fn ssh_public_key(doc: &NitroAdDoc) -> Result<KeyData, NitroAdError> {
+    if let Some(e) = doc.verification_error() {
+        return Err(NitroAdError::VerificationError(e));
+    }
+    let key = doc.payload().public_key.as_deref().ok_or(NitroAdError::UnsupportedPublicKey)?;
+    if let Ok(key) = p384::ecdsa::VerifyingKey::from_public_key_der(key) {
+        return Ok(EcdsaPublicKey::from(key).into());
+    }
+    PublicKey::from_bytes(key)
+        .map(|key| key.key_data().clone())
+        .map_err(|_| NitroAdError::UnsupportedPublicKey)
+}
+
+/// Certificate authority signing SSH certificates for attested enclave keys
+pub struct SshCertificateIssuer {
+    verifier: Verifier,
+    ca_key: PrivateKey,
+    cert_type: CertType,
+    principals: Vec<String>,
+    extensions: Vec<(String, String)>,
+    validity_secs: u64,
+}
+
+impl SshCertificateIssuer {
+    /// Issuer of user certificates signed with `ca_key`, valid for
+    /// [`DEFAULT_SSH_CERT_VALIDITY_SECS`], for documents `verifier` accepts. Add at
+    /// least one principal before issuing.
+    pub fn new(verifier: Verifier, ca_key: PrivateKey) -> Self {
+        SshCertificateIssuer {
+            verifier,
+            ca_key,
+            cert_type: CertType::User,
+            principals: Vec::new(),
+            extensions: Vec::new(),
+            validity_secs: DEFAULT_SSH_CERT_VALIDITY_SECS,
+        }
+    }
+
+    /// Issues certificates of `cert_type`, e.g. host certificates for enclaves serving
+    /// SSH themselves
+    pub fn with_cert_type(mut self, cert_type: CertType) -> Self {
+        self.cert_type = cert_type;
+        self
+    }
+
+    /// Adds `principal`, a user or host name, to the certificates
+    pub fn with_principal(mut self, principal: impl Into<String>) -> Self {
+        self.principals.push(principal.into());
+        self
+    }
+
+    /// Adds extension `name`, e.g. `permit-pty`, with `value`, usually empty
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extensions.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_validity_secs(mut self, validity_secs: u64) -> Self {
+        self.validity_secs = validity_secs;
+        self
+    }
+
+    /// Verifies `document` at `unix_ts_sec`, including the verifier's policy, and signs
+    /// a certificate for its key, valid from `unix_ts_sec`
+    pub fn issue(&self, document: &[u8], unix_ts_sec: u64) -> Result<Certificate, NitroAdError> {
+        let doc = self.verifier.verify(document, unix_ts_sec)?;
+        let key = ssh_public_key(&doc)?;
+        let payload = doc.payload();
+        let pcr0 = payload.pcrs.get(&0).ok_or(NitroAdError::MissingPcr(0))?;
+
+        let valid_before = unix_ts_sec.saturating_add(self.validity_secs);
+        let mut builder =
+            Builder::new_with_random_nonce(&mut OsRng, key, unix_ts_sec, valid_before)
+                .map_err(NitroAdError::SshError)?;
+        builder
+            .serial(OsRng.next_u64())
+            .and_then(|b| b.cert_type(self.cert_type))
+            .and_then(|b| b.key_id(format!("{}/{}", payload.module_id, hex::encode(pcr0))))
+            .and_then(|b| b.comment(payload.module_id.as_str()))
+            .map_err(NitroAdError::SshError)?;
+        for principal in &self.principals {
+            builder.valid_principal(principal.as_str()).map_err(NitroAdError::SshError)?;
+        }
+        for (name, value) in &self.extensions {
+            builder.extension(name.as_str(), value.as_str()).map_err(NitroAdError::SshError)?;
+        }
+        builder.sign(&self.ca_key).map_err(NitroAdError::SshError)
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use ssh_key::{Algorithm, HashAlg};
+
+    use crate::nsm::{AttestationRequest, AttestedKey, Attester};
+    use crate::testing::MockNsm;
+    use crate::VerifierPolicy;
+
+    fn ca_key() -> PrivateKey {
+        PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap()
+    }
+
+    #[test]
+    fn test_issue() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let ca_key = ca_key();
+        let ca_fingerprint = ca_key.public_key().fingerprint(HashAlg::Sha256);
+        let issuer = SshCertificateIssuer::new(Verifier::new(mock.root_cert()), ca_key)
+            .with_principal("deploy")
+            .with_extension("permit-pty", "");
+
+        let enclave_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        let certificate = issuer.issue(&enclave_key.document, now)?;
+        certificate.validate_at(now + 10, [&ca_fingerprint]).unwrap();
+        let expired = now + DEFAULT_SSH_CERT_VALIDITY_SECS + 1;
+        assert!(certificate.validate_at(expired, [&ca_fingerprint]).is_err());
+        assert_eq!(certificate.cert_type(), CertType::User);
+        assert_eq!(certificate.valid_principals(), ["deploy"]);
+        assert!(certificate.extensions().contains_key("permit-pty"));
+
+        let doc = NitroAdDoc::from_bytes(&enclave_key.document, &mock.root_cert(), now)?;
+        let payload = doc.payload();
+        assert_eq!(
+            certificate.key_id(),
+            format!("{}/{}", payload.module_id, hex::encode(&payload.pcrs[&0]))
+        );
+        let p384_key = p384::ecdsa::VerifyingKey::from(enclave_key.secret_key.public_key());
+        assert_eq!(certificate.public_key(), &KeyData::from(EcdsaPublicKey::from(p384_key)));
+
+        // SSH wire encoded keys
+        let ssh_key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let document = mock.attest(AttestationRequest {
+            public_key: Some(&ssh_key.public_key().to_bytes().unwrap()),
+            ..Default::default()
+        })?;
+        let certificate = issuer.issue(&document, now)?;
+        assert_eq!(certificate.public_key(), ssh_key.public_key().key_data());
+        Ok(())
+    }
+
+    #[test]
+    fn test_issue_checks_policy() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let now = chrono::Utc::now().timestamp() as u64;
+        let verifier = Verifier::new(mock.root_cert())
+            .with_policy(VerifierPolicy::new().with_pcr(0, vec![0xab; 48]));
+        let issuer = SshCertificateIssuer::new(verifier, ca_key()).with_principal("deploy");
+        let enclave_key = AttestedKey::generate(&mock, &mut OsRng, None, None)?;
+        assert!(matches!(
+            issuer.issue(&enclave_key.document, now),
+            Err(NitroAdError::PcrMismatch(0))
+        ));
+
+        let issuer = SshCertificateIssuer::new(Verifier::new(mock.root_cert()), ca_key())
+            .with_principal("deploy");
+        let document = mock.attest(AttestationRequest {
+            public_key: Some(b"not a key"),
+            ..Default::default()
+        })?;
+        assert!(matches!(issuer.issue(&document, now), Err(NitroAdError::UnsupportedPublicKey)));
+        Ok(())
+    }
+}
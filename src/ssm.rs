@@ -0,0 +1,281 @@
+//! Expected PCRs from SSM Parameter Store
+//!
+//! Many teams publish the measurements of each enclave build to an SSM parameter, e.g.
+//! from the CI job running `nitro-cli build-enclave`. An [`SsmMeasurementSource`] reads
+//! such a parameter, an object of hex values keyed `PCR0`, `PCR1`, ... as accepted by
+//! [`VerifierPolicy::with_measurements_json`], and caches it for [`DEFAULT_TTL`], so
+//! verifiers pick up new measurements within minutes without an SSM request for every
+//! document. Pinned with [`SsmMeasurementSource::with_version`], it reads that version
+//! of the parameter only, which never changes and is requested once; new measurements
+//! then take a reviewed configuration change rather than a parameter update.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::ssm::SsmMeasurementSource;
+//! use aws_nitro_enclaves_attestation::{NitroAdError, Verifier};
+//!
+//! # async fn run(ssm: aws_sdk_ssm::Client, aws_root_der: Vec<u8>) -> Result<(), NitroAdError> {
+//! let source = SsmMeasurementSource::new(ssm, "/enclave/payments/pcrs").with_version(7);
+//! let verifier = Verifier::new(aws_root_der).with_policy(source.policy().await?);
+//! # Ok(())
+//! # }
+//! ```
+//! Failed requests are errors, even with measurements of an older version cached:
+//! verifiers don't keep accepting measurements a parameter update meant to retire.
+//! Parameters holding no PCRs are errors too, rather than policies accepting any enclave.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use aws_sdk_ssm::error::DisplayErrorContext;
+
+use crate::profile::MeasurementProfile;
+use crate::{NitroAdError, VerifierPolicy};
+
+/// Time measurements of the latest parameter version are cached for, unless
+/// [`SsmMeasurementSource::with_ttl`] says otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Expected PCRs of a version of a parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SsmMeasurements {
+    /// Parameter name, without version selector
+    pub name: String,
+    /// Parameter version the PCRs were read from
+    pub version: i64,
+    /// PCR index to expected value
+    pub pcrs: BTreeMap<u8, Vec<u8>>,
+}
+
+impl SsmMeasurements {
+    /// Policy requiring the PCRs
+    pub fn policy(&self) -> VerifierPolicy {
+        let mut policy = VerifierPolicy::new();
+        policy.pcrs = self.pcrs.clone();
+        policy
+    }
+
+    /// Profile of the PCRs named after the parameter and its version, for a
+    /// [`ProfileRegistry`](crate::profile::ProfileRegistry) of several versions
+    pub fn profile(&self) -> MeasurementProfile {
+        let mut profile = MeasurementProfile::new(self.name.as_str(), self.version.to_string());
+        profile.pcrs = self.pcrs.clone();
+        profile
+    }
+}
+
+/// Cached measurements of an SSM parameter, see the [module documentation](self)
+pub struct SsmMeasurementSource {
+    client: aws_sdk_ssm::Client,
+    name: String,
+    version: Option<i64>,
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Arc<SsmMeasurements>)>>,
+}
+
+impl SsmMeasurementSource {
+    /// Source of the latest version of the parameter `name`, decrypted if it is a
+    /// `SecureString`
+    pub fn new(client: aws_sdk_ssm::Client, name: impl Into<String>) -> Self {
+        SsmMeasurementSource {
+            client,
+            name: name.into(),
+            version: None,
+            ttl: DEFAULT_TTL,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Reads version `version` of the parameter only, whatever its latest version
+    pub fn with_version(mut self, version: i64) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Requests the latest version again once the cached measurements are `ttl` old.
+    /// Pinned versions are never requested again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Measurements of the parameter, requested if none are cached or the cached ones
+    /// expired
+    pub async fn measurements(&self) -> Result<Arc<SsmMeasurements>, NitroAdError> {
+        if let Some(measurements) = self.fresh() {
+            return Ok(measurements);
+        }
+        let measurements = Arc::new(self.request().await?);
+        *self.cached() = Some((Instant::now(), Arc::clone(&measurements)));
+        Ok(measurements)
+    }
+
+    /// [`SsmMeasurements::policy`] of [`SsmMeasurementSource::measurements`]
+    pub async fn policy(&self) -> Result<VerifierPolicy, NitroAdError> {
+        Ok(self.measurements().await?.policy())
+    }
+
+    /// Forgets the cached measurements, so the next call requests the parameter
+    pub fn invalidate(&self) {
+        *self.cached() = None;
+    }
+
+    fn fresh(&self) -> Option<Arc<SsmMeasurements>> {
+        let cached = self.cached();
+        let (requested_at, measurements) = cached.as_ref()?;
+        let fresh = self.version.is_some() || requested_at.elapsed() < self.ttl;
+        fresh.then(|| Arc::clone(measurements))
+    }
+
+    async fn request(&self) -> Result<SsmMeasurements, NitroAdError> {
+        let selector = match self.version {
+            Some(version) => format!("{}:{}", self.name, version),
+            None => self.name.clone(),
+        };
+        let output = self
+            .client
+            .get_parameter()
+            .name(&selector)
+            .with_decryption(true)
+            .send()
+            .await
+            .map_err(|e| NitroAdError::SsmError(DisplayErrorContext(e).to_string()))?;
+        let parameter = output.parameter();
+        let value = parameter
+            .and_then(|parameter| parameter.value())
+            .ok_or_else(|| NitroAdError::SsmError(format!("parameter {} has no value", selector)))?;
+        let version = parameter.map_or(0, |parameter| parameter.version());
+        if let Some(pinned) = self.version.filter(|pinned| *pinned != version) {
+            let message = format!("parameter {} is version {}, not {}", self.name, version, pinned);
+            return Err(NitroAdError::SsmError(message));
+        }
+        // a policy without PCRs would accept any enclave
+        let pcrs = VerifierPolicy::new().with_measurements_json(value)?.pcrs;
+        if pcrs.is_empty() {
+            return Err(NitroAdError::SsmError(format!("parameter {} holds no PCRs", selector)));
+        }
+        Ok(SsmMeasurements {
+            name: self.name.clone(),
+            version,
+            pcrs,
+        })
+    }
+
+    fn cached(&self) -> MutexGuard<'_, Option<(Instant, Arc<SsmMeasurements>)>> {
+        // the entry is replaced whole, so that of a panicked thread is still valid
+        self.cached.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+    use aws_sdk_ssm::config::{BehaviorVersion, Credentials, Region};
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+
+    /// Parameter Store serving `/enclave/pcrs` at `latest`, counting requests. Version 0
+    /// holds no PCRs.
+    struct MockSsm {
+        latest: Arc<AtomicI64>,
+        requests: Arc<AtomicUsize>,
+        client: aws_sdk_ssm::Client,
+    }
+
+    impl MockSsm {
+        fn new() -> Self {
+            let latest = Arc::new(AtomicI64::new(1));
+            let requests = Arc::new(AtomicUsize::new(0));
+            let (serving, counting) = (Arc::clone(&latest), Arc::clone(&requests));
+            let http_client = infallible_client_fn(move |request| {
+                counting.fetch_add(1, Ordering::SeqCst);
+                let body: serde_json::Value =
+                    serde_json::from_slice(request.body().bytes().unwrap()).unwrap();
+                let version = match body["Name"].as_str().unwrap().split_once(':') {
+                    Some((_, version)) => version.parse().unwrap(),
+                    None => serving.load(Ordering::SeqCst),
+                };
+                // each version measures PCR0 as its number repeated
+                let value = match version {
+                    0 => serde_json::json!({}),
+                    _ => serde_json::json!({ "PCR0": format!("{:02x}", version).repeat(48) }),
+                };
+                let parameter = serde_json::json!({
+                    "Name": "/enclave/pcrs",
+                    "Value": value.to_string(),
+                    "Version": version,
+                });
+                let response = serde_json::json!({ "Parameter": parameter });
+                http::Response::builder().status(200).body(response.to_string()).unwrap()
+            });
+            let config = aws_sdk_ssm::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-east-1"))
+                .credentials_provider(Credentials::new("id", "secret", None, None, "test"))
+                .http_client(http_client)
+                .build();
+            let client = aws_sdk_ssm::Client::from_conf(config);
+            MockSsm {
+                latest,
+                requests,
+                client,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_latest_version() -> Result<(), NitroAdError> {
+        let ssm = MockSsm::new();
+        let source = SsmMeasurementSource::new(ssm.client.clone(), "/enclave/pcrs");
+        let first = source.measurements().await?;
+        assert_eq!((first.name.as_str(), first.version), ("/enclave/pcrs", 1));
+        assert_eq!(first.pcrs[&0], vec![1; 48]);
+        assert_eq!(source.policy().await?, VerifierPolicy::new().with_pcr(0, vec![1; 48]));
+        assert_eq!(ssm.requests.load(Ordering::SeqCst), 1);
+
+        // the cache hides new versions until it expires
+        ssm.latest.store(2, Ordering::SeqCst);
+        assert!(Arc::ptr_eq(&source.measurements().await?, &first));
+        source.invalidate();
+        assert_eq!(source.measurements().await?.version, 2);
+        assert_eq!(ssm.requests.load(Ordering::SeqCst), 2);
+
+        let source = SsmMeasurementSource::new(ssm.client.clone(), "/enclave/pcrs")
+            .with_ttl(Duration::ZERO);
+        source.measurements().await?;
+        source.measurements().await?;
+        assert_eq!(ssm.requests.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pinned_version() -> Result<(), NitroAdError> {
+        let ssm = MockSsm::new();
+        ssm.latest.store(3, Ordering::SeqCst);
+        let source = SsmMeasurementSource::new(ssm.client.clone(), "/enclave/pcrs")
+            .with_version(2)
+            .with_ttl(Duration::ZERO);
+        let pinned = source.measurements().await?;
+        assert_eq!(pinned.version, 2);
+        assert_eq!(pinned.pcrs[&0], vec![2; 48]);
+        assert!(Arc::ptr_eq(&source.measurements().await?, &pinned));
+        assert_eq!(ssm.requests.load(Ordering::SeqCst), 1);
+
+        let profile = pinned.profile();
+        assert_eq!((profile.name.as_str(), profile.version.as_str()), ("/enclave/pcrs", "2"));
+        assert_eq!(profile.pcrs, pinned.pcrs);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parameter_without_pcrs() {
+        let ssm = MockSsm::new();
+        ssm.latest.store(0, Ordering::SeqCst);
+        let source = SsmMeasurementSource::new(ssm.client.clone(), "/enclave/pcrs");
+        assert!(matches!(
+            source.policy().await,
+            Err(NitroAdError::SsmError(e)) if e.ends_with("holds no PCRs")
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+//! proptest strategies for attestation document payloads
+//!
+//! [`valid_payload`] generates payloads passing [`NitroAdDocPayload::validate`],
+//! [`near_valid_payload`] generates payloads with exactly one field check broken.
+//! Certificates are random bytes, so generated payloads exercise field and policy
+//! logic, not certificate chain verification.
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn policy_never_panics(payload in strategies::valid_payload()) {
+//!         my_policy(&payload);
+//!     }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use proptest::collection::{btree_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use crate::{Bytes, NitroAdDocPayload};
+
+// 2020-01-01 00:00:00, the earliest accepted timestamp
+const TIMESTAMP_MIN_MS: i64 = 1_577_836_800_000;
+
+/// Random bytes of the given length range
+fn byte_buf(len: impl Into<proptest::collection::SizeRange>) -> impl Strategy<Value = Bytes<'static>> {
+    vec(any::<u8>(), len).prop_map(Bytes::from)
+}
+
+/// Enclave module id, as in `i-0123456789abcdef0-enc0123456789abcdef`
+pub fn module_id() -> impl Strategy<Value = String> {
+    "i-[0-9a-f]{17}-enc[0-9a-f]{16}"
+}
+
+/// Timestamp between 2020-01-01 and now
+pub fn timestamp() -> impl Strategy<Value = DateTime<Utc>> {
+    (TIMESTAMP_MIN_MS + 1..Utc::now().timestamp_millis())
+        .prop_map(|ms| Utc.timestamp_millis_opt(ms).unwrap())
+}
+
+/// PCR value of 32, 48 or 64 bytes
+pub fn pcr_value() -> impl Strategy<Value = Bytes<'static>> {
+    prop_oneof![byte_buf(48), byte_buf(32), byte_buf(64)]
+}
+
+/// Consecutive PCRs starting at PCR0, `count` must lie within 1..32 for a valid payload
+pub fn pcrs(count: std::ops::Range<usize>) -> impl Strategy<Value = BTreeMap<u8, Bytes<'static>>> {
+    vec(pcr_value(), count).prop_map(|values| {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, val)| (i as u8, val))
+            .collect()
+    })
+}
+
+/// Payload passing [`NitroAdDocPayload::validate`], optional fields present at random
+pub fn valid_payload() -> impl Strategy<Value = NitroAdDocPayload<'static>> {
+    (
+        module_id(),
+        timestamp(),
+        pcrs(1..32),
+        byte_buf(64..1024),
+        vec(byte_buf(64..1024), 1..5),
+        option::of(byte_buf(32..128)),
+        option::of(byte_buf(0..1024)),
+        option::of(byte_buf(0..512)),
+    )
+        .prop_map(
+            |(module_id, timestamp, pcrs, certificate, cabundle, public_key, user_data, nonce)| {
+                NitroAdDocPayload {
+                    module_id,
+                    digest: String::from("SHA384"),
+                    timestamp,
+                    pcrs,
+                    certificate,
+                    cabundle,
+                    public_key,
+                    user_data,
+                    nonce,
+                }
+            },
+        )
+}
+
+/// Payload failing [`NitroAdDocPayload::validate`] on exactly one field
+pub fn near_valid_payload() -> impl Strategy<Value = NitroAdDocPayload<'static>> {
+    let with_payload = |f: fn(&mut NitroAdDocPayload)| {
+        valid_payload().prop_map(move |mut payload| {
+            f(&mut payload);
+            payload
+        })
+    };
+
+    prop_oneof![
+        with_payload(|p| p.module_id.clear()),
+        (valid_payload(), "SHA(1|256|512)|sha384|")
+            .prop_map(|(mut p, digest)| {
+                p.digest = digest;
+                p
+            }),
+        (valid_payload(), 0..=TIMESTAMP_MIN_MS).prop_map(|(mut p, ms)| {
+            p.timestamp = Utc.timestamp_millis_opt(ms).unwrap();
+            p
+        }),
+        with_payload(|p| p.pcrs.clear()),
+        (valid_payload(), any::<prop::sample::Index>()).prop_map(|(mut p, idx)| {
+            let missing = idx.index(p.pcrs.len()) as u8;
+            // keep the map size, but leave a hole in the indexes
+            let val = p.pcrs.remove(&missing).unwrap();
+            p.pcrs.insert(p.pcrs.len() as u8 + 1, val);
+            p
+        }),
+        (valid_payload(), any::<prop::sample::Index>(), 0..32usize).prop_map(
+            |(mut p, idx, len)| {
+                let index = idx.index(p.pcrs.len()) as u8;
+                p.pcrs.insert(index, Bytes::from(vec![0u8; len]));
+                p
+            }
+        ),
+    ]
+}
+
+/// Arbitrary, mostly invalid PCR maps with indexes and lengths out of spec
+pub fn arbitrary_pcrs() -> impl Strategy<Value = BTreeMap<u8, Bytes<'static>>> {
+    btree_map(any::<u8>(), byte_buf(0..80), 0..40)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_valid_payload_validates(payload in valid_payload()) {
+            prop_assert!(payload.validate().is_ok());
+        }
+
+        #[test]
+        fn test_near_valid_payload_fails(payload in near_valid_payload()) {
+            prop_assert!(payload.validate().is_err());
+        }
+    }
+}
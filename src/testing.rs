@@ -0,0 +1,513 @@
+//! Test support: attestation documents signed with a generated certificate chain
+//!
+//! Documents are signed by the leaf of a throwaway [`TestChain`] instead of the AWS
+//! Nitro Enclaves PKI, so they verify against the generated root only. [`DocumentBuilder`] builds documents with arbitrary field values, for
+//! testing verifier policies without hardware documents. [`MockNsm`] stands in for
+//! the `/dev/nsm` device, so enclave applications can be tested outside of enclaves.
+
+use std::collections::BTreeMap;
+
+use aws_nitro_enclaves_cose::sign::HeaderMap;
+use aws_nitro_enclaves_cose::COSESign1;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use openssl::asn1::{Asn1Integer, Asn1Time, Asn1TimeRef};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{BasicConstraints, KeyUsage};
+use openssl::x509::{X509Name, X509};
+
+use crate::nsm::{AttestationRequest, Attester};
+use crate::{Bytes, NitroAdDocPayload, NitroAdError};
+
+/// Validity of generated CA certificates from their creation time
+const CA_VALIDITY_DAYS: i64 = 30 * 365;
+
+/// Validity of generated signing certificates, backdated a day so they cover documents
+/// timestamped a little before the chain was generated
+const LEAF_VALIDITY_DAYS: i64 = 30;
+
+/// Common names of the intermediates of the AWS Nitro Enclaves PKI: regional, zonal
+/// and instance CAs
+const AWS_INTERMEDIATES: [&str; 3] = [
+    "us-east-1.test.nitro-enclaves",
+    "zonal.us-east-1.test.nitro-enclaves",
+    "i-0000000000000000.us-east-1.test.nitro-enclaves",
+];
+
+/// Generated P-384 certificate chain with the signing key of its leaf, structured like
+/// the AWS Nitro Enclaves PKI: a self-signed root, intermediates with decreasing path
+/// length constraints, and a leaf certificate for signing documents
+#[derive(Clone)]
+pub struct TestChain {
+    root: X509,
+    intermediates: Vec<X509>,
+    leaf: X509,
+    leaf_key: PKey<Private>,
+}
+
+fn p384_key() -> Result<PKey<Private>, ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+    PKey::from_ec_key(EcKey::generate(&group)?)
+}
+
+fn asn1_time(time: DateTime<Utc>) -> Result<Asn1Time, ErrorStack> {
+    Asn1Time::from_unix(time.timestamp())
+}
+
+/// Certificate for `key` named `cn`, signed by `issuer` or self-signed. CA certificates
+/// get `path_len`, or no path length constraint without one.
+fn certificate(
+    cn: &str,
+    key: &PKey<Private>,
+    issuer: Option<(&X509, &PKey<Private>)>,
+    ca: Option<Option<u32>>,
+    validity: (DateTime<Utc>, DateTime<Utc>),
+) -> Result<X509, ErrorStack> {
+    let mut name = X509Name::builder()?;
+    name.append_entry_by_nid(Nid::COUNTRYNAME, "US")?;
+    name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "Test")?;
+    name.append_entry_by_nid(Nid::COMMONNAME, cn)?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = Asn1Integer::from_bn(&serial)?;
+    let (not_before, not_after) = (asn1_time(validity.0)?, asn1_time(validity.1)?);
+
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    cert.set_serial_number(&serial)?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(issuer.map_or(&*name, |(cert, _)| cert.subject_name()))?;
+    cert.set_pubkey(key)?;
+    cert.set_not_before(&not_before)?;
+    cert.set_not_after(&not_after)?;
+    match ca {
+        Some(path_len) => {
+            let mut constraints = BasicConstraints::new();
+            constraints.critical().ca();
+            if let Some(path_len) = path_len {
+                constraints.pathlen(path_len);
+            }
+            cert.append_extension(constraints.build()?)?;
+            cert.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build()?)?;
+        }
+        None => {
+            cert.append_extension(BasicConstraints::new().critical().build()?)?;
+            cert.append_extension(KeyUsage::new().critical().digital_signature().build()?)?;
+        }
+    }
+    cert.sign(issuer.map_or(key, |(_, key)| key), MessageDigest::sha384())?;
+    Ok(cert.build())
+}
+
+/// Time of an ASN.1 time of a generated certificate
+fn to_datetime(time: &Asn1TimeRef) -> DateTime<Utc> {
+    // both times are well-formed, so neither conversion fails
+    let diff = Asn1Time::from_unix(0).unwrap().diff(time).unwrap();
+    Utc.timestamp_opt(i64::from(diff.days) * 86400 + i64::from(diff.secs), 0).unwrap()
+}
+
+impl TestChain {
+    /// Chain with the regional, zonal and instance intermediates of the AWS PKI and a
+    /// leaf valid from a day ago for 30 days
+    pub fn generate() -> Result<Self, NitroAdError> {
+        let now = Utc::now();
+        let validity = (now - Duration::days(1), now + Duration::days(LEAF_VALIDITY_DAYS));
+        Self::build(&AWS_INTERMEDIATES, validity).map_err(NitroAdError::SigningError)
+    }
+
+    /// Chain with `intermediates` intermediates and a leaf valid from `not_before` to
+    /// `not_after`, e.g. in the past to test expired chains
+    pub fn generate_with(
+        intermediates: usize,
+        not_before: DateTime<Utc>,
+        not_after: DateTime<Utc>,
+    ) -> Result<Self, NitroAdError> {
+        let names: Vec<_> = (0..intermediates)
+            .map(|i| format!("intermediate{}.test.nitro-enclaves", i))
+            .collect();
+        let names: Vec<_> = names.iter().map(String::as_str).collect();
+        Self::build(&names, (not_before, not_after)).map_err(NitroAdError::SigningError)
+    }
+
+    fn build(
+        intermediate_names: &[&str],
+        leaf_validity: (DateTime<Utc>, DateTime<Utc>),
+    ) -> Result<Self, ErrorStack> {
+        let now = Utc::now();
+        // CAs cover the leaf validity, however far it lies in the past
+        let ca_validity = (
+            leaf_validity.0.min(now) - Duration::days(1),
+            now + Duration::days(CA_VALIDITY_DAYS),
+        );
+
+        let mut issuer_key = p384_key()?;
+        let root = certificate("test.nitro-enclaves", &issuer_key, None, Some(None), ca_validity)?;
+        let mut intermediates = Vec::new();
+        for (i, name) in intermediate_names.iter().enumerate() {
+            let key = p384_key()?;
+            let path_len = (intermediate_names.len() - 1 - i) as u32;
+            let issuer = intermediates.last().unwrap_or(&root);
+            let ca = Some(Some(path_len));
+            let cert = certificate(name, &key, Some((issuer, &issuer_key)), ca, ca_validity)?;
+            intermediates.push(cert);
+            issuer_key = key;
+        }
+
+        let leaf_key = p384_key()?;
+        let issuer = intermediates.last().unwrap_or(&root);
+        let leaf = certificate(
+            "enc0000000000000000.us-east-1.test.nitro-enclaves",
+            &leaf_key,
+            Some((issuer, &issuer_key)),
+            None,
+            leaf_validity,
+        )?;
+        Ok(TestChain {
+            root,
+            intermediates,
+            leaf,
+            leaf_key,
+        })
+    }
+
+    /// DER encoded root certificate, to verify documents signed by the chain against
+    pub fn root_der(&self) -> Vec<u8> {
+        // encoding a certificate that was built successfully doesn't fail
+        self.root.to_der().unwrap()
+    }
+
+    /// DER encoded intermediates, from the one the root issued to the leaf's issuer
+    pub fn intermediates_der(&self) -> Vec<Vec<u8>> {
+        self.intermediates.iter().map(|cert| cert.to_der().unwrap()).collect()
+    }
+
+    /// DER encoded leaf certificate
+    pub fn leaf_der(&self) -> Vec<u8> {
+        self.leaf.to_der().unwrap()
+    }
+
+    /// PKCS#8 DER encoded private key of the leaf
+    pub fn leaf_key_der(&self) -> Vec<u8> {
+        self.leaf_key.private_key_to_pkcs8().unwrap()
+    }
+
+    /// Private key of the leaf, for signing with OpenSSL
+    pub fn leaf_key(&self) -> &PKey<Private> {
+        &self.leaf_key
+    }
+
+    /// End of the leaf validity, documents verified later fail with a certificate
+    /// chain error
+    pub fn leaf_expiry(&self) -> DateTime<Utc> {
+        to_datetime(self.leaf.not_after())
+    }
+
+    /// `cabundle` of documents signed by the chain: the root, then the intermediates
+    fn cabundle(&self) -> Vec<Vec<u8>> {
+        let mut cabundle = vec![self.root_der()];
+        cabundle.extend(self.intermediates_der());
+        cabundle
+    }
+}
+
+/// Attestation document with arbitrary fields, signed by the leaf of a generated chain.
+/// Fields default to those of a plausible production document: 16 PCRs with non-zero
+/// PCRs 0 to 2, no optional fields, timestamped at [`build`](Self::build) time.
+///
+/// Fields are not validated, so documents violating the specification can be built,
+/// e.g. with PCRs of the wrong length.
+#[derive(Clone)]
+pub struct DocumentBuilder {
+    chain: TestChain,
+    module_id: String,
+    digest: String,
+    timestamp: Option<DateTime<Utc>>,
+    pcrs: BTreeMap<u8, Vec<u8>>,
+    public_key: Option<Vec<u8>>,
+    user_data: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+}
+
+impl DocumentBuilder {
+    /// Builder signing with a fresh chain of [`TestChain::generate`]
+    pub fn new() -> Result<Self, NitroAdError> {
+        Ok(Self::with_chain(TestChain::generate()?))
+    }
+
+    /// Builder signing with `chain`
+    pub fn with_chain(chain: TestChain) -> Self {
+        let pcrs = (0..16u8)
+            .map(|index| (index, vec![if index < 3 { index + 1 } else { 0 }; 48]))
+            .collect();
+        DocumentBuilder {
+            chain,
+            module_id: String::from("i-0000000000000000-enc0000000000000000"),
+            digest: String::from("SHA384"),
+            timestamp: None,
+            pcrs,
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        }
+    }
+
+    /// Chain signing the documents
+    pub fn chain(&self) -> &TestChain {
+        &self.chain
+    }
+
+    /// DER encoded root certificate of the chain, to verify the documents against
+    pub fn root_cert(&self) -> Vec<u8> {
+        self.chain.root_der()
+    }
+
+    /// End of the validity of the signing certificate, documents verified later fail
+    /// with a certificate chain error
+    pub fn certificate_expiry(&self) -> DateTime<Utc> {
+        self.chain.leaf_expiry()
+    }
+
+    pub fn with_module_id(mut self, module_id: &str) -> Self {
+        self.module_id = String::from(module_id);
+        self
+    }
+
+    pub fn with_digest(mut self, digest: &str) -> Self {
+        self.digest = String::from(digest);
+        self
+    }
+
+    /// Fixed document timestamp instead of the build time
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets PCR `index` to `value`
+    pub fn with_pcr(mut self, index: u8, value: impl Into<Vec<u8>>) -> Self {
+        self.pcrs.insert(index, value.into());
+        self
+    }
+
+    /// Leaves PCR `index` out
+    pub fn without_pcr(mut self, index: u8) -> Self {
+        self.pcrs.remove(&index);
+        self
+    }
+
+    /// Zeroes PCRs 0 to 2, like the NSM does for enclaves in debug mode
+    pub fn with_debug_mode(mut self) -> Self {
+        for index in 0..3 {
+            self.pcrs.insert(index, vec![0; 48]);
+        }
+        self
+    }
+
+    pub fn with_public_key(mut self, public_key: impl Into<Vec<u8>>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    pub fn with_user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Untagged COSE_Sign1 document, with the chain's leaf as `certificate` and its
+    /// root and intermediates as `cabundle`
+    pub fn build(&self) -> Result<Vec<u8>, NitroAdError> {
+        let bytes = |b: &Vec<u8>| Bytes::from(b.clone());
+        let payload = NitroAdDocPayload {
+            module_id: self.module_id.clone(),
+            digest: self.digest.clone(),
+            timestamp: self.timestamp.unwrap_or_else(Utc::now),
+            pcrs: self.pcrs.iter().map(|(index, value)| (*index, bytes(value))).collect(),
+            certificate: Bytes::from(self.chain.leaf_der()),
+            cabundle: self.chain.cabundle().into_iter().map(Bytes::from).collect(),
+            public_key: self.public_key.as_ref().map(bytes),
+            user_data: self.user_data.as_ref().map(bytes),
+            nonce: self.nonce.as_ref().map(bytes),
+        };
+
+        let ec_key = self.chain.leaf_key.ec_key().map_err(NitroAdError::SigningError)?;
+        let cose = COSESign1::new(&payload.to_canonical_cbor()?, &HeaderMap::new(), &ec_key)?;
+        Ok(cose.as_bytes(false)?)
+    }
+}
+
+/// Stand-in for the Nitro Secure Module, issuing documents signed with a generated chain.
+/// Pass [`MockNsm::root_cert`] instead of the AWS root certificate to verify them.
+pub struct MockNsm {
+    builder: DocumentBuilder,
+}
+
+impl MockNsm {
+    /// Mock with a fresh chain and the PCRs of [`DocumentBuilder::new`]
+    pub fn new() -> Result<Self, NitroAdError> {
+        Ok(MockNsm {
+            builder: DocumentBuilder::new()?,
+        })
+    }
+
+    /// Mock issuing the documents of `builder`, with the fields of the attestation
+    /// requests added
+    pub fn from_builder(builder: DocumentBuilder) -> Self {
+        MockNsm { builder }
+    }
+
+    /// Sets PCR `index` of future documents to `value`
+    pub fn with_pcr(mut self, index: u8, value: &[u8]) -> Self {
+        self.builder = self.builder.with_pcr(index, value);
+        self
+    }
+
+    /// DER encoded root certificate of the chain signing the documents
+    pub fn root_cert(&self) -> Vec<u8> {
+        self.builder.root_cert()
+    }
+
+    /// Document of `request`, verified against [`MockNsm::root_cert`] at `unix_ts_sec`
+    #[cfg(all(test, feature = "ecdh"))]
+    pub(crate) fn verified_doc(
+        &self,
+        request: AttestationRequest<'_>,
+        unix_ts_sec: u64,
+    ) -> Result<crate::NitroAdDoc<'static>, NitroAdError> {
+        let document = self.attest(request)?;
+        let doc = crate::NitroAdDoc::from_bytes(&document, &self.root_cert(), unix_ts_sec)?;
+        Ok(doc.into_owned())
+    }
+}
+
+impl Attester for MockNsm {
+    fn attest(&self, request: AttestationRequest<'_>) -> Result<Vec<u8>, NitroAdError> {
+        let mut builder = self.builder.clone();
+        builder.user_data = request.user_data.map(<[u8]>::to_vec);
+        builder.nonce = request.nonce.map(<[u8]>::to_vec);
+        builder.public_key = request.public_key.map(<[u8]>::to_vec);
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{NitroAdDoc, VerifierPolicy};
+
+    #[test]
+    fn test_document_builder() -> Result<(), NitroAdError> {
+        let builder = DocumentBuilder::new()?
+            .with_pcr(16, vec![7; 48])
+            .with_nonce(&b"challenge"[..]);
+        let now = Utc::now().timestamp() as u64;
+        let blob = builder.build()?;
+
+        let doc = NitroAdDoc::from_bytes(&blob, &builder.root_cert(), now)?;
+        assert!(doc.verification_error().is_none());
+        let payload = doc.payload();
+        assert_eq!(payload.nonce.as_deref(), Some(&b"challenge"[..]));
+        assert_eq!(payload.pcrs.len(), 17);
+        assert!(!payload.is_debug_mode());
+        let policy = VerifierPolicy::new().with_pcr(16, vec![7; 48]);
+        assert!(policy.check(&doc).is_ok());
+        let policy = VerifierPolicy::new().with_pcr(16, vec![8; 48]);
+        assert!(matches!(policy.check(&doc), Err(NitroAdError::PcrMismatch(16))));
+
+        // the real AWS root doesn't anchor the generated chain
+        let aws_root = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(&blob, aws_root, now)?;
+        assert!(doc.verification_error().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_builder_fields() -> Result<(), NitroAdError> {
+        let builder = DocumentBuilder::new()?;
+        let root_cert = builder.root_cert();
+        let now = Utc::now().timestamp() as u64;
+
+        let expiry = builder.certificate_expiry().timestamp() as u64;
+        assert!(expiry > now);
+        let blob = builder.build()?;
+        assert!(NitroAdDoc::from_bytes(&blob, &root_cert, expiry)?.verification_error().is_none());
+        let doc = NitroAdDoc::from_bytes(&blob, &root_cert, expiry + 1)?;
+        assert!(matches!(doc.verification_error(), Some(webpki::Error::CertExpired { .. })));
+
+        let ts = Utc.timestamp_opt(now as i64 - 3600, 0).unwrap();
+        let blob = builder.clone().with_timestamp(ts).with_debug_mode().build()?;
+        let doc = NitroAdDoc::from_bytes(&blob, &root_cert, now)?;
+        assert_eq!(doc.payload().timestamp, ts);
+        assert!(doc.payload().is_debug_mode());
+
+        let blob = builder.clone().with_pcr(0, vec![0; 5]).build()?;
+        assert!(matches!(
+            NitroAdDoc::from_bytes(&blob, &root_cert, now),
+            Err(NitroAdError::BadPcrLength { index: 0, len: 5 })
+        ));
+
+        let blob = builder.clone().with_digest("SHA256").build()?;
+        assert!(matches!(
+            NitroAdDoc::from_bytes(&blob, &root_cert, now),
+            Err(NitroAdError::UnsupportedDigest { .. })
+        ));
+
+        let blob = builder.without_pcr(3).build()?;
+        assert!(matches!(
+            NitroAdDoc::from_bytes(&blob, &root_cert, now),
+            Err(NitroAdError::MissingPcr(3))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chain() -> Result<(), NitroAdError> {
+        let chain = TestChain::generate()?;
+        assert_eq!(chain.intermediates_der().len(), 3);
+        let root = X509::from_der(&chain.root_der()).unwrap();
+        assert!(root.verify(&root.public_key().unwrap()).unwrap());
+        assert!(PKey::private_key_from_pkcs8(&chain.leaf_key_der()).is_ok());
+
+        // a leaf that expired a week ago under a direct root
+        let now = Utc::now();
+        let chain = TestChain::generate_with(0, now - Duration::days(30), now - Duration::days(7))?;
+        assert!(chain.intermediates_der().is_empty());
+        let builder = DocumentBuilder::with_chain(chain).with_timestamp(now - Duration::days(8));
+        let blob = builder.build()?;
+        let root_cert = builder.root_cert();
+        let verify_at =
+            |time: DateTime<Utc>| NitroAdDoc::from_bytes(&blob, &root_cert, time.timestamp() as u64);
+        assert!(verify_at(now - Duration::days(8))?.verification_error().is_none());
+        assert!(matches!(
+            verify_at(now)?.verification_error(),
+            Some(webpki::Error::CertExpired { .. })
+        ));
+        assert_eq!(builder.certificate_expiry().timestamp(), (now - Duration::days(7)).timestamp());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mock_attester() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?.with_pcr(16, &[7; 48]);
+        let blob = mock.attest(AttestationRequest {
+            user_data: Some(b"config"),
+            ..Default::default()
+        })?;
+        let doc = NitroAdDoc::from_bytes(&blob, &mock.root_cert(), Utc::now().timestamp() as u64)?;
+        assert!(doc.verification_error().is_none());
+        assert_eq!(doc.payload().user_data.as_deref(), Some(&b"config"[..]));
+        assert_eq!(doc.payload().pcrs[&16].as_ref(), &[7; 48][..]);
+        Ok(())
+    }
+}
@@ -0,0 +1,571 @@
+//! Attested TLS with rustls
+//!
+//! [`AttestedCertificate::generate`] issues a self-signed P-384 certificate carrying a
+//! fresh attestation document in the [`ATTESTATION_EXTENSION_OID`] extension of the
+//! [`x509`](crate::x509) module. The document's `user_data` is the SHA-384 hash of the
+//! certificate's DER encoded SubjectPublicKeyInfo, binding the document to the key
+//! terminating TLS; a document copied into another certificate doesn't match that
+//! certificate's key.
+//!
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::nsm::Nsm;
+//! use aws_nitro_enclaves_attestation::tls::server_config;
+//!
+//! let config = server_config(&Nsm::open()?, &["enclave.example.com"])?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//!
+//! Clients trust such servers by their documents rather than a CA: [`client_config`]
+//! accepts a certificate only if its document verifies with a [`Verifier`], including
+//! its PCR policy, and binds the certificate's key.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::tls::client_config;
+//! use aws_nitro_enclaves_attestation::{Verifier, VerifierPolicy};
+//!
+//! # let aws_root_der = Vec::new();
+//! # let pcr0 = Vec::new();
+//! let policy = VerifierPolicy::new().with_pcr(0, pcr0);
+//! let config = client_config(Verifier::new(aws_root_der).with_policy(policy))?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//!
+//! Between two enclaves, [`mutual_server_config`] and [`mutual_client_config`] have
+//! both sides present an attested certificate and verify the other's in a single
+//! handshake, see [`AttestedClientVerifier`].
+//!
+//! Documents exchanged over an established connection, rather than in its certificates,
+//! are bound to that connection by their `nonce`: [`attest_channel`] requests one with
+//! the [`exporter_binding`] of the connection and [`verify_channel`] checks it against
+//! its own end's. Both ends derive the same value only if they share one TLS session, so
+//! a document relayed from another connection is rejected. [`certificate_binding`] is
+//! the alternative for applications which can only see the server's certificate.
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509Extension, X509Name, X509};
+use pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{
+    CertificateError, ClientConfig, ConnectionCommon, DigitallySignedStruct, DistinguishedName,
+    OtherError, ServerConfig, SignatureScheme,
+};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::crypto::sha384;
+use crate::nsm::{AttestationRequest, Attester};
+pub use crate::x509::ATTESTATION_EXTENSION_OID;
+use crate::x509::{key_binding, verify_certificate, x509_extension};
+use crate::{NitroAdDoc, NitroAdError, Verifier};
+
+/// Exporter label of the `tls-exporter` channel binding of RFC 9266, which also fixes
+/// its length of 32 bytes and the absent context
+pub static CHANNEL_BINDING_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+
+/// Validity of attested certificates. Verifiers judge the freshness of the document
+/// by its timestamp, the certificate validity only bounds the lifetime of the key.
+const CERTIFICATE_VALIDITY_DAYS: i64 = 365;
+
+/// Self-signed certificate with an embedded attestation document, and its private key
+pub struct AttestedCertificate {
+    /// DER encoded certificate
+    pub certificate: Vec<u8>,
+    /// PKCS#8 DER encoded P-384 private key
+    pub private_key: Zeroizing<Vec<u8>>,
+    /// Attestation document of the `ATTESTATION_EXTENSION_OID` extension
+    pub document: Vec<u8>,
+}
+
+impl AttestedCertificate {
+    /// Generates a key pair, requests a document from `attester` binding its public key,
+    /// and issues a certificate for `dns_names` carrying the document
+    pub fn generate<A: Attester + ?Sized>(
+        attester: &A,
+        dns_names: &[&str],
+    ) -> Result<Self, NitroAdError> {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).map_err(NitroAdError::SigningError)?;
+        let key = EcKey::generate(&group)
+            .and_then(PKey::from_ec_key)
+            .map_err(NitroAdError::SigningError)?;
+        let spki = key.public_key_to_der().map_err(NitroAdError::SigningError)?;
+
+        let binding = key_binding(&spki);
+        let document = attester.attest(AttestationRequest {
+            user_data: Some(&binding),
+            ..Default::default()
+        })?;
+
+        let extension = x509_extension(&document)?;
+        let certificate =
+            certificate(&key, dns_names, extension).map_err(NitroAdError::SigningError)?;
+        let private_key = key.private_key_to_pkcs8().map_err(NitroAdError::SigningError)?;
+        Ok(AttestedCertificate {
+            certificate,
+            private_key: Zeroizing::new(private_key),
+            document,
+        })
+    }
+
+    /// Certificate chain and key in the form rustls takes them
+    pub fn to_rustls(&self) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+        let key = PrivatePkcs8KeyDer::from(self.private_key.to_vec());
+        (vec![CertificateDer::from(self.certificate.clone())], key.into())
+    }
+}
+
+/// Self-signed certificate for `key` with the document `extension`
+fn certificate(
+    key: &PKey<Private>,
+    dns_names: &[&str],
+    extension: X509Extension,
+) -> Result<Vec<u8>, ErrorStack> {
+    let cn = dns_names.first().copied().unwrap_or("nitro-enclave");
+    let mut name = X509Name::builder()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, cn)?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = Asn1Integer::from_bn(&serial)?;
+    let now = Utc::now();
+    let not_before = Asn1Time::from_unix((now - Duration::minutes(5)).timestamp())?;
+    let not_after =
+        Asn1Time::from_unix((now + Duration::days(CERTIFICATE_VALIDITY_DAYS)).timestamp())?;
+
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    cert.set_serial_number(&serial)?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(&name)?;
+    cert.set_pubkey(key)?;
+    cert.set_not_before(&not_before)?;
+    cert.set_not_after(&not_after)?;
+    if !dns_names.is_empty() {
+        let mut san = SubjectAlternativeName::new();
+        for dns_name in dns_names {
+            san.dns(dns_name);
+        }
+        cert.append_extension(san.build(&cert.x509v3_context(None, None))?)?;
+    }
+    cert.append_extension(extension)?;
+    cert.sign(key, MessageDigest::sha384())?;
+    cert.build().to_der()
+}
+
+/// rustls error of a rejected attested certificate, keeping `e` as the cause
+fn certificate_error(e: NitroAdError) -> rustls::Error {
+    rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(Arc::new(e))))
+}
+
+/// rustls server certificate verifier accepting [`AttestedCertificate`]s whose document
+/// verifies
+///
+/// The server name isn't checked: an enclave is identified by its PCRs, which the
+/// [`Verifier`]'s policy pins, not by a name. Neither are the certificate's own
+/// signature and validity, which the enclave chose itself; the handshake signature
+/// proves possession of the key the document vouches for.
+#[derive(Debug)]
+pub struct AttestedServerVerifier {
+    verifier: Verifier,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl AttestedServerVerifier {
+    /// Verifier accepting servers whose documents pass `verifier`
+    pub fn new(verifier: Verifier) -> Self {
+        AttestedServerVerifier {
+            verifier,
+            algorithms: provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for AttestedServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        verify_certificate(&self.verifier, end_entity, now.as_secs())
+            .map_err(certificate_error)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// rustls client certificate verifier requiring [`AttestedCertificate`]s whose document
+/// verifies, for mutual attestation
+///
+/// Like [`AttestedServerVerifier`], it doesn't check the certificate's own signature
+/// and validity. No CA names are hinted to clients, which present their certificate
+/// unasked.
+#[derive(Debug)]
+pub struct AttestedClientVerifier {
+    verifier: Verifier,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl AttestedClientVerifier {
+    /// Verifier requiring clients whose documents pass `verifier`
+    pub fn new(verifier: Verifier) -> Self {
+        AttestedClientVerifier {
+            verifier,
+            algorithms: provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ClientCertVerifier for AttestedClientVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        verify_certificate(&self.verifier, end_entity, now.as_secs())
+            .map_err(certificate_error)?;
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// The ring provider all configurations use
+fn provider() -> rustls::crypto::CryptoProvider {
+    rustls::crypto::ring::default_provider()
+}
+
+/// rustls configuration of the ring provider with its safe default protocol versions
+fn config_builder(
+) -> Result<rustls::ConfigBuilder<ServerConfig, rustls::WantsVerifier>, NitroAdError> {
+    ServerConfig::builder_with_provider(Arc::new(provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(NitroAdError::TlsError)
+}
+
+/// Server configuration presenting a fresh [`AttestedCertificate`] for `dns_names`,
+/// without client authentication. ALPN protocols and other settings can be added to
+/// the returned configuration.
+pub fn server_config<A: Attester + ?Sized>(
+    attester: &A,
+    dns_names: &[&str],
+) -> Result<ServerConfig, NitroAdError> {
+    let (chain, key) = AttestedCertificate::generate(attester, dns_names)?.to_rustls();
+    config_builder()?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(NitroAdError::TlsError)
+}
+
+/// Server configuration presenting a fresh [`AttestedCertificate`] for `dns_names` and
+/// requiring clients to present one whose document passes `verifier`
+pub fn mutual_server_config<A: Attester + ?Sized>(
+    attester: &A,
+    dns_names: &[&str],
+    verifier: Verifier,
+) -> Result<ServerConfig, NitroAdError> {
+    let (chain, key) = AttestedCertificate::generate(attester, dns_names)?.to_rustls();
+    config_builder()?
+        .with_client_cert_verifier(Arc::new(AttestedClientVerifier::new(verifier)))
+        .with_single_cert(chain, key)
+        .map_err(NitroAdError::TlsError)
+}
+
+/// rustls client configuration of the ring provider verifying servers with
+/// [`AttestedServerVerifier`]
+fn client_config_builder(
+    verifier: Verifier,
+) -> Result<rustls::ConfigBuilder<ClientConfig, rustls::client::WantsClientCert>, NitroAdError> {
+    Ok(ClientConfig::builder_with_provider(Arc::new(provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(NitroAdError::TlsError)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AttestedServerVerifier::new(verifier))))
+}
+
+/// Client configuration accepting only servers presenting an [`AttestedCertificate`]
+/// whose document passes `verifier`, see [`AttestedServerVerifier`]
+pub fn client_config(verifier: Verifier) -> Result<ClientConfig, NitroAdError> {
+    Ok(client_config_builder(verifier)?.with_no_client_auth())
+}
+
+/// Client configuration of [`client_config`] which also presents a fresh
+/// [`AttestedCertificate`] to servers of [`mutual_server_config`]
+pub fn mutual_client_config<A: Attester + ?Sized>(
+    attester: &A,
+    verifier: Verifier,
+) -> Result<ClientConfig, NitroAdError> {
+    let (chain, key) = AttestedCertificate::generate(attester, &[])?.to_rustls();
+    client_config_builder(verifier)?
+        .with_client_auth_cert(chain, key)
+        .map_err(NitroAdError::TlsError)
+}
+
+/// `tls-exporter` channel binding of the connection, equal at both of its ends. Fails
+/// with [`NitroAdError::TlsError`] until the handshake completes.
+pub fn exporter_binding<Data>(conn: &ConnectionCommon<Data>) -> Result<[u8; 32], NitroAdError> {
+    conn.export_keying_material([0; 32], CHANNEL_BINDING_LABEL, None)
+        .map_err(NitroAdError::TlsError)
+}
+
+/// Channel binding of a connection by the SHA-384 hash of the DER encoded certificate
+/// the server presented, e.g. the first of a client's `peer_certificates()`. Weaker
+/// than [`exporter_binding`]: a server relaying documents under its own certificate
+/// binds them to all of its connections.
+pub fn certificate_binding(cert_der: &[u8]) -> [u8; 48] {
+    sha384(cert_der)
+}
+
+/// Checks that the `nonce` of `doc` is `binding`, failing with
+/// [`NitroAdError::NonceMismatch`] otherwise
+pub fn check_binding(doc: &NitroAdDoc, binding: &[u8]) -> Result<(), NitroAdError> {
+    match doc.payload().nonce.as_deref() {
+        Some(nonce) if bool::from(nonce.ct_eq(binding)) => Ok(()),
+        _ => Err(NitroAdError::NonceMismatch),
+    }
+}
+
+/// Document from `attester` whose `nonce` is the [`exporter_binding`] of `conn`, to send
+/// over it
+pub fn attest_channel<A: Attester + ?Sized, Data>(
+    attester: &A,
+    conn: &ConnectionCommon<Data>,
+) -> Result<Vec<u8>, NitroAdError> {
+    let binding = exporter_binding(conn)?;
+    attester.attest(AttestationRequest {
+        nonce: Some(&binding),
+        ..Default::default()
+    })
+}
+
+/// Verifies `document`, received over `conn`, with `verifier` at `unix_ts_sec` and
+/// checks that it was attested for `conn` by [`attest_channel`]
+pub fn verify_channel<'a, Data>(
+    verifier: &Verifier,
+    conn: &ConnectionCommon<Data>,
+    document: &'a [u8],
+    unix_ts_sec: u64,
+) -> Result<NitroAdDoc<'a>, NitroAdError> {
+    let binding = exporter_binding(conn)?;
+    let doc = verifier.verify(document, unix_ts_sec)?;
+    check_binding(&doc, &binding)?;
+    Ok(doc)
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use rustls::{ClientConnection, ServerConnection};
+
+    use crate::testing::{DocumentBuilder, MockNsm};
+    use crate::{NitroAdDoc, VerifierPolicy};
+
+    #[test]
+    fn test_attested_certificate() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let cert = AttestedCertificate::generate(&mock, &["enclave.example.com"])?;
+
+        let x509 = X509::from_der(&cert.certificate).unwrap();
+        let spki = x509.public_key().unwrap().public_key_to_der().unwrap();
+        let now = Utc::now().timestamp() as u64;
+        let doc = NitroAdDoc::from_bytes(&cert.document, &mock.root_cert(), now)?;
+        assert!(doc.verification_error().is_none());
+        assert_eq!(doc.payload().user_data.as_deref(), Some(&key_binding(&spki)[..]));
+
+        // the document is the value of the extension, raw in an OCTET STRING
+        let der = &cert.certificate;
+        assert!(der.windows(cert.document.len()).any(|w| w == &cert.document[..]));
+
+        assert!(server_config(&mock, &["enclave.example.com"]).is_ok());
+        Ok(())
+    }
+
+    /// Drives the handshake of `client` and `server` to completion in memory
+    fn handshake(
+        client: &mut ClientConnection,
+        server: &mut ServerConnection,
+    ) -> Result<(), rustls::Error> {
+        let mut buf = Vec::new();
+        while client.is_handshaking() || server.is_handshaking() {
+            buf.clear();
+            client.write_tls(&mut buf).unwrap();
+            let mut records = &buf[..];
+            while !records.is_empty() {
+                server.read_tls(&mut records).unwrap();
+                server.process_new_packets()?;
+            }
+            buf.clear();
+            server.write_tls(&mut buf).unwrap();
+            let mut records = &buf[..];
+            while !records.is_empty() {
+                client.read_tls(&mut records).unwrap();
+                client.process_new_packets()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Both ends of a connection of `client` to `server`
+    fn connection(
+        server: &Arc<ServerConfig>,
+        client: ClientConfig,
+    ) -> Result<(ClientConnection, ServerConnection), rustls::Error> {
+        let name = ServerName::try_from("enclave.example.com").unwrap();
+        let mut client = ClientConnection::new(Arc::new(client), name)?;
+        let mut server = ServerConnection::new(server.clone())?;
+        handshake(&mut client, &mut server)?;
+        Ok((client, server))
+    }
+
+    fn connect(server: &Arc<ServerConfig>, client: ClientConfig) -> Result<(), rustls::Error> {
+        connection(server, client).map(|_| ())
+    }
+
+    #[test]
+    fn test_attested_server_verifier() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?.with_pcr(0, &[1; 48]);
+        let server = Arc::new(server_config(&mock, &["enclave.example.com"])?);
+
+        let verifier = Verifier::new(mock.root_cert());
+        connect(&server, client_config(verifier)?).map_err(NitroAdError::TlsError)?;
+
+        let policy = VerifierPolicy::new().with_pcr(0, vec![1; 48]);
+        let verifier = Verifier::new(mock.root_cert()).with_policy(policy);
+        connect(&server, client_config(verifier)?).map_err(NitroAdError::TlsError)?;
+
+        let policy = VerifierPolicy::new().with_pcr(0, vec![2; 48]);
+        let verifier = Verifier::new(mock.root_cert()).with_policy(policy);
+        assert!(matches!(
+            connect(&server, client_config(verifier)?),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(_)))
+        ));
+
+        let other_root = MockNsm::new()?.root_cert();
+        assert!(connect(&server, client_config(Verifier::new(other_root))?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mutual_attestation() -> Result<(), NitroAdError> {
+        let server_nsm = MockNsm::new()?;
+        let builder = DocumentBuilder::new()?;
+        let client_nsm = MockNsm::from_builder(builder.clone().with_pcr(0, vec![1; 48]));
+        let server_verifier = || Verifier::new(server_nsm.root_cert());
+
+        let policy = VerifierPolicy::new().with_pcr(0, vec![1; 48]);
+        let verifier = Verifier::new(client_nsm.root_cert()).with_policy(policy);
+        let server = Arc::new(mutual_server_config(
+            &server_nsm,
+            &["enclave.example.com"],
+            verifier,
+        )?);
+        let client = mutual_client_config(&client_nsm, server_verifier())?;
+        connect(&server, client).map_err(NitroAdError::TlsError)?;
+
+        // clients without an attested certificate, or with other PCRs, are rejected
+        assert!(connect(&server, client_config(server_verifier())?).is_err());
+        let other = MockNsm::from_builder(builder.with_pcr(0, vec![2; 48]));
+        let client = mutual_client_config(&other, server_verifier())?;
+        assert!(connect(&server, client).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_binding() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let server = Arc::new(server_config(&mock, &["enclave.example.com"])?);
+        let verifier = Verifier::new(mock.root_cert());
+        let now = Utc::now().timestamp() as u64;
+        let config = || client_config(Verifier::new(mock.root_cert()));
+
+        let (client, server_end) = connection(&server, config()?).map_err(NitroAdError::TlsError)?;
+        assert_eq!(exporter_binding(&client)?, exporter_binding(&server_end)?);
+        let document = attest_channel(&mock, &server_end)?;
+        verify_channel(&verifier, &client, &document, now)?;
+
+        // relayed to another connection
+        let (other, _) = connection(&server, config()?).map_err(NitroAdError::TlsError)?;
+        assert!(matches!(
+            verify_channel(&verifier, &other, &document, now),
+            Err(NitroAdError::NonceMismatch)
+        ));
+
+        let server_cert = &client.peer_certificates().unwrap()[0];
+        let binding = certificate_binding(server_cert);
+        let document = mock.attest(AttestationRequest {
+            nonce: Some(&binding),
+            ..Default::default()
+        })?;
+        let doc = verifier.verify(&document, now)?;
+        check_binding(&doc, &binding)?;
+        assert!(check_binding(&doc, &exporter_binding(&client)?).is_err());
+
+        let name = ServerName::try_from("enclave.example.com").unwrap();
+        let unconnected =
+            ClientConnection::new(Arc::new(config()?), name).map_err(NitroAdError::TlsError)?;
+        assert!(matches!(exporter_binding(&unconnected), Err(NitroAdError::TlsError(_))));
+        Ok(())
+    }
+}
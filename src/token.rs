@@ -0,0 +1,389 @@
+//! Compact attestation result tokens
+//!
+//! After a document verified, [`AttestationToken`] carries its outcome as an ES384
+//! signed JWT (RFC 7519) or, for CBOR-native consumers, as a COSE_Sign1 signed CWT
+//! (RFC 8392), so downstream services trusting the verifier's key don't need to
+//! re-verify the raw document.
+
+use std::collections::BTreeMap;
+
+use aws_nitro_enclaves_cose::sign::HeaderMap;
+use aws_nitro_enclaves_cose::COSESign1;
+use openssl::ec::EcKeyRef;
+use openssl::pkey::{HasPublic, Private, Public};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value as CborValue;
+
+use crate::es384::{self, check_curve};
+use crate::{CoseSign1Raw, NitroAdDoc, NitroAdError, COSE_ES384_SIGNATURE_LEN};
+
+static JWT_HEADER: &str = r#"{"alg":"ES384","typ":"JWT"}"#;
+
+// registered CWT claim keys, see RFC 8392 section 3.1
+const CWT_ISS: i128 = 1;
+const CWT_SUB: i128 = 2;
+const CWT_EXP: i128 = 4;
+const CWT_IAT: i128 = 6;
+
+/// Claims of an [`AttestationToken`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// `module_id` of the attested enclave
+    pub sub: String,
+    /// Token issue time, seconds since the unix epoch
+    pub iat: u64,
+    /// Token expiry, seconds since the unix epoch
+    pub exp: u64,
+    /// PCR index to hex encoded value
+    pub pcrs: BTreeMap<u8, String>,
+    /// Enclave runs in debug mode, its PCRs are zeroed and attest nothing
+    pub debug: bool,
+    /// base64 encoded document nonce
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// Verification result of an attestation document as signed JWT claims
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationToken {
+    pub claims: AttestationClaims,
+}
+
+impl AttestationToken {
+    /// Claims of a document valid for `lifetime_sec` seconds from `now_sec`.
+    /// Fails for documents whose certificate chain didn't verify.
+    pub fn from_doc(
+        doc: &NitroAdDoc,
+        issuer: Option<&str>,
+        now_sec: u64,
+        lifetime_sec: u64,
+    ) -> Result<Self, NitroAdError> {
+        if let Some(e) = doc.verification_error() {
+            return Err(NitroAdError::VerificationError(e));
+        }
+
+        let payload = doc.payload();
+        Ok(AttestationToken {
+            claims: AttestationClaims {
+                iss: issuer.map(String::from),
+                sub: payload.module_id.clone(),
+                iat: now_sec,
+                exp: now_sec.saturating_add(lifetime_sec),
+                pcrs: payload
+                    .pcrs
+                    .iter()
+                    .map(|(i, val)| (*i, hex::encode(val)))
+                    .collect(),
+                debug: payload.is_debug_mode(),
+                nonce: payload.nonce.as_ref().map(base64::encode),
+            },
+        })
+    }
+
+    /// Compact serialized JWT signed with the P-384 `key`
+    pub fn sign(&self, key: &EcKeyRef<Private>) -> Result<String, NitroAdError> {
+        let mut token = format!(
+            "{}.{}",
+            b64url(JWT_HEADER.as_bytes()),
+            b64url(&serde_json::to_vec(&self.claims)?)
+        );
+
+        let raw_sig = es384::sign(token.as_bytes(), key)?;
+        token.push('.');
+        token.push_str(&b64url(&raw_sig));
+        Ok(token)
+    }
+
+    /// Checks the signature of a compact serialized JWT against the P-384 `key`
+    /// and that it isn't expired at `now_sec`
+    pub fn verify<T: HasPublic>(
+        token: &str,
+        key: &EcKeyRef<T>,
+        now_sec: u64,
+    ) -> Result<Self, NitroAdError> {
+        check_curve(key)?;
+
+        let mut parts = token.split('.');
+        let (header, claims, sig) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(claims), Some(sig), None) => (header, claims, sig),
+            _ => return Err(NitroAdError::MalformedToken("expected 3 dot separated parts")),
+        };
+
+        let header: serde_json::Value = serde_json::from_slice(&b64url_decode(header)?)
+            .map_err(|_| NitroAdError::MalformedToken("header is not JSON"))?;
+        if header["alg"] != "ES384" {
+            return Err(NitroAdError::MalformedToken("alg must be ES384"));
+        }
+
+        // header and claims exactly as signed, split() above guarantees the dots
+        let signed = &token[..token.rfind('.').unwrap()];
+        if !es384::verify(signed.as_bytes(), &b64url_decode(sig)?, key)? {
+            return Err(NitroAdError::InvalidTokenSignature);
+        }
+
+        let claims: AttestationClaims = serde_json::from_slice(&b64url_decode(claims)?)
+            .map_err(|_| NitroAdError::MalformedToken("claims don't match AttestationClaims"))?;
+        if claims.exp <= now_sec {
+            return Err(NitroAdError::TokenExpired { exp: claims.exp });
+        }
+
+        Ok(AttestationToken { claims })
+    }
+}
+
+impl AttestationToken {
+    /// COSE_Sign1 (untagged) CWT signed with the P-384 `key`. The registered `iss`,
+    /// `sub`, `exp` and `iat` claims use their integer keys, the others their JWT
+    /// names; PCR values and the nonce are byte strings.
+    pub fn sign_cwt(&self, key: &EcKeyRef<Private>) -> Result<Vec<u8>, NitroAdError> {
+        check_curve(key)?;
+
+        let claims = self.claims.to_cwt_claims()?;
+        let payload = serde_cbor::to_vec(&claims)?;
+        Ok(COSESign1::new(&payload, &HeaderMap::new(), key)?.as_bytes(false)?)
+    }
+
+    /// Checks the signature of a CWT against the P-384 `key` and that it isn't
+    /// expired at `now_sec`
+    pub fn verify_cwt(cwt: &[u8], key: &EcKeyRef<Public>, now_sec: u64) -> Result<Self, NitroAdError> {
+        check_curve(key)?;
+
+        let (_, _, _, signature): CoseSign1Raw = serde_cbor::from_slice(cwt)
+            .map_err(|_| NitroAdError::MalformedToken("CWT is not a COSE_Sign1 structure"))?;
+        // aws_cose splits the signature at the factor length without checking it
+        if signature.len() != COSE_ES384_SIGNATURE_LEN {
+            return Err(NitroAdError::InvalidTokenSignature);
+        }
+
+        let cose = COSESign1::from_bytes(cwt)
+            .map_err(|_| NitroAdError::MalformedToken("CWT is not a COSE_Sign1 structure"))?;
+        if !cose.verify_signature(key).unwrap_or(false) {
+            return Err(NitroAdError::InvalidTokenSignature);
+        }
+
+        let claims: CborValue = serde_cbor::from_slice(&cose.get_payload(None)?)
+            .map_err(|_| NitroAdError::MalformedToken("CWT claims are not CBOR"))?;
+        let claims = AttestationClaims::from_cwt_claims(claims)?;
+        if claims.exp <= now_sec {
+            return Err(NitroAdError::TokenExpired { exp: claims.exp });
+        }
+
+        Ok(AttestationToken { claims })
+    }
+}
+
+impl AttestationClaims {
+    fn to_cwt_claims(&self) -> Result<CborValue, NitroAdError> {
+        let text = |s: &str| CborValue::Text(String::from(s));
+
+        let mut pcrs = BTreeMap::new();
+        for (i, val) in &self.pcrs {
+            // claims built by from_doc() always hold hex
+            let val = hex::decode(val).map_err(|_| NitroAdError::MalformedToken("PCR is not hex"))?;
+            pcrs.insert(CborValue::Integer(*i as i128), CborValue::Bytes(val));
+        }
+
+        let mut map = BTreeMap::new();
+        if let Some(iss) = &self.iss {
+            map.insert(CborValue::Integer(CWT_ISS), text(iss));
+        }
+        map.insert(CborValue::Integer(CWT_SUB), text(&self.sub));
+        map.insert(CborValue::Integer(CWT_EXP), CborValue::Integer(self.exp as i128));
+        map.insert(CborValue::Integer(CWT_IAT), CborValue::Integer(self.iat as i128));
+        map.insert(text("pcrs"), CborValue::Map(pcrs));
+        map.insert(text("debug"), CborValue::Bool(self.debug));
+        if let Some(nonce) = &self.nonce {
+            let nonce = base64::decode(nonce)
+                .map_err(|_| NitroAdError::MalformedToken("nonce is not base64"))?;
+            map.insert(text("nonce"), CborValue::Bytes(nonce));
+        }
+
+        Ok(CborValue::Map(map))
+    }
+
+    fn from_cwt_claims(claims: CborValue) -> Result<Self, NitroAdError> {
+        let malformed = NitroAdError::MalformedToken;
+
+        let mut map = match claims {
+            CborValue::Map(map) => map,
+            _ => return Err(malformed("CWT claims are not a map")),
+        };
+        let mut take = |key: CborValue| map.remove(&key);
+        let time = |val: Option<CborValue>| match val {
+            Some(CborValue::Integer(t)) if t >= 0 && t <= u64::MAX as i128 => Ok(t as u64),
+            _ => Err(malformed("exp and iat must be unsigned integers")),
+        };
+
+        let iss = match take(CborValue::Integer(CWT_ISS)) {
+            None => None,
+            Some(CborValue::Text(iss)) => Some(iss),
+            Some(_) => return Err(malformed("iss must be text")),
+        };
+        let sub = match take(CborValue::Integer(CWT_SUB)) {
+            Some(CborValue::Text(sub)) => sub,
+            _ => return Err(malformed("sub must be text")),
+        };
+        let exp = time(take(CborValue::Integer(CWT_EXP)))?;
+        let iat = time(take(CborValue::Integer(CWT_IAT)))?;
+
+        let pcrs = match take(CborValue::Text(String::from("pcrs"))) {
+            Some(CborValue::Map(pcrs)) => pcrs
+                .into_iter()
+                .map(|(i, val)| match (i, val) {
+                    (CborValue::Integer(i), CborValue::Bytes(val)) if (0..=255).contains(&i) => {
+                        Ok((i as u8, hex::encode(val)))
+                    }
+                    _ => Err(malformed("pcrs must map PCR indexes to byte strings")),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(malformed("pcrs must be a map")),
+        };
+        let debug = match take(CborValue::Text(String::from("debug"))) {
+            Some(CborValue::Bool(debug)) => debug,
+            _ => return Err(malformed("debug must be a bool")),
+        };
+        let nonce = match take(CborValue::Text(String::from("nonce"))) {
+            None => None,
+            Some(CborValue::Bytes(nonce)) => Some(base64::encode(nonce)),
+            Some(_) => return Err(malformed("nonce must be a byte string")),
+        };
+
+        Ok(AttestationClaims {
+            iss,
+            sub,
+            iat,
+            exp,
+            pcrs,
+            debug,
+            nonce,
+        })
+    }
+}
+
+fn b64url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn b64url_decode(part: &str) -> Result<Vec<u8>, NitroAdError> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| NitroAdError::MalformedToken("part is not base64url"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+
+    fn test_doc() -> NitroAdDoc<'static> {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap()
+    }
+
+    fn p384_key() -> EcKey<Private> {
+        EcKey::generate(&EcGroup::from_curve_name(Nid::SECP384R1).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_token_roundtrip() -> Result<(), NitroAdError> {
+        let key = p384_key();
+        let token = AttestationToken::from_doc(&test_doc(), Some("verifier"), 1614967200, 300)?;
+        let jwt = token.sign(&key)?;
+
+        let verified = AttestationToken::verify(&jwt, &key, 1614967200 + 299)?;
+        assert_eq!(verified, token);
+        assert_eq!(verified.claims.exp, 1614967500);
+        assert!(verified.claims.debug);
+        assert!(verified.claims.sub.starts_with("i-"));
+
+        assert!(matches!(
+            AttestationToken::verify(&jwt, &key, 1614967500),
+            Err(NitroAdError::TokenExpired { exp: 1614967500 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_rejects_tampering() -> Result<(), NitroAdError> {
+        let key = p384_key();
+        let jwt = AttestationToken::from_doc(&test_doc(), None, 1614967200, 300)?.sign(&key)?;
+
+        let other_key = p384_key();
+        assert!(matches!(
+            AttestationToken::verify(&jwt, &other_key, 1614967200),
+            Err(NitroAdError::InvalidTokenSignature)
+        ));
+
+        let mut parts: Vec<_> = jwt.split('.').map(String::from).collect();
+        let mut claims: serde_json::Value =
+            serde_json::from_slice(&b64url_decode(&parts[1])?).unwrap();
+        claims["debug"] = serde_json::Value::Bool(false);
+        parts[1] = b64url(&serde_json::to_vec(&claims)?);
+        assert!(matches!(
+            AttestationToken::verify(&parts.join("."), &key, 1614967200),
+            Err(NitroAdError::InvalidTokenSignature)
+        ));
+
+        assert!(matches!(
+            AttestationToken::verify("abc.def", &key, 1614967200),
+            Err(NitroAdError::MalformedToken(_))
+        ));
+
+        let p256 = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap();
+        assert!(matches!(
+            AttestationToken::from_doc(&test_doc(), None, 1614967200, 300)?.sign(&p256),
+            Err(NitroAdError::UnsupportedSigningKey)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cwt_roundtrip() -> Result<(), NitroAdError> {
+        let key = p384_key();
+        let public = EcKey::from_public_key(key.group(), key.public_key()).unwrap();
+
+        let mut token = AttestationToken::from_doc(&test_doc(), Some("verifier"), 1614967200, 300)?;
+        token.claims.nonce = Some(base64::encode(b"nonce"));
+        let cwt = token.sign_cwt(&key)?;
+
+        let verified = AttestationToken::verify_cwt(&cwt, &public, 1614967200)?;
+        assert_eq!(verified, token);
+
+        assert!(matches!(
+            AttestationToken::verify_cwt(&cwt, &public, 1614967500),
+            Err(NitroAdError::TokenExpired { .. })
+        ));
+
+        let other = p384_key();
+        let other = EcKey::from_public_key(other.group(), other.public_key()).unwrap();
+        assert!(matches!(
+            AttestationToken::verify_cwt(&cwt, &other, 1614967200),
+            Err(NitroAdError::InvalidTokenSignature)
+        ));
+
+        // the attestation document itself is no CWT
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        assert!(AttestationToken::verify_cwt(ad_blob, &public, 1614967200).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_requires_verified_chain() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        // long after the signing certificate expired
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1714967200).unwrap();
+
+        assert!(matches!(
+            AttestationToken::from_doc(&doc, None, 1714967200, 300),
+            Err(NitroAdError::VerificationError(webpki::Error::CertExpired { .. }))
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+//! Reusable document verifier
+//!
+//! [`NitroAdDoc::from_bytes`] parses the root certificate on every call. A
+//! [`Verifier`] parses it once, on first use, and is `Send + Sync`, so a single
+//! instance can be shared by the worker threads of a verification service.
+
+use std::sync::{Arc, OnceLock};
+
+use pki_types::{CertificateDer, Der, TrustAnchor};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::audit::{AuditHook, AuditRecord, AuditSink};
+use crate::crypto::{CryptoBackend, DefaultBackend};
+use crate::output::DocumentOutput;
+#[cfg(feature = "rayon")]
+use crate::report::VerificationReport;
+use crate::{verify_payload, NitroAdDoc, NitroAdDocPayload, NitroAdError, VerifierPolicy};
+
+/// Verifies documents, policy included, wherever that happens: a local [`Verifier`] or
+/// e.g. a [`RemoteVerifier`](crate::remote::RemoteVerifier) service
+pub trait DocumentVerifier {
+    /// Fields of `document`, verified at `unix_ts_sec`
+    fn verify_document(
+        &self,
+        document: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<DocumentOutput, NitroAdError>;
+}
+
+/// Root certificate, policy and signature backend documents are verified with
+#[derive(Debug)]
+pub struct Verifier<B = DefaultBackend> {
+    root_cert: Vec<u8>,
+    anchor: OnceLock<Result<TrustAnchor<'static>, webpki::Error>>,
+    policy: VerifierPolicy,
+    backend: B,
+    audit: Option<AuditHook>,
+}
+
+// shared across threads by design, keep it that way
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Verifier>();
+};
+
+impl Verifier {
+    /// Verifier trusting the DER encoded `root_cert`, with the [`DefaultBackend`]
+    pub fn new(root_cert: impl Into<Vec<u8>>) -> Self {
+        Self::with_backend(root_cert, DefaultBackend::default())
+    }
+}
+
+impl<B: CryptoBackend> Verifier<B> {
+    /// Verifier trusting the DER encoded `root_cert`, checking signatures with `backend`
+    pub fn with_backend(root_cert: impl Into<Vec<u8>>, backend: B) -> Self {
+        Verifier {
+            root_cert: root_cert.into(),
+            anchor: OnceLock::new(),
+            policy: VerifierPolicy::default(),
+            backend,
+            audit: None,
+        }
+    }
+
+    /// Checks verified documents against `policy` too
+    pub fn with_policy(mut self, policy: VerifierPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Passes `sink` an [`AuditRecord`] of every verification
+    pub fn with_audit_sink(mut self, sink: impl AuditSink + 'static) -> Self {
+        self.audit = Some(AuditHook(Arc::new(sink)));
+        self
+    }
+
+    pub fn policy(&self) -> &VerifierPolicy {
+        &self.policy
+    }
+
+    /// DER encoded root certificate
+    pub fn root_cert(&self) -> &[u8] {
+        &self.root_cert
+    }
+
+    /// Verifies the document `bytes` at `unix_ts_sec` like [`NitroAdDoc::from_bytes`],
+    /// then checks it against the policy. Unlike `from_bytes`, certificate chain
+    /// failures are errors.
+    pub fn verify<'a>(&self, bytes: &'a [u8], unix_ts_sec: u64) -> Result<NitroAdDoc<'a>, NitroAdError> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let (doc, checked) = self.verify_unrecorded(bytes, unix_ts_sec);
+        #[cfg(feature = "metrics")]
+        {
+            crate::metrics::record_verification(checked.as_ref().copied(), started.elapsed());
+            if let (Some(doc), Ok(())) = (&doc, &checked) {
+                crate::metrics::record_expiry_horizon(doc, unix_ts_sec);
+            }
+        }
+        self.audit(bytes, unix_ts_sec, doc.as_ref().map(|doc| doc.payload()), &checked);
+        match (doc, checked) {
+            (Some(doc), Ok(())) => Ok(doc),
+            (_, Err(e)) => Err(e),
+            (None, Ok(())) => unreachable!("documents are checked once parsed"),
+        }
+    }
+
+    /// Document of `bytes`, if it parsed and its signature verified, and the outcome
+    fn verify_unrecorded<'a>(
+        &self,
+        bytes: &'a [u8],
+        unix_ts_sec: u64,
+    ) -> (Option<NitroAdDoc<'a>>, Result<(), NitroAdError>) {
+        let parsed = self.anchor().and_then(|anchor| {
+            NitroAdDoc::from_bytes_with_anchor(bytes, anchor, unix_ts_sec, &self.backend)
+        });
+        match parsed {
+            Ok(doc) => {
+                let checked = self.policy.check(&doc);
+                (Some(doc), checked)
+            }
+            Err(e) => (None, Err(e)),
+        }
+    }
+
+    /// Outcome of [`Verifier::verify`] only, for hot paths which don't look at the
+    /// document. Neither the document nor a report is built, so nothing of x509-parser
+    /// or serde_json runs; see `benches/verify.rs` for the cost of each phase.
+    pub fn verify_fast(&self, bytes: &[u8], unix_ts_sec: u64) -> Result<(), NitroAdError> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let (payload, checked) = self.verify_fast_unrecorded(bytes, unix_ts_sec);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_verification(checked.as_ref().copied(), started.elapsed());
+        self.audit(bytes, unix_ts_sec, payload.as_ref(), &checked);
+        checked
+    }
+
+    fn verify_fast_unrecorded<'a>(
+        &self,
+        bytes: &'a [u8],
+        unix_ts_sec: u64,
+    ) -> (Option<NitroAdDocPayload<'a>>, Result<(), NitroAdError>) {
+        let verified = self
+            .anchor()
+            .and_then(|anchor| verify_payload(bytes, anchor, unix_ts_sec, &self.backend));
+        match verified {
+            Ok((payload, Some(e))) => (Some(payload), Err(NitroAdError::VerificationError(e))),
+            Ok((payload, None)) => {
+                let checked = self.policy.check_payload_at(&payload, unix_ts_sec);
+                (Some(payload), checked)
+            }
+            Err(e) => (None, Err(e)),
+        }
+    }
+
+    fn audit(
+        &self,
+        bytes: &[u8],
+        unix_ts_sec: u64,
+        payload: Option<&NitroAdDocPayload>,
+        checked: &Result<(), NitroAdError>,
+    ) {
+        if let Some(AuditHook(sink)) = &self.audit {
+            sink.record(&AuditRecord::new(bytes, unix_ts_sec, payload, checked.as_ref().err()));
+        }
+    }
+
+    /// [`Verifier::verify`] of each of `docs`, in parallel on the current rayon thread
+    /// pool. Reports are in the order of `docs`. Run it within
+    /// `rayon::ThreadPool::install` to verify on a dedicated pool.
+    #[cfg(feature = "rayon")]
+    pub fn verify_batch(
+        &self,
+        docs: &[&[u8]],
+        unix_ts_sec: u64,
+    ) -> Vec<Result<VerificationReport, NitroAdError>>
+    where
+        B: Sync,
+    {
+        docs.par_iter()
+            .map(|bytes| self.verify(bytes, unix_ts_sec).map(|doc| doc.report()))
+            .collect()
+    }
+
+    fn anchor(&self) -> Result<TrustAnchor<'_>, NitroAdError> {
+        let anchor = self.anchor.get_or_init(|| {
+            webpki::anchor_from_trusted_cert(&CertificateDer::from(self.root_cert.as_slice()))
+                .map(|anchor| anchor.to_owned())
+        });
+        match anchor {
+            // borrowed from the cached anchor rather than cloning it
+            Ok(anchor) => Ok(TrustAnchor {
+                subject: Der::from(anchor.subject.as_ref()),
+                subject_public_key_info: Der::from(anchor.subject_public_key_info.as_ref()),
+                name_constraints: anchor.name_constraints.as_ref().map(|c| Der::from(c.as_ref())),
+            }),
+            Err(e) => Err(NitroAdError::InvalidRootCertificate(e.clone())),
+        }
+    }
+}
+
+impl<B: CryptoBackend> DocumentVerifier for Verifier<B> {
+    fn verify_document(
+        &self,
+        document: &[u8],
+        unix_ts_sec: u64,
+    ) -> Result<DocumentOutput, NitroAdError> {
+        DocumentOutput::from_doc(&self.verify(document, unix_ts_sec)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_verify() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..]);
+
+        let doc = verifier.verify(ad_blob, 1614967200).unwrap();
+        assert_eq!(doc.as_bytes(), &ad_blob[..]);
+        assert!(verifier.anchor.get().unwrap().is_ok());
+
+        assert!(matches!(
+            verifier.verify(ad_blob, 1714967200),
+            Err(NitroAdError::VerificationError(webpki::Error::CertExpired { .. }))
+        ));
+
+        let verifier = verifier.with_policy(VerifierPolicy::new().with_nonce(&b"challenge"[..]));
+        assert!(matches!(verifier.verify(ad_blob, 1614967200), Err(NitroAdError::NonceMismatch)));
+    }
+
+    #[test]
+    fn test_verify_fast() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..]);
+
+        assert!(verifier.verify_fast(ad_blob, 1614967200).is_ok());
+        assert!(matches!(
+            verifier.verify_fast(ad_blob, 1714967200),
+            Err(NitroAdError::VerificationError(webpki::Error::CertExpired { .. }))
+        ));
+
+        let mut tampered = *ad_blob;
+        tampered[ad_blob.len() - 1] ^= 0x01;
+        assert!(matches!(verifier.verify_fast(&tampered, 1614967200), Err(NitroAdError::InvalidSignature)));
+
+        let verifier = verifier.with_policy(VerifierPolicy::new().with_nonce(&b"challenge"[..]));
+        assert!(matches!(verifier.verify_fast(ad_blob, 1614967200), Err(NitroAdError::NonceMismatch)));
+    }
+
+    #[test]
+    fn test_verify_document() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier: &dyn DocumentVerifier = &Verifier::new(&root_cert[..]);
+
+        let output = verifier.verify_document(ad_blob, 1614967200).unwrap();
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+        assert_eq!(output, DocumentOutput::from_doc(&doc).unwrap());
+        assert!(verifier.verify_document(ad_blob, 1714967200).is_err());
+    }
+
+    #[test]
+    fn test_invalid_root_cert() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..100]);
+
+        for _ in 0..2 {
+            assert!(matches!(
+                verifier.verify(ad_blob, 1614967200),
+                Err(NitroAdError::InvalidRootCertificate(_))
+            ));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_verify_batch() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Verifier::new(&root_cert[..]);
+
+        let mut tampered = *ad_blob;
+        tampered[ad_blob.len() - 1] ^= 0x01;
+        let docs: Vec<&[u8]> = vec![ad_blob, &tampered, &ad_blob[..10], ad_blob];
+
+        let reports = verifier.verify_batch(&docs, 1614967200);
+        assert_eq!(reports.len(), 4);
+        assert!(reports[0].as_ref().unwrap().is_accepted());
+        assert!(matches!(reports[1], Err(NitroAdError::InvalidSignature)));
+        assert!(reports[2].as_ref().unwrap_err().is_malformed_input());
+        assert_eq!(reports[3].as_ref().unwrap(), reports[0].as_ref().unwrap());
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        assert_eq!(pool.install(|| verifier.verify_batch(&docs[..1], 1614967200)).len(), 1);
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let verifier = Arc::new(Verifier::new(&root_cert[..]));
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let verifier = Arc::clone(&verifier);
+                thread::spawn(move || verifier.verify(ad_blob, 1614967200).is_ok())
+            })
+            .collect();
+        for worker in workers {
+            assert!(worker.join().unwrap());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Exchanging documents between enclave and parent instance over vsock
+//!
+//! Enclaves reach the outside world only through vsock sockets. A [`Channel`] frames
+//! each attestation document or [`handshake`](crate::handshake) message with a big
+//! endian `u32` byte length, the framing `NitroAdDoc::from_async_reader` reads too.
+//! ```no_run
+//! use aws_nitro_enclaves_attestation::vsock::{Channel, PARENT_CID};
+//!
+//! # let document = Vec::new();
+//! let mut channel = Channel::connect(PARENT_CID, 5005)?;
+//! channel.send_document(&document)?;
+//! # Ok::<(), aws_nitro_enclaves_attestation::NitroAdError>(())
+//! ```
+//! The parent accepts with a [`Listener`]. [`Channel::new`] wraps any other
+//! `Read + Write` stream with the same framing.
+
+use std::io::{Read, Write};
+
+use ::vsock::{VsockListener, VsockStream, VMADDR_CID_ANY};
+
+#[cfg(feature = "handshake")]
+use crate::handshake::Message;
+use crate::{NitroAdError, MAX_DOCUMENT_SIZE};
+
+/// CID of the parent instance as seen from its enclaves
+pub const PARENT_CID: u32 = 3;
+
+/// Largest frame [`read_frame`] accepts: a document with room for the fields of the
+/// handshake message carrying it
+pub const MAX_FRAME_SIZE: usize = MAX_DOCUMENT_SIZE + 1024;
+
+/// Writes `payload` to `writer`, prefixed with its length
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), NitroAdError> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(NitroAdError::DocumentTooLarge { limit: MAX_FRAME_SIZE });
+    }
+    writer
+        .write_all(&(payload.len() as u32).to_be_bytes())
+        .and_then(|()| writer.write_all(payload))
+        .and_then(|()| writer.flush())
+        .map_err(NitroAdError::IoError)
+}
+
+/// Reads one frame of [`write_frame`] from `reader`. Lengths above [`MAX_FRAME_SIZE`]
+/// fail before the payload is read.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>, NitroAdError> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).map_err(NitroAdError::IoError)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(NitroAdError::DocumentTooLarge { limit: MAX_FRAME_SIZE });
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).map_err(NitroAdError::IoError)?;
+    Ok(payload)
+}
+
+/// Framed connection between enclave and parent
+pub struct Channel<S = VsockStream> {
+    stream: S,
+}
+
+impl Channel {
+    /// Connects to `port` of the vsock peer `cid`, e.g. [`PARENT_CID`] from an enclave
+    /// or the enclave's CID from the parent
+    pub fn connect(cid: u32, port: u32) -> Result<Self, NitroAdError> {
+        VsockStream::connect_with_cid_port(cid, port)
+            .map(Channel::new)
+            .map_err(NitroAdError::IoError)
+    }
+}
+
+impl<S: Read + Write> Channel<S> {
+    /// Channel framing the messages of `stream`
+    pub fn new(stream: S) -> Self {
+        Channel { stream }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub fn send_document(&mut self, document: &[u8]) -> Result<(), NitroAdError> {
+        write_frame(&mut self.stream, document)
+    }
+
+    /// Next document, unverified. Documents above [`MAX_DOCUMENT_SIZE`] fail.
+    pub fn receive_document(&mut self) -> Result<Vec<u8>, NitroAdError> {
+        let document = read_frame(&mut self.stream)?;
+        if document.len() > MAX_DOCUMENT_SIZE {
+            return Err(NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE });
+        }
+        Ok(document)
+    }
+
+    #[cfg(feature = "handshake")]
+    pub fn send_message(&mut self, message: &Message) -> Result<(), NitroAdError> {
+        write_frame(&mut self.stream, &message.to_bytes()?)
+    }
+
+    #[cfg(feature = "handshake")]
+    pub fn receive_message(&mut self) -> Result<Message, NitroAdError> {
+        Message::from_bytes(&read_frame(&mut self.stream)?)
+    }
+}
+
+/// Listening vsock socket accepting [`Channel`]s
+pub struct Listener {
+    listener: VsockListener,
+}
+
+impl Listener {
+    /// Listens on `port` for connections from any CID
+    pub fn bind(port: u32) -> Result<Self, NitroAdError> {
+        VsockListener::bind_with_cid_port(VMADDR_CID_ANY, port)
+            .map(|listener| Listener { listener })
+            .map_err(NitroAdError::IoError)
+    }
+
+    /// Waits for the next connection and returns it with the peer's CID
+    pub fn accept(&self) -> Result<(Channel, u32), NitroAdError> {
+        let (stream, addr) = self.listener.accept().map_err(NitroAdError::IoError)?;
+        Ok((Channel::new(stream), addr.cid()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_framing() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let mut wire = Vec::new();
+        write_frame(&mut wire, ad_blob).unwrap();
+        write_frame(&mut wire, b"").unwrap();
+        assert_eq!(&wire[..4], &(ad_blob.len() as u32).to_be_bytes());
+
+        let mut reader = &wire[..];
+        assert_eq!(read_frame(&mut reader).unwrap(), &ad_blob[..]);
+        assert!(read_frame(&mut reader).unwrap().is_empty());
+        assert!(matches!(read_frame(&mut reader), Err(NitroAdError::IoError(_))));
+
+        let mut oversized = &(MAX_FRAME_SIZE as u32 + 1).to_be_bytes()[..];
+        assert!(matches!(
+            read_frame(&mut oversized),
+            Err(NitroAdError::DocumentTooLarge { limit: MAX_FRAME_SIZE })
+        ));
+        assert!(write_frame(&mut Vec::new(), &vec![0; MAX_FRAME_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_channel() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let (enclave, parent) = UnixStream::pair().unwrap();
+        let mut enclave = Channel::new(enclave);
+        let mut parent = Channel::new(parent);
+
+        enclave.send_document(ad_blob).unwrap();
+        assert_eq!(parent.receive_document().unwrap(), &ad_blob[..]);
+
+        write_frame(&mut enclave.stream, &vec![0; MAX_DOCUMENT_SIZE + 1]).unwrap();
+        assert!(matches!(
+            parent.receive_document(),
+            Err(NitroAdError::DocumentTooLarge { limit: MAX_DOCUMENT_SIZE })
+        ));
+    }
+
+    #[test]
+    #[cfg(all(feature = "handshake", feature = "testing"))]
+    fn test_handshake_over_channel() -> Result<(), NitroAdError> {
+        use std::thread;
+
+        use crate::handshake::{Attester, Verifier};
+        use crate::testing::MockNsm;
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let enclave_nsm = MockNsm::new()?;
+        let verifier = Verifier::new(
+            crate::Verifier::new(enclave_nsm.root_cert()),
+            &mut rand_core::OsRng,
+        )?;
+        let (enclave, parent) = UnixStream::pair().unwrap();
+
+        let enclave = thread::spawn(move || -> Result<[u8; 32], NitroAdError> {
+            let mut channel = Channel::new(enclave);
+            let challenge = channel.receive_message()?;
+            let (attester, evidence) =
+                Attester::respond(&enclave_nsm, None, &mut rand_core::OsRng, &challenge, now)?;
+            channel.send_message(&evidence)?;
+            let session = attester.finish(&channel.receive_message()?)?;
+            Ok(*session.key())
+        });
+
+        let mut channel = Channel::new(parent);
+        channel.send_message(verifier.challenge())?;
+        let (session, finished) = verifier.verify(&channel.receive_message()?, now)?;
+        channel.send_message(&finished)?;
+        assert_eq!(&enclave.join().unwrap()?, session.key());
+        Ok(())
+    }
+}
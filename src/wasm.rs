@@ -0,0 +1,77 @@
+//! JavaScript API through wasm-bindgen
+//!
+//! ```bash
+//! wasm-pack build --target web wasm
+//! ```
+//! builds the `wasm/` crate into an npm package exporting [`verify_attestation`] as `verifyAttestation`,
+//! with TypeScript definitions of the returned object. See the README for the
+//! wasm32 build prerequisites.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::NitroAdDoc;
+
+// only read by the custom section below, at compile time
+#[allow(dead_code)]
+static TYPESCRIPT: &str = r#"
+export interface CertificateSummary {
+  issuer: string;
+  subject: string;
+  validity: { not_before: string; not_after: string };
+}
+
+export interface AttestationDocument {
+  module_id: string;
+  digest: string;
+  timestamp: string;
+  /** PCR index to hex encoded value */
+  pcrs: Record<number, string>;
+  /** cabundle certificates followed by the signing certificate */
+  certs: CertificateSummary[];
+  /** base64 encoded */
+  public_key: string | null;
+  /** base64 encoded */
+  user_data: string | null;
+  /** base64 encoded */
+  nonce: string | null;
+  /** certificate chain error, null when the chain verified */
+  verification_error: string | null;
+}
+"#;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &'static str = TYPESCRIPT;
+
+/// Verifies the document `bytes` against the DER encoded `root_der` at `timestamp`,
+/// seconds since the unix epoch, returning the object of
+/// [`NitroAdDoc::to_json`]. Throws when the document is malformed or its signature
+/// doesn't verify; certificate chain failures are reported in `verification_error`.
+#[wasm_bindgen(js_name = verifyAttestation, unchecked_return_type = "AttestationDocument")]
+pub fn verify_attestation(bytes: &[u8], root_der: &[u8], timestamp: f64) -> Result<JsValue, JsError> {
+    let doc = NitroAdDoc::from_bytes(bytes, root_der, timestamp as u64)?;
+    let output = doc.to_output()?;
+
+    // plain objects rather than Maps, like the JSON output
+    Ok(output.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typescript_covers_output() {
+        let ad_blob = include_bytes!("../tests/data/nitro_ad_debug.bin");
+        let root_cert = include_bytes!("../tests/data/aws_root.der");
+        let doc = NitroAdDoc::from_bytes(ad_blob, root_cert, 1614967200).unwrap();
+
+        let js: serde_json::Value = serde_json::from_str(&doc.to_json().unwrap()).unwrap();
+        for field in js.as_object().unwrap().keys() {
+            assert!(TYPESCRIPT.contains(&format!("  {}: ", field)), "{} is not typed", field);
+        }
+        for field in js["certs"][0].as_object().unwrap().keys() {
+            assert!(TYPESCRIPT.contains(&format!("  {}: ", field)), "{} is not typed", field);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Attestation documents in X.509 certificates
+//!
+//! An enclave proves that a certificate's key is its own by embedding a document whose
+//! `user_data` is [`key_binding`] of the certificate's SubjectPublicKeyInfo, in the
+//! non-critical [`ATTESTATION_EXTENSION_OID`] extension. The extension value is the raw
+//! document. [`extension_der`] encodes the extension for any certificate builder,
+//! [`x509_extension`] for OpenSSL's, and [`verify_certificate`] extracts and verifies
+//! the document of a presented certificate, whatever protocol presented it. The `tls`
+//! module builds rustls configurations on top.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "openssl")]
+use openssl::asn1::{Asn1Object, Asn1OctetString};
+#[cfg(feature = "openssl")]
+use openssl::x509::X509Extension;
+use subtle::ConstantTimeEq;
+use x509_parser::prelude::*;
+
+use crate::crypto::{der_header, sha384};
+use crate::verifier::Verifier;
+use crate::{NitroAdDoc, NitroAdError};
+
+/// Extension of attested certificates holding the raw attestation document as its
+/// OCTET STRING value. No OID is registered for Nitro attestation documents, so this
+/// one lies in the `2.25` arc of UUID based OIDs, which needs no registration.
+pub static ATTESTATION_EXTENSION_OID: &str = "2.25.110823058476075668376540190202720931853";
+
+/// DER contents of [`ATTESTATION_EXTENSION_OID`], for matching extensions by their
+/// encoding; x509-parser and other libraries can't print OIDs with arcs beyond 64 bits
+pub static ATTESTATION_EXTENSION_OID_DER: &[u8] = &[
+    0x69, 0x81, 0xa6, 0xdf, 0xdf, 0xa8, 0xf5, 0x92, 0xc2, 0x9b, 0xdd, 0x87, 0xd4, 0xd5, 0x88,
+    0xd6, 0xbd, 0xcf, 0xa0, 0x0d,
+];
+
+/// `user_data` binding a document to the DER encoded SubjectPublicKeyInfo `spki`: its
+/// SHA-384 hash
+pub fn key_binding(spki: &[u8]) -> [u8; 48] {
+    sha384(spki)
+}
+
+/// DER encoded `Extension` structure carrying `document`, for certificate builders
+/// taking extensions as raw DER
+pub fn extension_der(document: &[u8]) -> Vec<u8> {
+    let mut content = Vec::with_capacity(document.len() + 32);
+    der_header(&mut content, 0x06, ATTESTATION_EXTENSION_OID_DER.len());
+    content.extend_from_slice(ATTESTATION_EXTENSION_OID_DER);
+    der_header(&mut content, 0x04, document.len());
+    content.extend_from_slice(document);
+
+    let mut extension = Vec::with_capacity(content.len() + 4);
+    der_header(&mut extension, 0x30, content.len());
+    extension.extend_from_slice(&content);
+    extension
+}
+
+/// Extension carrying `document`, for `openssl::x509::X509Builder::append_extension`
+#[cfg(feature = "openssl")]
+pub fn x509_extension(document: &[u8]) -> Result<X509Extension, NitroAdError> {
+    let extension = || {
+        let oid = Asn1Object::from_str(ATTESTATION_EXTENSION_OID)?;
+        let document = Asn1OctetString::new_from_bytes(document)?;
+        X509Extension::new_from_der(&oid, false, &document)
+    };
+    extension().map_err(NitroAdError::SigningError)
+}
+
+/// Document embedded in `cert` and the certificate's DER encoded SubjectPublicKeyInfo
+fn document_and_spki<'a>(
+    cert: &X509Certificate<'a>,
+) -> Result<(&'a [u8], &'a [u8]), NitroAdError> {
+    let document = cert
+        .extensions()
+        .iter()
+        .find(|ext| ext.oid.as_bytes() == ATTESTATION_EXTENSION_OID_DER)
+        .ok_or(NitroAdError::MissingAttestationExtension)?
+        .value;
+    Ok((document, cert.tbs_certificate.subject_pki.raw))
+}
+
+fn parse(cert_der: &[u8]) -> Result<X509Certificate<'_>, NitroAdError> {
+    X509Certificate::from_der(cert_der)
+        .map(|(_, cert)| cert)
+        .map_err(|e| NitroAdError::X509Error(e.to_string()))
+}
+
+/// Unverified document embedded in the DER encoded certificate `cert_der`
+pub fn extract_document(cert_der: &[u8]) -> Result<&[u8], NitroAdError> {
+    document_and_spki(&parse(cert_der)?).map(|(document, _)| document)
+}
+
+/// Verifies the document embedded in the DER encoded certificate `cert_der` with
+/// `verifier` at `unix_ts_sec`, including its policy, and checks that the document binds
+/// the certificate's key. The certificate's own signature and validity aren't checked,
+/// the enclave chose them itself.
+pub fn verify_certificate<'a>(
+    verifier: &Verifier,
+    cert_der: &'a [u8],
+    unix_ts_sec: u64,
+) -> Result<NitroAdDoc<'a>, NitroAdError> {
+    let (document, spki) = document_and_spki(&parse(cert_der)?)?;
+    let doc = verifier.verify(document, unix_ts_sec)?;
+    let binding = key_binding(spki);
+    match doc.payload().user_data.as_deref() {
+        Some(user_data) if bool::from(user_data.ct_eq(&binding[..])) => Ok(doc),
+        _ => Err(NitroAdError::KeyBindingMismatch),
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::X509;
+
+    use crate::nsm::{AttestationRequest, Attester};
+    use crate::testing::{MockNsm, TestChain};
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// Self-signed certificate of `key` carrying `document`
+    fn certificate(key: &PKey<Private>, document: &[u8]) -> Vec<u8> {
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_pubkey(key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        cert.append_extension(x509_extension(document).unwrap()).unwrap();
+        cert.sign(key, MessageDigest::sha384()).unwrap();
+        cert.build().to_der().unwrap()
+    }
+
+    #[test]
+    fn test_verify_certificate() -> Result<(), NitroAdError> {
+        let mock = MockNsm::new()?;
+        let verifier = Verifier::new(mock.root_cert());
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        let key = key();
+        let binding = key_binding(&key.public_key_to_der().unwrap());
+        let document = mock.attest(AttestationRequest {
+            user_data: Some(&binding),
+            ..Default::default()
+        })?;
+        let cert = certificate(&key, &document);
+        assert_eq!(extract_document(&cert)?, &document[..]);
+        let doc = verify_certificate(&verifier, &cert, now)?;
+        assert_eq!(doc.payload().user_data.as_deref(), Some(&binding[..]));
+
+        // both encodings of the extension agree
+        let extension = extension_der(&document);
+        assert!(cert.windows(extension.len()).any(|w| w == &extension[..]));
+
+        // the document copied into a certificate for another key
+        let copied = certificate(&self::key(), &document);
+        assert!(matches!(
+            verify_certificate(&verifier, &copied, now),
+            Err(NitroAdError::KeyBindingMismatch)
+        ));
+
+        let plain = TestChain::generate()?.leaf_der();
+        assert!(matches!(extract_document(&plain), Err(NitroAdError::MissingAttestationExtension)));
+        assert!(matches!(extract_document(b"not a certificate"), Err(NitroAdError::X509Error(_))));
+
+        let oid = Asn1Object::from_str(ATTESTATION_EXTENSION_OID).unwrap();
+        assert_eq!(oid.as_slice(), ATTESTATION_EXTENSION_OID_DER);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extension_der_long_form_length() {
+        let extension = extension_der(&[0xab; 300]);
+        assert_eq!(&extension[..4], &[0x30, 0x82, 0x01, 0x46]);
+        assert_eq!(&extension[4..6], &[0x06, ATTESTATION_EXTENSION_OID_DER.len() as u8]);
+        assert_eq!(&extension[26..30], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(extension.len(), 30 + 300);
+    }
+}
@@ -0,0 +1,3 @@
+//! wasm-pack package of the `wasm` feature, see the library's wasm module
+
+pub use aws_nitro_enclaves_attestation::wasm::verify_attestation;